@@ -1,124 +1,2757 @@
 use axum::{
-    extract::Multipart,
-    http::StatusCode,
-    response::Json,
+    error_handling::HandleErrorLayer,
+    body::Bytes,
+    extract::{multipart::{Field, MultipartError}, DefaultBodyLimit, Multipart, Path, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Json, Response},
     routing::{get, post},
     Router,
 };
-use serde::Serialize;
-use std::io::Cursor;
+use metrics_exporter_prometheus::PrometheusHandle;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::{Read, Seek, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tempfile::SpooledTempFile;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::{BoxError, ServiceBuilder};
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::trace::TraceLayer;
 
-use crate::parser::{LocalPdfParser, Parser};
-use crate::splitter::{Chunk, SentenceTextSplitter, TextSplitter};
+use crate::auth::ApiKey;
+use crate::cache::{self, ImageStore, ParseCache};
+use crate::config::Config;
+use crate::dead_letter::{DeadLetterEntry, DeadLetterSink};
+use crate::fetch::{FetchError, FetchPolicy};
+use crate::health::Health;
+use crate::language;
+use crate::parser::{
+    detect_format, for_content_type, for_content_type_with, parse_with_timeout, sniff_content_type, unpack,
+    ArchiveError, FootnoteMarkers, HtmlParser, HttpOcr, LocalPdfParser, OcrEngine, Page, Parser, ParserError,
+    ParserRegistry, QualityRules, QualityWarning, DEFAULT_PAGE_CHARS, DEFAULT_SECTION_SELECTORS,
+};
+use crate::splitter::{
+    embed_text, fingerprint_chunks, order_chunks, prepend_headings, structure_tree, Chunk, ChunkOrder, ChunkProcessor,
+    ChunkProcessors, OverlapAlign, RecursiveCharacterTextSplitter, RedactionProcessor, SentenceTextSplitter,
+    SplitterKind, SplitterSettings, StructureNode, TextSplitter, TokenizerKind,
+};
+use crate::summarize::{HttpSummarizer, Summarizer};
+use crate::telemetry::{self, UploadFingerprint};
+
+/// Image store limits used when no parse cache is configured.
+const IMAGE_STORE_CAPACITY: usize = 256;
+const IMAGE_STORE_TTL: Duration = Duration::from_secs(600);
+
+/// Limits on a `/api/parse/batch` form; larger batches get `413`.
+const MAX_BATCH_FILES: usize = 32;
+const MAX_BATCH_BYTES: usize = 100 * 1024 * 1024;
+
+/// Route of the batch endpoint, whose body limit is the batch's.
+const BATCH_ROUTE: &str = "/api/parse/batch";
+
+/// Room for multipart boundaries and headers on top of the file size limits.
+const MULTIPART_OVERHEAD: usize = 64 * 1024;
+
+/// Bytes of an upload held in memory while it arrives; the rest is spooled
+/// to a temporary file until the upload is complete.
+const SPOOL_MEMORY_BYTES: usize = 1024 * 1024;
 
-#[derive(Serialize)]
+/// Time allowed for fetching a document for `/api/parse/url`.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Shared state available to every REST handler.
+#[derive(Clone)]
+struct AppState {
+    cache: Option<Arc<dyn ParseCache>>,
+    images: ImageStore,
+    max_upload_bytes: usize,
+    max_decompressed_bytes: usize,
+    pdf_max_images_per_page: usize,
+    /// Time allowed for parsing one upload.
+    parse_timeout: Duration,
+    /// Requests to the `/api` routes handled at once; more get `503`.
+    max_concurrent_requests: usize,
+    /// Required on the `/api` routes, when configured.
+    api_key: ApiKey,
+    batch_deadline: Duration,
+    /// Where failed batch documents are recorded, when configured.
+    dead_letters: Option<Arc<dyn DeadLetterSink>>,
+    /// Writes document summaries, when configured and network access is allowed.
+    summarizer: Option<Arc<dyn Summarizer>>,
+    /// Reads scanned PDF pages, when configured and network access is allowed.
+    ocr: Option<Arc<dyn OcrEngine>>,
+    quality_rules: QualityRules,
+    /// Run on every chunk returned.
+    chunk_processors: ChunkProcessors,
+    health: Health,
+    /// SSRF protections for `/api/parse/url`, unless network access is disabled.
+    fetch: Option<FetchPolicy>,
+    /// Renders the process-wide metrics for `/metrics`.
+    metrics: PrometheusHandle,
+}
+
+#[derive(Serialize, Deserialize)]
 struct HealthResponse {
     status: String,
     service: String,
     version: String,
+    uptime_seconds: u64,
+    /// Why the service is degraded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
 }
 
-#[derive(Serialize)]
+/// What `/api/parse` answers with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+    /// Chunks and document metadata, as JSON.
+    #[default]
+    Chunks,
+    /// The cleaned text as `text/plain`, one detected sentence per line,
+    /// without chunking.
+    Sentences,
+}
+
+/// Optional query parameters for `/api/parse`.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct ParseParams {
+    /// Response shape; `sentences` applies to `/api/parse` only.
+    format: OutputFormat,
+    /// Splitting strategy: `sentence` or `recursive` (paragraphs first).
+    splitter: SplitterKind,
+    language_spans: bool,
+    boundary_tolerance_percent: usize,
+    emit_embed_text: bool,
+    drop_empty_chunks: bool,
+    infer_headings: bool,
+    /// Rebuild PDF tables from text positions as Markdown table chunks.
+    detect_tables: bool,
+    extract_images: bool,
+    cross_page_merge: bool,
+    order: ChunkOrder,
+    structure_tree: bool,
+    /// Hard cap on chunk length in characters (0 = none).
+    max_chars: usize,
+    /// Merge a page's last chunk into the one before when it has fewer
+    /// tokens than this (0 = never).
+    min_chunk_tokens: usize,
+    emit_overlap: bool,
+    /// Round the overlap to whole sentences (`sentence`) or not (`token`).
+    overlap_align: OverlapAlign,
+    /// Token encoding chunks are sized in (`cl100k_base`, `o200k_base`, `p50k_base`).
+    tokenizer: TokenizerKind,
+    /// Strip or relocate footnote markers run into the text (PDF, DOCX, HTML).
+    handle_footnote_markers: FootnoteMarkers,
+    expand_ligatures: bool,
+    /// Rejoin hyphenated line breaks, drop page-number lines and join the
+    /// lines of each paragraph (PDF).
+    normalize_whitespace: bool,
+    /// Characters per virtual page of HTML, and of DOCX without page or
+    /// section breaks (0 = one page).
+    page_chars: usize,
+    /// Page HTML by its `section`, `article` and `main` elements instead of
+    /// by character count, falling back to `page_chars` when none match.
+    html_sections: bool,
+    /// Comma-separated selectors to page HTML by instead of the default
+    /// ones (implies `html_sections`).
+    html_section_selectors: Option<String>,
+    /// Tag pages and chunks with the language of the page text.
+    detect_language: bool,
+    /// Report where each page lies in the source (text formats only).
+    page_source_ranges: bool,
+    /// Split the pages as one text joined by `page_separator`.
+    merge_pages: bool,
+    page_separator: String,
+    /// Add an LLM-written summary to the metadata, when a summarizer is configured.
+    summarize: bool,
+    /// Add a SimHash fingerprint to every chunk.
+    simhash: bool,
+    /// Derive chunk IDs from the filename, page and text instead of
+    /// picking them at random, so re-ingesting yields the same IDs.
+    deterministic_ids: bool,
+    /// Prefix each chunk's text with its heading path.
+    prepend_heading: bool,
+    /// Replace email addresses, phone numbers and national IDs in chunk
+    /// text with placeholders such as `[EMAIL]`.
+    redact_pii: bool,
+    /// Return only chunks containing this text (case-insensitive), or
+    /// matching it as a regex when `filter_regex` is set.
+    filter: Option<String>,
+    filter_regex: bool,
+    /// Echo the effective parser and splitter settings in the response.
+    echo_config: bool,
+    /// Run the whole pipeline but leave `chunks` out of the response, for
+    /// sizing a document by its stats alone.
+    stats_only: bool,
+}
+
+impl Default for ParseParams {
+    fn default() -> Self {
+        Self {
+            format: OutputFormat::Chunks,
+            splitter: SplitterKind::Sentence,
+            language_spans: false,
+            boundary_tolerance_percent: 0,
+            emit_embed_text: false,
+            drop_empty_chunks: true,
+            infer_headings: false,
+            detect_tables: false,
+            extract_images: false,
+            cross_page_merge: false,
+            order: ChunkOrder::Document,
+            structure_tree: false,
+            max_chars: 0,
+            min_chunk_tokens: 0,
+            emit_overlap: false,
+            overlap_align: OverlapAlign::Token,
+            tokenizer: TokenizerKind::Cl100kBase,
+            handle_footnote_markers: FootnoteMarkers::Keep,
+            expand_ligatures: true,
+            normalize_whitespace: false,
+            page_chars: DEFAULT_PAGE_CHARS,
+            html_sections: false,
+            html_section_selectors: None,
+            detect_language: true,
+            page_source_ranges: false,
+            merge_pages: false,
+            page_separator: " ".to_string(),
+            summarize: false,
+            simhash: false,
+            deterministic_ids: false,
+            prepend_heading: false,
+            redact_pii: false,
+            filter: None,
+            filter_regex: false,
+            echo_config: false,
+            stats_only: false,
+        }
+    }
+}
+
+impl ParseParams {
+    /// The selectors to page HTML by, empty when not paging by sections.
+    fn section_selectors(&self) -> Vec<String> {
+        match self.html_section_selectors.as_deref() {
+            Some(selectors) if !selectors.trim().is_empty() => selectors
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect(),
+            _ if self.html_sections => DEFAULT_SECTION_SELECTORS.iter().map(|s| s.to_string()).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// The `filter` of a parse request, compiled once per request.
+enum ChunkFilter {
+    /// Lowercased needle.
+    Substring(String),
+    Regex(Regex),
+}
+
+impl ChunkFilter {
+    /// The filter described by `params`, or `400 Bad Request` naming the
+    /// problem when the regex doesn't compile.
+    fn from_params(params: &ParseParams) -> Result<Option<Self>, ApiError> {
+        let Some(filter) = params.filter.as_deref().filter(|f| !f.is_empty()) else {
+            return Ok(None);
+        };
+        if !params.filter_regex {
+            return Ok(Some(Self::Substring(filter.to_lowercase())));
+        }
+        Regex::new(filter).map(|re| Some(Self::Regex(re))).map_err(|e| ApiError {
+            status: StatusCode::BAD_REQUEST,
+            code: None,
+            message: Some(format!("invalid filter regex: {}", e)),
+        })
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        match self {
+            Self::Substring(needle) => text.to_lowercase().contains(needle),
+            Self::Regex(re) => re.is_match(text),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 struct ParseResponse {
     chunks: Vec<Chunk>,
     metadata: DocumentMetadata,
     stats: ProcessingStats,
+    /// Sections nested by heading level, present when requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    structure_tree: Option<StructureNode>,
+    /// Settings that produced this response, when requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    applied_config: Option<AppliedConfig>,
+}
+
+/// Effective settings behind a parse, defaults included.
+#[derive(Serialize, Deserialize)]
+struct AppliedConfig {
+    parser: String,
+    /// Splitter settings; `transforms` also lists the steps around splitting.
+    #[serde(flatten)]
+    splitter: SplitterSettings,
+}
+
+impl AppliedConfig {
+    fn new(params: &ParseParams, parser: &str, splitter: &dyn TextSplitter, summarized: bool) -> Self {
+        let mut settings = splitter.settings();
+        let pdf = parser == "LocalPdfParser";
+        let filtered = params.filter.as_deref().is_some_and(|f| !f.is_empty());
+        let flags = [
+            ("infer_headings", pdf && params.infer_headings),
+            ("detect_tables", pdf && params.detect_tables),
+            ("expand_ligatures", pdf && params.expand_ligatures),
+            ("normalize_whitespace", pdf && params.normalize_whitespace),
+            ("detect_language", params.detect_language),
+            ("extract_images", params.extract_images),
+            ("order=importance", params.order == ChunkOrder::Importance),
+            ("filter", filtered),
+            ("filter_regex", filtered && params.filter_regex),
+            ("simhash", params.simhash),
+            ("prepend_heading", params.prepend_heading),
+            ("redact_pii", params.redact_pii),
+            ("structure_tree", params.structure_tree),
+            ("page_source_ranges", params.page_source_ranges),
+            ("summarize", summarized),
+        ];
+        settings
+            .transforms
+            .extend(flags.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| name.to_string()));
+        if params.handle_footnote_markers.applies_to(parser) {
+            settings
+                .transforms
+                .push(format!("footnote_markers={}", params.handle_footnote_markers.name()));
+        }
+        if matches!(parser, "HtmlParser" | "DocxParser") {
+            settings.transforms.push(format!("page_chars={}", params.page_chars));
+        }
+        let sections = params.section_selectors();
+        if parser == "HtmlParser" && !sections.is_empty() {
+            settings.transforms.push(format!("html_sections={}", sections.join(",")));
+        }
+        Self {
+            parser: parser.to_string(),
+            splitter: settings,
+        }
+    }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 struct DocumentMetadata {
+    /// Content hash identifying the document in `/api/images` URLs.
+    document_hash: String,
     filename: String,
     content_type: String,
     size_bytes: usize,
     page_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_modified_by: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    revision: Option<u32>,
+    /// Document table of contents built from its headings.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    outline: Vec<OutlineEntry>,
+    /// Byte range of each page in the uploaded file, when requested.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    page_source_ranges: Vec<PageSourceRange>,
+    /// One-paragraph summary of the document, when requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<String>,
+    /// Pages whose extracted text looked corrupt.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    quality_warnings: Vec<QualityWarning>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OutlineEntry {
+    level: u8,
+    title: String,
+    page_num: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PageSourceRange {
+    page_num: u32,
+    source_start: usize,
+    source_end: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PageImageCount {
+    page_num: u32,
+    count: usize,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 struct ProcessingStats {
     processing_time_ms: u64,
     total_chunks: usize,
     total_tokens: usize,
+    /// Images extracted from the document; 0 unless `extract_images` was
+    /// requested and the parser extracts images.
+    #[serde(default)]
+    total_images: usize,
+    /// Images extracted per page, for pages with any.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    page_images: Vec<PageImageCount>,
+    /// Personal data replaced by placeholders, when `redact_pii` was requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    redactions: Option<usize>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 struct SupportedFormatsResponse {
     extensions: Vec<String>,
     mime_types: Vec<String>,
 }
 
-async fn health() -> Json<HealthResponse> {
-    Json(HealthResponse {
-        status: "healthy".to_string(),
-        service: "ingestion-service".to_string(),
-        version: "0.1.0".to_string(),
-    })
+/// Service health; `503 Service Unavailable` when degraded, so load
+/// balancers stop routing to the instance.
+async fn health(State(state): State<AppState>) -> (StatusCode, Json<HealthResponse>) {
+    let health = state.health.check().await;
+    let code = if health.degraded_reason.is_some() {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    (
+        code,
+        Json(HealthResponse {
+            status: health.status().to_string(),
+            service: "ingestion-service".to_string(),
+            version: "0.1.0".to_string(),
+            uptime_seconds: health.uptime.as_secs(),
+            reason: health.degraded_reason,
+        }),
+    )
+}
+
+/// Parse counters and latencies in the Prometheus text format.
+async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], state.metrics.render())
 }
 
+/// Formats the REST endpoints parse: those of every local parser.
 async fn supported_formats() -> Json<SupportedFormatsResponse> {
-    let parser = LocalPdfParser::new();
+    let registry = ParserRegistry::local();
     Json(SupportedFormatsResponse {
-        extensions: parser.supported_extensions().iter().map(|s| s.to_string()).collect(),
-        mime_types: parser.supported_mime_types().iter().map(|s| s.to_string()).collect(),
+        extensions: registry.extensions(),
+        mime_types: registry.mime_types(),
     })
 }
 
-async fn parse_document(mut multipart: Multipart) -> Result<Json<ParseResponse>, StatusCode> {
-    let start = std::time::Instant::now();
+/// Error response of the upload endpoints: a bare status, or a status with
+/// a JSON `{"error": ...}` body explaining it.
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    /// Machine-readable reason, for failures clients handle differently
+    /// from others with the same status.
+    code: Option<&'static str>,
+    message: Option<String>,
+}
 
-    let mut file_data: Option<Vec<u8>> = None;
-    let mut filename = String::new();
-    let mut content_type = String::new();
+impl ApiError {
+    fn payload_too_large(message: String) -> Self {
+        Self {
+            status: StatusCode::PAYLOAD_TOO_LARGE,
+            code: None,
+            message: Some(message),
+        }
+    }
+}
 
-    while let Ok(Some(field)) = multipart.next_field().await {
-        if field.name() == Some("file") {
-            filename = field.file_name().unwrap_or("unknown").to_string();
-            content_type = field.content_type().unwrap_or("application/octet-stream").to_string();
-            if let Ok(bytes) = field.bytes().await {
-                file_data = Some(bytes.to_vec());
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        Self {
+            status,
+            code: None,
+            message: None,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        match self.message {
+            Some(error) => {
+                let code = self.code.map(str::to_string);
+                (self.status, Json(ErrorResponse { error, code })).into_response()
             }
+            None => self.status.into_response(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ErrorResponse {
+    error: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    code: Option<String>,
+}
+
+/// Caps on the files read from one form.
+struct UploadLimits {
+    max_files: usize,
+    max_file_bytes: usize,
+    /// Most bytes of all files together, counted once decompressed.
+    max_total_bytes: usize,
+    /// Most bytes each compressed file may expand to.
+    max_decompressed_bytes: usize,
+}
+
+/// A file posted as the `file` field of a multipart form.
+struct Upload {
+    data: Bytes,
+    filename: String,
+    content_type: String,
+    /// Whether the file was decompressed from a gzip or zip upload.
+    unpacked: bool,
+}
+
+/// Every file posted under the `file` field, in form order, with gzip files
+/// decompressed and zip archives replaced by the documents they hold.
+/// Reading stops with `413 Payload Too Large` as soon as the form breaks one
+/// of `limits` or the request body limit.
+async fn read_uploads(multipart: &mut Multipart, limits: UploadLimits) -> Result<Vec<Upload>, ApiError> {
+    let too_large = || ApiError::payload_too_large("request body exceeds the upload limit".to_string());
+    let mut uploads = Vec::new();
+    let mut total_bytes = 0;
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Err(e) if e.status() == StatusCode::PAYLOAD_TOO_LARGE => return Err(too_large()),
+            Ok(None) | Err(_) => break,
+        };
+        if field.name() != Some("file") {
+            continue;
+        }
+        let filename = field.file_name().unwrap_or("unknown").to_string();
+        let declared = field.content_type().unwrap_or("application/octet-stream").to_string();
+        let spool = match spool_field(field, limits.max_file_bytes).await {
+            Ok(Some(spool)) => spool,
+            Ok(None) => {
+                return Err(ApiError::payload_too_large(format!(
+                    "{} exceeds the upload limit of {} bytes",
+                    filename, limits.max_file_bytes
+                )))
+            }
+            Err(FieldError::Multipart(e)) if e.status() == StatusCode::PAYLOAD_TOO_LARGE => return Err(too_large()),
+            Err(FieldError::Multipart(_)) => continue,
+            Err(FieldError::Spool(e)) => return Err(spool_failed(&filename, e)),
+        };
+        // Archives expand into what is left of the form's budget
+        let max_bytes = limits.max_decompressed_bytes.min(limits.max_total_bytes.saturating_sub(total_bytes));
+        // Reading the spool back may touch the disk, so it happens off the async workers
+        let read = {
+            let filename = filename.clone();
+            tokio::task::spawn_blocking(move || {
+                let bytes = spool.into_bytes()?;
+                let unpacked = unpack(&filename, &bytes, max_bytes);
+                Ok::<_, std::io::Error>((bytes, unpacked))
+            })
+            .await
+            .map_err(|_| ApiError::from(StatusCode::INTERNAL_SERVER_ERROR))?
+        };
+        let (bytes, unpacked) = read.map_err(|e| spool_failed(&filename, e))?;
+        // Generic types often hide a known format
+        let content_type = sniff_content_type(&declared, &bytes).map_or(declared, str::to_string);
+        let files = match unpacked {
+            None => vec![Upload {
+                data: bytes.clone(),
+                filename,
+                content_type,
+                unpacked: false,
+            }],
+            Some(Ok(files)) => files
+                .into_iter()
+                .map(|file| Upload {
+                    content_type: detect_format(&file.data).unwrap_or("application/octet-stream").to_string(),
+                    filename: file.filename,
+                    data: file.data.into(),
+                    unpacked: true,
+                })
+                .collect(),
+            Some(Err(e @ ArchiveError::TooLarge(..))) => return Err(ApiError::payload_too_large(e.to_string())),
+            Some(Err(e)) => {
+                return Err(ApiError {
+                    status: StatusCode::BAD_REQUEST,
+                    code: None,
+                    message: Some(e.to_string()),
+                })
+            }
+        };
+        total_bytes += files.iter().map(|file| file.data.len()).sum::<usize>();
+        if uploads.len() + files.len() > limits.max_files || total_bytes > limits.max_total_bytes {
+            return Err(ApiError::payload_too_large(format!(
+                "a batch holds at most {} files and {} bytes",
+                limits.max_files, limits.max_total_bytes
+            )));
+        }
+        uploads.extend(files);
+    }
+    Ok(uploads)
+}
+
+enum FieldError {
+    Multipart(MultipartError),
+    Spool(std::io::Error),
+}
+
+fn spool_failed(filename: &str, error: std::io::Error) -> ApiError {
+    tracing::error!("Spooling {} failed: {}", filename, error);
+    StatusCode::INTERNAL_SERVER_ERROR.into()
+}
+
+/// A form field as received: its first `SPOOL_MEMORY_BYTES` in memory and
+/// the rest in a temporary file, removed when the spool is dropped.
+struct Spool {
+    file: SpooledTempFile,
+    len: usize,
+}
+
+impl Spool {
+    /// The field's contents, read back once into an exactly sized buffer.
+    /// Parsers need the whole document: PDF and ZIP need random access.
+    fn into_bytes(mut self) -> std::io::Result<Bytes> {
+        let mut data = Vec::with_capacity(self.len);
+        self.file.rewind()?;
+        self.file.read_to_end(&mut data)?;
+        Ok(data.into())
+    }
+}
+
+/// Receive a form field chunk by chunk into a [`Spool`], stopping with
+/// `None` as soon as it grows beyond `max_bytes`.
+async fn spool_field(mut field: Field<'_>, max_bytes: usize) -> Result<Option<Spool>, FieldError> {
+    let mut spool = Spool {
+        file: SpooledTempFile::new(SPOOL_MEMORY_BYTES),
+        len: 0,
+    };
+    while let Some(chunk) = field.chunk().await.map_err(FieldError::Multipart)? {
+        spool.len += chunk.len();
+        if spool.len > max_bytes {
+            return Ok(None);
+        }
+        spool.file.write_all(&chunk).map_err(FieldError::Spool)?;
+    }
+    Ok(Some(spool))
+}
+
+/// The one document of a form; an archive of several is rejected with
+/// `400`, as they need the batch endpoint.
+async fn read_upload(multipart: &mut Multipart, state: &AppState) -> Result<Upload, ApiError> {
+    let limits = UploadLimits {
+        max_files: usize::MAX,
+        max_file_bytes: state.max_upload_bytes,
+        max_total_bytes: usize::MAX,
+        max_decompressed_bytes: state.max_decompressed_bytes,
+    };
+    let mut uploads = read_uploads(multipart, limits).await?;
+    if uploads.iter().filter(|upload| upload.unpacked).count() > 1 {
+        return Err(ApiError {
+            status: StatusCode::BAD_REQUEST,
+            code: None,
+            message: Some(format!(
+                "the archive holds {} documents; send it to /api/parse/batch",
+                uploads.len()
+            )),
+        });
+    }
+    uploads.pop().ok_or_else(|| StatusCode::BAD_REQUEST.into())
+}
+
+async fn parse_document(
+    State(state): State<AppState>,
+    Query(params): Query<ParseParams>,
+    mut multipart: Multipart,
+) -> Result<Response, ApiError> {
+    let filter = ChunkFilter::from_params(&params)?;
+    let upload = read_upload(&mut multipart, &state).await?;
+    if params.format == OutputFormat::Sentences {
+        let text = sentence_lines(&params, &upload, &state).await.map_err(parse_failure)?;
+        return Ok(([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], text).into_response());
+    }
+    parse_upload(&state, &params, filter.as_ref(), &upload)
+        .await
+        .map(|response| Json(response).into_response())
+        .map_err(parse_failure)
+}
+
+/// The upload's text, one sentence per line with its whitespace collapsed,
+/// as the sentence splitter for `params` detects them, and redacted when
+/// requested.
+async fn sentence_lines(params: &ParseParams, upload: &Upload, state: &AppState) -> Result<String, ParserError> {
+    let parser = upload_parser(params, upload, 0, state.ocr.clone())?;
+    let parser_used = parser.name();
+    let (mut pages, _) = parse_with_timeout(parser, upload.data.clone(), state.parse_timeout).await?;
+    params.handle_footnote_markers.apply(parser_used, &mut pages);
+    let splitter = sentence_splitter(params, &upload.filename);
+    let mut text = String::new();
+    for page in &pages {
+        for sentence in splitter.split_into_sentences(&page.text) {
+            let mut sentence = embed_text(&sentence);
+            if params.redact_pii {
+                sentence = RedactionProcessor::redact_text(&sentence);
+            }
+            if !sentence.is_empty() {
+                text.push_str(&sentence);
+                text.push('\n');
+            }
+        }
+    }
+    Ok(text)
+}
+
+/// The parser for an upload, by its content type and filename, reading up
+/// to `max_images` images per PDF page, kept when the request asks for
+/// them, and scanned pages with `ocr`.
+fn upload_parser(
+    params: &ParseParams,
+    upload: &Upload,
+    max_images: usize,
+    ocr: Option<Arc<dyn OcrEngine>>,
+) -> Result<Box<dyn Parser>, ParserError> {
+    let pdf = LocalPdfParser::new()
+        .with_infer_headings(params.infer_headings)
+        .with_detect_tables(params.detect_tables)
+        .with_expand_ligatures(params.expand_ligatures)
+        .with_normalize_whitespace(params.normalize_whitespace)
+        .with_max_images_per_page(max_images)
+        .with_extract_images(params.extract_images)
+        .with_ocr(ocr);
+    let html = HtmlParser::new()
+        .with_page_chars(params.page_chars)
+        .with_section_selectors(params.section_selectors());
+    for_content_type_with(&upload.content_type, &upload.filename, pdf, html, params.page_chars)
+}
+
+fn parse_failure(error: ParserError) -> ApiError {
+    match error {
+        ParserError::UnsupportedFormat(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE.into(),
+        ParserError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT.into(),
+        // Told apart from corrupt files, which share the status
+        ParserError::EmptyDocument(_) => ApiError {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            code: Some(error.kind()),
+            message: Some(error.to_string()),
+        },
+        _ => StatusCode::UNPROCESSABLE_ENTITY.into(),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ParseUrlRequest {
+    url: String,
+}
+
+/// Fetch a web page or remote document and parse it like an upload. URLs
+/// that aren't http(s) or that point at private addresses are rejected
+/// with `400`, and remote failures answered with `502`.
+async fn parse_url(
+    State(state): State<AppState>,
+    Query(params): Query<ParseParams>,
+    Json(request): Json<ParseUrlRequest>,
+) -> Result<Json<ParseResponse>, ApiError> {
+    let filter = ChunkFilter::from_params(&params)?;
+    let policy = state.fetch.as_ref().ok_or_else(|| ApiError {
+        status: StatusCode::SERVICE_UNAVAILABLE,
+        code: None,
+        message: Some("URL ingestion is unavailable while network access is disabled".to_string()),
+    })?;
+    let upload = fetch_upload(policy, &request.url, state.max_upload_bytes).await?;
+    parse_upload(&state, &params, filter.as_ref(), &upload)
+        .await
+        .map(Json)
+        .map_err(parse_failure)
+}
+
+/// Download `url` as an upload, named after the last segment of its path
+/// and typed by the response's `Content-Type`.
+async fn fetch_upload(policy: &FetchPolicy, url: &str, max_bytes: usize) -> Result<Upload, ApiError> {
+    let (url, client) = policy
+        .prepare(url, reqwest::Client::builder().timeout(FETCH_TIMEOUT))
+        .await
+        .map_err(fetch_failure)?;
+    let mut response = client.get(url.clone()).send().await.map_err(|e| fetch_failure(e.into()))?;
+    if !response.status().is_success() {
+        return Err(ApiError {
+            status: StatusCode::BAD_GATEWAY,
+            code: None,
+            message: Some(format!("{} answered with {}", url, response.status())),
+        });
+    }
+
+    let too_large = || ApiError::payload_too_large(format!("{} exceeds the upload limit of {} bytes", url, max_bytes));
+    if response.content_length().is_some_and(|length| length > max_bytes as u64) {
+        return Err(too_large());
+    }
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+    let mut data = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| fetch_failure(e.into()))? {
+        if data.len() + chunk.len() > max_bytes {
+            return Err(too_large());
         }
+        data.extend_from_slice(&chunk);
+    }
+    let content_type = sniff_content_type(&content_type, &data).map_or(content_type, str::to_string);
+
+    let filename = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|segment| !segment.is_empty())
+        .unwrap_or("index")
+        .to_string();
+    Ok(Upload {
+        data: data.into(),
+        filename,
+        content_type,
+        unpacked: false,
+    })
+}
+
+fn fetch_failure(error: FetchError) -> ApiError {
+    let status = match error {
+        FetchError::Resolve(_) | FetchError::Http(_) => StatusCode::BAD_GATEWAY,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    ApiError {
+        status,
+        code: None,
+        message: Some(error.to_string()),
     }
+}
 
-    let data = file_data.ok_or(StatusCode::BAD_REQUEST)?;
+/// Parse and split one upload, serving repeats from the parse cache.
+async fn parse_upload(
+    state: &AppState,
+    params: &ParseParams,
+    filter: Option<&ChunkFilter>,
+    upload: &Upload,
+) -> Result<ParseResponse, ParserError> {
+    let start = Instant::now();
+    telemetry::record_request("rest");
+
+    let data = &upload.data[..];
+    let filename = upload.filename.clone();
+    let content_type = upload.content_type.clone();
     let size_bytes = data.len();
 
-    let parser = LocalPdfParser::new();
-    let pages = parser.parse(Cursor::new(&data)).map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?;
+    let failed = |e: ParserError| {
+        telemetry::record_error("rest", &e);
+        e
+    };
+    let parser = upload_parser(params, upload, state.pdf_max_images_per_page, state.ocr.clone()).map_err(failed)?;
+    let parser_used = parser.name();
 
-    let splitter = SentenceTextSplitter::new(500, 10);
-    let chunks = splitter.split(&pages);
+    let fingerprint = UploadFingerprint::new(data, &filename, &content_type);
+    let document_hash = fingerprint.content_hash.clone();
+    // The same bytes can go to different parsers by content type or filename,
+    // and deterministic chunk IDs derive from the filename
+    let id_namespace = params.deterministic_ids.then_some(&filename);
+    let cache_key = cache::cache_key(&document_hash, &(params, parser_used, id_namespace));
+    if let Some(cache) = &state.cache {
+        match cache.get(&cache_key).await {
+            Ok(Some(bytes)) => {
+                let cached = serde_json::from_slice::<ParseResponse>(&bytes).ok();
+                // Images are cached apart from the response and can be evicted
+                // first; parse again to store them anew
+                let usable = match &cached {
+                    Some(cached) => images_stored(&state.images, &document_hash, &cached.chunks).await,
+                    None => false,
+                };
+                if let Some(mut cached) = cached.filter(|_| usable) {
+                    cached.metadata.filename = filename;
+                    cached.metadata.content_type = content_type;
+                    cached.stats.processing_time_ms = start.elapsed().as_millis() as u64;
+                    return Ok(cached);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Parse cache lookup failed: {}", e),
+        }
+    }
+
+    let (mut pages, info) = parse_with_timeout(parser, upload.data.clone(), state.parse_timeout)
+        .await
+        .map_err(failed)?;
+    params.handle_footnote_markers.apply(parser_used, &mut pages);
+    if params.detect_language {
+        language::detect_pages(&mut pages);
+    }
+    if params.extract_images {
+        store_images(&state.images, &document_hash, &pages).await;
+    } else {
+        pages.iter_mut().for_each(|page| page.images.clear());
+    }
+    let quality_warnings = state.quality_rules.check(&content_type, parser_used, &pages);
+    let summarized = state.summarizer.is_some() && params.summarize;
+    // Personal data neither leaves for the summarizer nor comes back in the summary
+    let redact = |text: String| {
+        if params.redact_pii {
+            RedactionProcessor::redact_text(&text)
+        } else {
+            text
+        }
+    };
+    let summary = match (&state.summarizer, params.summarize) {
+        (Some(summarizer), true) => {
+            let text: Vec<&str> = pages.iter().map(|page| page.text.as_str()).collect();
+            match summarizer.summarize(&redact(text.join("\n\n"))).await {
+                Ok(summary) => Some(redact(summary)),
+                Err(e) => {
+                    tracing::warn!("Summary for {} failed: {}", filename, e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    let splitter: Box<dyn TextSplitter> = match params.splitter {
+        SplitterKind::Sentence => Box::new(sentence_splitter(params, &filename)),
+        SplitterKind::Recursive => {
+            let splitter = RecursiveCharacterTextSplitter::new(500)
+                .with_tokenizer(params.tokenizer)
+                .with_embed_text(params.emit_embed_text);
+            if params.deterministic_ids {
+                Box::new(splitter.with_deterministic_ids(&filename))
+            } else {
+                Box::new(splitter)
+            }
+        }
+    };
+    let mut chunks = splitter.split(&pages);
+    order_chunks(&mut chunks, params.order);
+    // Chunks keep their original index, so consumers can tell what was left out
+    if let Some(filter) = filter {
+        chunks.retain(|chunk| filter.matches(&chunk.text));
+    }
+    if params.simhash {
+        fingerprint_chunks(&mut chunks);
+    }
+    if params.prepend_heading {
+        prepend_headings(&mut chunks, params.tokenizer);
+    }
+    // Redact before custom processors see the text
+    let redactions = params.redact_pii.then(|| {
+        let redactor = RedactionProcessor::new(params.tokenizer);
+        redactor.process_all(&mut chunks);
+        redactor.redactions()
+    });
+    state.chunk_processors.process_all(&mut chunks);
+    let structure_tree = params.structure_tree.then(|| structure_tree(&pages, &chunks));
+    let applied_config = params
+        .echo_config
+        .then(|| AppliedConfig::new(params, parser_used, splitter.as_ref(), summarized));
 
-    let total_tokens: usize = chunks.iter().map(|c| c.token_count).sum();
+    let (total_chunks, total_tokens) = (chunks.len(), chunks.iter().map(|c| c.token_count).sum());
+    telemetry::record_parse("rest", parser_used, start.elapsed(), total_chunks, total_tokens);
+    telemetry::log_parse("rest", &fingerprint, parser_used, pages.len(), total_chunks, start.elapsed());
+    if params.stats_only {
+        chunks.clear();
+    }
 
-    Ok(Json(ParseResponse {
-        chunks: chunks.clone(),
+    let response = ParseResponse {
+        chunks,
         metadata: DocumentMetadata {
+            document_hash,
             filename,
             content_type,
             size_bytes,
             page_count: pages.len(),
+            title: info.title,
+            author: info.author,
+            last_modified_by: info.last_modified_by,
+            created_at: info.created_at,
+            modified_at: info.modified_at,
+            revision: info.revision,
+            outline: pages
+                .iter()
+                .flat_map(|page| {
+                    page.headings.iter().map(|h| OutlineEntry {
+                        level: h.level,
+                        title: h.text.clone(),
+                        page_num: page.page_num,
+                    })
+                })
+                .collect(),
+            page_source_ranges: if params.page_source_ranges {
+                pages
+                    .iter()
+                    .filter_map(|page| {
+                        Some(PageSourceRange {
+                            page_num: page.page_num,
+                            source_start: page.source_start?,
+                            source_end: page.source_end?,
+                        })
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            summary,
+            quality_warnings,
         },
         stats: ProcessingStats {
             processing_time_ms: start.elapsed().as_millis() as u64,
-            total_chunks: chunks.len(),
+            total_chunks,
             total_tokens,
+            total_images: pages.iter().map(|page| page.images.len()).sum(),
+            page_images: pages
+                .iter()
+                .filter(|page| !page.images.is_empty())
+                .map(|page| PageImageCount {
+                    page_num: page.page_num,
+                    count: page.images.len(),
+                })
+                .collect(),
+            redactions,
+        },
+        structure_tree,
+        applied_config,
+    };
+
+    if let Some(cache) = &state.cache {
+        if let Ok(bytes) = serde_json::to_vec(&response) {
+            if let Err(e) = cache.put(&cache_key, bytes).await {
+                tracing::warn!("Parse cache store failed: {}", e);
+            }
+        }
+    }
+
+    Ok(response)
+}
+
+/// The sentence splitter configured by `params`, for the document `filename`.
+fn sentence_splitter(params: &ParseParams, filename: &str) -> SentenceTextSplitter {
+    let splitter = SentenceTextSplitter::new(500, 10)
+        .with_language_spans(params.language_spans)
+        .with_boundary_lookahead(params.boundary_tolerance_percent)
+        .with_embed_text(params.emit_embed_text)
+        .with_drop_empty_chunks(params.drop_empty_chunks)
+        .with_cross_page_merge(params.cross_page_merge)
+        .with_max_chars(params.max_chars)
+        .with_min_chunk_tokens(params.min_chunk_tokens)
+        .with_overlap_lengths(params.emit_overlap)
+        .with_overlap_align(params.overlap_align)
+        .with_tokenizer(params.tokenizer)
+        .with_merge_pages(params.merge_pages, &params.page_separator);
+    if params.deterministic_ids {
+        splitter.with_deterministic_ids(filename)
+    } else {
+        splitter
+    }
+}
+
+/// Body of `/api/chunk`: text extracted elsewhere, split like a parsed
+/// document.
+#[derive(Serialize, Deserialize)]
+struct ChunkRequest {
+    pages: Vec<ChunkPage>,
+    #[serde(default = "default_max_tokens")]
+    max_tokens: usize,
+    #[serde(default = "default_overlap_percent")]
+    overlap_percent: usize,
+    /// Tokens carried over between chunks, in place of `overlap_percent`.
+    #[serde(default)]
+    overlap_tokens: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChunkPage {
+    page_num: u32,
+    text: String,
+}
+
+fn default_max_tokens() -> usize {
+    500
+}
+
+fn default_overlap_percent() -> usize {
+    10
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChunkResponse {
+    chunks: Vec<Chunk>,
+    stats: ProcessingStats,
+}
+
+/// Split already-extracted text with the sentence splitter, without
+/// running a parser. An overlap of the whole chunk or more is answered with
+/// `400`.
+async fn chunk_text(
+    State(state): State<AppState>,
+    Json(request): Json<ChunkRequest>,
+) -> Result<Json<ChunkResponse>, ApiError> {
+    let overlap_tokens = request
+        .overlap_tokens
+        .unwrap_or(request.max_tokens * request.overlap_percent / 100);
+    if overlap_tokens >= request.max_tokens {
+        return Err(ApiError {
+            status: StatusCode::BAD_REQUEST,
+            code: None,
+            message: Some(format!(
+                "an overlap of {} tokens must be less than the {} tokens per chunk",
+                overlap_tokens, request.max_tokens
+            )),
+        });
+    }
+    let start = Instant::now();
+    let pages: Vec<Page> = request
+        .pages
+        .into_iter()
+        .map(|page| Page {
+            page_num: page.page_num,
+            text: page.text,
+            ..Default::default()
+        })
+        .collect();
+    let mut chunks = SentenceTextSplitter::with_overlap_tokens(request.max_tokens, overlap_tokens).split(&pages);
+    state.chunk_processors.process_all(&mut chunks);
+    Ok(Json(ChunkResponse {
+        stats: ProcessingStats {
+            processing_time_ms: start.elapsed().as_millis() as u64,
+            total_chunks: chunks.len(),
+            total_tokens: chunks.iter().map(|c| c.token_count).sum(),
+            total_images: 0,
+            page_images: Vec::new(),
+            redactions: None,
         },
+        chunks,
     }))
 }
 
-pub fn create_router() -> Router {
+/// Query parameters for `/api/parse/batch`, read alongside [`ParseParams`].
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct BatchParams {
+    /// Overrides the configured batch deadline, in milliseconds.
+    deadline_ms: Option<u64>,
+    /// Largest share of files that may fail before the batch as a whole
+    /// fails with `422 Unprocessable Entity`, from 0.0 to 1.0.
+    max_failure_ratio: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BatchResponse {
+    results: Vec<BatchResult>,
+    stats: BatchStats,
+    /// Why the batch failed as a whole, when too many files failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BatchStatus {
+    Processed,
+    Failed,
+    Skipped,
+}
+
+/// Outcome for one file of a batch, in upload order.
+#[derive(Serialize, Deserialize)]
+struct BatchResult {
+    filename: String,
+    status: BatchStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    document: Option<ParseResponse>,
+    /// Why the file failed or was skipped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BatchStats {
+    processing_time_ms: u64,
+    processed: usize,
+    failed: usize,
+    skipped: usize,
+}
+
+/// Parse every uploaded file in turn until the batch deadline passes.
+/// Each file reports its own result, so one corrupt file doesn't fail the
+/// rest; forms over [`MAX_BATCH_FILES`] or [`MAX_BATCH_BYTES`] are refused.
+///
+/// Parsing is CPU-bound and can't be interrupted, so a file that has started
+/// always finishes; files not yet started when the deadline passes are
+/// reported as `skipped` and the results gathered so far are returned.
+///
+/// With `max_failure_ratio`, a batch in which a larger share of files failed
+/// is answered with `422` instead of `200`, the per-file results attached.
+async fn parse_batch(
+    State(state): State<AppState>,
+    Query(params): Query<ParseParams>,
+    Query(batch): Query<BatchParams>,
+    mut multipart: Multipart,
+) -> Result<(StatusCode, Json<BatchResponse>), ApiError> {
+    if let Some(ratio) = batch.max_failure_ratio.filter(|r| !(0.0..=1.0).contains(r)) {
+        return Err(ApiError {
+            status: StatusCode::BAD_REQUEST,
+            code: None,
+            message: Some(format!("max_failure_ratio must be between 0 and 1, got {}", ratio)),
+        });
+    }
+    let filter = ChunkFilter::from_params(&params)?;
+    let limits = UploadLimits {
+        max_files: MAX_BATCH_FILES,
+        max_file_bytes: state.max_upload_bytes,
+        max_total_bytes: MAX_BATCH_BYTES,
+        max_decompressed_bytes: state.max_decompressed_bytes,
+    };
+    let uploads = read_uploads(&mut multipart, limits).await?;
+    if uploads.is_empty() {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let start = Instant::now();
+    let budget = batch.deadline_ms.map(Duration::from_millis).unwrap_or(state.batch_deadline);
+    let deadline = start + budget;
+
+    let mut results = Vec::with_capacity(uploads.len());
+    for upload in uploads {
+        let filename = upload.filename.clone();
+        if Instant::now() >= deadline {
+            results.push(BatchResult {
+                filename,
+                status: BatchStatus::Skipped,
+                document: None,
+                reason: Some(format!("batch deadline of {} ms exceeded", budget.as_millis())),
+            });
+            continue;
+        }
+        results.push(match parse_upload(&state, &params, filter.as_ref(), &upload).await {
+            Ok(document) => BatchResult {
+                filename,
+                status: BatchStatus::Processed,
+                document: Some(document),
+                reason: None,
+            },
+            Err(e) => {
+                if let Some(dead_letters) = &state.dead_letters {
+                    let entry = DeadLetterEntry::new(&filename, &upload.content_type, &upload.data, &e.to_string());
+                    if let Err(e) = dead_letters.record(entry, &upload.data).await {
+                        tracing::warn!("Dead-letter record for {} failed: {}", filename, e);
+                    }
+                }
+                BatchResult {
+                    filename,
+                    status: BatchStatus::Failed,
+                    document: None,
+                    reason: Some(e.to_string()),
+                }
+            }
+        });
+    }
+
+    let count = |status: BatchStatus| results.iter().filter(|r| r.status == status).count();
+    let stats = BatchStats {
+        processing_time_ms: start.elapsed().as_millis() as u64,
+        processed: count(BatchStatus::Processed),
+        failed: count(BatchStatus::Failed),
+        skipped: count(BatchStatus::Skipped),
+    };
+    let failure_ratio = stats.failed as f64 / results.len() as f64;
+    let error = batch.max_failure_ratio.filter(|max| failure_ratio > *max).map(|max| {
+        format!(
+            "{} of {} files failed, more than the allowed ratio of {}",
+            stats.failed,
+            results.len(),
+            max
+        )
+    });
+    let status = if error.is_some() { StatusCode::UNPROCESSABLE_ENTITY } else { StatusCode::OK };
+    Ok((status, Json(BatchResponse { results, stats, error })))
+}
+
+/// Query parameters for `/api/validate`.
+#[derive(Deserialize)]
+#[serde(default)]
+struct ValidateParams {
+    max_tokens: usize,
+}
+
+impl Default for ValidateParams {
+    fn default() -> Self {
+        Self { max_tokens: 500 }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ValidateResponse {
+    valid: bool,
+    violations: Vec<Violation>,
+    page_count: usize,
+    chunk_count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Violation {
+    /// Machine-readable kind: `unparsable`, `empty_page` or `oversized_chunk`.
+    kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_num: Option<u32>,
+    message: String,
+}
+
+/// Run the parse pipeline and report problems instead of chunks, so content
+/// can be checked before it is published.
+async fn validate_document(
+    State(state): State<AppState>,
+    Query(params): Query<ValidateParams>,
+    mut multipart: Multipart,
+) -> Result<Json<ValidateResponse>, ApiError> {
+    let upload = read_upload(&mut multipart, &state).await?;
+    let parser = for_content_type(&upload.content_type, &upload.filename)
+        .map_err(|_| StatusCode::UNSUPPORTED_MEDIA_TYPE)?;
+    let pages = match parse_with_timeout(parser, upload.data.clone(), state.parse_timeout).await {
+        Ok((pages, _)) => pages,
+        Err(ParserError::Timeout(_)) => return Err(StatusCode::GATEWAY_TIMEOUT.into()),
+        Err(e) => {
+            return Ok(Json(ValidateResponse {
+                valid: false,
+                violations: vec![Violation {
+                    kind: "unparsable".to_string(),
+                    page_num: None,
+                    message: format!("document could not be parsed: {}", e),
+                }],
+                page_count: 0,
+                chunk_count: 0,
+            }))
+        }
+    };
+    let chunks = SentenceTextSplitter::new(params.max_tokens, 10).split(&pages);
+
+    let mut violations: Vec<Violation> = pages
+        .iter()
+        .filter(|page| page.text.trim().is_empty())
+        .map(|page| Violation {
+            kind: "empty_page".to_string(),
+            page_num: Some(page.page_num),
+            message: format!("page {} has no extractable text", page.page_num),
+        })
+        .collect();
+    violations.extend(chunks.iter().filter(|c| c.token_count > params.max_tokens).map(|c| Violation {
+        kind: "oversized_chunk".to_string(),
+        page_num: Some(c.page_num),
+        message: format!(
+            "chunk {} on page {} has {} tokens, exceeding max_tokens {}",
+            c.index, c.page_num, c.token_count, params.max_tokens
+        ),
+    }));
+
+    Ok(Json(ValidateResponse {
+        valid: violations.is_empty(),
+        violations,
+        page_count: pages.len(),
+        chunk_count: chunks.len(),
+    }))
+}
+
+/// Keep extracted images available for `/api/images` so responses only need
+/// to carry references.
+async fn store_images(store: &ImageStore, document_hash: &str, pages: &[Page]) {
+    for image in pages.iter().flat_map(|page| &page.images) {
+        if let Err(e) = store.put(document_hash, image).await {
+            tracing::warn!("Failed to store image {}: {}", image.id, e);
+        }
+    }
+}
+
+/// Whether every image `chunks` refer to can still be fetched.
+async fn images_stored(store: &ImageStore, document_hash: &str, chunks: &[Chunk]) -> bool {
+    let ids: HashSet<&str> = chunks.iter().flat_map(|c| &c.images).map(|i| i.image_id.as_str()).collect();
+    for id in ids {
+        if !matches!(store.get(document_hash, id).await, Ok(Some(_))) {
+            return false;
+        }
+    }
+    true
+}
+
+async fn get_image(
+    State(state): State<AppState>,
+    Path((document_hash, image_id)): Path<(String, String)>,
+) -> Result<Response, StatusCode> {
+    match state.images.get(&document_hash, &image_id).await {
+        Ok(Some((content_type, data))) => Ok(([(header::CONTENT_TYPE, content_type)], data).into_response()),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::warn!("Image lookup failed: {}", e);
+            Err(StatusCode::SERVICE_UNAVAILABLE)
+        }
+    }
+}
+
+/// Answer `Expect: 100-continue` before the body is sent.
+///
+/// Uploads whose declared length exceeds the route's body limit, and unknown
+/// expectations, get `417 Expectation Failed` without the body ever being
+/// read. Otherwise
+/// hyper sends `100 Continue` once the handler starts reading the body.
+async fn expect_continue(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if let Some(expect) = request.headers().get(header::EXPECT) {
+        if !expect.as_bytes().eq_ignore_ascii_case(b"100-continue") {
+            return StatusCode::EXPECTATION_FAILED.into_response();
+        }
+        let limit = body_limit(&state, request.uri().path());
+        if content_length(request.headers()).is_some_and(|len| len > limit) {
+            tracing::debug!("Rejecting upload over {} bytes before body transfer", limit);
+            return StatusCode::EXPECTATION_FAILED.into_response();
+        }
+    }
+    next.run(request).await
+}
+
+/// The largest request body the route at `path` accepts: its files' size
+/// limit with room for the multipart framing.
+fn body_limit(state: &AppState, path: &str) -> usize {
+    if path == BATCH_ROUTE {
+        MAX_BATCH_BYTES + MULTIPART_OVERHEAD
+    } else {
+        state.max_upload_bytes + MULTIPART_OVERHEAD
+    }
+}
+
+fn content_length(headers: &HeaderMap) -> Option<usize> {
+    headers
+        .get(header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+pub fn create_router(config: Config) -> Router {
+    let cache = config.cache.build().unwrap_or_else(|e| {
+        tracing::error!("Parse cache unavailable, continuing without it: {}", e);
+        None
+    });
+    let images = match &cache {
+        Some(cache) => ImageStore::new(cache.clone()),
+        None => ImageStore::in_memory(IMAGE_STORE_CAPACITY, IMAGE_STORE_TTL),
+    };
+    let state = AppState {
+        cache,
+        images,
+        max_upload_bytes: config.max_upload_bytes,
+        max_decompressed_bytes: config.max_decompressed_bytes,
+        pdf_max_images_per_page: config.pdf_max_images_per_page,
+        parse_timeout: config.parse_timeout,
+        max_concurrent_requests: config.max_concurrent_requests,
+        api_key: config.api_key.clone(),
+        batch_deadline: config.batch_deadline,
+        dead_letters: config.dead_letter.as_ref().map(|dead_letter| dead_letter.build()),
+        summarizer: config
+            .summarizer()
+            .map(|summarizer| Arc::new(HttpSummarizer::new(summarizer.clone())) as Arc<dyn Summarizer>),
+        ocr: config.ocr().map(|ocr| Arc::new(HttpOcr::new(ocr.clone())) as Arc<dyn OcrEngine>),
+        quality_rules: config.quality_rules.clone(),
+        chunk_processors: config.chunk_processors.clone(),
+        health: Health::new(),
+        fetch: config.fetch().cloned(),
+        metrics: telemetry::handle(),
+    };
+    router(state)
+}
+
+fn router(state: AppState) -> Router {
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
         .allow_headers(Any);
 
+    // The batch route caps the sum of its files rather than each one
+    let batch = post(parse_batch).layer(DefaultBodyLimit::max(body_limit(&state, BATCH_ROUTE)));
+
+    // Layers apply to each route on its own, so the routes share one pool
+    // of permits; requests beyond it are shed rather than queued
+    let limit = ServiceBuilder::new()
+        .layer(HandleErrorLayer::new(|_: BoxError| async {
+            ApiError {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                code: None,
+                message: Some("Too many requests in progress, try again later".to_string()),
+            }
+        }))
+        .load_shed()
+        .layer(GlobalConcurrencyLimitLayer::new(state.max_concurrent_requests));
+
+    // Health checks and metrics stay outside the limit and need no API key;
+    // unauthenticated requests are turned away before taking a permit
     Router::new()
-        .route("/health", get(health))
         .route("/api/formats", get(supported_formats))
         .route("/api/parse", post(parse_document))
+        .route(BATCH_ROUTE, batch)
+        .route("/api/parse/url", post(parse_url))
+        .route("/api/validate", post(validate_document))
+        .route("/api/chunk", post(chunk_text))
+        .route("/api/images/{document_hash}/{image_id}", get(get_image))
+        .layer(limit)
+        .layer(state.api_key.clone())
+        .route("/health", get(health))
+        .route("/metrics", get(metrics))
+        .layer(DefaultBodyLimit::max(body_limit(&state, "/api/parse")))
+        .layer(middleware::from_fn_with_state(state.clone(), expect_continue))
         .layer(cors)
         .layer(TraceLayer::new_for_http())
+        .with_state(state)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    async fn spawn_server(config: Config) -> std::net::SocketAddr {
+        serve(create_router(config)).await
+    }
+
+    async fn serve(app: Router) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        addr
+    }
+
+    /// Send only the request head and return the first response line.
+    async fn send_head(addr: std::net::SocketAddr, path: &str, content_length: usize) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let head = format!(
+            "POST {} HTTP/1.1\r\nHost: localhost\r\n\
+             Content-Type: multipart/form-data; boundary=X\r\n\
+             Content-Length: {}\r\nExpect: 100-continue\r\n\r\n",
+            path, content_length
+        );
+        stream.write_all(head.as_bytes()).await.unwrap();
+
+        let mut buf = vec![0; 256];
+        let n = stream.read(&mut buf).await.unwrap();
+        String::from_utf8_lossy(&buf[..n]).lines().next().unwrap_or_default().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_expect_continue_checks_size_before_body() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            max_upload_bytes: 1024,
+            max_decompressed_bytes: 4096,
+            ..Default::default()
+        })
+        .await;
+
+        let (continued, failed) = ("HTTP/1.1 100 Continue", "HTTP/1.1 417 Expectation Failed");
+        assert_eq!(send_head(addr, "/api/parse", 10 * 1024 * 1024).await, failed);
+        assert_eq!(send_head(addr, "/api/parse", 512).await, continued);
+        // A file right at the limit, wrapped in its multipart framing
+        assert_eq!(send_head(addr, "/api/parse", 1024 + 200).await, continued);
+        assert_eq!(send_head(addr, "/api/parse", 1024 + MULTIPART_OVERHEAD + 1).await, failed);
+
+        // Batches are held to their own, larger limit
+        assert_eq!(send_head(addr, BATCH_ROUTE, 60 * 1024 * 1024).await, continued);
+        assert_eq!(send_head(addr, BATCH_ROUTE, MAX_BATCH_BYTES + MULTIPART_OVERHEAD + 1).await, failed);
+    }
+
+    #[tokio::test]
+    async fn test_requests_beyond_limit_get_503() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            max_concurrent_requests: 2,
+            ..Default::default()
+        })
+        .await;
+        // Uploads whose body never arrives hold their permits until dropped
+        let mut streams = Vec::new();
+        for _ in 0..4 {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let head = "POST /api/parse HTTP/1.1\r\nHost: localhost\r\n\
+                        Content-Type: multipart/form-data; boundary=X\r\nContent-Length: 512\r\n\r\n";
+            stream.write_all(head.as_bytes()).await.unwrap();
+            streams.push(stream);
+        }
+        let statuses = futures::future::join_all(streams.iter_mut().map(|stream| async move {
+            let mut buf = vec![0; 256];
+            let n = tokio::time::timeout(Duration::from_secs(1), stream.read(&mut buf)).await.ok()?.ok()?;
+            String::from_utf8_lossy(&buf[..n]).lines().next().map(str::to_string)
+        }))
+        .await;
+        let shed = statuses.iter().flatten().filter(|s| *s == "HTTP/1.1 503 Service Unavailable").count();
+        assert_eq!(shed, 2, "{:?}", statuses);
+
+        let response = reqwest::get(format!("http://{}/health", addr)).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_api_key_guards_document_routes() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            api_key: ApiKey::new(Some("s3cret".to_string())),
+            ..Default::default()
+        })
+        .await;
+        let get = |path: &'static str, key: Option<&'static str>| async move {
+            let mut request = reqwest::Client::new().get(format!("http://{}{}", addr, path));
+            if let Some(key) = key {
+                request = request.header(crate::auth::API_KEY_HEADER, key);
+            }
+            request.send().await.unwrap()
+        };
+
+        assert_eq!(get("/api/formats", Some("s3cret")).await.status(), reqwest::StatusCode::OK);
+        let wrong = get("/api/formats", Some("guess")).await;
+        assert_eq!(wrong.status(), reqwest::StatusCode::UNAUTHORIZED);
+        assert_eq!(wrong.json::<ErrorResponse>().await.unwrap().error, "Missing or invalid API key");
+        assert_eq!(get("/api/formats", None).await.status(), reqwest::StatusCode::UNAUTHORIZED);
+        assert_eq!(get("/health", None).await.status(), reqwest::StatusCode::OK);
+        assert_eq!(get("/metrics", None).await.status(), reqwest::StatusCode::OK);
+
+        // Without a configured key nothing is required
+        let open = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        let response = reqwest::get(format!("http://{}/api/formats", open)).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_health_reports_growing_uptime() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        let check = || async move {
+            let response = reqwest::get(format!("http://{}/health", addr)).await.unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            response.json::<HealthResponse>().await.unwrap()
+        };
+
+        let first = check().await;
+        assert_eq!(first.status, "healthy");
+        assert!(first.reason.is_none());
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert!(check().await.uptime_seconds > first.uptime_seconds);
+    }
+
+    #[tokio::test]
+    async fn test_stats_only_omits_chunks() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        let text = "Cells divide by mitosis. ".repeat(200);
+        let parse = |query: &'static str| {
+            let body = multipart_body("notes.txt", "text/plain", text.as_bytes());
+            async move {
+                let response = reqwest::Client::new()
+                    .post(format!("http://{}/api/parse{}", addr, query))
+                    .header("content-type", "multipart/form-data; boundary=X")
+                    .body(body)
+                    .send()
+                    .await
+                    .unwrap();
+                (response.content_length(), response.json::<ParseResponse>().await.unwrap())
+            }
+        };
+
+        let (full_size, full) = parse("").await;
+        let (dry_size, dry) = parse("?stats_only=true").await;
+
+        assert!(dry.chunks.is_empty());
+        assert!(full.chunks.len() > 1);
+        assert_eq!(dry.stats.total_chunks, full.chunks.len());
+        assert_eq!(dry.stats.total_tokens, full.chunks.iter().map(|c| c.token_count).sum::<usize>());
+        assert_eq!(dry.metadata.page_count, full.metadata.page_count);
+        assert!(dry_size.unwrap() * 5 < full_size.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_chunk_processors_run_on_every_chunk() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            chunk_processors: ChunkProcessors::new(vec![Box::new(crate::splitter::Uppercase)]),
+            ..Default::default()
+        })
+        .await;
+        let body = multipart_body("notes.txt", "text/plain", "Cells divide by mitosis. ".repeat(200).as_bytes());
+        let parsed: ParseResponse = reqwest::Client::new()
+            .post(format!("http://{}/api/parse", addr))
+            .header("content-type", "multipart/form-data; boundary=X")
+            .body(body)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(parsed.chunks.len() > 1);
+        assert!(parsed.chunks.iter().all(|c| c.text.contains("MITOSIS") && !c.text.chars().any(char::is_lowercase)));
+
+        let chunked: ChunkResponse = reqwest::Client::new()
+            .post(format!("http://{}/api/chunk", addr))
+            .json(&serde_json::json!({ "pages": [{ "page_num": 1, "text": "Meiosis halves the chromosome count." }] }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(chunked.chunks[0].text, "MEIOSIS HALVES THE CHROMOSOME COUNT.");
+    }
+
+    #[tokio::test]
+    async fn test_redact_pii_replaces_contact_details() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        let text = "Ask the tutor at tutor@school.example.org or on 555-123-4567. Grades are out.";
+        let parse = |query: &'static str| {
+            let body = multipart_body("notes.txt", "text/plain", text.as_bytes());
+            async move {
+                reqwest::Client::new()
+                    .post(format!("http://{}/api/parse{}", addr, query))
+                    .header("content-type", "multipart/form-data; boundary=X")
+                    .body(body)
+                    .send()
+                    .await
+                    .unwrap()
+                    .json::<ParseResponse>()
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let redacted = parse("?redact_pii=true").await;
+        assert_eq!(redacted.chunks[0].text, "Ask the tutor at [EMAIL] or on [PHONE]. Grades are out.");
+        assert_eq!(redacted.stats.redactions, Some(2));
+
+        let plain = parse("").await;
+        assert_eq!(plain.chunks[0].text, text);
+        assert_eq!(plain.stats.redactions, None);
+
+        let sentences = reqwest::Client::new()
+            .post(format!("http://{}/api/parse?format=sentences&redact_pii=true", addr))
+            .header("content-type", "multipart/form-data; boundary=X")
+            .body(multipart_body("notes.txt", "text/plain", text.as_bytes()))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(sentences, "Ask the tutor at [EMAIL] or on [PHONE].\nGrades are out.\n");
+    }
+
+    #[tokio::test]
+    async fn test_stats_count_extracted_images() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        let rgb = [255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255];
+        let pdf = crate::parser::fixtures::PdfBuilder::new()
+            .page(&["Figure 1 shows a cell."])
+            .page(&["No figures here."])
+            .page(&["Figures 2 and 3 show mitosis."])
+            .image(1, 2, 2, &rgb)
+            .image(3, 2, 2, &rgb)
+            .image(3, 2, 2, &rgb)
+            .build();
+        let parse = |query: &'static str| {
+            let body = multipart_body("cells.pdf", "application/pdf", &pdf);
+            async move {
+                let response = reqwest::Client::new()
+                    .post(format!("http://{}/api/parse{}", addr, query))
+                    .header("content-type", "multipart/form-data; boundary=X")
+                    .body(body)
+                    .send()
+                    .await
+                    .unwrap();
+                response.json::<serde_json::Value>().await.unwrap()["stats"].clone()
+            }
+        };
+
+        let stats = parse("?extract_images=true").await;
+        assert_eq!(stats["total_images"], 3);
+        assert_eq!(
+            stats["page_images"],
+            serde_json::json!([{ "page_num": 1, "count": 1 }, { "page_num": 3, "count": 2 }])
+        );
+
+        // Present, as 0, when no images are extracted
+        let stats = parse("").await;
+        assert_eq!(stats["total_images"], 0);
+        assert!(stats.get("page_images").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_formats_list_every_local_parser() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        let formats: SupportedFormatsResponse = reqwest::get(format!("http://{}/api/formats", addr))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        for extension in ["pdf", "docx", "html", "md", "csv"] {
+            assert!(formats.extensions.iter().any(|e| e == extension), "{}", extension);
+        }
+        assert!(formats.mime_types.iter().any(|m| m == "text/html"));
+        assert!(formats.extensions.windows(2).all(|pair| pair[0] < pair[1]), "sorted and unique");
+        assert!(formats.mime_types.windows(2).all(|pair| pair[0] < pair[1]), "sorted and unique");
+    }
+
+    #[tokio::test]
+    async fn test_oversized_upload_rejected_with_json_error() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            max_upload_bytes: 1024,
+            max_decompressed_bytes: 4096,
+            ..Default::default()
+        })
+        .await;
+
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/api/parse", addr))
+            .header("content-type", "multipart/form-data; boundary=X")
+            .body(multipart_body("notes.txt", "text/plain", &[b'a'; 4096]))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+        let error: ErrorResponse = response.json().await.unwrap();
+        assert!(error.error.contains("notes.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_large_upload_parses() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        // Large enough to be spooled to disk while it arrives
+        let mut text = "Cells divide by mitosis into two daughter cells.\n\n".repeat(SPOOL_MEMORY_BYTES / 40);
+        text.push_str("Meiosis comes last.");
+        assert!(text.len() > SPOOL_MEMORY_BYTES);
+
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/api/parse?detect_language=false", addr))
+            .header("content-type", "multipart/form-data; boundary=X")
+            .body(multipart_body("notes.txt", "text/plain", text.as_bytes()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let parsed: ParseResponse = response.json().await.unwrap();
+        assert_eq!(parsed.metadata.size_bytes, text.len());
+        assert!(parsed.chunks[0].text.starts_with("Cells divide by mitosis"));
+        assert!(parsed.chunks.last().unwrap().text.ends_with("Meiosis comes last."));
+    }
+
+    #[tokio::test]
+    async fn test_extracted_image_served_by_reference() {
+        let png = b"\x89PNG\r\n\x1a\nfake image bytes".to_vec();
+        let page = Page {
+            page_num: 1,
+            text: "A labelled diagram of a plant cell.".to_string(),
+            images: vec![crate::parser::Image {
+                id: "img-1".to_string(),
+                data: png.clone(),
+                content_type: "image/png".to_string(),
+            }],
+            ..Default::default()
+        };
+
+        let state = AppState {
+            cache: None,
+            images: ImageStore::in_memory(8, Duration::from_secs(60)),
+            max_upload_bytes: 1024,
+            max_decompressed_bytes: 4096,
+            pdf_max_images_per_page: 0,
+            parse_timeout: Duration::from_secs(60),
+            max_concurrent_requests: 4,
+            api_key: ApiKey::default(),
+            batch_deadline: Duration::from_secs(60),
+            dead_letters: None,
+            summarizer: None,
+            ocr: None,
+            quality_rules: QualityRules::empty(),
+            chunk_processors: ChunkProcessors::default(),
+            health: Health::new(),
+            fetch: None,
+            metrics: telemetry::handle(),
+        };
+        store_images(&state.images, "doc-hash", std::slice::from_ref(&page)).await;
+
+        // Chunks carry only the reference
+        let chunks = SentenceTextSplitter::new(500, 0).split(&[page]);
+        let reference = &chunks[0].images[0];
+        assert_eq!(reference.image_id, "img-1");
+        assert_eq!(reference.size_bytes, png.len());
+
+        let addr = serve(router(state)).await;
+        let response = reqwest::get(format!("http://{}/api/images/doc-hash/img-1", addr)).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "image/png");
+        assert_eq!(response.bytes().await.unwrap().to_vec(), png);
+
+        let missing = reqwest::get(format!("http://{}/api/images/doc-hash/img-2", addr)).await.unwrap();
+        assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_cache_keyed_by_parser() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Memory {
+                capacity: 8,
+                ttl: Duration::from_secs(3600),
+            },
+            ..Default::default()
+        })
+        .await;
+        let notes = b"# Mitosis\n\nCells divide.";
+        let parse = |filename: &'static str, content_type: &'static str| async move {
+            reqwest::Client::new()
+                .post(format!("http://{}/api/parse?echo_config=true", addr))
+                .header("content-type", "multipart/form-data; boundary=X")
+                .body(multipart_body(filename, content_type, notes))
+                .send()
+                .await
+                .unwrap()
+                .json::<serde_json::Value>()
+                .await
+                .unwrap()
+        };
+
+        let markdown = parse("notes.md", "text/markdown").await;
+        let text = parse("notes.txt", "text/plain").await;
+        assert_eq!(markdown["applied_config"]["parser"], "MarkdownParser");
+        assert_eq!(text["applied_config"]["parser"], "PlainTextParser");
+    }
+
+    #[tokio::test]
+    async fn test_cache_keeps_deterministic_ids_per_filename() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Memory {
+                capacity: 8,
+                ttl: Duration::from_secs(3600),
+            },
+            ..Default::default()
+        })
+        .await;
+        let first_id = |filename: &'static str| async move {
+            let response = reqwest::Client::new()
+                .post(format!("http://{}/api/parse?deterministic_ids=true", addr))
+                .header("content-type", "multipart/form-data; boundary=X")
+                .body(multipart_body(filename, "text/plain", b"Cells divide."))
+                .send()
+                .await
+                .unwrap()
+                .json::<serde_json::Value>()
+                .await
+                .unwrap();
+            response["chunks"][0]["id"].as_str().unwrap().to_string()
+        };
+
+        let week1 = first_id("week1.txt").await;
+        assert_ne!(first_id("week2.txt").await, week1);
+        assert_eq!(first_id("week1.txt").await, week1);
+    }
+
+    #[tokio::test]
+    async fn test_cache_hit_restores_evicted_images() {
+        // Room for one image and one response, or two responses
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Memory {
+                capacity: 2,
+                ttl: Duration::from_secs(3600),
+            },
+            ..Default::default()
+        })
+        .await;
+        let rgb = [255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255];
+        let pdf = crate::parser::fixtures::PdfBuilder::new()
+            .page(&["Figure 1 shows a cell."])
+            .image(1, 2, 2, &rgb)
+            .build();
+        let parse = |filename: &'static str, content_type: &'static str, data: Vec<u8>| async move {
+            reqwest::Client::new()
+                .post(format!("http://{}/api/parse?extract_images=true", addr))
+                .header("content-type", "multipart/form-data; boundary=X")
+                .body(multipart_body(filename, content_type, &data))
+                .send()
+                .await
+                .unwrap()
+                .json::<serde_json::Value>()
+                .await
+                .unwrap()
+        };
+        let image_url = |response: &serde_json::Value| {
+            let hash = response["metadata"]["document_hash"].as_str().unwrap();
+            let id = response["chunks"][0]["images"][0]["image_id"].as_str().unwrap();
+            format!("http://{}/api/images/{}/{}", addr, hash, id)
+        };
+
+        parse("cells.pdf", "application/pdf", pdf.clone()).await;
+        // Storing another response evicts the image, not the first response
+        parse("notes.txt", "text/plain", b"Cells divide.".to_vec()).await;
+
+        let response = parse("cells.pdf", "application/pdf", pdf).await;
+        let image = reqwest::get(image_url(&response)).await.unwrap();
+        assert_eq!(image.status(), reqwest::StatusCode::OK);
+    }
+
+    fn multipart_body(filename: &str, content_type: &str, data: &[u8]) -> Vec<u8> {
+        multipart_files(&[(filename, content_type, data)])
+    }
+
+    fn multipart_files(files: &[(&str, &str, &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (filename, content_type, data) in files {
+            body.extend_from_slice(
+                format!(
+                    "--X\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
+                     Content-Type: {}\r\n\r\n",
+                    filename, content_type
+                )
+                .as_bytes(),
+            );
+            body.extend_from_slice(data);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(b"--X--\r\n");
+        body
+    }
+
+    #[tokio::test]
+    async fn test_parse_dispatches_by_content_type() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        let parse = |filename: &'static str, content_type: &'static str, data: &'static [u8]| async move {
+            reqwest::Client::new()
+                .post(format!("http://{}/api/parse", addr))
+                .header("content-type", "multipart/form-data; boundary=X")
+                .body(multipart_body(filename, content_type, data))
+                .send()
+                .await
+                .unwrap()
+        };
+
+        let response = parse("notes.html", "text/html", b"<html><body><p>Cells divide by mitosis.</p></body></html>").await;
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let parsed: ParseResponse = response.json().await.unwrap();
+        assert!(parsed.chunks[0].text.contains("Cells divide by mitosis."));
+
+        let response = parse("notes.md", "application/octet-stream", b"# Cells\n\nCells divide by mitosis.\n").await;
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+        let response = parse("slides.zip", "application/zip", b"PK\x03\x04").await;
+        assert_eq!(response.status(), reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn test_empty_document_has_error_code() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        let parse = |filename: &'static str, content_type: &'static str, data: &'static [u8]| async move {
+            reqwest::Client::new()
+                .post(format!("http://{}/api/parse", addr))
+                .header("content-type", "multipart/form-data; boundary=X")
+                .body(multipart_body(filename, content_type, data))
+                .send()
+                .await
+                .unwrap()
+        };
+
+        let response = parse("notes.html", "text/html", b"<html><body><script>init();</script></body></html>").await;
+        assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+        let error: ErrorResponse = response.json().await.unwrap();
+        assert_eq!(error.code.as_deref(), Some("EmptyDocument"));
+        assert_eq!(error.error, "No text content found in HTML");
+
+        // Corrupt files share the status but not the code
+        let docx = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+        let response = parse("notes.docx", docx, b"not a document").await;
+        assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(response.json::<ErrorResponse>().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_metrics_count_parse_requests() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        // Other tests parse concurrently into the same registry
+        let requests = || async move {
+            let body = reqwest::get(format!("http://{}/metrics", addr)).await.unwrap().text().await.unwrap();
+            body.lines()
+                .find_map(|line| line.strip_prefix(r#"keiko_ingestion_parse_requests_total{interface="rest"} "#))
+                .map_or(0.0, |value| value.parse::<f64>().unwrap())
+        };
+
+        let before = requests().await;
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/api/parse", addr))
+            .header("content-type", "multipart/form-data; boundary=X")
+            .body(multipart_body("notes.txt", "text/plain", b"Cells divide by mitosis."))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+        let response = reqwest::get(format!("http://{}/metrics", addr)).await.unwrap();
+        assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/plain"));
+        let body = response.text().await.unwrap();
+        assert!(body.contains("keiko_ingestion_parse_duration_seconds_bucket{"), "{}", body);
+        assert!(body.contains(r#"parser="PlainTextParser""#));
+        assert!(requests().await >= before + 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_generic_content_type_sniffed_from_bytes() {
+        use crate::parser::fixtures::PdfBuilder;
+
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        let pdf = PdfBuilder::new().page(&["Cells divide by mitosis."]).build();
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/api/parse", addr))
+            .header("content-type", "multipart/form-data; boundary=X")
+            .body(multipart_body("scan.txt", "application/octet-stream", &pdf))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let parsed: ParseResponse = response.json().await.unwrap();
+        assert_eq!(parsed.metadata.content_type, "application/pdf");
+        assert!(parsed.chunks[0].text.contains("Cells divide by mitosis."));
+    }
+
+    #[tokio::test]
+    async fn test_chunk_splits_extracted_text() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        let chunk = |body: serde_json::Value| async move {
+            reqwest::Client::new()
+                .post(format!("http://{}/api/chunk", addr))
+                .json(&body)
+                .send()
+                .await
+                .unwrap()
+        };
+
+        let response = chunk(serde_json::json!({
+            "pages": [
+                { "page_num": 3, "text": "Cells divide by mitosis. Mitosis has four phases." },
+                { "page_num": 7, "text": "Meiosis halves the chromosome count." },
+            ],
+            "max_tokens": 8,
+            "overlap_percent": 0,
+        }))
+        .await;
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let chunked: ChunkResponse = response.json().await.unwrap();
+        let page_nums: Vec<u32> = chunked.chunks.iter().map(|c| c.page_num).collect();
+        assert_eq!(page_nums, [3, 3, 7]);
+        assert_eq!(chunked.chunks[2].text, "Meiosis halves the chromosome count.");
+        assert_eq!(chunked.stats.total_chunks, 3);
+
+        let response = chunk(serde_json::json!({ "pages": [], "max_tokens": 0 })).await;
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        let response = chunk(serde_json::json!({ "pages": [], "max_tokens": 8, "overlap_tokens": 8 })).await;
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_parse_url_fetches_and_parses_html() {
+        let html = "<html><body><h1>Mitosis</h1><p>Cells divide by mitosis.</p></body></html>";
+        let site = serve(Router::new().route(
+            "/lessons/mitosis.html",
+            get(move || async move { ([("content-type", "text/html; charset=utf-8")], html) }),
+        ))
+        .await;
+        let fetching = |allow_private_addresses: bool, network_disabled: bool| Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            fetch: FetchPolicy {
+                allow_private_addresses,
+                ..Default::default()
+            },
+            network_disabled,
+            ..Default::default()
+        };
+        let parse = |addr: std::net::SocketAddr, url: String| async move {
+            reqwest::Client::new()
+                .post(format!("http://{}/api/parse/url", addr))
+                .json(&serde_json::json!({ "url": url }))
+                .send()
+                .await
+                .unwrap()
+        };
+        let page = format!("http://{}/lessons/mitosis.html", site);
+
+        let addr = spawn_server(fetching(true, false)).await;
+        let response = parse(addr, page.clone()).await;
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let parsed: ParseResponse = response.json().await.unwrap();
+        assert_eq!(parsed.metadata.filename, "mitosis.html");
+        assert!(parsed.chunks[0].text.contains("Cells divide by mitosis."));
+        let missing = parse(addr, format!("http://{}/lessons/missing.html", site)).await;
+        assert_eq!(missing.status(), reqwest::StatusCode::BAD_GATEWAY);
+
+        // Loopback and non-http targets are refused by default
+        let addr = spawn_server(fetching(false, false)).await;
+        assert_eq!(parse(addr, page.clone()).await.status(), reqwest::StatusCode::BAD_REQUEST);
+        let response = parse(addr, "file:///etc/passwd".to_string()).await;
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        assert!(response.json::<ErrorResponse>().await.unwrap().error.contains("scheme"));
+
+        let offline = spawn_server(fetching(true, true)).await;
+        assert_eq!(parse(offline, page).await.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_sentences_format_returns_one_sentence_per_line() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        let text = "Dr. Lee  studies cells. Pi is about 3.14, e.g. in circles!\n\nDo cells divide? They do.";
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/api/parse?format=sentences", addr))
+            .header("content-type", "multipart/form-data; boundary=X")
+            .body(multipart_body("notes.txt", "text/plain", text.as_bytes()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/plain"));
+
+        let body = response.text().await.unwrap();
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(
+            lines,
+            vec!["Dr. Lee studies cells.", "Pi is about 3.14, e.g. in circles!", "Do cells divide?", "They do."]
+        );
+        assert_eq!(lines.len(), SentenceTextSplitter::new(500, 10).split_into_sentences(text).len());
+    }
+
+    #[tokio::test]
+    async fn test_filter_keeps_matching_chunks_with_original_indices() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        let parse = |query: &'static str| async move {
+            reqwest::Client::new()
+                .post(format!("http://{}/api/parse?{}", addr, query))
+                .header("content-type", "multipart/form-data; boundary=X")
+                .body(multipart_body(
+                    "notes.txt",
+                    "text/plain",
+                    b"Cells divide by Mitosis.\x0cPlants rely on photosynthesis.\x0c\
+                      Mitosis has four phases.\x0cMeiosis halves the chromosomes.",
+                ))
+                .send()
+                .await
+                .unwrap()
+        };
+
+        let parsed: ParseResponse = parse("filter=mitosis").await.json().await.unwrap();
+        let indices: Vec<usize> = parsed.chunks.iter().map(|c| c.index).collect();
+        assert_eq!(indices, vec![0, 2]);
+        assert_eq!(parsed.stats.total_chunks, 2);
+
+        let response = parse("filter=%5E(Plants%7CMeiosis)&filter_regex=true").await;
+        let parsed: ParseResponse = response.json().await.unwrap();
+        let indices: Vec<usize> = parsed.chunks.iter().map(|c| c.index).collect();
+        assert_eq!(indices, vec![1, 3]);
+
+        let response = parse("filter=(unclosed&filter_regex=true").await;
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        let error: ErrorResponse = response.json().await.unwrap();
+        assert!(error.error.contains("invalid filter regex"));
+    }
+
+    #[tokio::test]
+    async fn test_echo_config_reports_defaults() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        let parse = |query: &'static str| async move {
+            let response = reqwest::Client::new()
+                .post(format!("http://{}/api/parse?{}", addr, query))
+                .header("content-type", "multipart/form-data; boundary=X")
+                .body(multipart_body("notes.txt", "text/plain", b"Cells divide by mitosis."))
+                .send()
+                .await
+                .unwrap();
+            response.json::<serde_json::Value>().await.unwrap()
+        };
+
+        let parsed = parse("echo_config=true&simhash=true").await;
+        assert_eq!(
+            parsed["applied_config"],
+            serde_json::json!({
+                "parser": "PlainTextParser",
+                "splitter": "sentence",
+                "tokenizer": "cl100k_base",
+                "max_tokens": 500,
+                "overlap_tokens": 50,
+                "overlap_align": "token",
+                "transforms": ["drop_empty_chunks", "detect_language", "simhash"],
+            })
+        );
+        assert!(parse("simhash=true").await.get("applied_config").is_none());
+        let parsed = parse("echo_config=true&tokenizer=o200k_base").await;
+        assert_eq!(parsed["applied_config"]["tokenizer"], "o200k_base");
+    }
+
+    #[tokio::test]
+    async fn test_html_sections_page_by_section() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        let html = b"<html><body><article>Cells divide.</article><section>Cells grow.</section>\
+                     <div class=\"unit\">Cells die.</div></body></html>";
+        let parse = |query: &'static str| async move {
+            let response = reqwest::Client::new()
+                .post(format!("http://{}/api/parse?echo_config=true&{}", addr, query))
+                .header("content-type", "multipart/form-data; boundary=X")
+                .body(multipart_body("cells.html", "text/html", html))
+                .send()
+                .await
+                .unwrap();
+            response.json::<serde_json::Value>().await.unwrap()
+        };
+
+        let parsed = parse("html_sections=false").await;
+        assert_eq!(parsed["metadata"]["page_count"], 1);
+        let parsed = parse("html_sections=true").await;
+        assert_eq!(parsed["metadata"]["page_count"], 3);
+        let transforms = parsed["applied_config"]["transforms"].as_array().unwrap();
+        assert!(transforms.contains(&serde_json::json!("html_sections=section,article,main")));
+        let parsed = parse("html_section_selectors=div.unit").await;
+        assert_eq!(parsed["metadata"]["page_count"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_summary_from_llm_lands_in_metadata() {
+        async fn completions(axum::Json(body): axum::Json<serde_json::Value>) -> axum::Json<serde_json::Value> {
+            let text = body["messages"][1]["content"].as_str().unwrap_or_default();
+            // Says whether an address reached it, and names one of its own
+            let summary = format!(
+                "Summary of {} characters with {} addresses. Ask tutor@school.example.edu.",
+                text.len(),
+                text.matches('@').count()
+            );
+            axum::Json(serde_json::json!({ "choices": [{ "message": { "content": summary } }] }))
+        }
+        let llm = serve(Router::new().route("/v1/chat/completions", post(completions))).await;
+        let summarizing = |network_disabled: bool| Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            summarizer: Some(crate::summarize::SummarizerConfig {
+                base_url: format!("http://{}/v1", llm),
+                model: "test-chat".to_string(),
+                api_key: None,
+                max_input_tokens: 1000,
+            }),
+            network_disabled,
+            ..Default::default()
+        };
+        let html: &[u8] = b"<html><body><p>Cells divide by mitosis. Mail jane@school.example.edu.</p></body></html>";
+        let parse = |addr: std::net::SocketAddr, query: &'static str| async move {
+            reqwest::Client::new()
+                .post(format!("http://{}/api/parse{}", addr, query))
+                .header("content-type", "multipart/form-data; boundary=X")
+                .body(multipart_body("notes.html", "text/html", html))
+                .send()
+                .await
+                .unwrap()
+                .json::<ParseResponse>()
+                .await
+                .unwrap()
+        };
+
+        let addr = spawn_server(summarizing(false)).await;
+        let parsed = parse(addr, "?summarize=true").await;
+        let summary = parsed.metadata.summary.unwrap();
+        assert!(summary.starts_with("Summary of "), "{}", summary);
+        assert!(summary.contains("with 1 addresses") && summary.contains("tutor@"), "{}", summary);
+        assert!(parse(addr, "").await.metadata.summary.is_none());
+        let redacted = parse(addr, "?summarize=true&redact_pii=true").await.metadata.summary.unwrap();
+        assert!(redacted.ends_with("with 0 addresses. Ask [EMAIL]."), "{}", redacted);
+
+        // Safe mode never calls out
+        let offline = spawn_server(summarizing(true)).await;
+        assert!(parse(offline, "?summarize=true").await.metadata.summary.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_validate_reports_oversized_chunk() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        // Run-on sentences are cut at max_tokens, but code blocks are kept whole
+        let code = "let cells = divide(cells, phase);\n".repeat(8);
+        let guide = format!("# Mitosis\n\nCells divide.\n\n```rust\n{}```\n", code);
+
+        let validate = |max_tokens: usize| {
+            let body = multipart_body("guide.md", "text/markdown", guide.as_bytes());
+            async move {
+                reqwest::Client::new()
+                    .post(format!("http://{}/api/validate?max_tokens={}", addr, max_tokens))
+                    .header("content-type", "multipart/form-data; boundary=X")
+                    .body(body)
+                    .send()
+                    .await
+                    .unwrap()
+                    .json::<ValidateResponse>()
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let result = validate(20).await;
+        assert!(!result.valid);
+        assert_eq!(result.violations.len(), 1);
+        assert_eq!(result.violations[0].kind, "oversized_chunk");
+        assert!(result.violations[0].message.contains("exceeding max_tokens 20"));
+
+        let result = validate(500).await;
+        assert!(result.valid, "{:?}", result.violations);
+        assert_eq!(result.chunk_count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_batch_deadline_skips_remaining_files() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        let pdfs: Vec<(String, Vec<u8>)> = (0..20)
+            .map(|i| {
+                let text = format!("Report number {} covers the quarterly figures.", i);
+                (format!("report-{}.pdf", i), crate::parser::fixtures::PdfBuilder::new().page(&[text.as_str()]).build())
+            })
+            .collect();
+        let files: Vec<(&str, &str, &[u8])> = pdfs
+            .iter()
+            .map(|(name, pdf)| (name.as_str(), "application/pdf", pdf.as_slice()))
+            .collect();
+
+        let response: BatchResponse = reqwest::Client::new()
+            .post(format!("http://{}/api/parse/batch?deadline_ms=1", addr))
+            .header("content-type", "multipart/form-data; boundary=X")
+            .body(multipart_files(&files))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        assert_eq!(response.results.len(), 20);
+        assert!(response.stats.processed >= 1);
+        assert!(response.stats.skipped >= 1);
+        assert_eq!(response.stats.processed + response.stats.skipped, 20);
+
+        // The first file always starts, and everything after the cut is skipped
+        let first = &response.results[0];
+        assert_eq!(first.status, BatchStatus::Processed);
+        assert_eq!(first.filename, "report-0.pdf");
+        assert!(first.document.as_ref().unwrap().chunks[0].text.contains("Report number 0"));
+        let cut = response.results.iter().position(|r| r.status == BatchStatus::Skipped).unwrap();
+        assert!(response.results[cut..].iter().all(|r| r.status == BatchStatus::Skipped
+            && r.document.is_none()
+            && r.reason.as_deref() == Some("batch deadline of 1 ms exceeded")));
+    }
+
+    #[tokio::test]
+    async fn test_batch_reports_per_file_results() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        let first = crate::parser::fixtures::PdfBuilder::new().page(&["Week one covers cell structure."]).build();
+        let second = crate::parser::fixtures::PdfBuilder::new().page(&["Week two covers cell division."]).build();
+        let files: Vec<(&str, &str, &[u8])> = vec![
+            ("week-1.pdf", "application/pdf", first.as_slice()),
+            ("corrupt.pdf", "application/pdf", &b"not a pdf at all"[..]),
+            ("week-2.pdf", "application/pdf", second.as_slice()),
+        ];
+        let post = |body: Vec<u8>| {
+            reqwest::Client::new()
+                .post(format!("http://{}/api/parse/batch", addr))
+                .header("content-type", "multipart/form-data; boundary=X")
+                .body(body)
+                .send()
+        };
+
+        let response: BatchResponse = post(multipart_files(&files)).await.unwrap().json().await.unwrap();
+        let statuses: Vec<BatchStatus> = response.results.iter().map(|r| r.status).collect();
+        assert_eq!(statuses, vec![BatchStatus::Processed, BatchStatus::Failed, BatchStatus::Processed]);
+        assert_eq!((response.stats.processed, response.stats.failed), (2, 1));
+        assert_eq!(response.results[1].filename, "corrupt.pdf");
+        assert!(response.results[1].reason.is_some());
+        assert!(response.results[2].document.as_ref().unwrap().chunks[0].text.contains("cell division"));
+
+        let too_many: Vec<(&str, &str, &[u8])> =
+            vec![("week-1.pdf", "application/pdf", first.as_slice()); MAX_BATCH_FILES + 1];
+        let response = post(multipart_files(&too_many)).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_compressed_uploads_are_unpacked() {
+        use std::io::Write;
+
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        let post = |path: &str, body: Vec<u8>| {
+            reqwest::Client::new()
+                .post(format!("http://{}{}", addr, path))
+                .header("content-type", "multipart/form-data; boundary=X")
+                .body(body)
+                .send()
+        };
+        let pdf = crate::parser::fixtures::PdfBuilder::new().page(&["Week one covers cell structure."]).build();
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(&pdf).unwrap();
+        let body = multipart_body("week-1.pdf.gz", "application/gzip", &gzip.finish().unwrap());
+        let response: ParseResponse = post("/api/parse", body).await.unwrap().json().await.unwrap();
+        assert_eq!(response.metadata.filename, "week-1.pdf");
+        assert_eq!(response.metadata.content_type, "application/pdf");
+        assert!(response.chunks[0].text.contains("cell structure"));
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        zip.start_file("week-1.pdf", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(&pdf).unwrap();
+        zip.start_file("week-2.txt", zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"Week two covers cell division.").unwrap();
+        let zip = zip.finish().unwrap().into_inner();
+        let body = || multipart_body("course.zip", "application/zip", &zip);
+        let response: BatchResponse = post("/api/parse/batch", body()).await.unwrap().json().await.unwrap();
+        let names: Vec<&str> = response.results.iter().map(|r| r.filename.as_str()).collect();
+        assert_eq!(names, ["course.zip/week-1.pdf", "course.zip/week-2.txt"]);
+        assert_eq!(response.stats.processed, 2);
+        assert!(response.results[1].document.as_ref().unwrap().chunks[0].text.contains("cell division"));
+
+        let response = post("/api/parse", body()).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_large_field_spools_to_disk() {
+        use axum::extract::FromRequest;
+
+        let spool = |data: Vec<u8>| async move {
+            let request = Request::builder()
+                .header("content-type", "multipart/form-data; boundary=X")
+                .body(axum::body::Body::from(multipart_body("notes.txt", "text/plain", &data)))
+                .unwrap();
+            let mut multipart = Multipart::from_request(request, &()).await.unwrap();
+            let field = multipart.next_field().await.unwrap().unwrap();
+            let Ok(spool) = spool_field(field, 3 * SPOOL_MEMORY_BYTES / 2).await else {
+                panic!("the field is well-formed");
+            };
+            spool
+        };
+
+        let small = spool(vec![b'a'; 1000]).await.unwrap();
+        assert!(!small.file.is_rolled());
+        assert_eq!(small.into_bytes().unwrap().len(), 1000);
+
+        // Only the first part of a large field is held in memory
+        let data: Vec<u8> = (0..5 * SPOOL_MEMORY_BYTES / 4).map(|i| (i % 251) as u8).collect();
+        let large = spool(data.clone()).await.unwrap();
+        assert!(large.file.is_rolled());
+        assert_eq!(large.into_bytes().unwrap(), data);
+
+        assert!(spool(vec![b'a'; 3 * SPOOL_MEMORY_BYTES / 2 + 1]).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_batch_budget_counts_decompressed_bytes() {
+        use axum::extract::FromRequest;
+        use std::io::Write;
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(&[b'a'; 600]).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let form = |files: &[(&str, &str, &[u8])]| {
+            let request = Request::builder()
+                .header("content-type", "multipart/form-data; boundary=X")
+                .body(axum::body::Body::from(multipart_files(files)))
+                .unwrap();
+            async move { Multipart::from_request(request, &()).await.unwrap() }
+        };
+        let limits = || UploadLimits {
+            max_files: 10,
+            max_file_bytes: 1000,
+            max_total_bytes: 1000,
+            max_decompressed_bytes: 1000,
+        };
+
+        let one = [("a.txt.gz", "application/gzip", gzip.as_slice())];
+        let uploads = read_uploads(&mut form(&one).await, limits()).await.unwrap();
+        assert_eq!(uploads[0].data.len(), 600);
+
+        // Each file is within its own limit, but together they expand past the batch's
+        let two = [one[0], ("b.txt.gz", "application/gzip", gzip.as_slice())];
+        let Err(error) = read_uploads(&mut form(&two).await, limits()).await else {
+            panic!("the batch expands beyond its limit");
+        };
+        assert_eq!(error.status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_batch_fails_over_failure_ratio_with_results_attached() {
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            ..Default::default()
+        })
+        .await;
+        let pdf = crate::parser::fixtures::PdfBuilder::new().page(&["Week one covers cell structure."]).build();
+        let files: Vec<(&str, &str, &[u8])> = vec![
+            ("week-1.pdf", "application/pdf", pdf.as_slice()),
+            ("corrupt-1.pdf", "application/pdf", &b"not a pdf at all"[..]),
+            ("corrupt-2.pdf", "application/pdf", &b"not a pdf either"[..]),
+        ];
+        let post = |ratio: &'static str| {
+            reqwest::Client::new()
+                .post(format!("http://{}/api/parse/batch?max_failure_ratio={}", addr, ratio))
+                .header("content-type", "multipart/form-data; boundary=X")
+                .body(multipart_files(&files))
+                .send()
+        };
+
+        let response = post("0.5").await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+        let batch: BatchResponse = response.json().await.unwrap();
+        assert_eq!(batch.error.as_deref(), Some("2 of 3 files failed, more than the allowed ratio of 0.5"));
+        let statuses: Vec<BatchStatus> = batch.results.iter().map(|r| r.status).collect();
+        assert_eq!(statuses, vec![BatchStatus::Processed, BatchStatus::Failed, BatchStatus::Failed]);
+        assert!(batch.results[0].document.is_some());
+        assert!(batch.results[1..].iter().all(|r| r.reason.is_some()));
+
+        let response = post("0.7").await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert!(response.json::<BatchResponse>().await.unwrap().error.is_none());
+
+        assert_eq!(post("1.5").await.unwrap().status(), reqwest::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_batch_failure_writes_dead_letter() {
+        let dir = tempfile::tempdir().unwrap();
+        let addr = spawn_server(Config {
+            cache: crate::cache::CacheConfig::Disabled,
+            dead_letter: Some(crate::dead_letter::DeadLetterConfig {
+                dir: dir.path().to_path_buf(),
+                include_bytes: true,
+            }),
+            ..Default::default()
+        })
+        .await;
+        let pdf = crate::parser::fixtures::PdfBuilder::new().page(&["The annual report is ready."]).build();
+        let broken = b"%PDF-1.5 truncated".to_vec();
+        let files: Vec<(&str, &str, &[u8])> = vec![
+            ("report.pdf", "application/pdf", pdf.as_slice()),
+            ("broken.pdf", "application/pdf", broken.as_slice()),
+        ];
+
+        let response: BatchResponse = reqwest::Client::new()
+            .post(format!("http://{}/api/parse/batch", addr))
+            .header("content-type", "multipart/form-data; boundary=X")
+            .body(multipart_files(&files))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(response.stats.failed, 1);
+        let reason = response.results[1].reason.clone().unwrap();
+
+        // Only the failed document is recorded, with its error and bytes
+        let hash = blake3::hash(&broken).to_hex().to_string();
+        let entry: crate::dead_letter::DeadLetterEntry =
+            serde_json::from_slice(&std::fs::read(dir.path().join(format!("{}.json", hash))).unwrap()).unwrap();
+        assert_eq!(entry.filename, "broken.pdf");
+        assert_eq!(entry.content_type, "application/pdf");
+        assert_eq!(entry.size_bytes, broken.len());
+        assert_eq!(entry.error, reason);
+        assert_eq!(std::fs::read(dir.path().join(entry.bytes_file.unwrap())).unwrap(), broken);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+}
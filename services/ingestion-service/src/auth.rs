@@ -0,0 +1,228 @@
+// Optional API-key authentication shared by the REST and gRPC servers
+
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use axum::http::{Request, StatusCode};
+use axum::response::{IntoResponse, Json, Response};
+use futures::future::BoxFuture;
+use tonic::body::BoxBody;
+use tonic::server::NamedService;
+use tonic::Status;
+use tower::{Layer, Service};
+
+/// REST header and gRPC metadata key carrying the API key.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+const REJECTED: &str = "Missing or invalid API key";
+
+/// The key clients must present (`API_KEY`). Without one every request is
+/// let through, for local development.
+///
+/// Layers REST routes as a tower [`Layer`] answering `401`, and gRPC services
+/// through [`ApiKey::grpc_layer`] failing calls with `UNAUTHENTICATED`.
+#[derive(Clone, Default)]
+pub struct ApiKey(Option<Arc<str>>);
+
+impl ApiKey {
+    pub fn new(key: Option<String>) -> Self {
+        Self(key.filter(|key| !key.is_empty()).map(Into::into))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// A layer requiring the key on calls to a gRPC service, except to the
+    /// methods named in `open_methods`.
+    pub fn grpc_layer(&self, open_methods: &'static [&'static str]) -> GrpcApiKey {
+        GrpcApiKey {
+            key: self.clone(),
+            open_methods,
+        }
+    }
+
+    /// Whether `presented` is the key, or no key is required.
+    fn accepts(&self, presented: Option<&[u8]>) -> bool {
+        let Some(key) = &self.0 else { return true };
+        let Some(presented) = presented else { return false };
+        // Compare every byte so the time taken says nothing about the key
+        key.len() == presented.len()
+            && key.bytes().zip(presented).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+}
+
+impl std::fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ApiKey").field(&self.0.as_ref().map(|_| "<redacted>")).finish()
+    }
+}
+
+impl<S> Layer<S> for ApiKey {
+    type Service = RequireApiKey<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireApiKey {
+            inner,
+            key: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RequireApiKey<S> {
+    inner: S,
+    key: ApiKey,
+}
+
+impl<S, B> Service<Request<B>> for RequireApiKey<S>
+where
+    S: Service<Request<B>, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let presented = request.headers().get(API_KEY_HEADER).map(|value| value.as_bytes());
+        if !self.key.accepts(presented) {
+            let body = serde_json::json!({ "error": REJECTED });
+            return Box::pin(async move { Ok((StatusCode::UNAUTHORIZED, Json(body)).into_response()) });
+        }
+        Box::pin(self.inner.call(request))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GrpcApiKey {
+    key: ApiKey,
+    open_methods: &'static [&'static str],
+}
+
+impl<S> Layer<S> for GrpcApiKey {
+    type Service = RequireGrpcApiKey<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireGrpcApiKey {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RequireGrpcApiKey<S> {
+    inner: S,
+    layer: GrpcApiKey,
+}
+
+impl<S: NamedService> RequireGrpcApiKey<S> {
+    /// Whether `path` calls one of the service's open methods.
+    fn is_open(&self, path: &str) -> bool {
+        let Some((service, method)) = path.strip_prefix('/').and_then(|path| path.split_once('/')) else {
+            return false;
+        };
+        service == S::NAME && self.layer.open_methods.contains(&method)
+    }
+}
+
+impl<S: NamedService> NamedService for RequireGrpcApiKey<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S, B> Service<Request<B>> for RequireGrpcApiKey<S>
+where
+    S: NamedService + Service<Request<B>, Response = axum::http::Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<S::Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let presented = request.headers().get(API_KEY_HEADER).map(|value| value.as_bytes());
+        if !self.is_open(request.uri().path()) && !self.layer.key.accepts(presented) {
+            return Box::pin(async { Ok(Status::unauthenticated(REJECTED).into_http()) });
+        }
+        Box::pin(self.inner.call(request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use tonic::Code;
+
+    /// A gRPC service answering every call with success.
+    #[derive(Clone)]
+    struct Answer;
+
+    impl NamedService for Answer {
+        const NAME: &'static str = "test.v1.Service";
+    }
+
+    impl Service<Request<()>> for Answer {
+        type Response = axum::http::Response<BoxBody>;
+        type Error = Infallible;
+        type Future = BoxFuture<'static, Result<Self::Response, Infallible>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _: Request<()>) -> Self::Future {
+            Box::pin(async { Ok(Status::ok("").into_http()) })
+        }
+    }
+
+    async fn call(key: &ApiKey, path: &str, presented: Option<&'static str>) -> Result<(), Code> {
+        let mut request = Request::builder().uri(path);
+        if let Some(presented) = presented {
+            request = request.header(API_KEY_HEADER, presented);
+        }
+        let mut service = key.grpc_layer(&["Health"]).layer(Answer);
+        let response = service.call(request.body(()).unwrap()).await.unwrap();
+        match Status::from_header_map(response.headers()).map(|status| status.code()) {
+            Some(Code::Ok) | None => Ok(()),
+            Some(code) => Err(code),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_grpc_layer_checks_metadata() {
+        let key = ApiKey::new(Some("s3cret".to_string()));
+        let parse = "/test.v1.Service/Parse";
+        assert_eq!(call(&key, parse, Some("s3cret")).await, Ok(()));
+        assert_eq!(call(&key, parse, Some("s3cre")).await, Err(Code::Unauthenticated));
+        assert_eq!(call(&key, parse, None).await, Err(Code::Unauthenticated));
+    }
+
+    #[tokio::test]
+    async fn test_grpc_layer_leaves_open_methods_open() {
+        let key = ApiKey::new(Some("s3cret".to_string()));
+        assert_eq!(call(&key, "/test.v1.Service/Health", None).await, Ok(()));
+        // Only the exact method of this service is open
+        assert_eq!(call(&key, "/test.v1.Service/HealthCheck", None).await, Err(Code::Unauthenticated));
+        assert_eq!(call(&key, "/other.v1.Service/Health", None).await, Err(Code::Unauthenticated));
+    }
+
+    #[tokio::test]
+    async fn test_unset_or_empty_key_disables_auth() {
+        for key in [ApiKey::new(None), ApiKey::new(Some(String::new()))] {
+            assert!(!key.is_enabled());
+            assert_eq!(call(&key, "/test.v1.Service/Parse", None).await, Ok(()));
+            assert_eq!(call(&key, "/test.v1.Service/Parse", Some("anything")).await, Ok(()));
+        }
+        assert!(!format!("{:?}", ApiKey::new(Some("s3cret".to_string()))).contains("s3cret"));
+    }
+}
@@ -0,0 +1,54 @@
+// Short-lived storage for extracted image bytes, served by `/api/images`
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{CacheError, InMemoryParseCache, ParseCache};
+use crate::parser::Image;
+
+const KEY_PREFIX: &str = "image:";
+
+/// Images extracted during parsing, keyed by document hash and image id, so
+/// responses can carry references instead of inline bytes.
+#[derive(Clone)]
+pub struct ImageStore {
+    backend: Arc<dyn ParseCache>,
+}
+
+impl ImageStore {
+    /// Store images in `backend`, typically the parse cache.
+    pub fn new(backend: Arc<dyn ParseCache>) -> Self {
+        Self { backend }
+    }
+
+    /// A process-local store for when no parse cache is configured.
+    pub fn in_memory(capacity: usize, ttl: Duration) -> Self {
+        Self::new(Arc::new(InMemoryParseCache::new(capacity, ttl)))
+    }
+
+    pub async fn put(&self, document_hash: &str, image: &Image) -> Result<(), CacheError> {
+        // Stored as `<content type>\n<bytes>`
+        let mut value = Vec::with_capacity(image.content_type.len() + 1 + image.data.len());
+        value.extend_from_slice(image.content_type.as_bytes());
+        value.push(b'\n');
+        value.extend_from_slice(&image.data);
+        self.backend.put(&Self::key(document_hash, &image.id), value).await
+    }
+
+    /// The content type and bytes of a stored image.
+    pub async fn get(&self, document_hash: &str, image_id: &str) -> Result<Option<(String, Vec<u8>)>, CacheError> {
+        let Some(mut value) = self.backend.get(&Self::key(document_hash, image_id)).await? else {
+            return Ok(None);
+        };
+        let Some(split) = value.iter().position(|&b| b == b'\n') else {
+            return Ok(None);
+        };
+        let data = value.split_off(split + 1);
+        value.truncate(split);
+        Ok(Some((String::from_utf8_lossy(&value).into_owned(), data)))
+    }
+
+    fn key(document_hash: &str, image_id: &str) -> String {
+        format!("{}{}:{}", KEY_PREFIX, document_hash, image_id)
+    }
+}
@@ -0,0 +1,92 @@
+// In-process parse cache with FIFO eviction and a fixed TTL
+
+use async_trait::async_trait;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::{CacheError, ParseCache};
+
+struct Entries {
+    values: HashMap<String, (Instant, Vec<u8>)>,
+    order: VecDeque<String>,
+}
+
+/// Parse cache local to one service instance.
+pub struct InMemoryParseCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: Mutex<Entries>,
+}
+
+impl InMemoryParseCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            entries: Mutex::new(Entries {
+                values: HashMap::new(),
+                order: VecDeque::new(),
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl ParseCache for InMemoryParseCache {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CacheError> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.values.get(key) {
+            Some((stored_at, value)) if stored_at.elapsed() < self.ttl => Ok(Some(value.clone())),
+            Some(_) => {
+                entries.values.remove(key);
+                entries.order.retain(|k| k != key);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn put(&self, key: &str, value: Vec<u8>) -> Result<(), CacheError> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.values.insert(key.to_string(), (Instant::now(), value)).is_none() {
+            entries.order.push_back(key.to_string());
+        }
+        while entries.values.len() > self.capacity {
+            match entries.order.pop_front() {
+                Some(oldest) => {
+                    entries.values.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_cache_evicts_oldest() {
+        let cache = InMemoryParseCache::new(2, Duration::from_secs(60));
+        cache.put("a", b"1".to_vec()).await.unwrap();
+        cache.put("b", b"2".to_vec()).await.unwrap();
+        cache.put("c", b"3".to_vec()).await.unwrap();
+
+        assert_eq!(cache.get("a").await.unwrap(), None);
+        assert_eq!(cache.get("b").await.unwrap(), Some(b"2".to_vec()));
+        assert_eq!(cache.get("c").await.unwrap(), Some(b"3".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_memory_cache_expires_entries() {
+        let cache = InMemoryParseCache::new(2, Duration::ZERO);
+        cache.put("a", b"1".to_vec()).await.unwrap();
+        assert_eq!(cache.get("a").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,57 @@
+mod images;
+mod memory;
+mod redis;
+
+pub use images::ImageStore;
+pub use memory::InMemoryParseCache;
+pub use self::redis::RedisParseCache;
+
+use async_trait::async_trait;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CacheError {
+    #[error("Redis error: {0}")]
+    Redis(#[from] ::redis::RedisError),
+}
+
+/// Storage for serialized parse responses, keyed by document hash + options.
+///
+/// Implementations must be safe to share across requests; a failing backend
+/// should surface an error rather than panic so callers can fall back to
+/// parsing.
+#[async_trait]
+pub trait ParseCache: Send + Sync {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CacheError>;
+    async fn put(&self, key: &str, value: Vec<u8>) -> Result<(), CacheError>;
+}
+
+/// Which cache backend to use, selected by `PARSE_CACHE_BACKEND`.
+#[derive(Debug, Clone)]
+pub enum CacheConfig {
+    Disabled,
+    Memory { capacity: usize, ttl: Duration },
+    Redis { url: String, ttl: Duration },
+}
+
+impl CacheConfig {
+    /// Build the configured backend, or `None` when caching is disabled.
+    pub fn build(&self) -> Result<Option<Arc<dyn ParseCache>>, CacheError> {
+        Ok(match self {
+            CacheConfig::Disabled => None,
+            CacheConfig::Memory { capacity, ttl } => {
+                Some(Arc::new(InMemoryParseCache::new(*capacity, *ttl)))
+            }
+            CacheConfig::Redis { url, ttl } => Some(Arc::new(RedisParseCache::new(url, *ttl)?)),
+        })
+    }
+}
+
+/// Cache key for a document parsed with the given options.
+pub fn cache_key<O: Serialize>(document_hash: &str, options: &O) -> String {
+    let options = serde_json::to_vec(options).unwrap_or_default();
+    format!("{}:{}", document_hash, blake3::hash(&options).to_hex())
+}
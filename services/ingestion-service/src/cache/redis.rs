@@ -0,0 +1,133 @@
+// Redis-backed parse cache shared by all service replicas
+
+use async_trait::async_trait;
+use redis::aio::MultiplexedConnection;
+use redis::AsyncCommands;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+use super::{CacheError, ParseCache};
+
+const KEY_PREFIX: &str = "keiko:ingestion:parse:";
+
+/// Parse cache stored in Redis so that every instance sees the same entries.
+pub struct RedisParseCache {
+    client: redis::Client,
+    connection: OnceCell<MultiplexedConnection>,
+    ttl: Duration,
+}
+
+impl RedisParseCache {
+    /// Create a cache for `url`. The connection is opened lazily on first use.
+    pub fn new(url: &str, ttl: Duration) -> Result<Self, CacheError> {
+        Ok(Self {
+            client: redis::Client::open(url)?,
+            connection: OnceCell::new(),
+            ttl,
+        })
+    }
+
+    async fn connection(&self) -> Result<MultiplexedConnection, CacheError> {
+        let connection = self
+            .connection
+            .get_or_try_init(|| self.client.get_multiplexed_async_connection())
+            .await?;
+        Ok(connection.clone())
+    }
+}
+
+#[async_trait]
+impl ParseCache for RedisParseCache {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CacheError> {
+        let mut conn = self.connection().await?;
+        Ok(conn.get(format!("{}{}", KEY_PREFIX, key)).await?)
+    }
+
+    async fn put(&self, key: &str, value: Vec<u8>) -> Result<(), CacheError> {
+        let mut conn = self.connection().await?;
+        let _: () = conn
+            .set_ex(format!("{}{}", KEY_PREFIX, key), value, self.ttl.as_secs().max(1))
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// Minimal in-process server speaking enough RESP for GET/SETEX.
+    async fn spawn_fake_redis() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let store: Arc<Mutex<HashMap<Vec<u8>, Vec<u8>>>> = Arc::default();
+
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                let store = store.clone();
+                tokio::spawn(async move {
+                    let (read, mut write) = socket.into_split();
+                    let mut reader = BufReader::new(read);
+                    while let Some(args) = read_command(&mut reader).await {
+                        let reply = match args[0].to_ascii_uppercase().as_slice() {
+                            b"GET" => match store.lock().unwrap().get(&args[1]) {
+                                Some(v) => {
+                                    let mut out = format!("${}\r\n", v.len()).into_bytes();
+                                    out.extend_from_slice(v);
+                                    out.extend_from_slice(b"\r\n");
+                                    out
+                                }
+                                None => b"$-1\r\n".to_vec(),
+                            },
+                            b"SETEX" => {
+                                store.lock().unwrap().insert(args[1].clone(), args[3].clone());
+                                b"+OK\r\n".to_vec()
+                            }
+                            _ => b"-ERR unknown command\r\n".to_vec(),
+                        };
+                        write.write_all(&reply).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        format!("redis://{}", addr)
+    }
+
+    async fn read_command<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> Option<Vec<Vec<u8>>> {
+        let mut line = String::new();
+        reader.read_line(&mut line).await.ok()?;
+        let count: usize = line.trim().strip_prefix('*')?.parse().ok()?;
+        let mut args = Vec::with_capacity(count);
+        for _ in 0..count {
+            line.clear();
+            reader.read_line(&mut line).await.ok()?;
+            let len: usize = line.trim().strip_prefix('$')?.parse().ok()?;
+            let mut buf = vec![0; len + 2];
+            reader.read_exact(&mut buf).await.ok()?;
+            buf.truncate(len);
+            args.push(buf);
+        }
+        Some(args)
+    }
+
+    #[tokio::test]
+    async fn test_redis_cache_shared_across_instances() {
+        let url = spawn_fake_redis().await;
+        let instance_a = RedisParseCache::new(&url, Duration::from_secs(60)).unwrap();
+        let instance_b = RedisParseCache::new(&url, Duration::from_secs(60)).unwrap();
+
+        assert_eq!(instance_b.get("doc:opts").await.unwrap(), None);
+        instance_a.put("doc:opts", b"{\"chunks\":[]}".to_vec()).await.unwrap();
+
+        assert_eq!(
+            instance_b.get("doc:opts").await.unwrap(),
+            Some(b"{\"chunks\":[]}".to_vec())
+        );
+    }
+}
@@ -0,0 +1,256 @@
+// Service configuration loaded from environment variables at startup
+
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::auth::ApiKey;
+use crate::cache::CacheConfig;
+use crate::dead_letter::DeadLetterConfig;
+use crate::embed::EmbeddingConfig;
+use crate::fetch::FetchPolicy;
+use crate::parser::{parse_priority, OcrConfig, QualityRules, DEFAULT_MAX_IMAGES_PER_PAGE};
+use crate::splitter::{ChunkProcessor, ChunkProcessors, TrimWhitespace};
+use crate::summarize::SummarizerConfig;
+
+const DEFAULT_MAX_UPLOAD_BYTES: usize = 50 * 1024 * 1024;
+const DEFAULT_MAX_DECOMPRESSED_BYTES: usize = 4 * DEFAULT_MAX_UPLOAD_BYTES;
+const DEFAULT_BATCH_DEADLINE_SECS: u64 = 300;
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_PARSE_TIMEOUT_SECS: u64 = 120;
+const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 64;
+
+/// Runtime configuration shared by the REST and gRPC servers.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub cache: CacheConfig,
+    /// Azure Document Intelligence credentials, when configured.
+    pub azure: Option<AzureConfig>,
+    /// Embeddings provider, when `EMBEDDING_BASE_URL` is set.
+    pub embedding: Option<EmbeddingConfig>,
+    /// Document summaries provider, when `SUMMARY_BASE_URL` is set.
+    pub summarizer: Option<SummarizerConfig>,
+    /// OCR service for scanned PDF pages, when `OCR_URL` is set.
+    pub ocr: Option<OcrConfig>,
+    /// Safe mode for air-gapped deployments: parsers and endpoints that make
+    /// outbound calls are unavailable.
+    pub network_disabled: bool,
+    /// Largest accepted upload in bytes.
+    pub max_upload_bytes: usize,
+    /// Most bytes a gzip or zip upload may expand to, guarding against zip
+    /// bombs (`MAX_DECOMPRESSED_BYTES`).
+    pub max_decompressed_bytes: usize,
+    /// Embedded images kept per PDF page when images are requested
+    /// (`PDF_MAX_IMAGES_PER_PAGE`).
+    pub pdf_max_images_per_page: usize,
+    /// Time allowed for parsing one document before the request fails
+    /// (`PARSE_TIMEOUT_SECS`).
+    pub parse_timeout: Duration,
+    /// Document requests each of the REST and gRPC servers handles at once;
+    /// more are turned away with `503` or `RESOURCE_EXHAUSTED`
+    /// (`MAX_CONCURRENT_REQUESTS`).
+    pub max_concurrent_requests: usize,
+    /// Key clients must send as `x-api-key` to use the document endpoints
+    /// (`API_KEY`); when unset, requests are not authenticated.
+    pub api_key: ApiKey,
+    /// Total processing time allowed for one batch request.
+    pub batch_deadline: Duration,
+    /// How long in-flight requests may take to finish once a shutdown
+    /// signal arrives (`SHUTDOWN_TIMEOUT_SECS`).
+    pub shutdown_timeout: Duration,
+    /// SSRF protections for URL ingestion.
+    pub fetch: FetchPolicy,
+    /// Preferred parser order per MIME type (`PARSER_PRIORITY`).
+    pub parser_priority: HashMap<String, Vec<String>>,
+    /// Per-format checks for garbled extraction output (`QUALITY_RULES`).
+    pub quality_rules: QualityRules,
+    /// Retry pages failing `quality_rules` with the next parser in the
+    /// registry's chain (`QUALITY_FALLBACK`).
+    pub quality_fallback: bool,
+    /// Escalate documents yielding fewer non-whitespace characters than this
+    /// to the next parser in the registry's chain, e.g. scanned PDFs from the
+    /// local parser to Azure (`ESCALATE_MIN_CHARS`, 0 = never).
+    pub escalate_min_chars: usize,
+    /// Applied to every chunk both servers return. Only the whitespace trim
+    /// can be switched on from the environment (`CHUNK_TRIM_WHITESPACE`);
+    /// embedders of the service supply their own.
+    pub chunk_processors: ChunkProcessors,
+    /// Record documents that fail batch ingestion, when `DEAD_LETTER_DIR`
+    /// is set.
+    pub dead_letter: Option<DeadLetterConfig>,
+}
+
+/// Endpoint and key for Azure Document Intelligence.
+#[derive(Debug, Clone)]
+pub struct AzureConfig {
+    pub endpoint: String,
+    pub api_key: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            cache: CacheConfig::Memory {
+                capacity: 128,
+                ttl: Duration::from_secs(3600),
+            },
+            azure: None,
+            embedding: None,
+            summarizer: None,
+            ocr: None,
+            network_disabled: false,
+            max_upload_bytes: DEFAULT_MAX_UPLOAD_BYTES,
+            max_decompressed_bytes: DEFAULT_MAX_DECOMPRESSED_BYTES,
+            pdf_max_images_per_page: DEFAULT_MAX_IMAGES_PER_PAGE,
+            parse_timeout: Duration::from_secs(DEFAULT_PARSE_TIMEOUT_SECS),
+            max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+            api_key: ApiKey::default(),
+            batch_deadline: Duration::from_secs(DEFAULT_BATCH_DEADLINE_SECS),
+            shutdown_timeout: Duration::from_secs(DEFAULT_SHUTDOWN_TIMEOUT_SECS),
+            fetch: FetchPolicy::default(),
+            parser_priority: HashMap::new(),
+            quality_rules: QualityRules::default(),
+            quality_fallback: false,
+            escalate_min_chars: 0,
+            chunk_processors: ChunkProcessors::default(),
+            dead_letter: None,
+        }
+    }
+}
+
+impl Config {
+    /// Read configuration from the environment, falling back to defaults for
+    /// anything unset or unparsable.
+    pub fn from_env() -> Self {
+        let ttl = Duration::from_secs(env_or("PARSE_CACHE_TTL_SECS", 3600));
+        let cache = match env::var("PARSE_CACHE_BACKEND").as_deref() {
+            Ok("none") | Ok("disabled") => CacheConfig::Disabled,
+            Ok("redis") => CacheConfig::Redis {
+                url: env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string()),
+                ttl,
+            },
+            _ => CacheConfig::Memory {
+                capacity: env_or("PARSE_CACHE_CAPACITY", 128),
+                ttl,
+            },
+        };
+
+        let azure = match (
+            env::var("AZURE_DOCUMENT_INTELLIGENCE_ENDPOINT"),
+            env::var("AZURE_DOCUMENT_INTELLIGENCE_KEY"),
+        ) {
+            (Ok(endpoint), Ok(api_key)) if !endpoint.is_empty() && !api_key.is_empty() => {
+                Some(AzureConfig { endpoint, api_key })
+            }
+            _ => None,
+        };
+
+        let embedding = env::var("EMBEDDING_BASE_URL")
+            .ok()
+            .filter(|url| !url.is_empty())
+            .map(|base_url| EmbeddingConfig {
+                base_url,
+                model: env::var("EMBEDDING_MODEL").unwrap_or_else(|_| "text-embedding-3-small".to_string()),
+                api_key: env::var("EMBEDDING_API_KEY").ok().filter(|k| !k.is_empty()),
+                max_concurrent_batches: env_or("EMBEDDING_MAX_CONCURRENT_BATCHES", 4),
+                max_batch_size: env_or("EMBEDDING_MAX_BATCH_SIZE", 64),
+                max_batch_tokens: env_or("EMBEDDING_MAX_BATCH_TOKENS", 8000),
+            });
+
+        let summarizer = env::var("SUMMARY_BASE_URL")
+            .ok()
+            .filter(|url| !url.is_empty())
+            .map(|base_url| SummarizerConfig {
+                base_url,
+                model: env::var("SUMMARY_MODEL").unwrap_or_else(|_| "gpt-4o-mini".to_string()),
+                api_key: env::var("SUMMARY_API_KEY").ok().filter(|k| !k.is_empty()),
+                max_input_tokens: env_or("SUMMARY_MAX_INPUT_TOKENS", 6000),
+            });
+
+        let ocr = env::var("OCR_URL").ok().filter(|url| !url.is_empty()).map(|url| OcrConfig {
+            url,
+            api_key: env::var("OCR_API_KEY").ok().filter(|k| !k.is_empty()),
+        });
+
+        Self {
+            cache,
+            azure,
+            embedding,
+            summarizer,
+            ocr,
+            network_disabled: env_flag("NETWORK_DISABLED"),
+            max_upload_bytes: env_or("MAX_UPLOAD_BYTES", DEFAULT_MAX_UPLOAD_BYTES),
+            max_decompressed_bytes: env_or("MAX_DECOMPRESSED_BYTES", DEFAULT_MAX_DECOMPRESSED_BYTES),
+            pdf_max_images_per_page: env_or("PDF_MAX_IMAGES_PER_PAGE", DEFAULT_MAX_IMAGES_PER_PAGE),
+            parse_timeout: Duration::from_secs(env_or("PARSE_TIMEOUT_SECS", DEFAULT_PARSE_TIMEOUT_SECS)),
+            max_concurrent_requests: env_or("MAX_CONCURRENT_REQUESTS", DEFAULT_MAX_CONCURRENT_REQUESTS),
+            api_key: ApiKey::new(env::var("API_KEY").ok()),
+            batch_deadline: Duration::from_secs(env_or("BATCH_DEADLINE_SECS", DEFAULT_BATCH_DEADLINE_SECS)),
+            shutdown_timeout: Duration::from_secs(env_or("SHUTDOWN_TIMEOUT_SECS", DEFAULT_SHUTDOWN_TIMEOUT_SECS)),
+            fetch: FetchPolicy {
+                allow_private_addresses: env_flag("FETCH_ALLOW_PRIVATE_ADDRESSES"),
+                pin_resolved_ip: env_or("FETCH_PIN_RESOLVED_IP", true),
+            },
+            parser_priority: env::var("PARSER_PRIORITY")
+                .map(|spec| parse_priority(&spec))
+                .unwrap_or_default(),
+            quality_rules: env::var("QUALITY_RULES")
+                .map(|spec| QualityRules::parse(&spec))
+                .unwrap_or_default(),
+            quality_fallback: env_flag("QUALITY_FALLBACK"),
+            escalate_min_chars: env_or("ESCALATE_MIN_CHARS", 0),
+            chunk_processors: ChunkProcessors::new(
+                env_flag("CHUNK_TRIM_WHITESPACE")
+                    .then(|| Box::new(TrimWhitespace) as Box<dyn ChunkProcessor>)
+                    .into_iter()
+                    .collect(),
+            ),
+            dead_letter: env::var("DEAD_LETTER_DIR")
+                .ok()
+                .filter(|dir| !dir.is_empty())
+                .map(|dir| DeadLetterConfig {
+                    dir: dir.into(),
+                    include_bytes: env_flag("DEAD_LETTER_INCLUDE_BYTES"),
+                }),
+        }
+    }
+
+    /// Azure settings, unless network access is disabled.
+    pub fn azure(&self) -> Option<&AzureConfig> {
+        self.azure.as_ref().filter(|_| !self.network_disabled)
+    }
+
+    /// Embeddings provider settings, unless network access is disabled.
+    pub fn embedding(&self) -> Option<&EmbeddingConfig> {
+        self.embedding.as_ref().filter(|_| !self.network_disabled)
+    }
+
+    /// Summaries provider settings, unless network access is disabled.
+    pub fn summarizer(&self) -> Option<&SummarizerConfig> {
+        self.summarizer.as_ref().filter(|_| !self.network_disabled)
+    }
+
+    /// OCR service settings, unless network access is disabled.
+    pub fn ocr(&self) -> Option<&OcrConfig> {
+        self.ocr.as_ref().filter(|_| !self.network_disabled)
+    }
+
+    /// URL ingestion policy, unless network access is disabled.
+    pub fn fetch(&self) -> Option<&FetchPolicy> {
+        Some(&self.fetch).filter(|_| !self.network_disabled)
+    }
+}
+
+fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+fn env_flag(key: &str) -> bool {
+    env::var(key)
+        .map(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+        .unwrap_or(false)
+}
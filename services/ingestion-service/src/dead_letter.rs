@@ -0,0 +1,97 @@
+// Dead-letter records of documents that failed batch ingestion, kept so
+// operators can reprocess them once the cause is fixed.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DeadLetterError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Serialization error: {0}")]
+    Serialize(#[from] serde_json::Error),
+}
+
+/// A failed document and why it failed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetterEntry {
+    /// Content hash of the document; also names the entry's files.
+    pub document_hash: String,
+    pub filename: String,
+    pub content_type: String,
+    pub size_bytes: usize,
+    pub error: String,
+    /// Unix time of the failure, in seconds.
+    pub failed_at: u64,
+    /// File next to the entry holding the document bytes, when kept.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes_file: Option<String>,
+}
+
+impl DeadLetterEntry {
+    pub fn new(filename: &str, content_type: &str, data: &[u8], error: &str) -> Self {
+        Self {
+            document_hash: blake3::hash(data).to_hex().to_string(),
+            filename: filename.to_string(),
+            content_type: content_type.to_string(),
+            size_bytes: data.len(),
+            error: error.to_string(),
+            failed_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+            bytes_file: None,
+        }
+    }
+}
+
+/// Destination for failed documents.
+#[async_trait]
+pub trait DeadLetterSink: Send + Sync {
+    /// Record `entry`; `data` is the document, which sinks may keep.
+    async fn record(&self, entry: DeadLetterEntry, data: &[u8]) -> Result<(), DeadLetterError>;
+}
+
+/// Where dead letters go, selected by `DEAD_LETTER_DIR`.
+#[derive(Debug, Clone)]
+pub struct DeadLetterConfig {
+    pub dir: PathBuf,
+    /// Keep the document bytes next to each entry (`DEAD_LETTER_INCLUDE_BYTES`).
+    pub include_bytes: bool,
+}
+
+impl DeadLetterConfig {
+    pub fn build(&self) -> Arc<dyn DeadLetterSink> {
+        Arc::new(DirectoryDeadLetters::new(self.dir.clone(), self.include_bytes))
+    }
+}
+
+/// Writes each entry as `<document_hash>.json`, plus `<document_hash>.bin`
+/// with the document when bytes are kept. A document that fails again
+/// replaces its earlier entry.
+pub struct DirectoryDeadLetters {
+    dir: PathBuf,
+    include_bytes: bool,
+}
+
+impl DirectoryDeadLetters {
+    pub fn new(dir: PathBuf, include_bytes: bool) -> Self {
+        Self { dir, include_bytes }
+    }
+}
+
+#[async_trait]
+impl DeadLetterSink for DirectoryDeadLetters {
+    async fn record(&self, mut entry: DeadLetterEntry, data: &[u8]) -> Result<(), DeadLetterError> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        if self.include_bytes {
+            let bytes_file = format!("{}.bin", entry.document_hash);
+            tokio::fs::write(self.dir.join(&bytes_file), data).await?;
+            entry.bytes_file = Some(bytes_file);
+        }
+        let json = serde_json::to_vec_pretty(&entry)?;
+        tokio::fs::write(self.dir.join(format!("{}.json", entry.document_hash)), json).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+// Embedder backed by an OpenAI-compatible `/embeddings` endpoint
+
+use async_trait::async_trait;
+use futures::{StreamExt, TryStreamExt};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use super::{plan_batches, EmbedError, Embedder, EmbeddingConfig};
+use crate::splitter::{load_bpe, TokenizerKind};
+
+#[derive(Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+/// Calls the provider in batches limited by count and token budget, with a
+/// bounded number of requests in flight.
+pub struct HttpEmbedder {
+    config: EmbeddingConfig,
+    client: Client,
+}
+
+impl HttpEmbedder {
+    pub fn new(config: EmbeddingConfig) -> Self {
+        Self {
+            config,
+            client: Client::new(),
+        }
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        let url = format!("{}/embeddings", self.config.base_url.trim_end_matches('/'));
+        let mut request = self.client.post(&url).json(&EmbeddingRequest {
+            model: &self.config.model,
+            input: texts,
+        });
+        if let Some(key) = &self.config.api_key {
+            request = request.bearer_auth(key);
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(EmbedError::Provider(format!("status {}", response.status())));
+        }
+        let mut data = response.json::<EmbeddingResponse>().await?.data;
+        if data.len() != texts.len() {
+            return Err(EmbedError::Provider(format!(
+                "expected {} embeddings, got {}",
+                texts.len(),
+                data.len()
+            )));
+        }
+        data.sort_by_key(|d| d.index);
+        Ok(data.into_iter().map(|d| d.embedding).collect())
+    }
+}
+
+#[async_trait]
+impl Embedder for HttpEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        let bpe = load_bpe(TokenizerKind::Cl100kBase)
+            .as_ref()
+            .map_err(|e| EmbedError::Provider(e.clone()))?;
+        let token_counts: Vec<usize> = texts
+            .iter()
+            .map(|t| bpe.encode_with_special_tokens(t).len())
+            .collect();
+        let batches = plan_batches(&token_counts, self.config.max_batch_size, self.config.max_batch_tokens);
+
+        let results: Vec<Vec<Vec<f32>>> = futures::stream::iter(batches)
+            .map(|range| self.embed_batch(&texts[range]))
+            .buffered(self.config.max_concurrent_batches.max(1))
+            .try_collect()
+            .await?;
+        Ok(results.into_iter().flatten().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State, routing::post, Json, Router};
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Recorder {
+        in_flight: AtomicUsize,
+        peak_in_flight: AtomicUsize,
+        batches: Mutex<Vec<Vec<String>>>,
+    }
+
+    async fn embeddings(State(recorder): State<Arc<Recorder>>, Json(body): Json<Value>) -> Json<Value> {
+        let now = recorder.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        recorder.peak_in_flight.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let input = body["input"].as_array().unwrap();
+        let batch = input.iter().map(|t| t.as_str().unwrap().to_string()).collect();
+        recorder.batches.lock().unwrap().push(batch);
+        let data: Vec<Value> = input
+            .iter()
+            .enumerate()
+            .map(|(i, text)| json!({ "index": i, "embedding": [text.as_str().unwrap().len() as f32, 1.0] }))
+            .collect();
+
+        recorder.in_flight.fetch_sub(1, Ordering::SeqCst);
+        Json(json!({ "data": data }))
+    }
+
+    #[tokio::test]
+    async fn test_batches_respect_size_token_and_concurrency_limits() {
+        let recorder = Arc::new(Recorder::default());
+        let app = Router::new()
+            .route("/v1/embeddings", post(embeddings))
+            .with_state(recorder.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let embedder = HttpEmbedder::new(EmbeddingConfig {
+            base_url: format!("http://{}/v1", addr),
+            model: "test-embedding".to_string(),
+            api_key: None,
+            max_concurrent_batches: 2,
+            max_batch_size: 8,
+            max_batch_tokens: 40,
+        });
+
+        // 40 short chunks (~5 tokens each) and a few long ones (~20 tokens)
+        let mut texts: Vec<String> = (0..40).map(|i| format!("chunk number {} text", i)).collect();
+        for i in [5, 17, 30] {
+            texts[i] = "word ".repeat(20);
+        }
+        let vectors = embedder.embed(&texts).await.unwrap();
+
+        assert_eq!(vectors.len(), texts.len());
+        for (text, vector) in texts.iter().zip(&vectors) {
+            assert_eq!(vector[0], text.len() as f32);
+        }
+
+        let bpe = load_bpe(TokenizerKind::Cl100kBase).as_ref().unwrap();
+        let batches = recorder.batches.lock().unwrap().clone();
+        assert_eq!(batches.iter().map(Vec::len).sum::<usize>(), texts.len());
+        for batch in &batches {
+            let tokens: usize = batch.iter().map(|t| bpe.encode_with_special_tokens(t).len()).sum();
+            assert!(batch.len() <= 8);
+            assert!(tokens <= 40, "batch of {} tokens", tokens);
+        }
+        assert!(recorder.peak_in_flight.load(Ordering::SeqCst) <= 2);
+    }
+}
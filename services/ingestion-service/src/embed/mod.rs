@@ -0,0 +1,73 @@
+mod http;
+
+pub use http::HttpEmbedder;
+
+use async_trait::async_trait;
+use std::ops::Range;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum EmbedError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Embedding provider error: {0}")]
+    Provider(String),
+}
+
+/// Turns chunk texts into embedding vectors, one per input text, in order.
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, EmbedError>;
+}
+
+/// Connection and throttling settings for an OpenAI-compatible embeddings API.
+#[derive(Debug, Clone)]
+pub struct EmbeddingConfig {
+    /// API root, e.g. `https://api.openai.com/v1`; requests go to `{base_url}/embeddings`.
+    pub base_url: String,
+    pub model: String,
+    pub api_key: Option<String>,
+    /// Batches in flight at once.
+    pub max_concurrent_batches: usize,
+    /// Texts per request.
+    pub max_batch_size: usize,
+    /// Provider limit on input tokens per request.
+    pub max_batch_tokens: usize,
+}
+
+/// Group consecutive texts into batches honouring both the size and token
+/// budgets. A single text over the token budget is sent on its own.
+pub(crate) fn plan_batches(token_counts: &[usize], max_size: usize, max_tokens: usize) -> Vec<Range<usize>> {
+    let max_size = max_size.max(1);
+    let mut batches = Vec::new();
+    let mut start = 0;
+    let mut tokens = 0;
+
+    for (i, &count) in token_counts.iter().enumerate() {
+        let full = i - start >= max_size || (i > start && tokens + count > max_tokens);
+        if full {
+            batches.push(start..i);
+            start = i;
+            tokens = 0;
+        }
+        tokens += count;
+    }
+    if start < token_counts.len() {
+        batches.push(start..token_counts.len());
+    }
+    batches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_batches_respects_size_and_tokens() {
+        assert_eq!(plan_batches(&[1; 5], 2, 100), vec![0..2, 2..4, 4..5]);
+        assert_eq!(plan_batches(&[40, 40, 40, 10], 10, 100), vec![0..2, 2..4]);
+        // An oversized text still gets sent, alone
+        assert_eq!(plan_batches(&[10, 500, 10], 10, 100), vec![0..1, 1..2, 2..3]);
+        assert!(plan_batches(&[], 10, 100).is_empty());
+    }
+}
@@ -0,0 +1,223 @@
+// Guarded fetching of remote documents for URL ingestion.
+//
+// URLs are canonicalized before any SSRF check so alternative spellings of an
+// address can't slip past it, and hosts are checked again after DNS
+// resolution, against the exact addresses the request will connect to.
+
+use reqwest::{redirect, Client, Url};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum FetchError {
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+    #[error("Unsupported URL scheme: {0}")]
+    UnsupportedScheme(String),
+    #[error("URLs with embedded credentials are not allowed")]
+    EmbeddedCredentials,
+    #[error("Unusual IP address encoding: {0}")]
+    UnusualIpEncoding(String),
+    #[error("Address {0} is not publicly routable")]
+    PrivateAddress(IpAddr),
+    #[error("Could not resolve {0}")]
+    Resolve(String),
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+}
+
+/// SSRF protections applied to URL ingestion.
+#[derive(Debug, Clone)]
+pub struct FetchPolicy {
+    /// Permit loopback/private targets (local development and tests only).
+    pub allow_private_addresses: bool,
+    /// Connect to the address that passed the check instead of resolving the
+    /// host again, closing the DNS-rebinding window.
+    pub pin_resolved_ip: bool,
+}
+
+impl Default for FetchPolicy {
+    fn default() -> Self {
+        Self {
+            allow_private_addresses: false,
+            pin_resolved_ip: true,
+        }
+    }
+}
+
+impl FetchPolicy {
+    /// Parse `raw` into a canonical URL, rejecting spellings commonly used to
+    /// disguise internal targets. IP literals are checked here; hostnames are
+    /// checked by [`FetchPolicy::resolve`].
+    pub fn canonicalize(&self, raw: &str) -> Result<Url, FetchError> {
+        let raw = raw.trim();
+        let (scheme, rest) = raw
+            .split_once("://")
+            .ok_or_else(|| FetchError::InvalidUrl(raw.to_string()))?;
+        if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+            return Err(FetchError::UnsupportedScheme(scheme.to_string()));
+        }
+
+        // Inspect the authority as written, before the URL parser normalizes it
+        let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+        if authority.contains('@') {
+            return Err(FetchError::EmbeddedCredentials);
+        }
+        if authority.contains('\\') || authority.contains('%') {
+            return Err(FetchError::InvalidUrl(raw.to_string()));
+        }
+        let host = if authority.starts_with('[') {
+            authority.split_inclusive(']').next().unwrap_or_default()
+        } else {
+            authority.rsplit_once(':').map_or(authority, |(host, _)| host)
+        };
+        if looks_numeric(host) && host.parse::<Ipv4Addr>().is_err() {
+            return Err(FetchError::UnusualIpEncoding(host.to_string()));
+        }
+
+        let url = Url::parse(raw).map_err(|e| FetchError::InvalidUrl(e.to_string()))?;
+        if url.host_str().is_none_or(str::is_empty) {
+            return Err(FetchError::InvalidUrl(raw.to_string()));
+        }
+        if let Some(url::Host::Ipv4(ip)) = url.host() {
+            self.check_ip(IpAddr::V4(ip))?;
+        }
+        if let Some(url::Host::Ipv6(ip)) = url.host() {
+            self.check_ip(IpAddr::V6(ip))?;
+        }
+        Ok(url)
+    }
+
+    /// Resolve the URL's host and check every address it maps to.
+    pub async fn resolve(&self, url: &Url) -> Result<SocketAddr, FetchError> {
+        let host = url.host_str().ok_or_else(|| FetchError::InvalidUrl(url.to_string()))?;
+        let port = url.port_or_known_default().unwrap_or(80);
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|_| FetchError::Resolve(host.to_string()))?
+            .collect();
+        for addr in &addrs {
+            self.check_ip(addr.ip())?;
+        }
+        addrs.first().copied().ok_or_else(|| FetchError::Resolve(host.to_string()))
+    }
+
+    /// Canonicalize and resolve `raw`, returning the URL and an HTTP client
+    /// that will only connect to the checked address. Redirects are disabled
+    /// since their targets would bypass the check.
+    pub async fn prepare(&self, raw: &str, base: reqwest::ClientBuilder) -> Result<(Url, Client), FetchError> {
+        let url = self.canonicalize(raw)?;
+        let addr = self.resolve(&url).await?;
+        let mut builder = base.redirect(redirect::Policy::none());
+        if self.pin_resolved_ip {
+            if let Some(host) = url.host_str() {
+                builder = builder.resolve(host, addr);
+            }
+        }
+        Ok((url, builder.build()?))
+    }
+
+    fn check_ip(&self, ip: IpAddr) -> Result<(), FetchError> {
+        if self.allow_private_addresses || is_public(ip) {
+            Ok(())
+        } else {
+            Err(FetchError::PrivateAddress(ip))
+        }
+    }
+}
+
+/// Whether a host is made of number-like labels (decimal, octal or hex), which
+/// URL parsers interpret as an IPv4 address.
+fn looks_numeric(host: &str) -> bool {
+    let last = host.trim_end_matches('.').rsplit('.').next().unwrap_or_default();
+    !last.is_empty()
+        && (last.chars().all(|c| c.is_ascii_digit())
+            || (last.len() > 2
+                && last[..2].eq_ignore_ascii_case("0x")
+                && last[2..].chars().all(|c| c.is_ascii_hexdigit())))
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(v4) => is_public_v4(v4),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || a == 0
+        // Carrier-grade NAT 100.64.0.0/10
+        || (a == 100 && (64..128).contains(&b))
+        // Benchmarking 198.18.0.0/15
+        || (a == 198 && (b == 18 || b == 19))
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // Unique local fc00::/7 and link-local fe80::/10
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejects_disguised_local_addresses() {
+        let policy = FetchPolicy::default();
+
+        for url in ["http://2130706433/", "http://0x7f000001/", "http://0177.0.0.1/", "http://127.1/"] {
+            assert!(
+                matches!(policy.canonicalize(url), Err(FetchError::UnusualIpEncoding(_))),
+                "{} accepted",
+                url
+            );
+        }
+        assert!(matches!(
+            policy.canonicalize("http://example.com@127.0.0.1/admin"),
+            Err(FetchError::EmbeddedCredentials)
+        ));
+        assert!(matches!(
+            policy.canonicalize("http://169.254.169.254/latest/meta-data"),
+            Err(FetchError::PrivateAddress(_))
+        ));
+        assert!(matches!(policy.canonicalize("http://[::ffff:10.0.0.1]/"), Err(FetchError::PrivateAddress(_))));
+        assert!(matches!(policy.canonicalize("file:///etc/passwd"), Err(FetchError::UnsupportedScheme(_))));
+
+        assert_eq!(
+            policy.canonicalize("HTTPS://Example.COM/docs/a.pdf").unwrap().as_str(),
+            "https://example.com/docs/a.pdf"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejects_hostname_resolving_to_private_ip() {
+        let policy = FetchPolicy::default();
+        let url = policy.canonicalize("http://localhost:8004/internal").unwrap();
+        assert!(matches!(policy.resolve(&url).await, Err(FetchError::PrivateAddress(_))));
+
+        let permissive = FetchPolicy {
+            allow_private_addresses: true,
+            ..Default::default()
+        };
+        assert!(permissive.prepare("http://localhost:8004/", Client::builder()).await.is_ok());
+    }
+}
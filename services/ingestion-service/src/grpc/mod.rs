@@ -1,8 +1,36 @@
-use std::io::Cursor;
+// tonic::Status is large, but it's the error type the generated service traits require
+#![allow(clippy::result_large_err)]
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, OwnedSemaphorePermit, Semaphore};
+use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Request, Response, Status};
+use tower::Layer;
 
-use crate::parser::{LocalPdfParser, Parser};
-use crate::splitter::{SentenceTextSplitter, TextSplitter};
+use crate::auth::RequireGrpcApiKey;
+use crate::config::Config;
+use crate::embed::{Embedder, HttpEmbedder};
+use crate::health::Health;
+use crate::language;
+use crate::parser::{
+    check_text_amount, detect_format, for_content_type_with, parse_with_timeout, sniff_content_type, unpack,
+    ArchiveError, AzureDocIntelligenceParser, DocumentInfo, DocxParser, FootnoteMarkers, Heading, HtmlParser, HttpOcr,
+    Image, LocalPdfParser, OcrEngine, Page, Parser, ParserError, ParserRegistry, QualityWarning, Unpacked,
+    DEFAULT_PAGE_CHARS, DEFAULT_SECTION_SELECTORS,
+};
+use crate::splitter::{
+    fingerprint_chunks, order_chunks, prepend_heading, prepend_headings, simhash, structure_tree, Chunk, ChunkOrder,
+    ChunkProcessor, ChunkProcessors, RedactionProcessor, StructureNode,
+};
+use crate::splitter::{
+    OverlapAlign, RecursiveCharacterTextSplitter, SentenceTextSplitter, SplitterKind, TextSplitter, TokenizerKind,
+};
+use crate::summarize::{HttpSummarizer, Summarizer};
+use crate::telemetry::{self, UploadFingerprint};
 
 pub mod proto {
     tonic::include_proto!("keiko.ingestion.v1");
@@ -10,13 +38,56 @@ pub mod proto {
 
 use proto::ingestion_service_server::{IngestionService, IngestionServiceServer};
 use proto::{
-    Chunk as ProtoChunk, DocumentMetadata, GetSupportedFormatsRequest,
+    AppliedConfig, Chunk as ProtoChunk, DocumentMetadata, Image as ProtoImage, GetSupportedFormatsRequest,
     GetSupportedFormatsResponse, HealthCheckRequest, HealthCheckResponse,
-    ParseDocumentRequest, ParseDocumentResponse, ProcessingStats,
+    LanguageSpan as ProtoLanguageSpan, OutlineEntry, PageImageCount, PageSourceRange, PageSpan as ProtoPageSpan,
+    ParseDocumentRequest, ParseDocumentResponse, ParseOptions, ParserSelection, ProcessingStats,
+    QualityWarning as ProtoQualityWarning, StructureNode as ProtoStructureNode,
 };
 
-#[derive(Default)]
-pub struct IngestionServiceImpl;
+/// Output of parsing and splitting one document.
+struct ProcessedDocument {
+    chunks: Vec<Chunk>,
+    page_count: usize,
+    parser_used: &'static str,
+    info: DocumentInfo,
+    outline: Vec<OutlineEntry>,
+    page_source_ranges: Vec<PageSourceRange>,
+    structure_tree: Option<StructureNode>,
+    quality_warnings: Vec<QualityWarning>,
+    /// Page text to summarize, when a summary was requested.
+    summary_input: Option<String>,
+    applied_config: Option<AppliedConfig>,
+    /// Images the chunks refer to, when requested.
+    images: Vec<Image>,
+    /// How many of `images` each page holds, for pages with any.
+    page_images: Vec<PageImageCount>,
+    /// Personal data replaced in the chunks, when redaction was requested.
+    redactions: usize,
+}
+
+/// Chunks buffered ahead of a slow stream consumer.
+const STREAM_BUFFER: usize = 32;
+
+pub struct IngestionServiceImpl {
+    config: Config,
+    registry: ParserRegistry,
+    azure_poll_interval: Duration,
+    summarizer: Option<Arc<dyn Summarizer>>,
+    /// Fills in chunk embeddings, when configured and network access is allowed.
+    embedder: Option<Arc<dyn Embedder>>,
+    /// Reads scanned PDF pages, when configured and network access is allowed.
+    ocr: Option<Arc<dyn OcrEngine>>,
+    /// One permit per document request in progress.
+    permits: Arc<Semaphore>,
+    health: Health,
+}
+
+impl Default for IngestionServiceImpl {
+    fn default() -> Self {
+        Self::new(Config::default())
+    }
+}
 
 #[tonic::async_trait]
 impl IngestionService for IngestionServiceImpl {
@@ -24,64 +95,184 @@ impl IngestionService for IngestionServiceImpl {
         &self,
         request: Request<ParseDocumentRequest>,
     ) -> Result<Response<ParseDocumentResponse>, Status> {
-        let start = std::time::Instant::now();
-        let req = request.into_inner();
+        let start = Instant::now();
+        let _permit = self.admit()?;
+        let req = self.unpack_request(request.into_inner()).await?;
+        check_token_budget(req.options.as_ref())?;
+        telemetry::record_request("grpc");
 
-        let (chunks, page_count, _doc_len) = process_document(&req)?;
-        let total_tokens: usize = chunks.iter().map(|c| c.token_count).sum();
+        let ProcessedDocument {
+            chunks,
+            page_count,
+            parser_used,
+            info,
+            outline,
+            page_source_ranges,
+            structure_tree,
+            quality_warnings,
+            summary_input,
+            applied_config,
+            images,
+            page_images,
+            redactions,
+        } = self.process_document(&req).await?;
+        let summary = match (&self.summarizer, summary_input) {
+            (Some(summarizer), Some(text)) => match summarizer.summarize(&text).await {
+                Ok(summary) => redact_summary(req.options.as_ref().is_some_and(|o| o.redact_pii), summary),
+                Err(e) => {
+                    tracing::warn!("Summary for {} failed: {}", req.filename, e);
+                    String::new()
+                }
+            },
+            _ => String::new(),
+        };
+        let stats_only = req.options.as_ref().is_some_and(|options| options.stats_only);
+        let generate_embeddings = req.options.as_ref().is_some_and(|options| options.generate_embeddings);
+        let embeddings = match (&self.embedder, generate_embeddings && !stats_only) {
+            (Some(embedder), true) => {
+                let texts: Vec<String> = chunks
+                    .iter()
+                    .map(|c| c.embed_text.clone().unwrap_or_else(|| c.text.clone()))
+                    .collect();
+                embedder
+                    .embed(&texts)
+                    .await
+                    .map_err(|e| Status::unavailable(format!("Embedding {} failed: {}", req.filename, e)))?
+            }
+            _ => Vec::new(),
+        };
+        let (total_chunks, total_tokens) = (chunks.len(), chunks.iter().map(|c| c.token_count).sum::<usize>());
 
-        let proto_chunks: Vec<ProtoChunk> = chunks
-            .iter()
-            .cloned()
-            .map(map_chunk_to_proto)
-            .collect();
+        let mut proto_chunks: Vec<ProtoChunk> = if stats_only {
+            Vec::new()
+        } else {
+            chunks.into_iter().map(map_chunk_to_proto).collect()
+        };
+        for (chunk, embedding) in proto_chunks.iter_mut().zip(embeddings) {
+            chunk.embedding = embedding;
+        }
+        attach_image_data(&mut proto_chunks, &images, &mut HashSet::new());
 
         Ok(Response::new(ParseDocumentResponse {
-            chunks: proto_chunks.clone(),
+            chunks: proto_chunks,
             metadata: Some(DocumentMetadata {
                 filename: req.filename,
                 content_type: req.content_type,
                 size_bytes: req.content.len() as i64,
                 page_count: page_count as i32,
-                title: String::new(),
-                author: String::new(),
-                created_at: String::new(),
+                title: info.title.unwrap_or_default(),
+                author: info.author.unwrap_or_default(),
+                created_at: info.created_at.unwrap_or_default(),
+                modified_at: info.modified_at.unwrap_or_default(),
+                revision: info.revision.unwrap_or_default() as i32,
+                last_modified_by: info.last_modified_by.unwrap_or_default(),
+                outline,
+                key_values: info.key_values.into_iter().collect(),
+                page_source_ranges,
+                summary,
+                quality_warnings: quality_warnings
+                    .into_iter()
+                    .map(|w| ProtoQualityWarning {
+                        page_num: w.page_num as i32,
+                        parser: w.parser,
+                        reason: w.reason,
+                    })
+                    .collect(),
             }),
             stats: Some(ProcessingStats {
                 processing_time_ms: start.elapsed().as_millis() as i64,
-                total_chunks: proto_chunks.len() as i32,
+                total_chunks: total_chunks as i32,
                 total_tokens: total_tokens as i32,
-                total_images: 0,
-                parser_used: "LocalPdfParser".to_string(),
+                total_images: images.len() as i32,
+                parser_used: parser_used.to_string(),
+                redactions: redactions as i32,
+                page_images,
             }),
+            structure_tree: structure_tree.map(map_structure_to_proto),
+            applied_config,
         }))
     }
 
-    type ParseDocumentStreamStream = futures::stream::Iter<std::vec::IntoIter<Result<ProtoChunk, Status>>>;
+    type ParseDocumentStreamStream = ReceiverStream<Result<ProtoChunk, Status>>;
 
     async fn parse_document_stream(
         &self,
         request: Request<ParseDocumentRequest>,
     ) -> Result<Response<Self::ParseDocumentStreamStream>, Status> {
-        let req = request.into_inner();
-        let (chunks, _, _) = process_document(&req)?;
+        // Held by the task sending the chunks, until the stream ends
+        let permit = self.admit()?;
+        let req = self.unpack_request(request.into_inner()).await?;
+        check_token_budget(req.options.as_ref())?;
+        telemetry::record_request("grpc");
+        let options = req.options.clone().unwrap_or_default();
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
 
-        let proto_chunks: Vec<Result<ProtoChunk, Status>> = chunks
-            .into_iter()
-            .map(|c| Ok(map_chunk_to_proto(c)))
-            .collect();
+        // Merging across page breaks and importance ordering need the whole
+        // document, so only split page by page when they are all off
+        let page_by_page = !options.cross_page_merge
+            && !options.merge_pages
+            && ChunkOrder::parse(&options.order) == ChunkOrder::Document;
+        let azure = self.azure_parser(&req, &options)?;
+        if let Some(parser) = azure.filter(|_| page_by_page) {
+            // Azure returns every page in one response; split and send each
+            // page as it is converted instead of assembling the whole document
+            let timeout = self.config.parse_timeout;
+            let pages = tokio::time::timeout(timeout, parser.analyze_pages(&req.content))
+                .await
+                .unwrap_or(Err(ParserError::Timeout(timeout)))
+                .map_err(|e| {
+                    telemetry::record_error("grpc", &e);
+                    parse_status(&e)
+                })?;
+            let page_total = pages.size_hint().1.unwrap_or(1);
+            let upload = UploadFingerprint::new(&req.content, &req.filename, &req.content_type);
+            let processors = self.config.chunk_processors.clone();
+            let mut chunker = PageChunker::new(&options, processors, upload, "AzureDocIntelligenceParser", page_total);
+            tokio::spawn(async move {
+                let _permit = permit;
+                for (page_i, page) in pages.enumerate() {
+                    for chunk in chunker.split(page_i, page) {
+                        if tx.send(Ok(chunk)).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                chunker.finish();
+            });
+        } else if page_by_page && !self.config.quality_fallback && self.config.escalate_min_chars == 0 {
+            // Local parsers that build pages incrementally let the first
+            // chunks go out while later pages are still being extracted.
+            // A fallback re-parse needs every page checked first.
+            let parser = local_parser(&req, &options, self.config.pdf_max_images_per_page, self.ocr.clone())?;
+            let upload = UploadFingerprint::new(&req.content, &req.filename, &req.content_type);
+            let processors = self.config.chunk_processors.clone();
+            let chunker = move |parser, page_total| PageChunker::new(&options, processors, upload, parser, page_total);
+            stream_pages(parser, req.content, chunker, self.config.parse_timeout, tx, permit);
+        } else {
+            let processed = self.process_document(&req).await?;
+            tokio::spawn(async move {
+                let _permit = permit;
+                let mut sent_images = HashSet::new();
+                for chunk in processed.chunks {
+                    let mut chunk = map_chunk_to_proto(chunk);
+                    attach_image_data(std::slice::from_mut(&mut chunk), &processed.images, &mut sent_images);
+                    if tx.send(Ok(chunk)).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
 
-        Ok(Response::new(futures::stream::iter(proto_chunks)))
+        Ok(Response::new(ReceiverStream::new(rx)))
     }
 
     async fn get_supported_formats(
         &self,
         _request: Request<GetSupportedFormatsRequest>,
     ) -> Result<Response<GetSupportedFormatsResponse>, Status> {
-        let parser = LocalPdfParser::new();
         Ok(Response::new(GetSupportedFormatsResponse {
-            extensions: parser.supported_extensions().iter().map(|s| s.to_string()).collect(),
-            mime_types: parser.supported_mime_types().iter().map(|s| s.to_string()).collect(),
+            extensions: self.registry.extensions(),
+            mime_types: self.registry.mime_types(),
         }))
     }
 
@@ -89,51 +280,1728 @@ impl IngestionService for IngestionServiceImpl {
         &self,
         _request: Request<HealthCheckRequest>,
     ) -> Result<Response<HealthCheckResponse>, Status> {
+        let health = self.health.check().await;
         Ok(Response::new(HealthCheckResponse {
-            status: "healthy".to_string(),
+            status: health.status().to_string(),
             version: "0.1.0".to_string(),
-            uptime_seconds: 0,
+            uptime_seconds: health.uptime.as_secs() as i64,
+            reason: health.degraded_reason.unwrap_or_default(),
         }))
     }
 }
 
-fn process_document(req: &ParseDocumentRequest) -> Result<(Vec<crate::splitter::Chunk>, usize, usize), Status> {
-    let parser = LocalPdfParser::new();
-    let pages = parser
-        .parse(Cursor::new(&req.content))
-        .map_err(|e| Status::invalid_argument(e.to_string()))?;
+impl IngestionServiceImpl {
+    pub fn new(config: Config) -> Self {
+        Self {
+            registry: ParserRegistry::from_config(&config),
+            summarizer: config
+                .summarizer()
+                .map(|summarizer| Arc::new(HttpSummarizer::new(summarizer.clone())) as Arc<dyn Summarizer>),
+            embedder: config
+                .embedding()
+                .map(|embedding| Arc::new(HttpEmbedder::new(embedding.clone())) as Arc<dyn Embedder>),
+            ocr: config.ocr().map(|ocr| Arc::new(HttpOcr::new(ocr.clone())) as Arc<dyn OcrEngine>),
+            permits: Arc::new(Semaphore::new(config.max_concurrent_requests)),
+            config,
+            azure_poll_interval: Duration::from_secs(2),
+            health: Health::new(),
+        }
+    }
+
+    /// Interval between polls for Azure analysis results.
+    pub fn with_azure_poll_interval(mut self, interval: Duration) -> Self {
+        self.azure_poll_interval = interval;
+        self
+    }
+
+    /// A permit to process one document, or `RESOURCE_EXHAUSTED` while
+    /// `max_concurrent_requests` are already in progress.
+    fn admit(&self) -> Result<OwnedSemaphorePermit, Status> {
+        self.permits
+            .clone()
+            .try_acquire_owned()
+            .map_err(|_| Status::resource_exhausted("Too many requests in progress, try again later"))
+    }
+
+    /// The request with its document decompressed, when it was sent gzipped
+    /// or as a zip archive, off the async workers. A response covers one
+    /// document, so archives of several are rejected.
+    async fn unpack_request(&self, req: ParseDocumentRequest) -> Result<ParseDocumentRequest, Status> {
+        let max_bytes = self.config.max_decompressed_bytes;
+        tokio::task::spawn_blocking(move || unpack_document(req, max_bytes))
+            .await
+            .map_err(|e| Status::internal(format!("Decompression failed: {}", e)))?
+    }
+
+    /// The Azure parser, when the request selects it or the registry does for
+    /// this request, and it is configured.
+    fn azure_parser(
+        &self,
+        req: &ParseDocumentRequest,
+        options: &ParseOptions,
+    ) -> Result<Option<AzureDocIntelligenceParser>, Status> {
+        let selection = options.parser();
+        let required = selection == ParserSelection::Azure || options.use_document_intelligence;
+        if required && self.config.network_disabled {
+            let error = ParserError::NetworkDisabled("Azure Document Intelligence".to_string());
+            telemetry::record_error("grpc", &error);
+            return Err(Status::failed_precondition(error.to_string()));
+        }
+        match selection {
+            ParserSelection::Auto => {}
+            ParserSelection::Azure => {
+                return self
+                    .build_azure(options)
+                    .map(Some)
+                    .ok_or_else(|| Status::failed_precondition("Azure Document Intelligence is not configured"));
+            }
+            _ => return Ok(None),
+        }
+
+        let preferred = options.use_document_intelligence.then_some("AzureDocIntelligenceParser");
+        let selected = self.registry.select(declared_mime(req), preferred);
+        let parser = if selected == Some("AzureDocIntelligenceParser") {
+            self.build_azure(options)
+        } else {
+            None
+        };
+        if parser.is_none() && options.use_document_intelligence {
+            tracing::warn!("Azure Document Intelligence requested but unavailable for this document, using local parser");
+        }
+        Ok(parser)
+    }
+
+    /// The parser to retry with after `failed` produced corrupt-looking or
+    /// too little text. Azure is the only second opinion available for
+    /// formats the local parsers handle. A parser the request selected is
+    /// not second-guessed.
+    fn fallback_parser(
+        &self,
+        req: &ParseDocumentRequest,
+        options: &ParseOptions,
+        failed: &str,
+    ) -> Option<AzureDocIntelligenceParser> {
+        if options.parser() != ParserSelection::Auto {
+            return None;
+        }
+        self.registry
+            .fallbacks(declared_mime(req), failed)
+            .contains(&"AzureDocIntelligenceParser")
+            .then(|| self.build_azure(options))
+            .flatten()
+    }
+
+    fn build_azure(&self, options: &ParseOptions) -> Option<AzureDocIntelligenceParser> {
+        self.config.azure().map(|azure| {
+            AzureDocIntelligenceParser::new(azure.endpoint.clone(), azure.api_key.clone())
+                .with_poll_interval(self.azure_poll_interval)
+                .with_key_value_pairs(options.key_value_pairs, options.inject_key_values)
+        })
+    }
+
+    /// Parse and split a document.
+    async fn process_document(&self, req: &ParseDocumentRequest) -> Result<ProcessedDocument, Status> {
+        let start = Instant::now();
+        let options = req.options.as_ref().cloned().unwrap_or_default();
+        let (parsed, mut parser_used) = if let Some(parser) = self.azure_parser(req, &options)? {
+            (parser.parse_with_info(&req.content).await, "AzureDocIntelligenceParser")
+        } else {
+            let parser = local_parser(req, &options, self.config.pdf_max_images_per_page, self.ocr.clone())?;
+            let name = parser.name();
+            (parse_with_timeout(parser, req.content.clone().into(), self.config.parse_timeout).await, name)
+        };
+        // A scan without text layer or OCR is the sparsest document of all
+        let parsed = match parsed {
+            Err(e @ ParserError::NoExtractableText(_)) => match self.fallback_parser(req, &options, parser_used) {
+                Some(parser) => {
+                    tracing::warn!("{} for {} with {}, escalating", e, req.filename, parser_used);
+                    parser_used = "AzureDocIntelligenceParser";
+                    parser.parse_with_info(&req.content).await
+                }
+                None => Err(e),
+            },
+            parsed => parsed,
+        };
+        let (mut pages, mut info) = parsed.map_err(|e| {
+            telemetry::record_error("grpc", &e);
+            parse_status(&e)
+        })?;
+        let mut quality_warnings = self.config.quality_rules.check(declared_mime(req), parser_used, &pages);
+        let garbled = !quality_warnings.is_empty();
+        if garbled {
+            tracing::warn!(
+                "{} produced {} corrupt-looking pages for {}",
+                parser_used,
+                quality_warnings.len(),
+                req.filename
+            );
+        }
+        // Too little text escalates even without quality fallback
+        let sparse = check_text_amount(parser_used, &pages, self.config.escalate_min_chars);
+        if let Some(warning) = &sparse {
+            tracing::warn!("{} for {} with {}", warning.reason, req.filename, parser_used);
+        }
+        quality_warnings.extend(sparse.iter().cloned());
+        if (garbled && self.config.quality_fallback) || sparse.is_some() {
+            if let Some(parser) = self.fallback_parser(req, &options, parser_used) {
+                match parser.parse_with_info(&req.content).await {
+                    Ok((fallback_pages, fallback_info)) => {
+                        if sparse.is_some() {
+                            let warning = quality_warnings.last_mut().unwrap();
+                            warning.reason = format!("{}, escalated to AzureDocIntelligenceParser", warning.reason);
+                        }
+                        parser_used = "AzureDocIntelligenceParser";
+                        quality_warnings.extend(self.config.quality_rules.check(
+                            declared_mime(req),
+                            parser_used,
+                            &fallback_pages,
+                        ));
+                        (pages, info) = (fallback_pages, fallback_info);
+                    }
+                    Err(e) => tracing::warn!("Fallback parse of {} failed: {}", req.filename, e),
+                }
+            }
+        }
+        FootnoteMarkers::parse(&options.handle_footnote_markers).apply(parser_used, &mut pages);
+        if options.detect_language.unwrap_or(true) {
+            language::detect_pages(&mut pages);
+        }
+        if !options.extract_images {
+            pages.iter_mut().for_each(|page| page.images.clear());
+        }
+
+        let splitter = splitter_for(&options, &req.filename);
+        let mut chunks = splitter.split(&pages);
+        order_chunks(&mut chunks, ChunkOrder::parse(&options.order));
+        if options.simhash {
+            fingerprint_chunks(&mut chunks);
+        }
+        if options.prepend_heading {
+            prepend_headings(&mut chunks, TokenizerKind::parse(&options.tokenizer));
+        }
+        // Redact before custom processors see the text
+        let redactor = options.redact_pii.then(|| RedactionProcessor::new(TokenizerKind::parse(&options.tokenizer)));
+        if let Some(redactor) = &redactor {
+            redactor.process_all(&mut chunks);
+        }
+        self.config.chunk_processors.process_all(&mut chunks);
+        let tokens = chunks.iter().map(|c| c.token_count).sum();
+        telemetry::record_parse("grpc", parser_used, start.elapsed(), chunks.len(), tokens);
+        let upload = UploadFingerprint::new(&req.content, &req.filename, &req.content_type);
+        telemetry::log_parse("grpc", &upload, parser_used, pages.len(), chunks.len(), start.elapsed());
+        let structure_tree = options.structure_tree.then(|| structure_tree(&pages, &chunks));
+
+        let outline = pages
+            .iter()
+            .flat_map(|page| {
+                page.headings.iter().map(|h| OutlineEntry {
+                    level: h.level as i32,
+                    title: h.text.clone(),
+                    page_num: page.page_num as i32,
+                })
+            })
+            .collect();
+        let page_source_ranges = pages
+            .iter()
+            .filter(|_| options.page_source_ranges)
+            .filter_map(|page| {
+                Some(PageSourceRange {
+                    page_num: page.page_num as i32,
+                    source_start: page.source_start? as i64,
+                    source_end: page.source_end? as i64,
+                })
+            })
+            .collect();
+
+        let applied_config = options.echo_config.then(|| {
+            let summarized = options.summarize && self.summarizer.is_some();
+            applied_config(&options, parser_used, splitter.as_ref(), summarized)
+        });
+
+        Ok(ProcessedDocument {
+            chunks,
+            page_count: pages.len(),
+            parser_used,
+            info,
+            outline,
+            page_source_ranges,
+            structure_tree,
+            quality_warnings,
+            summary_input: options.summarize.then(|| {
+                let text = pages.iter().map(|page| page.text.as_str()).collect::<Vec<_>>().join("\n\n");
+                redact_summary(options.redact_pii, text)
+            }),
+            applied_config,
+            page_images: pages
+                .iter()
+                .filter(|page| !page.images.is_empty())
+                .map(|page| PageImageCount {
+                    page_num: page.page_num as i32,
+                    count: page.images.len() as i32,
+                })
+                .collect(),
+            images: pages.into_iter().flat_map(|page| page.images).collect(),
+            redactions: redactor.map_or(0, |redactor| redactor.redactions()),
+        })
+    }
+}
+
+fn unpack_document(req: ParseDocumentRequest, max_bytes: usize) -> Result<ParseDocumentRequest, Status> {
+    let files = match unpack(&req.filename, &req.content, max_bytes) {
+        None => return Ok(req),
+        Some(Ok(files)) => files,
+        Some(Err(e @ ArchiveError::TooLarge(..))) => return Err(Status::resource_exhausted(e.to_string())),
+        Some(Err(e)) => return Err(Status::invalid_argument(e.to_string())),
+    };
+    let Ok([file]) = <[Unpacked; 1]>::try_from(files) else {
+        return Err(Status::invalid_argument(format!(
+            "{} must hold exactly one document",
+            req.filename
+        )));
+    };
+    Ok(ParseDocumentRequest {
+        content_type: detect_format(&file.data).unwrap_or("application/octet-stream").to_string(),
+        filename: file.filename,
+        content: file.data,
+        ..req
+    })
+}
+
+/// The request's MIME type, sniffed from the content when the declared type
+/// is missing or generic. Undetected documents without a declared type have
+/// always been treated as PDFs.
+fn declared_mime(req: &ParseDocumentRequest) -> &str {
+    match sniff_content_type(&req.content_type, &req.content) {
+        Some(detected) => detected,
+        None if req.content_type.is_empty() => "application/pdf",
+        None => req.content_type.as_str(),
+    }
+}
+
+/// The local parser the request selects, or else the one for its format,
+/// reading up to `max_images` images per PDF page, kept when the request
+/// asks for them, and scanned pages with `ocr`.
+fn local_parser(
+    req: &ParseDocumentRequest,
+    options: &ParseOptions,
+    max_images: usize,
+    ocr: Option<Arc<dyn OcrEngine>>,
+) -> Result<Box<dyn Parser>, Status> {
+    let pdf = LocalPdfParser::new()
+        .with_infer_headings(options.infer_headings)
+        .with_detect_tables(options.detect_tables)
+        .with_expand_ligatures(options.expand_ligatures.unwrap_or(true))
+        .with_normalize_whitespace(options.normalize_whitespace)
+        .with_max_images_per_page(max_images)
+        .with_extract_images(options.extract_images)
+        .with_ocr(ocr);
+    match options.parser() {
+        ParserSelection::LocalPdf => return Ok(Box::new(pdf)),
+        ParserSelection::Docx => return Ok(Box::new(DocxParser::new().with_page_chars(page_chars(options)))),
+        ParserSelection::Html => return Ok(Box::new(html_parser(options))),
+        ParserSelection::Auto | ParserSelection::Azure => {}
+    }
+    let html = html_parser(options);
+    for_content_type_with(declared_mime(req), &req.filename, pdf, html, page_chars(options)).map_err(|e| {
+        telemetry::record_error("grpc", &e);
+        Status::unimplemented(e.to_string())
+    })
+}
+
+/// Characters per virtual page of HTML and DOCX documents the options ask for.
+fn page_chars(options: &ParseOptions) -> usize {
+    options.page_chars.map_or(DEFAULT_PAGE_CHARS, |chars| chars.max(0) as usize)
+}
+
+/// The selectors the options ask to page HTML by, empty when not paging by
+/// sections.
+fn section_selectors(options: &ParseOptions) -> Vec<String> {
+    if !options.html_section_selectors.is_empty() {
+        options.html_section_selectors.clone()
+    } else if options.html_sections {
+        DEFAULT_SECTION_SELECTORS.iter().map(|s| s.to_string()).collect()
+    } else {
+        Vec::new()
+    }
+}
+
+/// The HTML parser with the page size and section selectors the options ask for.
+fn html_parser(options: &ParseOptions) -> HtmlParser {
+    HtmlParser::new()
+        .with_page_chars(page_chars(options))
+        .with_section_selectors(section_selectors(options))
+}
+
+/// The status for a document that failed to parse. Empty documents are
+/// well-formed, so they are told apart from corrupt ones.
+/// Send the chunks of `parser`'s pages of `content` as each page is
+/// extracted, split by the chunker `new_chunker` makes for the parser's name
+/// and page count. Extraction, not the wait on a slow consumer, is held to
+/// `timeout`: checked between pages, and for the first page, which a stuck
+/// parser may never deliver, by a timer outside the parse.
+fn stream_pages(
+    parser: Box<dyn Parser>,
+    content: Vec<u8>,
+    new_chunker: impl FnOnce(&'static str, usize) -> PageChunker + Send + 'static,
+    timeout: Duration,
+    tx: mpsc::Sender<Result<ProtoChunk, Status>>,
+    permit: OwnedSemaphorePermit,
+) {
+    let expired = move || {
+        telemetry::record_error("grpc", &ParserError::Timeout(timeout));
+        Err(parse_status(&ParserError::Timeout(timeout)))
+    };
+    // Whoever settles the first page first, the parse or the timer, decides the stream
+    let first_page = Arc::new(AtomicBool::new(false));
+    let (first_page_sent, first_page_seen) = oneshot::channel::<()>();
+    let mut first_page_sent = Some(first_page_sent);
+    {
+        let (first_page, tx) = (first_page.clone(), tx.clone());
+        tokio::spawn(async move {
+            let timed_out = tokio::time::timeout(timeout, first_page_seen).await.is_err();
+            if timed_out && !first_page.swap(true, Ordering::SeqCst) {
+                let _ = tx.send(expired()).await;
+            }
+        });
+    }
+    tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let mut parsing = Instant::now();
+        let mut parse_time = Duration::ZERO;
+        let pages = parser.parse_stream(&content);
+        let page_total = pages.size_hint().1.unwrap_or(1);
+        let mut chunker = new_chunker(parser.name(), page_total);
+        for (page_i, page) in pages.enumerate() {
+            parse_time += parsing.elapsed();
+            if let Some(sent) = first_page_sent.take() {
+                if first_page.swap(true, Ordering::SeqCst) {
+                    return;
+                }
+                let _ = sent.send(());
+            }
+            if parse_time > timeout {
+                let _ = tx.blocking_send(expired());
+                return;
+            }
+            let page = match page {
+                Ok(page) => page,
+                Err(e) => {
+                    telemetry::record_error("grpc", &e);
+                    let _ = tx.blocking_send(Err(parse_status(&e)));
+                    return;
+                }
+            };
+            for chunk in chunker.split(page_i, page) {
+                if tx.blocking_send(Ok(chunk)).is_err() {
+                    return;
+                }
+            }
+            parsing = Instant::now();
+        }
+        chunker.finish();
+    });
+}
+
+fn parse_status(error: &ParserError) -> Status {
+    match error {
+        ParserError::Timeout(_) => Status::deadline_exceeded(error.to_string()),
+        ParserError::EmptyDocument(_) => Status::failed_precondition(error.to_string()),
+        _ => Status::invalid_argument(error.to_string()),
+    }
+}
+
+/// Splits a document page by page for streaming, numbering chunks and
+/// following the outline across pages. The chunk total is unknown until the
+/// last page, so position is estimated from page progress.
+struct PageChunker {
+    splitter: Box<dyn TextSplitter>,
+    extract_images: bool,
+    fingerprint: bool,
+    /// Encoding to recount chunks in once their heading path is prepended,
+    /// when requested.
+    prepend_heading: Option<TokenizerKind>,
+    /// Replaces personal data in every chunk, when requested.
+    redactor: Option<RedactionProcessor>,
+    /// Run on every chunk before it is sent.
+    processors: ChunkProcessors,
+    page_total: f32,
+    index: usize,
+    /// Headings enclosing the end of the pages split so far.
+    outline: Vec<Heading>,
+    footnotes: FootnoteMarkers,
+    detect_language: bool,
+    /// Parser producing the pages.
+    parser: &'static str,
+    upload: UploadFingerprint,
+    started: Instant,
+    pages: usize,
+    tokens: usize,
+    /// Images whose data an earlier chunk of the stream carried.
+    sent_images: HashSet<String>,
+}
+
+impl PageChunker {
+    fn new(
+        options: &ParseOptions,
+        processors: ChunkProcessors,
+        upload: UploadFingerprint,
+        parser: &'static str,
+        page_total: usize,
+    ) -> Self {
+        Self {
+            splitter: splitter_for(options, &upload.filename),
+            extract_images: options.extract_images,
+            fingerprint: options.simhash,
+            prepend_heading: options.prepend_heading.then(|| TokenizerKind::parse(&options.tokenizer)),
+            redactor: options.redact_pii.then(|| RedactionProcessor::new(TokenizerKind::parse(&options.tokenizer))),
+            processors,
+            page_total: page_total.max(1) as f32,
+            index: 0,
+            outline: Vec::new(),
+            footnotes: FootnoteMarkers::parse(&options.handle_footnote_markers),
+            detect_language: options.detect_language.unwrap_or(true),
+            parser,
+            upload,
+            started: Instant::now(),
+            pages: 0,
+            tokens: 0,
+            sent_images: HashSet::new(),
+        }
+    }
+
+    /// Record the parse once every page has been sent.
+    fn finish(&self) {
+        let elapsed = self.started.elapsed();
+        telemetry::record_parse("grpc", self.parser, elapsed, self.index, self.tokens);
+        telemetry::log_parse("grpc", &self.upload, self.parser, self.pages, self.index, elapsed);
+    }
+
+    /// Chunks of the `page_i`th page, ready to send.
+    fn split(&mut self, page_i: usize, mut page: Page) -> Vec<ProtoChunk> {
+        self.pages += 1;
+        self.footnotes.apply(self.parser, std::slice::from_mut(&mut page));
+        if self.detect_language {
+            language::detect_pages(std::slice::from_mut(&mut page));
+        }
+        if !self.extract_images {
+            page.images.clear();
+        }
+        let chunks = self.splitter.split(std::slice::from_ref(&page));
+        let page_chunks = chunks.len() as f32;
+        let mut chunks: Vec<ProtoChunk> = chunks
+            .into_iter()
+            .enumerate()
+            .map(|(j, mut chunk)| {
+                chunk.index = self.index;
+                chunk.heading_path = self.heading_path(&page, chunk.heading_path.take());
+                if self.fingerprint {
+                    chunk.simhash = Some(simhash(&chunk.text));
+                }
+                if let Some(tokenizer) = self.prepend_heading {
+                    prepend_heading(&mut chunk, tokenizer);
+                }
+                chunk.position = ((page_i as f32 + j as f32 / page_chunks) / self.page_total).min(1.0);
+                let chunk = match &self.redactor {
+                    Some(redactor) => redactor.process(chunk),
+                    None => chunk,
+                };
+                let chunk = self.processors.process(chunk);
+                self.index += 1;
+                self.tokens += chunk.token_count;
+                map_chunk_to_proto(chunk)
+            })
+            .collect();
+        attach_image_data(&mut chunks, &page.images, &mut self.sent_images);
+        for heading in page.headings {
+            while self.outline.last().is_some_and(|h| h.level >= heading.level) {
+                self.outline.pop();
+            }
+            self.outline.push(heading);
+        }
+        chunks
+    }
+
+    /// A chunk's heading path within the whole document: the splitter only
+    /// sees one page, so headings still open from earlier pages are added.
+    fn heading_path(&self, page: &Page, path: Option<Vec<String>>) -> Option<Vec<String>> {
+        let first_level = path
+            .as_ref()
+            .and_then(|path| page.headings.iter().find(|h| h.text == path[0]))
+            .map(|h| h.level);
+        let full: Vec<String> = self
+            .outline
+            .iter()
+            .filter(|h| first_level.is_none_or(|level| h.level < level))
+            .map(|h| h.text.clone())
+            .chain(path.into_iter().flatten())
+            .collect();
+        (!full.is_empty()).then_some(full)
+    }
+}
+
+/// Effective settings behind a response: the splitter's, the parser that
+/// produced the pages and the steps applied around splitting.
+fn applied_config(
+    options: &ParseOptions,
+    parser: &str,
+    splitter: &dyn TextSplitter,
+    summarized: bool,
+) -> AppliedConfig {
+    let settings = splitter.settings();
+    let footnotes = FootnoteMarkers::parse(&options.handle_footnote_markers);
+    let sections = section_selectors(options);
+    let pdf = parser == "LocalPdfParser";
+    let azure = parser == "AzureDocIntelligenceParser";
+    let flags = [
+        ("infer_headings", pdf && options.infer_headings),
+        ("detect_tables", pdf && options.detect_tables),
+        ("expand_ligatures", pdf && options.expand_ligatures.unwrap_or(true)),
+        ("normalize_whitespace", pdf && options.normalize_whitespace),
+        ("detect_language", options.detect_language.unwrap_or(true)),
+        ("key_value_pairs", azure && options.key_value_pairs),
+        ("inject_key_values", azure && options.key_value_pairs && options.inject_key_values),
+        ("extract_images", options.extract_images),
+        ("order=importance", ChunkOrder::parse(&options.order) == ChunkOrder::Importance),
+        ("simhash", options.simhash),
+        ("prepend_heading", options.prepend_heading),
+        ("redact_pii", options.redact_pii),
+        ("structure_tree", options.structure_tree),
+        ("page_source_ranges", options.page_source_ranges),
+        ("summarize", summarized),
+    ];
+    let transforms = settings
+        .transforms
+        .into_iter()
+        .chain(flags.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| name.to_string()))
+        .chain(footnotes.applies_to(parser).then(|| format!("footnote_markers={}", footnotes.name())))
+        .chain(matches!(parser, "HtmlParser" | "DocxParser").then(|| format!("page_chars={}", page_chars(options))))
+        .chain(
+            (parser == "HtmlParser" && !sections.is_empty()).then(|| format!("html_sections={}", sections.join(","))),
+        )
+        .collect();
+    AppliedConfig {
+        parser: parser.to_string(),
+        splitter: settings.splitter,
+        tokenizer: settings.tokenizer,
+        max_tokens: settings.max_tokens as i32,
+        overlap_tokens: settings.overlap_tokens as i32,
+        overlap_align: match settings.overlap_align {
+            OverlapAlign::Token => "token",
+            OverlapAlign::Sentence => "sentence",
+        }
+        .to_string(),
+        transforms,
+    }
+}
 
-    let options = req.options.as_ref().cloned().unwrap_or_default();
+/// Chunk size and overlap in tokens the options ask for, defaults filled
+/// in. An absolute `overlap_tokens` takes precedence over `overlap_percent`.
+fn token_budget(options: &ParseOptions) -> (usize, usize) {
     let max_tokens = if options.max_tokens_per_chunk > 0 {
         options.max_tokens_per_chunk as usize
     } else {
         500
     };
-    let overlap = if options.overlap_percent > 0 {
+    let overlap_percent = if options.overlap_percent > 0 {
         options.overlap_percent as usize
     } else {
         10
     };
+    let overlap_tokens = match options.overlap_tokens {
+        Some(tokens) => tokens.max(0) as usize,
+        None => (max_tokens * overlap_percent) / 100,
+    };
+    (max_tokens, overlap_tokens)
+}
 
-    let splitter = SentenceTextSplitter::new(max_tokens, overlap);
-    let chunks = splitter.split(&pages);
+/// Reject options whose overlap would fill a whole chunk.
+fn check_token_budget(options: Option<&ParseOptions>) -> Result<(), Status> {
+    let Some((max_tokens, overlap_tokens)) = options.map(token_budget) else {
+        return Ok(());
+    };
+    if overlap_tokens >= max_tokens {
+        return Err(Status::invalid_argument(format!(
+            "an overlap of {} tokens must be less than the {} tokens per chunk",
+            overlap_tokens, max_tokens
+        )));
+    }
+    Ok(())
+}
+
+/// Build the splitter described by the request options.
+/// The splitter `options` ask for, deriving chunk IDs from `filename` when
+/// they should be deterministic.
+fn splitter_for(options: &ParseOptions, filename: &str) -> Box<dyn TextSplitter> {
+    let (max_tokens, overlap_tokens) = token_budget(options);
+
+    let embed_text = options.generate_embeddings || options.emit_embed_text;
+    let tokenizer = TokenizerKind::parse(&options.tokenizer);
+    if SplitterKind::parse(&options.splitter) == SplitterKind::Recursive {
+        let splitter = RecursiveCharacterTextSplitter::new(max_tokens)
+            .with_tokenizer(tokenizer)
+            .with_embed_text(embed_text);
+        if options.deterministic_ids {
+            return Box::new(splitter.with_deterministic_ids(filename));
+        }
+        return Box::new(splitter);
+    }
 
-    Ok((chunks, pages.len(), req.content.len()))
+    let splitter = SentenceTextSplitter::with_overlap_tokens(max_tokens, overlap_tokens)
+        .with_language_spans(options.language_spans)
+        .with_boundary_lookahead(options.boundary_tolerance_percent.max(0) as usize)
+        .with_embed_text(embed_text)
+        .with_drop_empty_chunks(options.drop_empty_chunks.unwrap_or(true))
+        .with_cross_page_merge(options.cross_page_merge)
+        .with_max_chars(options.max_chars_per_chunk.max(0) as usize)
+        .with_min_chunk_tokens(options.min_chunk_tokens.max(0) as usize)
+        .with_overlap_lengths(options.emit_overlap)
+        .with_overlap_align(OverlapAlign::parse(&options.overlap_align))
+        .with_tokenizer(tokenizer)
+        .with_merge_pages(options.merge_pages, options.page_separator.as_deref().unwrap_or(" "));
+    if options.deterministic_ids {
+        Box::new(splitter.with_deterministic_ids(filename))
+    } else {
+        Box::new(splitter)
+    }
 }
 
-fn map_chunk_to_proto(c: crate::splitter::Chunk) -> ProtoChunk {
+/// Fill in the base64 data of the images `chunks` refer to, for images not
+/// in `sent` yet: a response carries each image once, on the first chunk
+/// referring to it, and later references give the ID alone.
+fn attach_image_data(chunks: &mut [ProtoChunk], images: &[Image], sent: &mut HashSet<String>) {
+    for image in chunks.iter_mut().flat_map(|chunk| chunk.images.iter_mut()) {
+        if let Some(source) = images.iter().find(|source| source.id == image.id) {
+            if sent.insert(image.id.clone()) {
+                image.data_base64 = BASE64.encode(&source.data);
+            }
+        }
+    }
+}
+
+fn map_chunk_to_proto(c: Chunk) -> ProtoChunk {
     ProtoChunk {
         id: c.id,
+        index: c.index as i32,
+        position: c.position,
         page_num: c.page_num as i32,
+        page_span: c.page_span.map(|(start, end)| ProtoPageSpan {
+            start_page: start as i32,
+            end_page: end as i32,
+        }),
         text: c.text,
         token_count: c.token_count as i32,
         char_count: c.char_count as i32,
         embedding: vec![],
-        images: vec![],
+        images: c
+            .images
+            .into_iter()
+            .map(|image| ProtoImage {
+                id: image.image_id,
+                page_num: c.page_num as i32,
+                content_type: image.content_type,
+                size_bytes: image.size_bytes as i64,
+                ..Default::default()
+            })
+            .collect(),
+        highlighted: c.highlighted,
+        highlight_color: c.highlight_color.unwrap_or_default(),
+        language_spans: c
+            .language_spans
+            .unwrap_or_default()
+            .into_iter()
+            .map(|span| ProtoLanguageSpan {
+                start: span.start as i32,
+                end: span.end as i32,
+                lang: span.lang,
+            })
+            .collect(),
+        language: c.language.unwrap_or_default(),
+        code_language: c.code_language.unwrap_or_default(),
+        kind: c.kind.unwrap_or_default(),
+        heading_path: c.heading_path.unwrap_or_default(),
+        embed_text: c.embed_text.unwrap_or_default(),
+        embed_token_count: c.embed_token_count.unwrap_or_default() as i32,
+        overlap_prefix_len: c.overlap_prefix_len.unwrap_or_default() as i32,
+        overlap_suffix_len: c.overlap_suffix_len.unwrap_or_default() as i32,
+        simhash: c.simhash,
+        start_offset: c.start_offset.map(|offset| offset as i32),
+        end_offset: c.end_offset.map(|offset| offset as i32),
+        original_text: c.original_text.unwrap_or_default(),
     }
 }
 
-pub fn create_service() -> IngestionServiceServer<IngestionServiceImpl> {
-    IngestionServiceServer::new(IngestionServiceImpl::default())
+/// Summary text, or the page text sent to the summarizer, with personal
+/// data replaced when the request asks for redaction.
+fn redact_summary(redact_pii: bool, text: String) -> String {
+    if redact_pii {
+        RedactionProcessor::redact_text(&text)
+    } else {
+        text
+    }
 }
 
+fn map_structure_to_proto(node: StructureNode) -> ProtoStructureNode {
+    ProtoStructureNode {
+        title: node.title,
+        level: node.level as i32,
+        page_num: node.page_num.unwrap_or_default() as i32,
+        chunk_indices: node.chunk_indices.into_iter().map(|i| i as i32).collect(),
+        children: node.children.into_iter().map(map_structure_to_proto).collect(),
+    }
+}
+
+/// The gRPC service, requiring the configured API key on every call but
+/// health checks, which probes make without one.
+pub fn create_service(config: Config) -> RequireGrpcApiKey<IngestionServiceServer<IngestionServiceImpl>> {
+    let api_key = config.api_key.grpc_layer(&["HealthCheck"]);
+    api_key.layer(IngestionServiceServer::new(IngestionServiceImpl::new(config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AzureConfig;
+    use axum::http::{header::HeaderName, StatusCode};
+    use axum::routing::{get, post};
+    use futures::StreamExt;
+
+    /// Recorded prebuilt-read result for a three page scan.
+    const AZURE_RESULT: &str = r#"{
+        "status": "succeeded",
+        "analyzeResult": {
+            "apiVersion": "2023-07-31",
+            "modelId": "prebuilt-read",
+            "content": "Invoice 4711\nPayment is due in 30 days.\nTerms apply.\nContact us.",
+            "pages": [
+                {"pageNumber": 1, "lines": [{"content": "Invoice 4711"}, {"content": "Payment is due in 30 days."}]},
+                {"pageNumber": 2, "lines": [{"content": "Terms apply."}]},
+                {"pageNumber": 3, "lines": [{"content": "Contact us."}]}
+            ]
+        }
+    }"#;
+
+    /// Recorded prebuilt-layout result for a one page form, with key-value
+    /// pairs.
+    const AZURE_LAYOUT_RESULT: &str = r#"{
+        "status": "succeeded",
+        "analyzeResult": {
+            "apiVersion": "2023-07-31",
+            "modelId": "prebuilt-layout",
+            "content": "Invoice Number: 4711\nCustomer: Contoso Ltd.\nThank you for your business.",
+            "pages": [
+                {"pageNumber": 1, "lines": [
+                    {"content": "Invoice Number: 4711"},
+                    {"content": "Customer: Contoso Ltd."},
+                    {"content": "Thank you for your business."}
+                ]}
+            ],
+            "keyValuePairs": [
+                {
+                    "key": {"content": "Invoice Number:", "boundingRegions": [{"pageNumber": 1, "polygon": [1, 1, 2, 1, 2, 2, 1, 2]}]},
+                    "value": {"content": "4711", "boundingRegions": [{"pageNumber": 1, "polygon": [3, 1, 4, 1, 4, 2, 3, 2]}]},
+                    "confidence": 0.95
+                },
+                {
+                    "key": {"content": "Customer:", "boundingRegions": [{"pageNumber": 1, "polygon": [1, 3, 2, 3, 2, 4, 1, 4]}]},
+                    "value": {"content": "Contoso Ltd.", "boundingRegions": [{"pageNumber": 1, "polygon": [3, 3, 4, 3, 4, 4, 3, 4]}]},
+                    "confidence": 0.91
+                },
+                {
+                    "key": {"content": "Signature", "boundingRegions": [{"pageNumber": 1, "polygon": [1, 5, 2, 5, 2, 6, 1, 6]}]},
+                    "confidence": 0.42
+                }
+            ]
+        }
+    }"#;
+
+    /// Serve a recorded Azure analysis from a local endpoint, for either
+    /// model.
+    async fn mock_azure(result: &'static str) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let operation = format!("http://{}/operations/1", addr);
+        let submit = post(move || async move {
+            (StatusCode::ACCEPTED, [(HeaderName::from_static("operation-location"), operation)])
+        });
+        let app = axum::Router::new()
+            .route("/formrecognizer/documentModels/prebuilt-read:analyze", submit.clone())
+            .route("/formrecognizer/documentModels/prebuilt-layout:analyze", submit)
+            .route(
+                "/operations/1",
+                get(move || async move { ([("content-type", "application/json")], result) }),
+            );
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_network_disabled_rejects_azure() {
+        let service = IngestionServiceImpl::new(Config {
+            azure: Some(AzureConfig {
+                endpoint: "https://example.cognitiveservices.azure.com".to_string(),
+                api_key: "key".to_string(),
+            }),
+            network_disabled: true,
+            ..Default::default()
+        });
+        let request = ParseDocumentRequest {
+            content: b"%PDF-1.5".to_vec(),
+            filename: "scan.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            options: Some(ParseOptions {
+                use_document_intelligence: true,
+                ..Default::default()
+            }),
+        };
+
+        let status = service.parse_document(Request::new(request)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+        assert!(status.message().contains("Network access is disabled"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_emits_azure_chunks_page_by_page() {
+        let service = IngestionServiceImpl::new(Config {
+            azure: Some(AzureConfig {
+                endpoint: mock_azure(AZURE_RESULT).await,
+                api_key: "key".to_string(),
+            }),
+            ..Default::default()
+        })
+        .with_azure_poll_interval(Duration::from_millis(1));
+        let request = ParseDocumentRequest {
+            content: b"%PDF-1.5".to_vec(),
+            filename: "scan.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            options: Some(ParseOptions {
+                use_document_intelligence: true,
+                ..Default::default()
+            }),
+        };
+
+        let chunks: Vec<ProtoChunk> = service
+            .parse_document_stream(Request::new(request))
+            .await
+            .unwrap()
+            .into_inner()
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(chunks.iter().map(|c| c.page_num).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(chunks.iter().map(|c| c.index).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(chunks[0].text, "Invoice 4711\nPayment is due in 30 days.");
+        assert_eq!(chunks[1].text, "Terms apply.");
+        assert_eq!(chunks[2].text, "Contact us.");
+        assert!(chunks.windows(2).all(|w| w[0].position < w[1].position));
+    }
+
+    #[tokio::test]
+    async fn test_garbled_pdf_page_warns_and_falls_back() {
+        use crate::parser::fixtures::PdfBuilder;
+
+        // Text in a font without a usable encoding comes out as U+FFFD
+        let garbled = ["\u{FFFD}\u{FFFD}\u{FFFD}"; 8].join(" ");
+        let request = ParseDocumentRequest {
+            content: PdfBuilder::new().page(&[garbled.as_str(), garbled.as_str()]).build(),
+            filename: "scan.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            options: None,
+        };
+        let azure = Some(AzureConfig {
+            endpoint: mock_azure(AZURE_RESULT).await,
+            api_key: "key".to_string(),
+        });
+
+        let service = IngestionServiceImpl::new(Config {
+            azure: azure.clone(),
+            ..Default::default()
+        });
+        let response = service.parse_document(Request::new(request.clone())).await.unwrap().into_inner();
+        let warnings = response.metadata.unwrap().quality_warnings;
+        assert_eq!(warnings.len(), 1);
+        assert_eq!((warnings[0].page_num, warnings[0].parser.as_str()), (1, "LocalPdfParser"));
+        assert_eq!(response.stats.unwrap().parser_used, "LocalPdfParser");
+
+        let service = IngestionServiceImpl::new(Config {
+            azure,
+            quality_fallback: true,
+            ..Default::default()
+        })
+        .with_azure_poll_interval(Duration::from_millis(1));
+        let response = service.parse_document(Request::new(request)).await.unwrap().into_inner();
+        assert_eq!(response.metadata.unwrap().quality_warnings.len(), 1);
+        assert_eq!(response.stats.unwrap().parser_used, "AzureDocIntelligenceParser");
+        assert_eq!(response.chunks[0].text, "Invoice 4711\nPayment is due in 30 days.");
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_attached_when_requested() {
+        async fn embeddings(axum::Json(body): axum::Json<serde_json::Value>) -> axum::Json<serde_json::Value> {
+            let data: Vec<serde_json::Value> = body["input"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .enumerate()
+                .map(|(i, text)| {
+                    let len = text.as_str().unwrap().len();
+                    serde_json::json!({ "index": i, "embedding": [len, 1.0, 0.5] })
+                })
+                .collect();
+            axum::Json(serde_json::json!({ "data": data }))
+        }
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = axum::Router::new().route("/v1/embeddings", axum::routing::post(embeddings));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let service = IngestionServiceImpl::new(Config {
+            embedding: Some(crate::embed::EmbeddingConfig {
+                base_url: format!("http://{}/v1", addr),
+                model: "test-embedding".to_string(),
+                api_key: None,
+                max_concurrent_batches: 2,
+                max_batch_size: 2,
+                max_batch_tokens: 1000,
+            }),
+            ..Default::default()
+        });
+        let text = (1..=6)
+            .map(|i| format!("Paragraph {} explains one more step of cell division in some detail.\n\n", i))
+            .collect::<String>();
+        let request = |generate_embeddings: bool| ParseDocumentRequest {
+            content: text.clone().into_bytes(),
+            filename: "notes.txt".to_string(),
+            content_type: "text/plain".to_string(),
+            options: Some(ParseOptions {
+                max_tokens_per_chunk: 20,
+                generate_embeddings,
+                ..Default::default()
+            }),
+        };
+
+        let chunks = service.parse_document(Request::new(request(true))).await.unwrap().into_inner().chunks;
+        assert!(chunks.len() > 2);
+        for chunk in &chunks {
+            assert_eq!(chunk.embedding, vec![chunk.embed_text.len() as f32, 1.0, 0.5]);
+        }
+
+        let chunks = service.parse_document(Request::new(request(false))).await.unwrap().into_inner().chunks;
+        assert!(chunks.iter().all(|chunk| chunk.embedding.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn test_near_empty_pdf_escalates_to_azure() {
+        use crate::parser::fixtures::PdfBuilder;
+
+        // A scan with only a page number in its text layer
+        let request = ParseDocumentRequest {
+            content: PdfBuilder::new().page(&["1"]).build(),
+            filename: "scan.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            options: None,
+        };
+        let escalating = |azure: Option<AzureConfig>| {
+            IngestionServiceImpl::new(Config {
+                azure,
+                escalate_min_chars: 50,
+                ..Default::default()
+            })
+            .with_azure_poll_interval(Duration::from_millis(1))
+        };
+
+        let azure = AzureConfig {
+            endpoint: mock_azure(AZURE_RESULT).await,
+            api_key: "key".to_string(),
+        };
+        let service = escalating(Some(azure));
+        let response = service.parse_document(Request::new(request.clone())).await.unwrap().into_inner();
+        let warnings = response.metadata.unwrap().quality_warnings;
+        assert_eq!(warnings.len(), 1);
+        assert_eq!((warnings[0].page_num, warnings[0].parser.as_str()), (0, "LocalPdfParser"));
+        assert!(warnings[0].reason.ends_with("escalated to AzureDocIntelligenceParser"), "{}", warnings[0].reason);
+        assert_eq!(response.stats.unwrap().parser_used, "AzureDocIntelligenceParser");
+        assert_eq!(response.chunks[0].text, "Invoice 4711\nPayment is due in 30 days.");
+
+        // Nothing to escalate to: the local result stands, flagged
+        let response = escalating(None).parse_document(Request::new(request)).await.unwrap().into_inner();
+        let warnings = response.metadata.unwrap().quality_warnings;
+        assert_eq!(warnings[0].reason, "text too short: 1 of at least 50 characters");
+        assert_eq!(response.stats.unwrap().parser_used, "LocalPdfParser");
+    }
+
+    #[tokio::test]
+    async fn test_azure_key_value_pairs_in_metadata() {
+        let service = IngestionServiceImpl::new(Config {
+            azure: Some(AzureConfig {
+                endpoint: mock_azure(AZURE_LAYOUT_RESULT).await,
+                api_key: "key".to_string(),
+            }),
+            ..Default::default()
+        })
+        .with_azure_poll_interval(Duration::from_millis(1));
+        let request = |inject_key_values| ParseDocumentRequest {
+            content: b"%PDF-1.5".to_vec(),
+            filename: "invoice.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            options: Some(ParseOptions {
+                use_document_intelligence: true,
+                key_value_pairs: true,
+                inject_key_values,
+                ..Default::default()
+            }),
+        };
+
+        let response = service.parse_document(Request::new(request(false))).await.unwrap().into_inner();
+        let key_values = response.metadata.unwrap().key_values;
+        assert_eq!(key_values.len(), 3);
+        assert_eq!(key_values["Invoice Number"], "4711");
+        assert_eq!(key_values["Customer"], "Contoso Ltd.");
+        assert_eq!(key_values["Signature"], "");
+        assert!(!response.chunks[0].text.contains("Signature"));
+
+        let response = service.parse_document(Request::new(request(true))).await.unwrap().into_inner();
+        let text = &response.chunks[0].text;
+        assert!(text.ends_with("Invoice Number: 4711\nCustomer: Contoso Ltd.\nSignature:"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_stream_over_grpc_matches_unary_parse() {
+        use crate::parser::fixtures::PdfBuilder;
+        use proto::ingestion_service_client::IngestionServiceClient;
+        use tonic::transport::server::TcpIncoming;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(create_service(Config::default()))
+                .serve_with_incoming(incoming),
+        );
+        let mut client = IngestionServiceClient::connect(format!("http://{}", addr)).await.unwrap();
+
+        let pdf = PdfBuilder::new()
+            .page(&[
+                "Cells are the basic unit of life.",
+                "Every organism is made of one or more cells.",
+            ])
+            .page(&[
+                "Mitochondria supply the cell with energy.",
+                "The nucleus holds the genetic material.",
+            ])
+            .build();
+        let request = ParseDocumentRequest {
+            content: pdf,
+            filename: "biology.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            options: Some(ParseOptions {
+                max_tokens_per_chunk: 12,
+                ..Default::default()
+            }),
+        };
+
+        let unary = client.parse_document(request.clone()).await.unwrap().into_inner().chunks;
+        let streamed: Vec<ProtoChunk> = client
+            .parse_document_stream(request)
+            .await
+            .unwrap()
+            .into_inner()
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+
+        // Chunk ids are generated per parse, so compare everything else
+        let summary = |chunks: &[ProtoChunk]| {
+            chunks
+                .iter()
+                .map(|c| (c.index, c.page_num, c.text.clone(), c.token_count))
+                .collect::<Vec<_>>()
+        };
+        assert!(unary.len() > 2);
+        assert_eq!(summary(&streamed), summary(&unary));
+        assert!(streamed.iter().all(|c| c.token_count > 0));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_api_key_required_in_metadata() {
+        use crate::auth::{ApiKey, API_KEY_HEADER};
+        use proto::ingestion_service_client::IngestionServiceClient;
+        use tonic::transport::server::TcpIncoming;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        let config = Config {
+            api_key: ApiKey::new(Some("s3cret".to_string())),
+            ..Default::default()
+        };
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(create_service(config))
+                .serve_with_incoming(incoming),
+        );
+        let mut client = IngestionServiceClient::connect(format!("http://{}", addr)).await.unwrap();
+        fn request<T>(message: T, key: Option<&str>) -> Request<T> {
+            let mut request = Request::new(message);
+            if let Some(key) = key {
+                request.metadata_mut().insert(API_KEY_HEADER, key.parse().unwrap());
+            }
+            request
+        }
+        let formats = |key| request(GetSupportedFormatsRequest {}, key);
+
+        // Health checks are open to probes without the key
+        assert!(client.health_check(request(HealthCheckRequest {}, None)).await.is_ok());
+        assert!(client.get_supported_formats(formats(Some("s3cret"))).await.is_ok());
+        let missing = client.get_supported_formats(formats(None)).await.unwrap_err();
+        assert_eq!(missing.code(), tonic::Code::Unauthenticated);
+        let wrong = client.get_supported_formats(formats(Some("guess"))).await.unwrap_err();
+        assert_eq!(wrong.code(), tonic::Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn test_stream_times_out_on_slow_parse() {
+        let stream = |timeout: Duration| async move {
+            let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+            let permit = Arc::new(Semaphore::new(1)).acquire_owned().await.unwrap();
+            let upload = UploadFingerprint::new(b"Cells divide.", "notes.txt", "text/plain");
+            let chunker = |parser, page_total| {
+                PageChunker::new(&ParseOptions::default(), ChunkProcessors::default(), upload, parser, page_total)
+            };
+            let parser = Box::new(crate::parser::fixtures::SlowParser);
+            stream_pages(parser, b"Cells divide.".to_vec(), chunker, timeout, tx, permit);
+            rx
+        };
+
+        let start = Instant::now();
+        let mut rx = stream(Duration::from_millis(50)).await;
+        let status = rx.recv().await.unwrap().unwrap_err();
+        assert_eq!(status.code(), tonic::Code::DeadlineExceeded);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        // The parse runs on, but sends nothing after the deadline
+        assert!(rx.recv().await.is_none());
+
+        let mut rx = stream(Duration::from_secs(10)).await;
+        assert_eq!(rx.recv().await.unwrap().unwrap().text, "Cells divide.");
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream_emits_docx_chunks_in_document_order() {
+        let paragraphs: Vec<String> = (1..=120)
+            .map(|i| format!("Paragraph {} describes how cells divide, grow and specialise.", i))
+            .collect();
+        let paragraphs: Vec<&str> = paragraphs.iter().map(String::as_str).collect();
+        let core = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties"/>"#;
+        let request = ParseDocumentRequest {
+            content: crate::parser::fixtures::docx(&paragraphs, core),
+            filename: "cells.docx".to_string(),
+            content_type: "application/vnd.openxmlformats-officedocument.wordprocessingml.document".to_string(),
+            options: Some(ParseOptions {
+                max_tokens_per_chunk: 100,
+                ..Default::default()
+            }),
+        };
+        let service = IngestionServiceImpl::new(Config::default());
+
+        let unary = service
+            .parse_document(Request::new(request.clone()))
+            .await
+            .unwrap()
+            .into_inner()
+            .chunks;
+        let streamed: Vec<ProtoChunk> = service
+            .parse_document_stream(Request::new(request))
+            .await
+            .unwrap()
+            .into_inner()
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+
+        let summary = |chunks: &[ProtoChunk]| {
+            chunks
+                .iter()
+                .map(|c| (c.index, c.page_num, c.text.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert!(streamed.last().unwrap().page_num > 2);
+        assert_eq!(summary(&streamed), summary(&unary));
+        assert!(streamed.windows(2).all(|w| w[0].position < w[1].position));
+    }
+
+    #[tokio::test]
+    async fn test_parse_dispatches_by_content_type() {
+        let service = IngestionServiceImpl::default();
+        let request = |filename: &str, content_type: &str, content: &[u8]| {
+            Request::new(ParseDocumentRequest {
+                content: content.to_vec(),
+                filename: filename.to_string(),
+                content_type: content_type.to_string(),
+                options: None,
+            })
+        };
+
+        let response = service
+            .parse_document(request("notes.md", "text/markdown", b"# Cells\n\nCells divide by mitosis.\n"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.stats.unwrap().parser_used, "MarkdownParser");
+        assert!(response.chunks[0].text.contains("Cells divide by mitosis."));
+
+        // A generic type is sniffed from the content, outranking the extension
+        let response = service
+            .parse_document(request("export.txt", "application/octet-stream", b"<html><p>Cells divide.</p></html>"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.stats.unwrap().parser_used, "HtmlParser");
+
+        let status = service
+            .parse_document(request("slides.zip", "application/zip", b"PK\x03\x04"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unimplemented);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_parse_logged_with_fingerprint() {
+        use std::sync::Mutex;
+
+        #[derive(Clone, Default)]
+        struct Captured(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt().json().with_writer(move || writer.clone()).finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let text = "Confidential: cells divide by mitosis.";
+        let response = IngestionServiceImpl::default()
+            .parse_document(Request::new(ParseDocumentRequest {
+                content: text.as_bytes().to_vec(),
+                filename: "notes.txt".to_string(),
+                content_type: "text/plain".to_string(),
+                options: None,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(!output.contains("Confidential"), "{}", output);
+        let event: serde_json::Value = output
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .find(|event| event["fields"]["message"] == "Parsed document")
+            .expect("a parse event");
+        let fields = &event["fields"];
+        assert_eq!(fields["content_hash"], blake3::hash(text.as_bytes()).to_hex().as_str());
+        assert_eq!(fields["filename"], "notes.txt");
+        assert_eq!(fields["content_type"], "text/plain");
+        assert_eq!(fields["size_bytes"], text.len());
+        assert_eq!(fields["parser"], "PlainTextParser");
+        assert_eq!(fields["page_count"], response.metadata.unwrap().page_count);
+        assert_eq!(fields["chunk_count"], response.chunks.len());
+        assert_eq!(fields["interface"], "grpc");
+    }
+
+    #[tokio::test]
+    async fn test_gzipped_document_is_unpacked() {
+        use std::io::Write;
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(b"Cells divide by mitosis.").unwrap();
+        let response = IngestionServiceImpl::default()
+            .parse_document(Request::new(ParseDocumentRequest {
+                content: gzip.finish().unwrap(),
+                filename: "notes.txt.gz".to_string(),
+                content_type: "application/gzip".to_string(),
+                options: None,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(response.chunks[0].text, "Cells divide by mitosis.");
+        assert_eq!(response.metadata.unwrap().filename, "notes.txt");
+    }
+
+    #[tokio::test]
+    async fn test_requests_beyond_limit_exhausted() {
+        let service = IngestionServiceImpl::new(Config {
+            max_concurrent_requests: 2,
+            ..Default::default()
+        });
+        let parse = || {
+            service.parse_document(Request::new(ParseDocumentRequest {
+                content: b"Cells divide by mitosis.".to_vec(),
+                filename: "notes.txt".to_string(),
+                content_type: "text/plain".to_string(),
+                options: None,
+            }))
+        };
+
+        // Each request holds its permit across the parse on the blocking pool
+        let results = futures::future::join_all((0..6).map(|_| parse())).await;
+        let exhausted = results
+            .iter()
+            .filter(|r| matches!(r, Err(status) if status.code() == tonic::Code::ResourceExhausted))
+            .count();
+        assert!(results[..2].iter().all(Result::is_ok));
+        assert!(exhausted > 0);
+        assert_eq!(exhausted + results.iter().filter(|r| r.is_ok()).count(), 6);
+
+        // Permits are returned once requests finish
+        assert!(parse().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_empty_document_fails_precondition() {
+        let service = IngestionServiceImpl::default();
+        let parse = |content: &[u8], content_type: &str| {
+            service.parse_document(Request::new(ParseDocumentRequest {
+                content: content.to_vec(),
+                filename: "notes".to_string(),
+                content_type: content_type.to_string(),
+                options: None,
+            }))
+        };
+
+        let empty = parse(b"<html><body><script>init();</script></body></html>", "text/html").await;
+        assert_eq!(empty.unwrap_err().code(), tonic::Code::FailedPrecondition);
+        let corrupt = parse(b"not a document", DocxParser::new().supported_mime_types()[0]).await;
+        assert_eq!(corrupt.unwrap_err().code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_overlap_in_percent_or_tokens() {
+        let service = IngestionServiceImpl::default();
+        let parse = |overlap_percent: i32, overlap_tokens: Option<i32>| {
+            service.parse_document(Request::new(ParseDocumentRequest {
+                content: b"Cells divide by mitosis.".to_vec(),
+                filename: "notes.txt".to_string(),
+                content_type: "text/plain".to_string(),
+                options: Some(ParseOptions {
+                    max_tokens_per_chunk: 200,
+                    overlap_percent,
+                    overlap_tokens,
+                    echo_config: true,
+                    ..Default::default()
+                }),
+            }))
+        };
+        let overlap = |response: Response<ParseDocumentResponse>| {
+            response.into_inner().applied_config.unwrap().overlap_tokens
+        };
+
+        assert_eq!(overlap(parse(20, None).await.unwrap()), 40);
+        assert_eq!(overlap(parse(20, Some(50)).await.unwrap()), 50);
+        // Unlike a zero percentage, zero tokens turns overlap off
+        assert_eq!(overlap(parse(0, None).await.unwrap()), 20);
+        assert_eq!(overlap(parse(0, Some(0)).await.unwrap()), 0);
+
+        let error = parse(0, Some(200)).await.unwrap_err();
+        assert_eq!(error.code(), tonic::Code::InvalidArgument);
+        assert!(error.message().contains("200 tokens"));
+    }
+
+    #[tokio::test]
+    async fn test_image_data_sent_once_per_response() {
+        let rgb = [255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255];
+        let pdf = crate::parser::fixtures::PdfBuilder::new()
+            .page(&[
+                "Figure 1 shows a cell during mitosis.",
+                "The chromosomes line up along the middle.",
+                "Each half then moves to one of the poles.",
+            ])
+            .image(1, 2, 2, &rgb)
+            .build();
+        let response = IngestionServiceImpl::default()
+            .parse_document(Request::new(ParseDocumentRequest {
+                content: pdf,
+                filename: "mitosis.pdf".to_string(),
+                content_type: "application/pdf".to_string(),
+                options: Some(ParseOptions {
+                    max_tokens_per_chunk: 12,
+                    extract_images: true,
+                    ..Default::default()
+                }),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let images: Vec<&ProtoImage> = response.chunks.iter().flat_map(|c| &c.images).collect();
+        assert!(images.len() > 1);
+        assert!(images.iter().all(|image| image.id == "img-1"));
+        assert_eq!(images.iter().filter(|image| !image.data_base64.is_empty()).count(), 1);
+        assert!(!response.chunks[0].images[0].data_base64.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stats_only_omits_chunks() {
+        let service = IngestionServiceImpl::default();
+        let text = (1..=6)
+            .map(|i| format!("Paragraph {} explains one more step of cell division in some detail.\n\n", i))
+            .collect::<String>();
+        let parse = |stats_only: bool| {
+            service.parse_document(Request::new(ParseDocumentRequest {
+                content: text.clone().into_bytes(),
+                filename: "notes.txt".to_string(),
+                content_type: "text/plain".to_string(),
+                options: Some(ParseOptions {
+                    max_tokens_per_chunk: 20,
+                    stats_only,
+                    ..Default::default()
+                }),
+            }))
+        };
+
+        let full = parse(false).await.unwrap().into_inner();
+        let dry = parse(true).await.unwrap().into_inner();
+
+        assert!(dry.chunks.is_empty());
+        let (full_stats, dry_stats) = (full.stats.unwrap(), dry.stats.unwrap());
+        assert!(full.chunks.len() > 2);
+        assert_eq!(dry_stats.total_chunks, full.chunks.len() as i32);
+        assert_eq!(dry_stats.total_tokens, full.chunks.iter().map(|c| c.token_count).sum::<i32>());
+        assert_eq!(dry_stats.total_tokens, full_stats.total_tokens);
+        assert_eq!(dry.metadata.unwrap().page_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_chunk_processors_run_on_every_chunk() {
+        let service = IngestionServiceImpl::new(Config {
+            chunk_processors: ChunkProcessors::new(vec![Box::new(crate::splitter::Uppercase)]),
+            ..Default::default()
+        });
+        let request = ParseDocumentRequest {
+            content: "Cells divide by mitosis. ".repeat(40).into_bytes(),
+            filename: "notes.txt".to_string(),
+            content_type: "text/plain".to_string(),
+            options: Some(ParseOptions {
+                max_tokens_per_chunk: 20,
+                ..Default::default()
+            }),
+        };
+
+        let unary = service.parse_document(Request::new(request.clone())).await.unwrap().into_inner().chunks;
+        let streamed: Vec<ProtoChunk> = service
+            .parse_document_stream(Request::new(request))
+            .await
+            .unwrap()
+            .into_inner()
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        for chunks in [unary, streamed] {
+            assert!(chunks.len() > 2);
+            assert!(chunks.iter().all(|c| c.text.contains("MITOSIS") && !c.text.chars().any(char::is_lowercase)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_page_chars_sets_html_page_size() {
+        let service = IngestionServiceImpl::default();
+        let html = format!("<html><body><p>{}</p></body></html>", "Cells divide. ".repeat(300));
+        let page_count = |page_chars: Option<i32>| {
+            let request = ParseDocumentRequest {
+                content: html.clone().into_bytes(),
+                filename: "cells.html".to_string(),
+                content_type: "text/html".to_string(),
+                options: Some(ParseOptions {
+                    page_chars,
+                    ..Default::default()
+                }),
+            };
+            async {
+                let response = service.parse_document(Request::new(request)).await.unwrap().into_inner();
+                response.metadata.unwrap().page_count
+            }
+        };
+
+        assert_eq!(page_count(None).await, 3);
+        assert_eq!(page_count(Some(1000)).await, 5);
+        assert_eq!(page_count(Some(0)).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_html_sections_page_by_section() {
+        let service = IngestionServiceImpl::default();
+        let html = "<html><body><article>Cells divide.</article><section>Cells grow.</section>\
+                    <div class=\"unit\">Cells die.</div></body></html>";
+        let page_count = |html_sections: bool, html_section_selectors: Vec<String>| {
+            let request = ParseDocumentRequest {
+                content: html.as_bytes().to_vec(),
+                filename: "cells.html".to_string(),
+                content_type: "text/html".to_string(),
+                options: Some(ParseOptions {
+                    html_sections,
+                    html_section_selectors,
+                    ..Default::default()
+                }),
+            };
+            async {
+                let response = service.parse_document(Request::new(request)).await.unwrap().into_inner();
+                response.metadata.unwrap().page_count
+            }
+        };
+
+        assert_eq!(page_count(false, Vec::new()).await, 1);
+        assert_eq!(page_count(true, Vec::new()).await, 3);
+        assert_eq!(page_count(false, vec!["div.unit".to_string()]).await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_redact_pii_counts_redactions() {
+        let service = IngestionServiceImpl::default();
+        let request = ParseDocumentRequest {
+            content: b"Mail tutor@school.example.org, call +1 555 123 4567 or 555.987.6543.".to_vec(),
+            filename: "notes.txt".to_string(),
+            content_type: "text/plain".to_string(),
+            options: Some(ParseOptions {
+                redact_pii: true,
+                ..Default::default()
+            }),
+        };
+
+        let unary = service.parse_document(Request::new(request.clone())).await.unwrap().into_inner();
+        assert_eq!(unary.chunks[0].text, "Mail [EMAIL], call [PHONE] or [PHONE].");
+        assert_eq!(unary.stats.unwrap().redactions, 3);
+        let streamed: Vec<ProtoChunk> = service
+            .parse_document_stream(Request::new(request))
+            .await
+            .unwrap()
+            .into_inner()
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+        assert_eq!(streamed[0].text, unary.chunks[0].text);
+    }
+
+    #[tokio::test]
+    async fn test_supported_formats_cover_registered_parsers() {
+        let formats = |config: Config| async move {
+            IngestionServiceImpl::new(config)
+                .get_supported_formats(Request::new(GetSupportedFormatsRequest {}))
+                .await
+                .unwrap()
+                .into_inner()
+        };
+
+        let local = formats(Config::default()).await;
+        for extension in ["docx", "html", "pdf"] {
+            assert!(local.extensions.iter().any(|e| e == extension), "{}", extension);
+        }
+        assert!(local.mime_types.iter().any(|m| m == "application/pdf"));
+        assert!(!local.extensions.iter().any(|e| e == "png"));
+        assert_eq!(local.extensions.iter().filter(|e| *e == "pdf").count(), 1);
+
+        // Configuring Azure adds its image formats
+        let azure = formats(Config {
+            azure: Some(AzureConfig {
+                endpoint: "https://example.cognitiveservices.azure.com".to_string(),
+                api_key: "key".to_string(),
+            }),
+            ..Default::default()
+        })
+        .await;
+        assert!(azure.extensions.iter().any(|e| e == "png"));
+        assert_eq!(azure.extensions.iter().filter(|e| *e == "pdf").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_parser_selection_overrides_content_type() {
+        let request = |content_type: &str, content: &[u8], parser: ParserSelection| ParseDocumentRequest {
+            content: content.to_vec(),
+            filename: "lesson".to_string(),
+            content_type: content_type.to_string(),
+            options: Some(ParseOptions {
+                parser: parser as i32,
+                ..Default::default()
+            }),
+        };
+        let html = b"<html><body><p>Cells divide by mitosis.</p></body></html>";
+        let parser_used = |response: ParseDocumentResponse| response.stats.unwrap().parser_used;
+
+        let service = IngestionServiceImpl::default();
+        let auto = service.parse_document(Request::new(request("text/html", html, ParserSelection::Auto))).await;
+        assert_eq!(parser_used(auto.unwrap().into_inner()), "HtmlParser");
+        let auto = service.parse_document(Request::new(request("text/plain", html, ParserSelection::Auto))).await;
+        assert_eq!(parser_used(auto.unwrap().into_inner()), "PlainTextParser");
+        let html_selected = request("text/plain", html, ParserSelection::Html);
+        let response = service.parse_document(Request::new(html_selected)).await.unwrap().into_inner();
+        assert_eq!(response.chunks[0].text, "Cells divide by mitosis.");
+        assert_eq!(parser_used(response), "HtmlParser");
+
+        // Azure only when configured
+        let pdf = b"%PDF-1.5";
+        let status = service
+            .parse_document(Request::new(request("application/pdf", pdf, ParserSelection::Azure)))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+
+        let service = IngestionServiceImpl::new(Config {
+            azure: Some(AzureConfig {
+                endpoint: mock_azure(AZURE_RESULT).await,
+                api_key: "key".to_string(),
+            }),
+            ..Default::default()
+        })
+        .with_azure_poll_interval(Duration::from_millis(1));
+        let azure = service.parse_document(Request::new(request("application/pdf", pdf, ParserSelection::Azure))).await;
+        assert_eq!(parser_used(azure.unwrap().into_inner()), "AzureDocIntelligenceParser");
+    }
+
+    #[tokio::test]
+    async fn test_echo_config_fills_in_defaults() {
+        use crate::parser::fixtures::PdfBuilder;
+
+        let service = IngestionServiceImpl::default();
+        let request = ParseDocumentRequest {
+            content: PdfBuilder::new().page(&["Cells divide by mitosis."]).build(),
+            filename: "cells.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            options: Some(ParseOptions {
+                echo_config: true,
+                ..Default::default()
+            }),
+        };
+
+        let response = service.parse_document(Request::new(request)).await.unwrap().into_inner();
+        assert_eq!(
+            response.applied_config,
+            Some(AppliedConfig {
+                parser: "LocalPdfParser".to_string(),
+                splitter: "sentence".to_string(),
+                tokenizer: "cl100k_base".to_string(),
+                max_tokens: 500,
+                overlap_tokens: 50,
+                overlap_align: "token".to_string(),
+                transforms: vec![
+                    "drop_empty_chunks".to_string(),
+                    "expand_ligatures".to_string(),
+                    "detect_language".to_string(),
+                ],
+            })
+        );
+    }
+}
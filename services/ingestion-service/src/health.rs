@@ -0,0 +1,83 @@
+// Service health shared by the REST and gRPC health checks
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+use crate::parser::for_content_type;
+use crate::splitter::encoder_status;
+
+/// Documents every healthy service can parse.
+const SAMPLES: &[(&str, &[u8])] = &[
+    ("text/plain", b"Cells divide by mitosis."),
+    ("text/markdown", b"# Cells\n\nCells divide by mitosis."),
+    ("text/html", b"<p>Cells divide by mitosis.</p>"),
+];
+
+/// Uptime since construction plus readiness checks. The checks run once,
+/// on the first health request, since loading the encoder takes a while.
+#[derive(Clone)]
+pub struct Health {
+    started: Instant,
+    readiness: Arc<OnceCell<Result<(), String>>>,
+}
+
+/// Outcome of a health check.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthStatus {
+    pub degraded_reason: Option<String>,
+    pub uptime: Duration,
+}
+
+impl HealthStatus {
+    /// `healthy`, or `degraded` when a readiness check failed.
+    pub fn status(&self) -> &'static str {
+        if self.degraded_reason.is_some() {
+            "degraded"
+        } else {
+            "healthy"
+        }
+    }
+}
+
+impl Health {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            readiness: Arc::new(OnceCell::new()),
+        }
+    }
+
+    pub async fn check(&self) -> HealthStatus {
+        let readiness = self
+            .readiness
+            .get_or_init(|| async {
+                tokio::task::spawn_blocking(check_readiness)
+                    .await
+                    .unwrap_or_else(|e| Err(format!("readiness check panicked: {}", e)))
+            })
+            .await;
+        HealthStatus {
+            degraded_reason: readiness.clone().err(),
+            uptime: self.started.elapsed(),
+        }
+    }
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The token encoder loads and the built-in parsers handle a sample each.
+fn check_readiness() -> Result<(), String> {
+    encoder_status().map_err(|e| format!("token encoder unavailable: {}", e))?;
+    for (content_type, sample) in SAMPLES {
+        let parser = for_content_type(content_type, "").map_err(|e| e.to_string())?;
+        parser
+            .parse_bytes(sample)
+            .map_err(|e| format!("{} failed on a {} sample: {}", parser.name(), content_type, e))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,239 @@
+// Language detection helpers built on whatlang
+
+use serde::{Deserialize, Serialize};
+use whatlang::Lang;
+
+use crate::parser::Page;
+
+/// Segments with fewer letters than this are too short for a reliable guess
+/// and inherit the language of their neighbours.
+const MIN_SEGMENT_LETTERS: usize = 12;
+
+/// A character range of a text written in a single language.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LanguageSpan {
+    /// Start offset in Unicode scalar values (inclusive).
+    pub start: usize,
+    /// End offset in Unicode scalar values (exclusive).
+    pub end: usize,
+    /// ISO 639-1 language code.
+    pub lang: String,
+}
+
+/// Detect the dominant language of `text` as an ISO 639-1 code.
+pub fn detect(text: &str) -> Option<&'static str> {
+    whatlang::detect(text).map(|info| iso_639_1(info.lang()))
+}
+
+/// Set each page's `language` to the dominant language of its text. Pages
+/// with too few letters to classify, including empty ones, get none.
+pub fn detect_pages(pages: &mut [Page]) {
+    for page in pages {
+        let letters = page.text.chars().filter(|c| c.is_alphabetic()).count();
+        page.language = if letters < MIN_SEGMENT_LETTERS {
+            None
+        } else {
+            detect(&page.text).map(str::to_string)
+        };
+    }
+}
+
+/// Split `text` into runs of a single language.
+///
+/// The text is cut into clauses at punctuation, each clause is classified on
+/// its own, and neighbouring clauses in the same language are merged. Clauses
+/// too short to classify are absorbed by the preceding (or following) run.
+pub fn language_spans(text: &str) -> Vec<LanguageSpan> {
+    let segments = clause_segments(text);
+
+    let mut detected: Vec<Option<&'static str>> = segments
+        .iter()
+        .map(|&(start, end)| {
+            let clause: String = text.chars().skip(start).take(end - start).collect();
+            if clause.chars().filter(|c| c.is_alphabetic()).count() < MIN_SEGMENT_LETTERS {
+                None
+            } else {
+                detect(&clause)
+            }
+        })
+        .collect();
+
+    // Short clauses take the language of the nearest classified clause
+    let mut previous = None;
+    for lang in detected.iter_mut() {
+        if lang.is_none() {
+            *lang = previous;
+        }
+        previous = *lang;
+    }
+    let first = detected.iter().flatten().next().copied();
+    for lang in detected.iter_mut().take_while(|l| l.is_none()) {
+        *lang = first;
+    }
+
+    let mut spans: Vec<LanguageSpan> = Vec::new();
+    for (&(start, end), lang) in segments.iter().zip(detected) {
+        let Some(lang) = lang else { continue };
+        match spans.last_mut() {
+            Some(last) if last.lang == lang => last.end = end,
+            _ => spans.push(LanguageSpan {
+                start,
+                end,
+                lang: lang.to_string(),
+            }),
+        }
+    }
+    spans
+}
+
+/// Clause boundaries as `(start, end)` char offsets, excluding the leading
+/// whitespace of each clause.
+fn clause_segments(text: &str) -> Vec<(usize, usize)> {
+    let mut segments = Vec::new();
+    let mut start = None;
+
+    for (i, c) in text.chars().enumerate() {
+        if start.is_none() && !c.is_whitespace() {
+            start = Some(i);
+        }
+        if matches!(c, '.' | ',' | ';' | ':' | '!' | '?' | '\n') {
+            if let Some(s) = start.take() {
+                segments.push((s, i + 1));
+            }
+        }
+    }
+    if let Some(s) = start {
+        let end = text.chars().count();
+        let trailing_ws = text.chars().rev().take_while(|c| c.is_whitespace()).count();
+        segments.push((s, end - trailing_ws));
+    }
+    segments
+}
+
+/// Map whatlang's ISO 639-3 languages to ISO 639-1 codes.
+fn iso_639_1(lang: Lang) -> &'static str {
+    match lang {
+        Lang::Epo => "eo",
+        Lang::Eng => "en",
+        Lang::Rus => "ru",
+        Lang::Cmn => "zh",
+        Lang::Spa => "es",
+        Lang::Por => "pt",
+        Lang::Ita => "it",
+        Lang::Ben => "bn",
+        Lang::Fra => "fr",
+        Lang::Deu => "de",
+        Lang::Ukr => "uk",
+        Lang::Kat => "ka",
+        Lang::Ara => "ar",
+        Lang::Hin => "hi",
+        Lang::Jpn => "ja",
+        Lang::Heb => "he",
+        Lang::Yid => "yi",
+        Lang::Pol => "pl",
+        Lang::Amh => "am",
+        Lang::Jav => "jv",
+        Lang::Kor => "ko",
+        Lang::Nob => "nb",
+        Lang::Dan => "da",
+        Lang::Swe => "sv",
+        Lang::Fin => "fi",
+        Lang::Tur => "tr",
+        Lang::Nld => "nl",
+        Lang::Hun => "hu",
+        Lang::Ces => "cs",
+        Lang::Ell => "el",
+        Lang::Bul => "bg",
+        Lang::Bel => "be",
+        Lang::Mar => "mr",
+        Lang::Kan => "kn",
+        Lang::Ron => "ro",
+        Lang::Slv => "sl",
+        Lang::Hrv => "hr",
+        Lang::Srp => "sr",
+        Lang::Mkd => "mk",
+        Lang::Lit => "lt",
+        Lang::Lav => "lv",
+        Lang::Est => "et",
+        Lang::Tam => "ta",
+        Lang::Vie => "vi",
+        Lang::Urd => "ur",
+        Lang::Tha => "th",
+        Lang::Guj => "gu",
+        Lang::Uzb => "uz",
+        Lang::Pan => "pa",
+        Lang::Aze => "az",
+        Lang::Ind => "id",
+        Lang::Tel => "te",
+        Lang::Pes => "fa",
+        Lang::Mal => "ml",
+        Lang::Ori => "or",
+        Lang::Mya => "my",
+        Lang::Nep => "ne",
+        Lang::Sin => "si",
+        Lang::Khm => "km",
+        Lang::Tuk => "tk",
+        Lang::Aka => "ak",
+        Lang::Zul => "zu",
+        Lang::Sna => "sn",
+        Lang::Afr => "af",
+        Lang::Lat => "la",
+        Lang::Slk => "sk",
+        Lang::Cat => "ca",
+        Lang::Tgl => "tl",
+        Lang::Hye => "hy",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_language_per_page() {
+        use crate::splitter::{SentenceTextSplitter, TextSplitter};
+
+        let page = |page_num: u32, text: &str| Page {
+            page_num,
+            text: text.to_string(),
+            ..Default::default()
+        };
+        let mut pages = vec![
+            page(1, "Photosynthesis converts light energy into chemical energy stored in glucose."),
+            page(2, "Die Photosynthese wandelt Lichtenergie in chemische Energie um, die in Glukose gespeichert wird."),
+            page(3, "12"),
+            page(4, ""),
+        ];
+        detect_pages(&mut pages);
+
+        let languages: Vec<Option<&str>> = pages.iter().map(|p| p.language.as_deref()).collect();
+        assert_eq!(languages, vec![Some("en"), Some("de"), None, None]);
+
+        let chunks = SentenceTextSplitter::new(500, 0).split(&pages[..2]);
+        assert_eq!(chunks[0].language.as_deref(), Some("en"));
+        assert_eq!(chunks[1].language.as_deref(), Some("de"));
+    }
+
+    #[test]
+    fn test_language_spans_code_switching() {
+        let text = "I really enjoy reading long books in the evening, aber morgens lese ich lieber die Zeitung.";
+        let spans = language_spans(text);
+
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].lang, "en");
+        assert_eq!(spans[1].lang, "de");
+
+        let comma = text.find(',').unwrap();
+        assert_eq!(spans[0].start, 0);
+        assert_eq!(spans[0].end, comma + 1);
+        assert_eq!(spans[1].start, comma + 2);
+        assert_eq!(spans[1].end, text.chars().count());
+    }
+
+    #[test]
+    fn test_language_spans_monolingual() {
+        let spans = language_spans("This is a plain English sentence. It has two parts, both English.");
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].lang, "en");
+    }
+}
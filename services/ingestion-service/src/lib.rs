@@ -0,0 +1,15 @@
+pub mod api;
+pub mod auth;
+pub mod cache;
+pub mod config;
+pub mod dead_letter;
+pub mod embed;
+pub mod fetch;
+pub mod grpc;
+pub mod health;
+pub mod language;
+pub mod parser;
+pub mod server;
+pub mod splitter;
+pub mod summarize;
+pub mod telemetry;
@@ -1,8 +1,4 @@
-mod api;
-mod grpc;
-mod parser;
-mod splitter;
-
+use keiko_ingestion::{api, config::Config, grpc, server};
 use std::net::SocketAddr;
 use tokio::net::TcpListener;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -23,26 +19,24 @@ async fn main() -> anyhow::Result<()> {
     tracing::info!("Starting REST server on {}", rest_addr);
     tracing::info!("Starting gRPC server on {}", grpc_addr);
 
-    let rest_app = api::create_router();
-    let grpc_service = grpc::create_service();
+    let config = Config::from_env();
+
+    let grpc_service = grpc::create_service(config.clone());
+    let drain_timeout = config.shutdown_timeout;
+    let rest_app = api::create_router(config);
 
     let rest_listener = TcpListener::bind(rest_addr).await?;
     let grpc_listener = TcpListener::bind(grpc_addr).await?;
 
-    tokio::select! {
-        result = axum::serve(rest_listener, rest_app) => {
-            if let Err(e) = result {
-                tracing::error!("REST server error: {}", e);
-            }
-        }
-        result = tonic::transport::Server::builder()
-            .add_service(grpc_service)
-            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(grpc_listener)) => {
-            if let Err(e) = result {
-                tracing::error!("gRPC server error: {}", e);
-            }
-        }
-    }
+    server::serve(
+        rest_listener,
+        rest_app,
+        grpc_listener,
+        grpc_service,
+        drain_timeout,
+        server::shutdown_signal(),
+    )
+    .await?;
 
     Ok(())
 }
@@ -0,0 +1,137 @@
+// Transparent decompression of gzip- and zip-compressed uploads
+
+use std::io::{Cursor, Read};
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+    #[error("{0} expands beyond the limit of {1} bytes")]
+    TooLarge(String, usize),
+    #[error("{0} is not a readable archive: {1}")]
+    Invalid(String, String),
+}
+
+/// A document unpacked from a compressed upload.
+#[derive(Debug)]
+pub struct Unpacked {
+    pub filename: String,
+    pub data: Vec<u8>,
+}
+
+/// The documents in `data` when it is gzip-compressed (one) or a zip
+/// archive (one per file entry), or `None` for any other upload. ZIP-based
+/// document formats such as DOCX are documents, not archives, and an
+/// unreadable ZIP is left for the parsers to reject. At most
+/// `max_bytes` are decompressed in all, whatever the archive claims, so a
+/// zip bomb fails instead of exhausting memory.
+pub fn unpack(filename: &str, data: &[u8], max_bytes: usize) -> Option<Result<Vec<Unpacked>, ArchiveError>> {
+    match data {
+        [0x1f, 0x8b, ..] => Some(gunzip(filename, data, max_bytes)),
+        [b'P', b'K', 0x03, 0x04, ..] => {
+            let archive = zip::ZipArchive::new(Cursor::new(data)).ok()?;
+            (!is_package(&archive)).then(|| unzip(filename, archive, max_bytes))
+        }
+        _ => None,
+    }
+}
+
+fn gunzip(filename: &str, data: &[u8], max_bytes: usize) -> Result<Vec<Unpacked>, ArchiveError> {
+    let decoder = flate2::read::MultiGzDecoder::new(data);
+    let data = read_capped(decoder, max_bytes)
+        .map_err(|e| ArchiveError::Invalid(filename.to_string(), e.to_string()))?
+        .ok_or_else(|| ArchiveError::TooLarge(filename.to_string(), max_bytes))?;
+    let inner = filename
+        .strip_suffix(".gz")
+        .or_else(|| filename.strip_suffix(".gzip"))
+        .unwrap_or(filename);
+    Ok(vec![Unpacked {
+        filename: inner.to_string(),
+        data,
+    }])
+}
+
+fn unzip(
+    filename: &str,
+    mut archive: zip::ZipArchive<Cursor<&[u8]>>,
+    max_bytes: usize,
+) -> Result<Vec<Unpacked>, ArchiveError> {
+    let invalid = |e: &dyn std::fmt::Display| ArchiveError::Invalid(filename.to_string(), e.to_string());
+    let mut files = Vec::new();
+    let mut remaining = max_bytes;
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index).map_err(|e| invalid(&e))?;
+        let name = entry.name().to_string();
+        // Folders and the metadata macOS adds are not documents
+        let hidden = name.split('/').any(|part| part.starts_with('.') || part == "__MACOSX");
+        if entry.is_dir() || hidden {
+            continue;
+        }
+        let data = read_capped(entry, remaining)
+            .map_err(|e| invalid(&e))?
+            .ok_or_else(|| ArchiveError::TooLarge(filename.to_string(), max_bytes))?;
+        remaining -= data.len();
+        files.push(Unpacked {
+            filename: format!("{}/{}", filename, name),
+            data,
+        });
+    }
+    Ok(files)
+}
+
+/// Whether a ZIP is an Open Packaging Conventions (DOCX, XLSX) or
+/// OpenDocument/EPUB package rather than an archive of documents.
+fn is_package(archive: &zip::ZipArchive<Cursor<&[u8]>>) -> bool {
+    archive.index_for_name("[Content_Types].xml").is_some() || archive.index_for_name("mimetype").is_some()
+}
+
+/// All of `reader`, or `None` once it yields more than `max_bytes`.
+fn read_capped(reader: impl Read, max_bytes: usize) -> std::io::Result<Option<Vec<u8>>> {
+    let mut data = Vec::new();
+    reader.take(max_bytes as u64 + 1).read_to_end(&mut data)?;
+    Ok((data.len() <= max_bytes).then_some(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::fixtures::{self, PdfBuilder};
+    use std::io::Write;
+
+    #[test]
+    fn test_unpacks_gzip_and_zip() {
+        let pdf = PdfBuilder::new().page(&["Cells divide by mitosis."]).build();
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(&pdf).unwrap();
+        let gzip = gzip.finish().unwrap();
+
+        let files = unpack("notes.pdf.gz", &gzip, 1 << 20).unwrap().unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].filename, "notes.pdf");
+        assert_eq!(files[0].data, pdf);
+        assert!(matches!(unpack("notes.pdf.gz", &gzip, 100), Some(Err(ArchiveError::TooLarge(..)))));
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        zip.add_directory("week1/", options).unwrap();
+        zip.start_file("week1/notes.pdf", options).unwrap();
+        zip.write_all(&pdf).unwrap();
+        zip.start_file("week1/summary.txt", options).unwrap();
+        zip.write_all(b"Mitosis has four phases.").unwrap();
+        zip.start_file("__MACOSX/week1/._notes.pdf", options).unwrap();
+        zip.write_all(b"resource fork").unwrap();
+        let zip = zip.finish().unwrap().into_inner();
+
+        let files = unpack("course.zip", &zip, 1 << 20).unwrap().unwrap();
+        let names: Vec<&str> = files.iter().map(|f| f.filename.as_str()).collect();
+        assert_eq!(names, ["course.zip/week1/notes.pdf", "course.zip/week1/summary.txt"]);
+        assert_eq!(files[1].data, b"Mitosis has four phases.");
+        // The limit covers every entry together
+        assert!(matches!(unpack("course.zip", &zip, pdf.len() + 10), Some(Err(ArchiveError::TooLarge(..)))));
+
+        // Documents that happen to be ZIPs are left alone
+        let docx = fixtures::docx(&["Cells divide."], "");
+        assert!(unpack("notes.docx", &docx, 1 << 20).is_none());
+        assert!(unpack("notes.pdf", &pdf, 1 << 20).is_none());
+    }
+}
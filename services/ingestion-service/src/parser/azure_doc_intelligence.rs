@@ -1,23 +1,47 @@
 // Azure Document Intelligence parser implementation
 
-use reqwest::Client;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Client, StatusCode};
 use serde::Deserialize;
-use std::io::Read;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hasher};
 use std::time::Duration;
 
-use super::traits::{Page, Parser, ParserError};
+use super::traits::{DocumentInfo, Page, Parser, ParserError};
+
+/// Polls for an analysis result before giving up.
+const DEFAULT_MAX_POLLS: u32 = 30;
+
+/// Longest wait between two polls, however many came before.
+const MAX_POLL_DELAY: Duration = Duration::from_secs(30);
 
 /// Azure Document Intelligence API response
 #[derive(Debug, Deserialize)]
 struct AnalyzeResult {
+    /// `notStarted`, `running`, `succeeded` or `failed`.
+    #[serde(default)]
+    status: Option<String>,
     #[serde(rename = "analyzeResult")]
     analyze_result: Option<DocumentAnalysis>,
+    /// Why the analysis failed.
+    error: Option<AnalysisError>,
 }
 
 #[derive(Debug, Deserialize)]
+struct AnalysisError {
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct DocumentAnalysis {
     pages: Option<Vec<DocumentPage>>,
+    #[allow(dead_code)]
     content: Option<String>,
+    /// Only returned when key-value extraction was requested.
+    #[serde(default)]
+    key_value_pairs: Vec<KeyValuePair>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -32,11 +56,107 @@ struct Line {
     content: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct KeyValuePair {
+    key: KeyValueElement,
+    /// Absent for keys detected without a value, such as empty form fields.
+    value: Option<KeyValueElement>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KeyValueElement {
+    content: String,
+    #[serde(default)]
+    bounding_regions: Vec<BoundingRegion>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BoundingRegion {
+    page_number: u32,
+}
+
+/// Pages of an analysis result. Blank pages are skipped.
+pub struct AnalyzedPages {
+    pages: std::vec::IntoIter<DocumentPage>,
+    key_values: BTreeMap<String, String>,
+    /// `key: value` lines to append to each page, by page number.
+    injected: HashMap<u32, Vec<String>>,
+}
+
+impl AnalyzedPages {
+    fn new(analysis: DocumentAnalysis, inject_key_values: bool) -> Self {
+        let mut key_values = BTreeMap::new();
+        let mut injected: HashMap<u32, Vec<String>> = HashMap::new();
+        for pair in analysis.key_value_pairs {
+            let key = pair.key.content.trim().trim_end_matches(':').trim_end().to_string();
+            if key.is_empty() {
+                continue;
+            }
+            let value = pair.value.map(|v| v.content.trim().to_string()).unwrap_or_default();
+            if inject_key_values {
+                if let Some(region) = pair.key.bounding_regions.first() {
+                    injected.entry(region.page_number).or_default().push(format!("{}: {}", key, value));
+                }
+            }
+            // Forms repeat labels on continuation pages; keep the first value
+            key_values.entry(key).or_insert(value);
+        }
+        Self {
+            pages: analysis.pages.unwrap_or_default().into_iter(),
+            key_values,
+            injected,
+        }
+    }
+
+    /// Key-value pairs of the whole document, field name to value.
+    pub fn key_values(&self) -> &BTreeMap<String, String> {
+        &self.key_values
+    }
+}
+
+impl Iterator for AnalyzedPages {
+    type Item = Page;
+
+    fn next(&mut self) -> Option<Page> {
+        for doc_page in self.pages.by_ref() {
+            let mut text = String::new();
+            for line in doc_page.lines.unwrap_or_default() {
+                text.push_str(&line.content);
+                text.push('\n');
+            }
+            let page_num = doc_page.page_number as u32;
+            for line in self.injected.remove(&page_num).unwrap_or_default() {
+                text.push_str(&line);
+                text.push('\n');
+            }
+            if !text.trim().is_empty() {
+                return Some(Page {
+                    page_num,
+                    text: text.trim().to_string(),
+                    images: Vec::new(),
+                    ..Default::default()
+                });
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.pages.len()))
+    }
+}
+
 /// Parser using Azure Document Intelligence (Form Recognizer)
 pub struct AzureDocIntelligenceParser {
     endpoint: String,
     api_key: String,
     client: Client,
+    poll_interval: Duration,
+    max_polls: u32,
+    key_value_pairs: bool,
+    inject_key_values: bool,
 }
 
 impl AzureDocIntelligenceParser {
@@ -45,12 +165,79 @@ impl AzureDocIntelligenceParser {
             endpoint,
             api_key,
             client: Client::new(),
+            poll_interval: Duration::from_secs(2),
+            max_polls: DEFAULT_MAX_POLLS,
+            key_value_pairs: false,
+            inject_key_values: false,
         }
     }
 
+    /// Time to wait before the first poll for the analysis result. Each
+    /// later poll waits about twice as long as the one before, up to 30s.
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Polls for the analysis result before giving up (default 30).
+    pub fn with_max_polls(mut self, max_polls: u32) -> Self {
+        self.max_polls = max_polls;
+        self
+    }
+
+    /// Analyze with the layout model and capture the form fields it detects
+    /// as key-value pairs. With `inject`, each pair is also appended to the
+    /// text of the page its key appears on as a `key: value` line.
+    pub fn with_key_value_pairs(mut self, enabled: bool, inject: bool) -> Self {
+        self.key_value_pairs = enabled;
+        self.inject_key_values = enabled && inject;
+        self
+    }
+
+    /// Analyze `data` and return its pages, converted one at a time as the
+    /// iterator is advanced.
+    pub async fn analyze_pages(&self, data: &[u8]) -> Result<AnalyzedPages, ParserError> {
+        let analysis = self
+            .analyze_document(data)
+            .await?
+            .analyze_result
+            .ok_or_else(|| ParserError::ParseError("No analysis result".to_string()))?;
+
+        Ok(AnalyzedPages::new(analysis, self.inject_key_values))
+    }
+
+    /// Parse `data` into pages.
+    pub async fn parse_async(&self, data: &[u8]) -> Result<Vec<Page>, ParserError> {
+        self.parse_with_info(data).await.map(|(pages, _)| pages)
+    }
+
+    /// Parse `data` into pages along with the document's key-value pairs.
+    pub async fn parse_with_info(&self, data: &[u8]) -> Result<(Vec<Page>, DocumentInfo), ParserError> {
+        let analyzed = self.analyze_pages(data).await?;
+        let info = DocumentInfo {
+            key_values: analyzed.key_values().clone(),
+            ..Default::default()
+        };
+        let pages: Vec<Page> = analyzed.collect();
+
+        if pages.is_empty() {
+            return Err(ParserError::EmptyDocument("the Azure analysis".to_string()));
+        }
+
+        Ok((pages, info))
+    }
+
     /// Analyze document using Azure Document Intelligence
     async fn analyze_document(&self, data: &[u8]) -> Result<AnalyzeResult, ParserError> {
-        let url = format!("{}/formrecognizer/documentModels/prebuilt-read:analyze?api-version=2023-07-31", self.endpoint);
+        // The read model does not detect key-value pairs
+        let url = if self.key_value_pairs {
+            format!(
+                "{}/formrecognizer/documentModels/prebuilt-layout:analyze?api-version=2023-07-31&features=keyValuePairs",
+                self.endpoint
+            )
+        } else {
+            format!("{}/formrecognizer/documentModels/prebuilt-read:analyze?api-version=2023-07-31", self.endpoint)
+        };
 
         // Submit document for analysis
         let response = self
@@ -79,9 +266,12 @@ impl AzureDocIntelligenceParser {
             .map_err(|e| ParserError::ParseError(format!("Invalid header: {}", e)))?
             .to_string();
 
-        // Poll for results
-        for _ in 0..30 {
-            tokio::time::sleep(Duration::from_secs(2)).await;
+        // Poll for results, backing off while the analysis runs or the
+        // service is overloaded
+        let mut delay = self.poll_delay(0);
+        for attempt in 1..=self.max_polls {
+            tokio::time::sleep(delay).await;
+            delay = self.poll_delay(attempt);
 
             let result_response = self
                 .client
@@ -91,68 +281,71 @@ impl AzureDocIntelligenceParser {
                 .await
                 .map_err(|e| ParserError::ParseError(format!("Failed to get results: {}", e)))?;
 
-            if result_response.status().is_success() {
-                let result: AnalyzeResult = result_response
-                    .json()
-                    .await
-                    .map_err(|e| ParserError::ParseError(format!("Failed to parse response: {}", e)))?;
-
-                if result.analyze_result.is_some() {
-                    return Ok(result);
+            let status = result_response.status();
+            if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+                if status == StatusCode::TOO_MANY_REQUESTS {
+                    delay = retry_after(result_response.headers()).unwrap_or(delay);
                 }
+                tracing::debug!("Azure analysis poll returned {}, retrying in {:?}", status, delay);
+                continue;
+            }
+            if !status.is_success() {
+                return Err(ParserError::ParseError(format!("Azure API error while polling: {}", status)));
+            }
+
+            let result: AnalyzeResult = result_response
+                .json()
+                .await
+                .map_err(|e| ParserError::ParseError(format!("Failed to parse response: {}", e)))?;
+            if result.status.as_deref() == Some("failed") {
+                let reason = result.error.map(|e| e.message).unwrap_or_default();
+                return Err(ParserError::ParseError(format!("Azure analysis failed: {}", reason)));
+            }
+            if result.analyze_result.is_some() {
+                return Ok(result);
             }
         }
 
-        Err(ParserError::ParseError("Analysis timeout".to_string()))
+        Err(ParserError::ParseError(format!("Analysis timeout after {} polls", self.max_polls)))
     }
-}
-
-impl Parser for AzureDocIntelligenceParser {
-    fn parse<R: Read>(&self, mut reader: R) -> Result<Vec<Page>, ParserError> {
-        // Read bytes from reader
-        let mut data = Vec::new();
-        reader.read_to_end(&mut data)
-            .map_err(|e| ParserError::Io(e))?;
-
-        // Use tokio::task::block_in_place to run async code in sync context
-        let result = tokio::task::block_in_place(|| {
-            tokio::runtime::Handle::current().block_on(self.analyze_document(&data))
-        })?;
-
-        let analysis = result
-            .analyze_result
-            .ok_or_else(|| ParserError::ParseError("No analysis result".to_string()))?;
-
-        let mut pages = Vec::new();
 
-        if let Some(doc_pages) = analysis.pages {
-            for doc_page in doc_pages {
-                let mut text = String::new();
-
-                if let Some(lines) = doc_page.lines {
-                    for line in lines {
-                        text.push_str(&line.content);
-                        text.push('\n');
-                    }
-                }
-
-                if !text.trim().is_empty() {
-                    pages.push(Page {
-                        page_num: doc_page.page_number as u32,
-                        text: text.trim().to_string(),
-                        images: Vec::new(),
-                    });
-                }
-            }
-        }
+    /// Wait before poll `attempt` (counting from 0): the poll interval
+    /// doubled per attempt, capped, plus up to a quarter more at random so
+    /// clients that started together don't poll in lockstep.
+    fn poll_delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .poll_interval
+            .saturating_mul(1 << attempt.min(16))
+            .min(MAX_POLL_DELAY);
+        let random = std::collections::hash_map::RandomState::new().build_hasher().finish();
+        backoff.mul_f64(1.0 + 0.25 * (random as f64 / u64::MAX as f64))
+    }
+}
 
-        if pages.is_empty() {
-            return Err(ParserError::ParseError(
-                "No pages extracted".to_string(),
-            ));
-        }
+/// The wait a `Retry-After` header asks for, when given in seconds, capped
+/// like the backoff so a bad header can't stall the parse for hours.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let seconds = headers.get(RETRY_AFTER)?.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(seconds).min(MAX_POLL_DELAY))
+}
 
-        Ok(pages)
+impl Parser for AzureDocIntelligenceParser {
+    /// Blocks on `parse_async` from a thread of its own, so callers inside
+    /// an async runtime of either flavor neither panic nor stall a worker
+    /// that the analysis needs. Async callers should use `parse_async`.
+    fn parse_bytes(&self, data: &[u8]) -> Result<Vec<Page>, ParserError> {
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .map_err(|e| ParserError::ParseError(format!("Failed to start runtime: {}", e)))?
+                        .block_on(self.parse_async(data))
+                })
+                .join()
+                .unwrap_or_else(|_| Err(ParserError::ParseError("Azure analysis panicked".to_string())))
+        })
     }
 
     fn supported_extensions(&self) -> &[&str] {
@@ -170,3 +363,103 @@ impl Parser for AzureDocIntelligenceParser {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::IntoResponse;
+    use axum::routing::{get, post};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const RESULT: &str = r#"{
+        "status": "succeeded",
+        "analyzeResult": {"pages": [{"pageNumber": 1, "lines": [{"content": "Invoice 4711"}]}]}
+    }"#;
+
+    /// An Azure endpoint answering successive polls with `polls`, the last
+    /// repeating; rate-limited answers ask for a one second wait. Returns
+    /// the endpoint and the number of polls served.
+    async fn mock_azure(polls: Vec<(u16, &'static str)>) -> (String, Arc<AtomicUsize>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let operation = format!("http://{}/operations/1", addr);
+        let served = Arc::new(AtomicUsize::new(0));
+        let count = served.clone();
+        let poll = get(move || async move {
+            let (status, body) = polls[count.fetch_add(1, Ordering::SeqCst).min(polls.len() - 1)];
+            let status = axum::http::StatusCode::from_u16(status).unwrap();
+            let mut response = (status, [("content-type", "application/json")], body).into_response();
+            if status == axum::http::StatusCode::TOO_MANY_REQUESTS {
+                response.headers_mut().insert("retry-after", "1".parse().unwrap());
+            }
+            response
+        });
+        let app = axum::Router::new()
+            .route(
+                "/formrecognizer/documentModels/prebuilt-read:analyze",
+                post(move || async move { (axum::http::StatusCode::ACCEPTED, [("operation-location", operation)]) }),
+            )
+            .route("/operations/1", poll);
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        (format!("http://{}", addr), served)
+    }
+
+    #[tokio::test]
+    async fn test_polling_backs_off_on_rate_limit_and_server_errors() {
+        let (endpoint, served) = mock_azure(vec![(503, ""), (429, ""), (200, RESULT)]).await;
+        let parser =
+            AzureDocIntelligenceParser::new(endpoint, "key".to_string()).with_poll_interval(Duration::from_millis(1));
+
+        let start = std::time::Instant::now();
+        let pages: Vec<Page> = parser.analyze_pages(b"%PDF-1.5").await.unwrap().collect();
+        assert_eq!(pages[0].text, "Invoice 4711");
+        assert_eq!(served.load(Ordering::SeqCst), 3);
+        // Retry-After outweighs the millisecond backoff
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_retry_after_is_capped() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, "5".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(5)));
+        headers.insert(RETRY_AFTER, "86400".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(MAX_POLL_DELAY));
+    }
+
+    #[tokio::test]
+    async fn test_failed_analysis_stops_polling() {
+        let running = r#"{"status": "running"}"#;
+        let failed = r#"{
+            "status": "failed",
+            "error": {"code": "InvalidContent", "message": "The file is corrupted."}
+        }"#;
+        let (endpoint, served) = mock_azure(vec![(200, running), (200, failed)]).await;
+        let parser = AzureDocIntelligenceParser::new(endpoint, "key".to_string())
+            .with_poll_interval(Duration::from_millis(1))
+            .with_max_polls(10);
+
+        let Err(error) = parser.analyze_pages(b"%PDF-1.5").await else {
+            panic!("a failed analysis must not succeed");
+        };
+        assert!(error.to_string().contains("The file is corrupted."));
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_parses_on_current_thread_runtime() {
+        let (endpoint, _) = mock_azure(vec![(200, RESULT)]).await;
+        let parser =
+            AzureDocIntelligenceParser::new(endpoint, "key".to_string()).with_poll_interval(Duration::from_millis(1));
+
+        let pages = parser.parse_async(b"%PDF-1.5").await.unwrap();
+        assert_eq!(pages[0].text, "Invoice 4711");
+
+        // The sync wrapper must not block the runtime serving the mock
+        let pages = tokio::task::spawn_blocking(move || parser.parse_bytes(b"%PDF-1.5")).await.unwrap().unwrap();
+        assert_eq!(pages[0].text, "Invoice 4711");
+    }
+}
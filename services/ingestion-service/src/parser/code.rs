@@ -0,0 +1,64 @@
+// Helpers for tagging code blocks found by the Markdown and HTML parsers
+
+/// Keyword signatures checked in order; the first language with enough hits wins.
+const SIGNATURES: &[(&str, &[&str])] = &[
+    ("rust", &["fn ", "let mut ", "impl ", "pub fn", "::new(", "-> "]),
+    ("python", &["def ", "import ", "elif ", "self.", "print(", "None"]),
+    ("java", &["public class", "public static void", "System.out", "private ", "new "]),
+    ("typescript", &["interface ", ": string", ": number", "export ", "=> "]),
+    ("javascript", &["function ", "const ", "console.log", "=> ", "let "]),
+    ("c", &["#include", "int main", "printf(", "->", "NULL"]),
+    ("sql", &["SELECT ", "FROM ", "WHERE ", "INSERT INTO", "JOIN "]),
+    ("bash", &["#!/bin/", "echo ", "fi\n", "$(", "export "]),
+];
+
+const MIN_SIGNATURE_HITS: usize = 2;
+
+/// Language named by a Markdown fence info string (```` ```python title="x" ````).
+pub(crate) fn language_from_info(info: &str) -> Option<String> {
+    info.split_whitespace()
+        .next()
+        .map(|lang| lang.trim_matches(|c| c == '{' || c == '}' || c == '.').to_ascii_lowercase())
+        .filter(|lang| !lang.is_empty())
+}
+
+/// Language named by an HTML class list (`language-python`, `lang-rust`).
+pub(crate) fn language_from_class(class: &str) -> Option<String> {
+    class.split_whitespace().find_map(|class| {
+        class
+            .strip_prefix("language-")
+            .or_else(|| class.strip_prefix("lang-"))
+            .filter(|lang| !lang.is_empty())
+            .map(str::to_ascii_lowercase)
+    })
+}
+
+/// Guess the language of an untagged code block from keyword signatures.
+pub(crate) fn detect_language(code: &str) -> Option<String> {
+    SIGNATURES
+        .iter()
+        .map(|(lang, keywords)| (lang, keywords.iter().filter(|k| code.contains(*k)).count()))
+        .filter(|(_, hits)| *hits >= MIN_SIGNATURE_HITS)
+        .max_by_key(|(_, hits)| *hits)
+        .map(|(lang, _)| lang.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_language_from_markers() {
+        assert_eq!(language_from_info("python title=\"x.py\"").as_deref(), Some("python"));
+        assert_eq!(language_from_info("").as_deref(), None);
+        assert_eq!(language_from_class("hljs language-Rust").as_deref(), Some("rust"));
+        assert_eq!(language_from_class("highlight").as_deref(), None);
+    }
+
+    #[test]
+    fn test_detect_language_from_keywords() {
+        let code = "import os\n\ndef main():\n    print(os.getcwd())\n";
+        assert_eq!(detect_language(code).as_deref(), Some("python"));
+        assert_eq!(detect_language("just some words").as_deref(), None);
+    }
+}
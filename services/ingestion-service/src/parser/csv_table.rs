@@ -0,0 +1,116 @@
+// CSV parser implementation
+
+use csv::{ReaderBuilder, StringRecord};
+
+use super::traits::{Page, Parser, ParserError};
+
+const DEFAULT_ROWS_PER_PAGE: usize = 50;
+
+/// Parser for CSV tables. The header row names the columns and each data
+/// row becomes a block of `column: value` lines; rows are grouped into
+/// pages so a page stays a reasonable number of tokens.
+pub struct CsvParser {
+    rows_per_page: usize,
+}
+
+impl CsvParser {
+    pub fn new() -> Self {
+        Self {
+            rows_per_page: DEFAULT_ROWS_PER_PAGE,
+        }
+    }
+
+    /// Data rows per page (at least 1, default 50).
+    pub fn with_rows_per_page(mut self, rows: usize) -> Self {
+        self.rows_per_page = rows.max(1);
+        self
+    }
+}
+
+impl Default for CsvParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `column: value` lines for one row. Missing trailing fields read as
+/// empty and extra fields are numbered past the header.
+fn render_row(headers: &StringRecord, row: &StringRecord) -> String {
+    let columns = headers.len().max(row.len());
+    (0..columns)
+        .map(|i| {
+            let name = headers.get(i).map(str::trim).filter(|name| !name.is_empty());
+            let name = name.map_or_else(|| format!("column {}", i + 1), str::to_string);
+            format!("{}: {}", name, row.get(i).unwrap_or_default().trim())
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl Parser for CsvParser {
+    fn parse_bytes(&self, data: &[u8]) -> Result<Vec<Page>, ParserError> {
+        let mut reader = ReaderBuilder::new().flexible(true).from_reader(data);
+        let headers = reader
+            .headers()
+            .map_err(|e| ParserError::ParseError(format!("Invalid CSV header: {}", e)))?
+            .clone();
+
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            let record = record.map_err(|e| ParserError::ParseError(format!("Invalid CSV row: {}", e)))?;
+            if record.iter().all(|field| field.trim().is_empty()) {
+                continue;
+            }
+            rows.push(render_row(&headers, &record));
+        }
+
+        if rows.is_empty() {
+            return Err(ParserError::ParseError("No data rows found in CSV".to_string()));
+        }
+        Ok(rows
+            .chunks(self.rows_per_page)
+            .enumerate()
+            .map(|(i, rows)| Page {
+                page_num: i as u32 + 1,
+                text: rows.join("\n\n"),
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["csv"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &["text/csv"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quoted_fields_and_embedded_newlines() {
+        let data = b"student,comment,grade\n\"Doe, Jane\",\"Strong work,\nkeep going\",A\nRoe,\"Said \"\"hi\"\"\",B\n";
+        let pages = CsvParser::new().parse_bytes(data).unwrap();
+
+        assert_eq!(pages.len(), 1);
+        assert_eq!(
+            pages[0].text,
+            "student: Doe, Jane\ncomment: Strong work,\nkeep going\ngrade: A\n\n\
+             student: Roe\ncomment: Said \"hi\"\ngrade: B"
+        );
+    }
+
+    #[test]
+    fn test_missing_trailing_field_and_paging() {
+        let data = b"name,score,remark\nAda,98,top\nBen,72\nCy,85,ok\n";
+        let pages = CsvParser::new().with_rows_per_page(2).parse_bytes(data).unwrap();
+
+        assert_eq!(pages.iter().map(|p| p.page_num).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(pages[0].text.ends_with("name: Ben\nscore: 72\nremark: "));
+        assert_eq!(pages[1].text, "name: Cy\nscore: 85\nremark: ok");
+    }
+}
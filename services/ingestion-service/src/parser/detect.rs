@@ -0,0 +1,117 @@
+// Format detection from a document's leading bytes
+
+use std::io::{Cursor, Read};
+
+use super::registry::normalize_mime;
+
+const PDF: &str = "application/pdf";
+const DOCX: &str = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+const HTML: &str = "text/html";
+const EPUB: &str = "application/epub+zip";
+
+/// Content type of the main part of a Word document, as listed in a
+/// package's `[Content_Types].xml`.
+const DOCX_MAIN_PART: &str = "application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml";
+
+/// The canonical MIME type of `data`, judged by its content alone.
+///
+/// Recognizes PDF (`%PDF-`), DOCX (a ZIP package whose content types list a
+/// Word main document), EPUB (a ZIP whose `mimetype` entry says so) and HTML
+/// (a leading `<!DOCTYPE html>` or `<html`). Other ZIP packages, such as
+/// spreadsheets, are neither.
+pub fn detect_format(data: &[u8]) -> Option<&'static str> {
+    match data {
+        [b'%', b'P', b'D', b'F', b'-', ..] => Some(PDF),
+        [b'P', b'K', 0x03, 0x04, ..] if is_epub(data) => Some(EPUB),
+        [b'P', b'K', 0x03, 0x04, ..] => is_docx(data).then_some(DOCX),
+        _ => is_html(data).then_some(HTML),
+    }
+}
+
+/// The format sniffed from `data` when `declared` says nothing useful,
+/// i.e. is missing or `application/octet-stream`. A specific declared type
+/// is trusted as is.
+pub fn sniff_content_type(declared: &str, data: &[u8]) -> Option<&'static str> {
+    let mime = normalize_mime(declared);
+    if mime.is_empty() || mime == "application/octet-stream" {
+        detect_format(data)
+    } else {
+        None
+    }
+}
+
+fn is_docx(data: &[u8]) -> bool {
+    read_content_types(data).is_some_and(|xml| xml.contains(DOCX_MAIN_PART))
+}
+
+fn is_epub(data: &[u8]) -> bool {
+    let Ok(mut archive) = zip::ZipArchive::new(Cursor::new(data)) else { return false };
+    let mut mimetype = String::new();
+    archive
+        .by_name("mimetype")
+        .is_ok_and(|mut file| file.read_to_string(&mut mimetype).is_ok() && mimetype.trim() == EPUB)
+}
+
+/// Read `[Content_Types].xml` from an Open Packaging Conventions ZIP.
+fn read_content_types(data: &[u8]) -> Option<String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).ok()?;
+    let mut xml = String::new();
+    archive.by_name("[Content_Types].xml").ok()?.read_to_string(&mut xml).ok()?;
+    Some(xml)
+}
+
+fn is_html(data: &[u8]) -> bool {
+    let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+    let start = data.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(data.len());
+    let head = &data[start..data.len().min(start + 14)];
+    head.len() >= 5 && (head.eq_ignore_ascii_case(b"<!doctype html") || head[..5].eq_ignore_ascii_case(b"<html"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::fixtures::PdfBuilder;
+    use crate::parser::for_content_type;
+
+    fn zip(parts: &[(&str, &str)]) -> Vec<u8> {
+        use std::io::Write;
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in parts {
+            writer.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_detects_formats_by_magic_bytes() {
+        let pdf = PdfBuilder::new().page(&["Cells divide."]).build();
+        assert_eq!(detect_format(&pdf), Some(PDF));
+
+        let mut docx = Cursor::new(Vec::new());
+        docx_rs::Docx::new().build().pack(&mut docx).unwrap();
+        assert_eq!(detect_format(docx.get_ref()), Some(DOCX));
+        let xlsx = zip(&[("[Content_Types].xml", "<Types/>"), ("xl/workbook.xml", "<workbook/>")]);
+        assert_eq!(detect_format(&xlsx), None);
+        let epub = crate::parser::fixtures::epub("On Cells", "R. Hooke", &[("ch1.xhtml", "<p>Cells divide.</p>")]);
+        assert_eq!(detect_format(&epub), Some(EPUB));
+
+        assert_eq!(detect_format(b"\xEF\xBB\xBF\n  <!DOCTYPE html><p>Hi</p>"), Some(HTML));
+        assert_eq!(detect_format(b"<HTML><body>Hi</body></HTML>"), Some(HTML));
+        assert_eq!(detect_format(b"<!DOCTYPE note><note/>"), None);
+        assert_eq!(detect_format(b"Plain notes"), None);
+        assert_eq!(detect_format(b""), None);
+    }
+
+    #[test]
+    fn test_sniffs_only_generic_declared_types() {
+        let pdf = PdfBuilder::new().page(&["Cells divide."]).build();
+        assert_eq!(sniff_content_type("application/octet-stream", &pdf), Some(PDF));
+        assert_eq!(sniff_content_type("", &pdf), Some(PDF));
+        assert_eq!(sniff_content_type("text/plain", &pdf), None);
+
+        // The sniffed type picks the parser a wrong extension would not
+        let mime = sniff_content_type("application/octet-stream", b"<html><p>Hi</p></html>").unwrap();
+        assert_eq!(for_content_type(mime, "page.txt").unwrap().name(), "HtmlParser");
+    }
+}
@@ -1,84 +1,311 @@
 // DOCX parser implementation using docx-rs
 
-use std::io::Read;
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
 
-use super::traits::{Page, Parser, ParserError};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use super::html::DEFAULT_PAGE_CHARS;
+use super::traits::{DocumentInfo, Image, Page, PageStream, Parser, ParserError};
+
+/// Package part holding the Dublin Core document properties.
+const CORE_PROPERTIES_PART: &str = "docProps/core.xml";
 
 /// Parser for DOCX (Microsoft Word) documents
-pub struct DocxParser;
+pub struct DocxParser {
+    page_chars: usize,
+}
 
 impl DocxParser {
     pub fn new() -> Self {
-        Self
+        Self {
+            page_chars: DEFAULT_PAGE_CHARS,
+        }
+    }
+
+    /// Close a page after about `page_chars` characters of text (default
+    /// [`DEFAULT_PAGE_CHARS`]) when the document has no page or section
+    /// breaks of its own; 0 keeps such documents on one page.
+    pub fn with_page_chars(mut self, page_chars: usize) -> Self {
+        self.page_chars = page_chars;
+        self
     }
 }
 
-impl Parser for DocxParser {
-    fn parse<R: Read>(&self, mut reader: R) -> Result<Vec<Page>, ParserError> {
-        // Read bytes from reader
-        let mut data = Vec::new();
-        reader.read_to_end(&mut data)
-            .map_err(|e| ParserError::Io(e))?;
-
-        // Parse DOCX file
-        let docx = docx_rs::read_docx(&data)
-            .map_err(|e| ParserError::ParseError(format!("Failed to parse DOCX: {}", e)))?;
-
-        let mut pages = Vec::new();
-        let mut current_text = String::new();
-        let mut page_num = 1u32;
-
-        // Extract text from document
-        for child in docx.document.children {
+/// Read `docProps/core.xml` from a DOCX package.
+fn read_core_properties(data: &[u8]) -> Option<DocumentInfo> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).ok()?;
+    let mut xml = String::new();
+    archive.by_name(CORE_PROPERTIES_PART).ok()?.read_to_string(&mut xml).ok()?;
+    Some(parse_core_properties(&xml))
+}
+
+fn parse_core_properties(xml: &str) -> DocumentInfo {
+    let mut info = DocumentInfo::default();
+    let mut reader = Reader::from_str(xml);
+    let mut element: Option<String> = None;
+    let mut value = String::new();
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                element = Some(String::from_utf8_lossy(e.local_name().as_ref()).into_owned());
+                value.clear();
+            }
+            Ok(Event::Text(t)) => {
+                if let Ok(text) = t.decode() {
+                    value.push_str(&text);
+                }
+            }
+            Ok(Event::GeneralRef(r)) => {
+                if let Ok(name) = r.decode() {
+                    value.push_str(&format!("&{};", name));
+                }
+            }
+            Ok(Event::End(_)) => {
+                let Some(name) = element.take() else { continue };
+                let text = quick_xml::escape::unescape(&value)
+                    .map(|v| v.trim().to_string())
+                    .unwrap_or_default();
+                if text.is_empty() {
+                    continue;
+                }
+                match name.as_str() {
+                    "title" => info.title = Some(text),
+                    "creator" => info.author = Some(text),
+                    "lastModifiedBy" => info.last_modified_by = Some(text),
+                    "created" => info.created_at = Some(text),
+                    "modified" => info.modified_at = Some(text),
+                    "revision" => info.revision = text.parse().ok(),
+                    _ => {}
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+
+    info
+}
+
+/// MIME type of an image from its leading magic bytes.
+fn image_content_type(data: &[u8]) -> &'static str {
+    match data {
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        [0xFF, 0xD8, 0xFF, ..] => "image/jpeg",
+        [b'G', b'I', b'F', b'8', ..] => "image/gif",
+        [b'B', b'M', ..] => "image/bmp",
+        [b'I', b'I', 0x2A, 0x00, ..] | [b'M', b'M', 0x00, 0x2A, ..] => "image/tiff",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "image/webp",
+        [0x01, 0x00, 0x00, 0x00, ..] => "image/emf",
+        _ => "application/octet-stream",
+    }
+}
+
+impl Default for DocxParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Text of a paragraph's runs, and the relationship ids of its pictures.
+fn paragraph_content(para: &docx_rs::Paragraph) -> (String, Vec<&str>) {
+    let mut text = String::new();
+    let mut pictures = Vec::new();
+    for child in &para.children {
+        let docx_rs::ParagraphChild::Run(run) = child else { continue };
+        for child in &run.children {
             match child {
-                docx_rs::DocumentChild::Paragraph(para) => {
-                    let mut para_text = String::new();
-                    for child in para.children {
-                        if let docx_rs::ParagraphChild::Run(run) = child {
-                            for child in run.children {
-                                if let docx_rs::RunChild::Text(text) = child {
-                                    para_text.push_str(&text.text);
-                                }
-                            }
-                        }
+                docx_rs::RunChild::Text(t) => text.push_str(&t.text),
+                docx_rs::RunChild::Drawing(drawing) => {
+                    if let Some(docx_rs::DrawingData::Pic(pic)) = &drawing.data {
+                        pictures.push(pic.id.as_str());
                     }
+                }
+                _ => {}
+            }
+        }
+    }
+    (text, pictures)
+}
 
-                    if !para_text.is_empty() {
-                        current_text.push_str(&para_text);
-                        current_text.push('\n');
-                    }
+/// Explicit page breaks around a paragraph.
+struct PageBreaks {
+    /// A new page starts with the paragraph.
+    before: bool,
+    /// The page ends with the paragraph.
+    after: bool,
+}
 
-                    // Split into pages every ~2000 characters (approximate page)
-                    if current_text.len() > 2000 {
-                        pages.push(Page {
-                            page_num,
-                            text: current_text.trim().to_string(),
-                            images: Vec::new(),
-                        });
-                        current_text.clear();
-                        page_num += 1;
+/// The page breaks a paragraph asks for. A page break ahead of any of its
+/// content starts a new page with it; one further in, or the end of a
+/// section that starts on a new page, ends the page after it.
+fn page_breaks(para: &docx_rs::Paragraph) -> PageBreaks {
+    let page_break = docx_rs::Break::new(docx_rs::BreakType::Page);
+    let mut breaks = PageBreaks {
+        before: para.property.page_break_before == Some(true),
+        after: false,
+    };
+    let mut content = false;
+    for child in &para.children {
+        let docx_rs::ParagraphChild::Run(run) = child else { continue };
+        for child in &run.children {
+            match child {
+                docx_rs::RunChild::Break(br) if *br == page_break => {
+                    if content {
+                        breaks.after = true;
+                    } else {
+                        breaks.before = true;
                     }
                 }
+                docx_rs::RunChild::Text(t) if !t.text.trim().is_empty() => content = true,
+                docx_rs::RunChild::Drawing(_) => content = true,
                 _ => {}
             }
         }
+    }
+    if let Some(section) = &para.property.section_property {
+        use docx_rs::SectionType;
+        breaks.after |= !matches!(section.section_type, Some(SectionType::Continuous | SectionType::NextColumn));
+    }
+    breaks
+}
+
+/// Pages of a read DOCX, each built from its paragraphs when requested.
+/// Where pages break is worked out up front from the document's page and
+/// section breaks, or from paragraph lengths when it has none, which is
+/// cheap next to assembling page text and copying images.
+struct DocxPages {
+    paragraphs: std::vec::IntoIter<docx_rs::Paragraph>,
+    /// Number and paragraph count of each page still to be built. Blank
+    /// pages are left out, but still counted in the numbering.
+    page_sizes: std::vec::IntoIter<(u32, usize)>,
+    /// Media bytes by relationship id, which drawings refer to
+    media: HashMap<String, Vec<u8>>,
+    image_count: usize,
+}
+
+impl DocxPages {
+    /// Pages of `docx`, closed after `page_chars` characters when it has no
+    /// explicit page or section breaks; DOCX files don't record where the
+    /// authoring application broke pages by itself.
+    fn new(docx: docx_rs::Docx, page_chars: usize) -> Self {
+        let media: HashMap<String, Vec<u8>> = docx
+            .images
+            .into_iter()
+            .map(|(id, _, image, _)| (id, image.0))
+            .collect();
+        let paragraphs: Vec<docx_rs::Paragraph> = docx
+            .document
+            .children
+            .into_iter()
+            .filter_map(|child| match child {
+                docx_rs::DocumentChild::Paragraph(para) => Some(*para),
+                _ => None,
+            })
+            .collect();
+
+        let breaks: Vec<PageBreaks> = paragraphs.iter().map(page_breaks).collect();
+        let explicit = breaks.iter().any(|b| b.before || b.after);
+
+        let mut page_sizes = Vec::new();
+        let (mut page_num, mut count, mut chars, mut blank) = (1, 0, 0, true);
+        let mut close_page = |page_num: &mut u32, count: &mut usize, blank: bool| {
+            // A blank page's paragraphs carry over to the next page
+            if !blank {
+                page_sizes.push((*page_num, *count));
+                *count = 0;
+            }
+            *page_num += 1;
+        };
+        for (para, breaks) in paragraphs.iter().zip(&breaks) {
+            if breaks.before && count > 0 {
+                close_page(&mut page_num, &mut count, blank);
+                (chars, blank) = (0, true);
+            }
+            let (text, pictures) = paragraph_content(para);
+            count += 1;
+            if !text.is_empty() {
+                chars += text.len() + 1;
+            }
+            blank &= text.trim().is_empty() && !pictures.iter().any(|id| media.contains_key(*id));
+            if breaks.after || (!explicit && page_chars > 0 && chars > page_chars) {
+                close_page(&mut page_num, &mut count, blank);
+                (chars, blank) = (0, true);
+            }
+        }
+        // Remaining text and images form the last page
+        if !blank {
+            page_sizes.push((page_num, count));
+        }
 
-        // Add remaining text as last page
-        if !current_text.trim().is_empty() {
-            pages.push(Page {
-                page_num,
-                text: current_text.trim().to_string(),
-                images: Vec::new(),
-            });
+        Self {
+            paragraphs: paragraphs.into_iter(),
+            page_sizes: page_sizes.into_iter(),
+            media,
+            image_count: 0,
         }
+    }
+}
+
+impl Iterator for DocxPages {
+    type Item = Page;
 
-        if pages.is_empty() {
-            return Err(ParserError::ParseError(
-                "No text content found in DOCX".to_string(),
-            ));
+    fn next(&mut self) -> Option<Page> {
+        let (page_num, size) = self.page_sizes.next()?;
+        let mut text = String::new();
+        let mut images = Vec::new();
+        for para in self.paragraphs.by_ref().take(size) {
+            let (para_text, pictures) = paragraph_content(&para);
+            for data in pictures.iter().filter_map(|id| self.media.get(*id)) {
+                self.image_count += 1;
+                images.push(Image {
+                    id: format!("img-{}", self.image_count),
+                    content_type: image_content_type(data).to_string(),
+                    data: data.clone(),
+                });
+            }
+            if !para_text.is_empty() {
+                text.push_str(&para_text);
+                text.push('\n');
+            }
         }
 
-        Ok(pages)
+        Some(Page {
+            page_num,
+            text: text.trim().to_string(),
+            images,
+            ..Default::default()
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.page_sizes.size_hint()
+    }
+}
+
+impl Parser for DocxParser {
+    fn parse_bytes(&self, data: &[u8]) -> Result<Vec<Page>, ParserError> {
+        self.parse_stream(data).collect()
+    }
+
+    fn parse_stream<'a>(&'a self, data: &'a [u8]) -> PageStream<'a> {
+        // Parse DOCX file; embedded images are kept as stored, not re-encoded
+        let options = docx_rs::ReadDocxOptions::default().with_image_previews(false);
+        let failed = |error: ParserError| -> PageStream<'a> { Box::new(std::iter::once(Err(error))) };
+        let pages = match docx_rs::read_docx_with_options(data, options) {
+            Ok(docx) => DocxPages::new(docx, self.page_chars),
+            Err(e) => return failed(ParserError::ParseError(format!("Failed to parse DOCX: {}", e))),
+        };
+        if pages.page_sizes.as_slice().is_empty() {
+            return failed(ParserError::EmptyDocument("DOCX".to_string()));
+        }
+        Box::new(pages.map(Ok))
+    }
+
+    fn document_info(&self, data: &[u8]) -> DocumentInfo {
+        read_core_properties(data).unwrap_or_default()
     }
 
     fn supported_extensions(&self) -> &[&str] {
@@ -93,6 +320,7 @@ impl Parser for DocxParser {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parser::fixtures;
 
     #[test]
     fn test_docx_parser_supported_extensions() {
@@ -107,5 +335,127 @@ mod tests {
         let mime_types = parser.supported_mime_types();
         assert!(mime_types.contains(&"application/vnd.openxmlformats-officedocument.wordprocessingml.document"));
     }
-}
 
+    #[test]
+    fn test_empty_docx_is_told_from_corrupt_one() {
+        let parser = DocxParser::new();
+        let empty = parser.parse_bytes(&fixtures::docx(&[], "")).unwrap_err();
+        assert!(matches!(empty, ParserError::EmptyDocument(_)));
+        let corrupt = parser.parse_bytes(b"PK\x03\x04 not a document").unwrap_err();
+        assert!(matches!(corrupt, ParserError::ParseError(_)));
+    }
+
+    #[test]
+    fn test_explicit_page_breaks_start_pages() {
+        use docx_rs::{BreakType, Docx, Paragraph, Run};
+        let text = |text: &str| Paragraph::new().add_run(Run::new().add_text(text));
+        let ending_page = Run::new().add_text("Mitosis has four phases.").add_break(BreakType::Page);
+        let docx = Docx::new()
+            .add_paragraph(text("Cells divide by mitosis."))
+            .add_paragraph(Paragraph::new().add_run(ending_page))
+            .add_paragraph(text("Meiosis halves the chromosome count."))
+            // A page left blank on purpose, then a heading on the page after
+            .add_paragraph(Paragraph::new().add_run(Run::new().add_break(BreakType::Page)))
+            .add_paragraph(text("Summary").page_break_before(true));
+        let mut data = Cursor::new(Vec::new());
+        docx.build().pack(&mut data).unwrap();
+
+        let pages = DocxParser::new().parse_bytes(&data.into_inner()).unwrap();
+        let pages: Vec<(u32, &str)> = pages.iter().map(|p| (p.page_num, p.text.as_str())).collect();
+        assert_eq!(
+            pages,
+            [
+                (1, "Cells divide by mitosis.\nMitosis has four phases."),
+                (2, "Meiosis halves the chromosome count."),
+                (4, "Summary"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_docx_core_properties() {
+        let core = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+  <dc:title>Cells &amp; Tissues</dc:title>
+  <dc:creator>Ada Lovelace</dc:creator>
+  <cp:lastModifiedBy>Grace Hopper</cp:lastModifiedBy>
+  <cp:revision>7</cp:revision>
+  <dcterms:created xsi:type="dcterms:W3CDTF">2024-02-01T09:30:00Z</dcterms:created>
+  <dcterms:modified xsi:type="dcterms:W3CDTF">2024-03-15T16:45:00Z</dcterms:modified>
+</cp:coreProperties>"#;
+        let data = fixtures::docx(&["Cells are the basic unit of life."], core);
+
+        let parser = DocxParser::new();
+        assert_eq!(
+            parser.document_info(&data),
+            DocumentInfo {
+                title: Some("Cells & Tissues".to_string()),
+                author: Some("Ada Lovelace".to_string()),
+                last_modified_by: Some("Grace Hopper".to_string()),
+                created_at: Some("2024-02-01T09:30:00Z".to_string()),
+                modified_at: Some("2024-03-15T16:45:00Z".to_string()),
+                revision: Some(7),
+                ..Default::default()
+            }
+        );
+        assert!(parser.parse(Cursor::new(data)).unwrap()[0].text.contains("basic unit"));
+    }
+
+    #[test]
+    fn test_extracts_embedded_png() {
+        let png = b"\x89PNG\r\n\x1a\nfake image bytes".to_vec();
+        let data = fixtures::docx_with_image("Figure 1 shows a plant cell.", &png);
+
+        let pages = DocxParser::new().parse(Cursor::new(data)).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].images.len(), 1);
+        let image = &pages[0].images[0];
+        assert_eq!(image.id, "img-1");
+        assert_eq!(image.content_type, "image/png");
+        assert_eq!(image.data, png);
+        assert!(pages[0].text.contains("plant cell"));
+    }
+
+    #[test]
+    fn test_stream_yields_batch_pages_in_order() {
+        let paragraphs: Vec<String> = (1..=120)
+            .map(|i| format!("Paragraph {} describes how cells divide, grow and specialise.", i))
+            .collect();
+        let paragraphs: Vec<&str> = paragraphs.iter().map(String::as_str).collect();
+        let core = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties"/>"#;
+        let data = fixtures::docx(&paragraphs, core);
+
+        let parser = DocxParser::new();
+        let summary = |pages: &[Page]| pages.iter().map(|p| (p.page_num, p.text.clone())).collect::<Vec<_>>();
+        let batch = parser.parse_bytes(&data).unwrap();
+        let stream = parser.parse_stream(&data);
+        assert_eq!(stream.size_hint(), (batch.len(), Some(batch.len())));
+        let streamed: Vec<Page> = stream.collect::<Result<_, _>>().unwrap();
+
+        assert!(batch.len() > 2);
+        assert_eq!(summary(&streamed), summary(&batch));
+        let page_nums: Vec<u32> = streamed.iter().map(|p| p.page_num).collect();
+        assert_eq!(page_nums, (1..=batch.len() as u32).collect::<Vec<_>>());
+        assert!(streamed[..streamed.len() - 1].iter().all(|p| p.text.len() >= DEFAULT_PAGE_CHARS));
+        let text: Vec<&str> = streamed.iter().map(|p| p.text.as_str()).collect();
+        assert_eq!(text.join("\n"), paragraphs.join("\n"));
+    }
+
+    #[test]
+    fn test_custom_page_size_and_single_page() {
+        let paragraphs = vec!["Cells divide, grow and specialise over time."; 60];
+        let core = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties"/>"#;
+        let data = fixtures::docx(&paragraphs, core);
+
+        let default = DocxParser::new().parse_bytes(&data).unwrap();
+        let small = DocxParser::new().with_page_chars(500).parse_bytes(&data).unwrap();
+        assert!(small.len() > default.len());
+        assert!(small[..small.len() - 1].iter().all(|p| p.text.len() >= 500 && p.text.len() < 600));
+
+        let single = DocxParser::new().with_page_chars(0).parse_bytes(&data).unwrap();
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].text, paragraphs.join("\n"));
+    }
+}
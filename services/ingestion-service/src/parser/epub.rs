@@ -0,0 +1,288 @@
+// EPUB parser: one page per content document, in spine order
+
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, XmlVersion};
+
+use super::html::HtmlParser;
+use super::traits::{DocumentInfo, Page, Parser, ParserError};
+
+/// Where every EPUB names its package (OPF) document.
+const CONTAINER_PART: &str = "META-INF/container.xml";
+
+/// Media types of spine items holding readable text.
+const CONTENT_TYPES: &[&str] = &["application/xhtml+xml", "text/html"];
+
+/// Default cap on the bytes read out of one book, across all its parts.
+const DEFAULT_MAX_BOOK_BYTES: usize = 200 << 20;
+
+type Archive<'a> = zip::ZipArchive<Cursor<&'a [u8]>>;
+
+/// Parser for EPUB e-books. Each spine item becomes a page, numbered by its
+/// position in the reading order, with its text extracted like HTML.
+pub struct EpubParser {
+    html: HtmlParser,
+    max_book_bytes: usize,
+}
+
+impl EpubParser {
+    pub fn new() -> Self {
+        Self {
+            html: HtmlParser::new(),
+            max_book_bytes: DEFAULT_MAX_BOOK_BYTES,
+        }
+    }
+
+    /// Fail books whose parts unpack to more than `max_bytes` in total.
+    pub fn with_max_book_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_book_bytes = max_bytes;
+        self
+    }
+}
+
+impl Default for EpubParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What the parser uses from the package document.
+#[derive(Default)]
+struct Package {
+    info: DocumentInfo,
+    /// Archive paths of the content documents, in reading order.
+    spine: Vec<String>,
+}
+
+/// Open the archive and read its package document, taking the bytes read
+/// from `budget`.
+fn open<'a>(data: &'a [u8], budget: &mut usize) -> Result<(Archive<'a>, Package), ParserError> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data))
+        .map_err(|e| ParserError::ParseError(format!("Failed to read EPUB: {}", e)))?;
+    let container = read_part(&mut archive, CONTAINER_PART, budget)?;
+    let package_path = find_attribute(&container, b"rootfile", b"full-path")
+        .ok_or_else(|| ParserError::ParseError("Failed to read EPUB: no package document".to_string()))?;
+    let package = read_part(&mut archive, &package_path, budget)?;
+    let base = package_path.rsplit_once('/').map_or("", |(dir, _)| dir);
+    Ok((archive, parse_package(&package, base)))
+}
+
+/// Read the part `name`, failing once the book has unpacked to more than
+/// `budget` bytes so a highly compressed part cannot exhaust memory.
+fn read_part(archive: &mut Archive, name: &str, budget: &mut usize) -> Result<String, ParserError> {
+    let failed = |e: &dyn std::fmt::Display| {
+        ParserError::ParseError(format!("Failed to read EPUB part {}: {}", name, e))
+    };
+    let mut data = Vec::new();
+    archive
+        .by_name(name)
+        .map_err(|e| failed(&e))?
+        .take(*budget as u64 + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| failed(&e))?;
+    *budget = budget
+        .checked_sub(data.len())
+        .ok_or_else(|| ParserError::ParseError(format!("EPUB part {} unpacks beyond the size limit", name)))?;
+    String::from_utf8(data).map_err(|e| failed(&e))
+}
+
+fn attribute(e: &BytesStart, name: &[u8]) -> Option<String> {
+    let value = e.try_get_attribute(name).ok()??;
+    value.normalized_value(XmlVersion::Implicit1_0).ok().map(|v| v.into_owned())
+}
+
+/// The value of `name` on the first `element` in `xml`.
+fn find_attribute(xml: &str, element: &[u8], name: &[u8]) -> Option<String> {
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e) | Event::Empty(e)) if e.local_name().as_ref() == element => {
+                return attribute(&e, name);
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => {}
+        }
+    }
+}
+
+/// Read the title and author from the metadata, and resolve the spine's
+/// item references through the manifest to paths relative to the archive
+/// root. `base` is the directory of the package document.
+fn parse_package(xml: &str, base: &str) -> Package {
+    let mut info = DocumentInfo::default();
+    // Manifest item id to (href, media type)
+    let mut manifest: HashMap<String, (String, String)> = HashMap::new();
+    let mut item_refs = Vec::new();
+    let mut reader = Reader::from_str(xml);
+    let mut element: Option<String> = None;
+    let mut value = String::new();
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                element = Some(String::from_utf8_lossy(e.local_name().as_ref()).into_owned());
+                value.clear();
+            }
+            Ok(Event::Empty(e)) => match e.local_name().as_ref() {
+                b"item" => {
+                    let (Some(id), Some(href)) = (attribute(&e, b"id"), attribute(&e, b"href")) else { continue };
+                    manifest.insert(id, (href, attribute(&e, b"media-type").unwrap_or_default()));
+                }
+                b"itemref" => item_refs.extend(attribute(&e, b"idref")),
+                _ => {}
+            },
+            Ok(Event::Text(t)) => {
+                if let Ok(text) = t.decode() {
+                    value.push_str(&text);
+                }
+            }
+            Ok(Event::GeneralRef(r)) => {
+                if let Ok(name) = r.decode() {
+                    value.push_str(&format!("&{};", name));
+                }
+            }
+            Ok(Event::End(_)) => {
+                let Some(name) = element.take() else { continue };
+                let text = quick_xml::escape::unescape(&value)
+                    .map(|v| v.trim().to_string())
+                    .unwrap_or_default();
+                if text.is_empty() {
+                    continue;
+                }
+                // A book may list several; the first is the main one
+                match name.as_str() {
+                    "title" if info.title.is_none() => info.title = Some(text),
+                    "creator" if info.author.is_none() => info.author = Some(text),
+                    _ => {}
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+
+    let spine = item_refs
+        .iter()
+        .filter_map(|id| manifest.get(id))
+        .filter(|(_, media_type)| CONTENT_TYPES.contains(&media_type.as_str()))
+        .map(|(href, _)| resolve(base, href))
+        .collect();
+    Package { info, spine }
+}
+
+/// Join `href` onto the directory `base`, dropping any fragment.
+fn resolve(base: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let mut parts: Vec<&str> = base.split('/').filter(|p| !p.is_empty()).collect();
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+impl Parser for EpubParser {
+    fn parse_bytes(&self, data: &[u8]) -> Result<Vec<Page>, ParserError> {
+        let mut budget = self.max_book_bytes;
+        let (mut archive, package) = open(data, &mut budget)?;
+
+        let mut pages = Vec::new();
+        for (i, path) in package.spine.iter().enumerate() {
+            let extracted = self.html.extract_text(&read_part(&mut archive, path, &mut budget)?)?;
+            // Covers and image-only pages have no text, but keep their number
+            if extracted.text.is_empty() {
+                continue;
+            }
+            pages.push(Page {
+                page_num: i as u32 + 1,
+                text: extracted.text,
+                code_blocks: extracted.code_blocks,
+                ..Default::default()
+            });
+        }
+
+        if pages.is_empty() {
+            return Err(ParserError::EmptyDocument("EPUB".to_string()));
+        }
+        Ok(pages)
+    }
+
+    fn document_info(&self, data: &[u8]) -> DocumentInfo {
+        let mut budget = self.max_book_bytes;
+        open(data, &mut budget).map(|(_, package)| package.info).unwrap_or_default()
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["epub"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &["application/epub+zip"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::fixtures;
+
+    #[test]
+    fn test_chapters_become_pages_in_spine_order() {
+        // The manifest lists chapter two first; the spine decides the order
+        let epub = fixtures::epub(
+            "On Cells",
+            "R. Hooke",
+            &[
+                ("text/ch1.xhtml", "<h1>Chapter 1</h1><p>Cells divide.</p>"),
+                ("text/ch2.xhtml", "<h1>Chapter 2</h1><p>Cells grow.</p>"),
+            ],
+        );
+        let parser = EpubParser::new();
+
+        let pages = parser.parse_bytes(&epub).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!((pages[0].page_num, pages[1].page_num), (1, 2));
+        assert!(pages[0].text.contains("Chapter 1") && pages[0].text.contains("Cells divide."));
+        assert!(pages[1].text.contains("Chapter 2") && pages[1].text.contains("Cells grow."));
+
+        let info = parser.document_info(&epub);
+        assert_eq!(info.title.as_deref(), Some("On Cells"));
+        assert_eq!(info.author.as_deref(), Some("R. Hooke"));
+    }
+
+    #[test]
+    fn test_epub_without_text_or_package() {
+        let parser = EpubParser::new();
+        let blank = fixtures::epub("Blank", "Nobody", &[("cover.xhtml", "<img src=\"cover.png\"/>")]);
+        assert!(matches!(parser.parse_bytes(&blank), Err(ParserError::EmptyDocument(_))));
+        assert!(matches!(parser.parse_bytes(b"not a zip"), Err(ParserError::ParseError(_))));
+        assert!(parser.document_info(b"not a zip").title.is_none());
+    }
+
+    #[test]
+    fn test_book_unpacking_beyond_limit_fails() {
+        let chapter = format!("<p>{}</p>", "Cells divide. ".repeat(10_000));
+        let epub = fixtures::epub("Big", "Nobody", &[("ch1.xhtml", chapter.as_str())]);
+        assert!(EpubParser::new().parse_bytes(&epub).is_ok());
+
+        let parser = EpubParser::new().with_max_book_bytes(64 * 1024);
+        match parser.parse_bytes(&epub) {
+            Err(ParserError::ParseError(message)) => assert!(message.contains("ch1.xhtml"), "{}", message),
+            other => panic!("expected a parse error, got {:?}", other.map(|pages| pages.len())),
+        }
+    }
+
+    #[test]
+    fn test_resolves_hrefs_against_package_directory() {
+        assert_eq!(resolve("OEBPS", "text/ch1.xhtml#start"), "OEBPS/text/ch1.xhtml");
+        assert_eq!(resolve("OEBPS/content", "../text/ch1.xhtml"), "OEBPS/text/ch1.xhtml");
+        assert_eq!(resolve("", "./ch1.xhtml"), "ch1.xhtml");
+    }
+}
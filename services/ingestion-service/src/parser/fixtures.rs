@@ -0,0 +1,445 @@
+// Programmatically built documents for parser tests.
+
+use std::io::{Cursor, Read, Write};
+use std::time::Duration;
+
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Document, Object, Stream, StringFormat};
+
+use super::traits::{Page, Parser, ParserError};
+
+const PAGE_WIDTH: i64 = 612;
+const PAGE_HEIGHT: i64 = 792;
+const LEFT_MARGIN: f32 = 72.0;
+const TOP_MARGIN: f32 = 720.0;
+const BODY_SIZE: f32 = 12.0;
+const LINE_GAP: f32 = 8.0;
+const COLUMN_WIDTH: f32 = 180.0;
+
+/// Code written for U+FFFD, which the unmapped font's `/ToUnicode` reports
+/// as U+FFFD, like a font whose glyphs map to no characters.
+const UNMAPPED_CODE: u8 = 0x7F;
+/// `/ToUnicode` map of the unmapped font: ASCII as itself, plus [`UNMAPPED_CODE`].
+const UNMAPPED_CMAP: &str = "/CIDInit /ProcSet findresource begin 12 dict begin begincmap
+1 begincodespacerange <00> <FF> endcodespacerange
+1 beginbfrange <20> <7E> <0020> endbfrange
+1 beginbfchar <7F> <FFFD> endbfchar
+endcmap CMapName currentdict /CMap defineresource pop end end";
+
+struct Line {
+    text: String,
+    size: f32,
+    /// Further table cells, one column width apart after `text`.
+    cells: Vec<String>,
+}
+
+struct ImageSpec {
+    page: usize,
+    width: i64,
+    height: i64,
+    rgb: Vec<u8>,
+}
+
+struct HighlightSpec {
+    page: usize,
+    line: usize,
+    color: [f32; 3],
+}
+
+/// Builds simple single-column PDFs with one Helvetica text line per entry.
+/// Lines with U+FFFD are set in a font that extracts them as U+FFFD.
+pub(crate) struct PdfBuilder {
+    pages: Vec<Vec<Line>>,
+    highlights: Vec<HighlightSpec>,
+    images: Vec<ImageSpec>,
+    rotations: Vec<(usize, i64)>,
+    info: Vec<(String, String)>,
+    codepage: Option<(&'static encoding_rs::Encoding, String)>,
+}
+
+impl PdfBuilder {
+    pub fn new() -> Self {
+        Self {
+            pages: Vec::new(),
+            highlights: Vec::new(),
+            images: Vec::new(),
+            rotations: Vec::new(),
+            info: Vec::new(),
+            codepage: None,
+        }
+    }
+
+    /// Write text as `encoding` bytes in a WinAnsi font named `base_font`,
+    /// the way legacy PDFs embed non-Latin text without a Unicode mapping.
+    pub fn codepage(mut self, encoding: &'static encoding_rs::Encoding, base_font: &str) -> Self {
+        self.codepage = Some((encoding, base_font.to_string()));
+        self
+    }
+
+    /// Set `/Info` dictionary entries such as `Title` or `CreationDate`.
+    pub fn info(mut self, entries: &[(&str, &str)]) -> Self {
+        self.info
+            .extend(entries.iter().map(|(key, value)| (key.to_string(), value.to_string())));
+        self
+    }
+
+    /// Give page `page` (1-based) a `/Rotate` of `degrees`. Its lines are laid
+    /// out so they read upright once the rotation is applied, as scanners do.
+    pub fn rotate(mut self, page: usize, degrees: i64) -> Self {
+        self.rotations.push((page, degrees));
+        self
+    }
+
+    fn rotation(&self, page: usize) -> i64 {
+        self.rotations
+            .iter()
+            .find(|(p, _)| *p == page)
+            .map_or(0, |(_, degrees)| degrees.rem_euclid(360))
+    }
+
+    /// Add a page of body-sized lines.
+    pub fn page(self, lines: &[&str]) -> Self {
+        let sized: Vec<(&str, f32)> = lines.iter().map(|l| (*l, BODY_SIZE)).collect();
+        self.sized_page(&sized)
+    }
+
+    /// Add a page whose lines each carry an explicit font size.
+    pub fn sized_page(mut self, lines: &[(&str, f32)]) -> Self {
+        self.pages.push(
+            lines
+                .iter()
+                .map(|(text, size)| Line {
+                    text: text.to_string(),
+                    size: *size,
+                    cells: Vec::new(),
+                })
+                .collect(),
+        );
+        self
+    }
+
+    /// Add a page with a table: one line per row, its cells in columns.
+    pub fn table_page(mut self, rows: &[&[&str]]) -> Self {
+        self.pages.push(
+            rows.iter()
+                .map(|cells| Line {
+                    text: cells[0].to_string(),
+                    size: BODY_SIZE,
+                    cells: cells[1..].iter().map(|cell| cell.to_string()).collect(),
+                })
+                .collect(),
+        );
+        self
+    }
+
+    /// Highlight line `line` (0-based) on page `page` (1-based).
+    pub fn highlight(mut self, page: usize, line: usize, color: [f32; 3]) -> Self {
+        self.highlights.push(HighlightSpec { page, line, color });
+        self
+    }
+
+    /// Draw a `width` x `height` image of 8-bit RGB samples, Flate-encoded,
+    /// below the text of page `page` (1-based).
+    pub fn image(mut self, page: usize, width: i64, height: i64, rgb: &[u8]) -> Self {
+        self.images.push(ImageSpec {
+            page,
+            width,
+            height,
+            rgb: rgb.to_vec(),
+        });
+        self
+    }
+
+    /// Baseline y coordinate of every line on a page of the given displayed height.
+    fn baselines(lines: &[Line], height: f32) -> Vec<f32> {
+        let mut y = height - (PAGE_HEIGHT as f32 - TOP_MARGIN);
+        lines
+            .iter()
+            .map(|line| {
+                y -= line.size;
+                let baseline = y;
+                y -= LINE_GAP;
+                baseline
+            })
+            .collect()
+    }
+
+    fn encode(&self, text: &str) -> Object {
+        match self.codepage {
+            Some((encoding, _)) => Object::String(encoding.encode(text).0.into_owned(), StringFormat::Literal),
+            None => Object::string_literal(text),
+        }
+    }
+
+    pub fn build(self) -> Vec<u8> {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => self.codepage.as_ref().map_or("Helvetica", |(_, font)| font.as_str()),
+            "Encoding" => "WinAnsiEncoding",
+        });
+        let cmap_id = doc.add_object(Stream::new(dictionary! {}, UNMAPPED_CMAP.as_bytes().to_vec()));
+        let unmapped_font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => "WinAnsiEncoding",
+            "ToUnicode" => cmap_id,
+        });
+        let resources_id = doc.add_object(dictionary! {
+            "Font" => dictionary! { "F1" => font_id, "F2" => unmapped_font_id },
+        });
+
+        let mut kids = Vec::new();
+        for (index, lines) in self.pages.iter().enumerate() {
+            let rotation = self.rotation(index + 1);
+            let (w, h) = (PAGE_WIDTH as f32, PAGE_HEIGHT as f32);
+            let display_height = if rotation % 180 == 0 { h } else { w };
+            // Map a point on the displayed page back to user space, and the
+            // text direction that reads left to right once rotated
+            let to_user = |x: f32, y: f32| -> (f32, f32) {
+                match rotation {
+                    90 => (w - y, x),
+                    180 => (w - x, h - y),
+                    270 => (y, h - x),
+                    _ => (x, y),
+                }
+            };
+            let (cos, sin): (f32, f32) = match rotation {
+                90 => (0.0, 1.0),
+                180 => (-1.0, 0.0),
+                270 => (0.0, -1.0),
+                _ => (1.0, 0.0),
+            };
+
+            let baselines = Self::baselines(lines, display_height);
+            let mut operations = Vec::new();
+            for (line, y) in lines.iter().zip(&baselines) {
+                for (column, text) in std::iter::once(&line.text).chain(&line.cells).enumerate() {
+                    let (ux, uy) = to_user(LEFT_MARGIN + column as f32 * COLUMN_WIDTH, *y);
+                    let (font, text) = if text.contains(char::REPLACEMENT_CHARACTER) {
+                        let codes = text.chars().map(|c| if c.is_ascii() { c as u8 } else { UNMAPPED_CODE });
+                        ("F2", Object::String(codes.collect(), StringFormat::Literal))
+                    } else {
+                        ("F1", self.encode(text))
+                    };
+                    operations.extend([
+                        Operation::new("BT", vec![]),
+                        Operation::new("Tf", vec![font.into(), line.size.into()]),
+                        Operation::new(
+                            "Tm",
+                            vec![cos.into(), sin.into(), (-sin).into(), cos.into(), ux.into(), uy.into()],
+                        ),
+                        Operation::new("Tj", vec![text]),
+                        Operation::new("ET", vec![]),
+                    ]);
+                }
+            }
+            let mut xobjects = lopdf::Dictionary::new();
+            for (i, image) in self.images.iter().filter(|image| image.page == index + 1).enumerate() {
+                let mut stream = Stream::new(
+                    dictionary! {
+                        "Type" => "XObject",
+                        "Subtype" => "Image",
+                        "Width" => image.width,
+                        "Height" => image.height,
+                        "ColorSpace" => "DeviceRGB",
+                        "BitsPerComponent" => 8,
+                    },
+                    image.rgb.clone(),
+                );
+                stream.compress().unwrap();
+                let name = format!("Im{}", i + 1);
+                xobjects.set(name.as_str(), doc.add_object(stream));
+                operations.extend([
+                    Operation::new("q", vec![]),
+                    Operation::new(
+                        "cm",
+                        vec![image.width.into(), 0.into(), 0.into(), image.height.into(), 72.into(), 72.into()],
+                    ),
+                    Operation::new("Do", vec![Object::Name(name.into_bytes())]),
+                    Operation::new("Q", vec![]),
+                ]);
+            }
+            let content = Content { operations };
+            let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+
+            let annots: Vec<Object> = self
+                .highlights
+                .iter()
+                .filter(|h| h.page == index + 1)
+                .map(|h| {
+                    let line = &lines[h.line];
+                    let y0 = baselines[h.line] - line.size * 0.25;
+                    let y1 = baselines[h.line] + line.size;
+                    let x1 = LEFT_MARGIN + line.text.len() as f32 * line.size * 0.5;
+                    let corners = [(LEFT_MARGIN, y1), (x1, y1), (LEFT_MARGIN, y0), (x1, y0)].map(|(x, y)| to_user(x, y));
+                    let (xs, ys): (Vec<f32>, Vec<f32>) = corners.iter().copied().unzip();
+                    let rect = [
+                        xs.iter().copied().fold(f32::INFINITY, f32::min),
+                        ys.iter().copied().fold(f32::INFINITY, f32::min),
+                        xs.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+                        ys.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+                    ];
+                    doc.add_object(dictionary! {
+                        "Type" => "Annot",
+                        "Subtype" => "Highlight",
+                        "Rect" => rect.iter().map(|&v| v.into()).collect::<Vec<Object>>(),
+                        "QuadPoints" => corners.iter().flat_map(|&(x, y)| [x.into(), y.into()]).collect::<Vec<Object>>(),
+                        "C" => h.color.iter().map(|&c| c.into()).collect::<Vec<Object>>(),
+                    })
+                    .into()
+                })
+                .collect();
+
+            let mut page = dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "Contents" => content_id,
+            };
+            if !annots.is_empty() {
+                page.set("Annots", annots);
+            }
+            if rotation != 0 {
+                page.set("Rotate", rotation);
+            }
+            if !xobjects.is_empty() {
+                page.set(
+                    "Resources",
+                    dictionary! { "Font" => dictionary! { "F1" => font_id }, "XObject" => xobjects },
+                );
+            }
+            kids.push(doc.add_object(page).into());
+        }
+
+        let count = kids.len() as i64;
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => kids,
+                "Count" => count,
+                "Resources" => resources_id,
+                "MediaBox" => vec![0.into(), 0.into(), PAGE_WIDTH.into(), PAGE_HEIGHT.into()],
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        doc.trailer.set("Root", catalog_id);
+        if !self.info.is_empty() {
+            let mut info = lopdf::Dictionary::new();
+            for (key, value) in &self.info {
+                info.set(key.as_str(), lopdf::text_string(value));
+            }
+            let info_id = doc.add_object(info);
+            doc.trailer.set("Info", info_id);
+        }
+
+        let mut out = Vec::new();
+        doc.save_to(&mut out).unwrap();
+        out
+    }
+}
+
+/// A DOCX with one paragraph per entry whose `docProps/core.xml` is `core_xml`.
+pub(crate) fn docx(paragraphs: &[&str], core_xml: &str) -> Vec<u8> {
+    let mut docx = docx_rs::Docx::new();
+    for text in paragraphs {
+        docx = docx.add_paragraph(docx_rs::Paragraph::new().add_run(docx_rs::Run::new().add_text(*text)));
+    }
+    let mut built = Cursor::new(Vec::new());
+    docx.build().pack(&mut built).unwrap();
+
+    // Copy the package, swapping in the requested core properties
+    let mut source = zip::ZipArchive::new(Cursor::new(built.into_inner())).unwrap();
+    let mut out = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for i in 0..source.len() {
+        let mut file = source.by_index(i).unwrap();
+        let name = file.name().to_string();
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).unwrap();
+        if name == "docProps/core.xml" {
+            content = core_xml.as_bytes().to_vec();
+        }
+        out.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+        out.write_all(&content).unwrap();
+    }
+    out.finish().unwrap().into_inner()
+}
+
+/// A DOCX with a paragraph of `text` followed by a paragraph holding `image`.
+pub(crate) fn docx_with_image(text: &str, image: &[u8]) -> Vec<u8> {
+    let pic = docx_rs::Pic::new_with_dimensions(image.to_vec(), 16, 16);
+    let docx = docx_rs::Docx::new()
+        .add_paragraph(docx_rs::Paragraph::new().add_run(docx_rs::Run::new().add_text(text)))
+        .add_paragraph(docx_rs::Paragraph::new().add_run(docx_rs::Run::new().add_image(pic)));
+    let mut built = Cursor::new(Vec::new());
+    docx.build().pack(&mut built).unwrap();
+    built.into_inner()
+}
+
+/// An EPUB whose package document lives in `OEBPS/` and whose spine lists
+/// `chapters` (path under `OEBPS/`, body markup) in the given order. The
+/// manifest lists them in reverse, so only the spine gives the reading order.
+pub(crate) fn epub(title: &str, author: &str, chapters: &[(&str, &str)]) -> Vec<u8> {
+    let items: String = chapters
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(i, (path, _))| format!(r#"<item id="c{i}" href="{path}" media-type="application/xhtml+xml"/>"#))
+        .collect();
+    let item_refs: String = (0..chapters.len()).map(|i| format!(r#"<itemref idref="c{i}"/>"#)).collect();
+    let package = format!(
+        r#"<?xml version="1.0"?><package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+<metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+<dc:title>{title}</dc:title><dc:creator>{author}</dc:creator>
+</metadata>
+<manifest>{items}</manifest><spine>{item_refs}</spine></package>"#
+    );
+    let container = r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container"><rootfiles>
+<rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+</rootfiles></container>"#;
+
+    let mut parts = vec![
+        ("mimetype".to_string(), "application/epub+zip".to_string()),
+        ("META-INF/container.xml".to_string(), container.to_string()),
+        ("OEBPS/content.opf".to_string(), package),
+    ];
+    for (path, body) in chapters {
+        let xhtml = format!(r#"<html xmlns="http://www.w3.org/1999/xhtml"><body>{body}</body></html>"#);
+        parts.push((format!("OEBPS/{path}"), xhtml));
+    }
+    let mut out = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, content) in parts {
+        out.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+        out.write_all(content.as_bytes()).unwrap();
+    }
+    out.finish().unwrap().into_inner()
+}
+
+/// Takes a second over any document, which it returns as one page of text.
+pub(crate) struct SlowParser;
+
+impl Parser for SlowParser {
+    fn parse_bytes(&self, data: &[u8]) -> Result<Vec<Page>, ParserError> {
+        std::thread::sleep(Duration::from_secs(1));
+        Ok(vec![Page {
+            page_num: 1,
+            text: String::from_utf8_lossy(data).into_owned(),
+            ..Default::default()
+        }])
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["txt"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &["text/plain"]
+    }
+}
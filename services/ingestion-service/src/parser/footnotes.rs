@@ -0,0 +1,242 @@
+// Footnote reference numbers that extraction ran into the running text
+
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::Page;
+
+/// Parsers whose output flattens superscript footnote references into the
+/// line they sit on.
+const SUPERSCRIPT_PARSERS: &[&str] = &["LocalPdfParser", "AzureDocIntelligenceParser", "DocxParser", "HtmlParser"];
+
+/// Words followed by a period and a number that refers to something, as in
+/// `Fig.3`, rather than by a footnote marker.
+const NUMBERED_ABBREVIATIONS: &[&str] = &[
+    "fig", "figs", "tab", "vol", "sec", "ref", "refs", "art", "chap", "para", "eqn", "nos",
+];
+
+const SUPERSCRIPT_DIGITS: &str = "⁰¹²³⁴⁵⁶⁷⁸⁹";
+
+/// What to do with footnote markers stuck to the surrounding words, such as
+/// `as shown.12 The` or `four phases¹³ and`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FootnoteMarkers {
+    /// Leave the text as extracted.
+    #[default]
+    Keep,
+    /// Remove the markers.
+    Strip,
+    /// Move each marker, as ` [12]`, to the end of its sentence.
+    Relocate,
+}
+
+impl FootnoteMarkers {
+    /// Parse a request value; empty or unknown values keep the markers.
+    pub fn parse(value: &str) -> Self {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "strip" => Self::Strip,
+            "relocate" => Self::Relocate,
+            _ => Self::Keep,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Keep => "keep",
+            Self::Strip => "strip",
+            Self::Relocate => "relocate",
+        }
+    }
+
+    /// Whether `apply` rewrites pages extracted by `parser`.
+    pub fn applies_to(self, parser: &str) -> bool {
+        self != Self::Keep && SUPERSCRIPT_PARSERS.contains(&parser)
+    }
+
+    /// Rewrite the markers in `pages` as extracted by `parser`. Formats
+    /// without superscripts are left alone, as are code blocks and tables,
+    /// whose offsets are updated to the new text.
+    pub fn apply(self, parser: &str, pages: &mut [Page]) {
+        if !self.applies_to(parser) {
+            return;
+        }
+        for page in pages {
+            let mut text = String::with_capacity(page.text.len());
+            let mut last = 0;
+            let mut blocks: Vec<(&mut usize, &mut usize)> = page
+                .code_blocks
+                .iter_mut()
+                .map(|b| (&mut b.start, &mut b.end))
+                .chain(page.tables.iter_mut().map(|t| (&mut t.start, &mut t.end)))
+                .collect();
+            blocks.sort_by_key(|(start, _)| **start);
+            for (block_start, block_end) in blocks {
+                text.push_str(&self.rewrite(&page.text[last..*block_start]));
+                let start = text.len();
+                text.push_str(&page.text[*block_start..*block_end]);
+                last = *block_end;
+                (*block_start, *block_end) = (start, text.len());
+            }
+            text.push_str(&self.rewrite(&page.text[last..]));
+            page.text = text;
+            for heading in &mut page.headings {
+                heading.text = self.rewrite(&heading.text);
+            }
+            for highlight in &mut page.highlights {
+                highlight.text = self.rewrite(&highlight.text);
+            }
+        }
+    }
+
+    fn rewrite(self, text: &str) -> String {
+        let markers = find_markers(text);
+        if markers.is_empty() {
+            return text.to_string();
+        }
+
+        // Cut the markers out, remembering where each one was
+        let mut cleaned = String::with_capacity(text.len());
+        let mut cut_at = Vec::with_capacity(markers.len());
+        let mut last = 0;
+        for (range, number) in markers {
+            cleaned.push_str(&text[last..range.start]);
+            cut_at.push((cleaned.len(), number));
+            last = range.end;
+        }
+        cleaned.push_str(&text[last..]);
+        if self != Self::Relocate {
+            return cleaned;
+        }
+
+        // Sentence ends never move backwards past a later marker, so the
+        // insertion points stay in order
+        let mut relocated = String::with_capacity(cleaned.len() + cut_at.len() * 6);
+        let mut last = 0;
+        for (at, number) in cut_at {
+            let end = sentence_end(&cleaned, at);
+            relocated.push_str(&cleaned[last..end]);
+            relocated.push_str(&format!(" [{}]", number));
+            last = end;
+        }
+        relocated.push_str(&cleaned[last..]);
+        relocated
+    }
+}
+
+/// Byte ranges of the footnote markers in `text`, in order, with the
+/// footnote number each one holds.
+fn find_markers(text: &str) -> Vec<(Range<usize>, String)> {
+    static INLINE: OnceLock<Regex> = OnceLock::new();
+    static SUPERSCRIPT: OnceLock<Regex> = OnceLock::new();
+    // A short number right after a word's closing punctuation and before a
+    // space; a bare number after a word is too often part of a name
+    let inline = INLINE.get_or_init(|| {
+        Regex::new(r#"(\p{L}{3,})(?:[.,;:!?][)"”’]?|[)"”’])(\d{1,3})(?:\s|$)"#).unwrap()
+    });
+    let superscript = SUPERSCRIPT.get_or_init(|| Regex::new(r"[^\s⁰¹²³⁴-⁹]([⁰¹²³⁴-⁹]+)").unwrap());
+
+    let mut markers: Vec<(Range<usize>, String)> = inline
+        .captures_iter(text)
+        .filter(|caps| !NUMBERED_ABBREVIATIONS.contains(&caps[1].to_lowercase().as_str()))
+        .map(|caps| {
+            let number = caps.get(2).unwrap();
+            (number.range(), number.as_str().to_string())
+        })
+        .collect();
+    markers.extend(superscript.captures_iter(text).map(|caps| {
+        let number = caps.get(1).unwrap();
+        let digits = number
+            .as_str()
+            .chars()
+            .filter_map(|c| SUPERSCRIPT_DIGITS.chars().position(|d| d == c))
+            .map(|d| char::from(b'0' + d as u8))
+            .collect();
+        (number.range(), digits)
+    }));
+    markers.sort_by_key(|(range, _)| range.start);
+    markers
+}
+
+/// Where a marker cut out at byte `at` goes: before the terminal
+/// punctuation it followed, or else before the end of the sentence it sits
+/// in, which a line break also ends.
+fn sentence_end(text: &str, at: usize) -> usize {
+    let before = text[..at].trim_end_matches([')', '"', '”', '’']);
+    if before.ends_with(['.', '!', '?']) {
+        return before.len() - 1;
+    }
+    let rest = &text[at..];
+    let mut chars = rest.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let closes = matches!(c, '.' | '!' | '?') && chars.peek().is_none_or(|(_, next)| next.is_whitespace());
+        if closes || c == '\n' {
+            return at + i;
+        }
+    }
+    at + rest.trim_end().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::CodeBlock;
+
+    const TEXT: &str = "Cells divide by mitosis, as shown.12 The cycle has four phases¹³ and lasts a day.\n\
+                        See Fig.3 and p.12 for the stages,4 which repeat.";
+
+    fn page(text: &str) -> Page {
+        Page {
+            page_num: 1,
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_markers_stripped_or_moved_to_sentence_end() {
+        let mut pages = vec![page(TEXT)];
+        FootnoteMarkers::Strip.apply("LocalPdfParser", &mut pages);
+        assert_eq!(
+            pages[0].text,
+            "Cells divide by mitosis, as shown. The cycle has four phases and lasts a day.\n\
+             See Fig.3 and p.12 for the stages, which repeat."
+        );
+
+        let mut pages = vec![page(TEXT)];
+        FootnoteMarkers::Relocate.apply("DocxParser", &mut pages);
+        assert_eq!(
+            pages[0].text,
+            "Cells divide by mitosis, as shown [12]. The cycle has four phases and lasts a day [13].\n\
+             See Fig.3 and p.12 for the stages, which repeat [4]."
+        );
+
+        // Plain text has no superscripts to flatten
+        let mut pages = vec![page(TEXT)];
+        FootnoteMarkers::Strip.apply("PlainTextParser", &mut pages);
+        assert_eq!(pages[0].text, TEXT);
+    }
+
+    #[test]
+    fn test_code_blocks_untouched() {
+        let text = "Results vary.4 Run:\nreturn pages,1 limit\nDone.";
+        let start = text.find("return").unwrap();
+        let end = start + "return pages,1 limit".len();
+        let mut pages = vec![Page {
+            code_blocks: vec![CodeBlock {
+                start,
+                end,
+                language: None,
+            }],
+            ..page(text)
+        }];
+
+        FootnoteMarkers::Strip.apply("HtmlParser", &mut pages);
+        let block = &pages[0].code_blocks[0];
+        assert_eq!(pages[0].text, "Results vary. Run:\nreturn pages,1 limit\nDone.");
+        assert_eq!(&pages[0].text[block.start..block.end], "return pages,1 limit");
+    }
+}
@@ -1,94 +1,313 @@
 // HTML parser implementation using scraper
 
-use std::io::Read;
-use scraper::{Html, Selector};
+use std::collections::HashSet;
+use scraper::{ElementRef, Html, Selector};
+use unicode_segmentation::UnicodeSegmentation;
 
-use super::traits::{Page, Parser, ParserError};
+use super::code;
+use super::traits::{CodeBlock, Page, Parser, ParserError};
+use crate::splitter::grapheme_floor;
+
+/// Semantic container elements used when paging by section.
+pub const DEFAULT_SECTION_SELECTORS: &[&str] = &["section", "article", "main"];
+
+/// Elements that flow within a line of text; any other element starts a
+/// new word.
+const INLINE_ELEMENTS: &[&str] = &[
+    "a", "abbr", "b", "bdi", "bdo", "cite", "code", "data", "dfn", "em", "i", "kbd", "mark", "q", "s", "samp",
+    "small", "span", "strong", "sub", "sup", "time", "u", "var",
+];
+
+/// Characters per virtual page of documents that don't record their own
+/// page breaks (HTML, DOCX without page or section breaks).
+pub const DEFAULT_PAGE_CHARS: usize = 2000;
 
 /// Parser for HTML documents
-pub struct HtmlParser;
+pub struct HtmlParser {
+    section_selectors: Vec<String>,
+    page_chars: usize,
+}
 
 impl HtmlParser {
     pub fn new() -> Self {
-        Self
+        Self {
+            section_selectors: Vec::new(),
+            page_chars: DEFAULT_PAGE_CHARS,
+        }
+    }
+
+    /// Cut the text into pages of about `page_chars` characters (default
+    /// [`DEFAULT_PAGE_CHARS`]); 0 keeps the whole document on one page.
+    pub fn with_page_chars(mut self, page_chars: usize) -> Self {
+        self.page_chars = page_chars;
+        self
+    }
+
+    /// Page by the elements matching `selectors` (e.g. `section`, `article`,
+    /// `main`) instead of by character count. Each outermost match becomes one
+    /// page; documents without any match fall back to character pagination.
+    pub fn with_section_selectors<I, S>(mut self, selectors: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.section_selectors = selectors.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Extract the text of each outermost element matching the section
+    /// selectors, and of each run of text between them, in document order.
+    /// Empty when nothing matches.
+    fn extract_sections(&self, html: &str) -> Result<Vec<ExtractedText>, ParserError> {
+        let selector = Selector::parse(&self.section_selectors.join(", "))
+            .map_err(|e| ParserError::ParseError(format!("Invalid selector: {:?}", e)))?;
+        let body_selector = Selector::parse("body")
+            .map_err(|e| ParserError::ParseError(format!("Invalid selector: {:?}", e)))?;
+        let document = Html::parse_document(html);
+
+        let ids: HashSet<_> = document.select(&selector).map(|el| el.id()).collect();
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let root = document
+            .select(&body_selector)
+            .next()
+            .unwrap_or_else(|| document.root_element());
+
+        let mut sections = Vec::new();
+        let mut between = ExtractedText::default();
+        collect_sections(root, &ids, &mut between, &mut sections);
+        sections.push(between.finish());
+        sections.retain(|section| !section.text.is_empty());
+        Ok(sections)
     }
 
     /// Extract text from HTML, removing scripts and styles
-    fn extract_text(&self, html: &str) -> Result<String, ParserError> {
+    pub(super) fn extract_text(&self, html: &str) -> Result<ExtractedText, ParserError> {
         let document = Html::parse_document(html);
 
-        // Remove script and style tags
         let body_selector = Selector::parse("body")
             .map_err(|e| ParserError::ParseError(format!("Invalid selector: {:?}", e)))?;
 
-        let mut text = String::new();
-
-        // Get body content
-        if let Some(body) = document.select(&body_selector).next() {
-            // Remove scripts and styles
-            let body_html = body.html();
-            let cleaned_doc = Html::parse_fragment(&body_html);
-
-            // Extract text
-            for element in cleaned_doc.tree.nodes() {
-                if let scraper::node::Node::Text(text_node) = element.value() {
-                    let content = text_node.text.trim();
-                    if !content.is_empty() {
-                        text.push_str(content);
-                        text.push(' ');
-                    }
-                }
-            }
+        // Get body content, falling back to the whole document
+        let root = document
+            .select(&body_selector)
+            .next()
+            .unwrap_or_else(|| document.root_element());
+
+        Ok(ExtractedText::from_element(root))
+    }
+}
+
+/// Walk `element`, turning each outermost element in `ids` into a section and
+/// gathering the text around them in `between`, which becomes a section of
+/// its own when the next one starts. Nested sections belong to the page of
+/// their outermost container.
+fn collect_sections(
+    element: ElementRef,
+    ids: &HashSet<ego_tree::NodeId>,
+    between: &mut ExtractedText,
+    sections: &mut Vec<ExtractedText>,
+) {
+    for child in element.children() {
+        if let Some(text) = child.value().as_text() {
+            between.push_text(text);
+            continue;
+        }
+        let Some(child) = ElementRef::wrap(child) else { continue };
+        if ids.contains(&child.id()) {
+            sections.push(std::mem::take(between).finish());
+            sections.push(ExtractedText::from_element(child));
+        } else if child.descendants().any(|node| ids.contains(&node.id())) {
+            between.break_word();
+            collect_sections(child, ids, between, sections);
+            between.break_word();
         } else {
-            // Fallback: extract all text
-            for text_node in document.tree.nodes() {
-                if let scraper::node::Node::Text(text_node) = text_node.value() {
-                    let content = text_node.text.trim();
-                    if !content.is_empty() {
-                        text.push_str(content);
-                        text.push(' ');
-                    }
-                }
+            between.collect_element(child);
+        }
+    }
+}
+
+/// Text extracted from an element subtree, with the code blocks found in it.
+#[derive(Default)]
+pub(super) struct ExtractedText {
+    pub(super) text: String,
+    pub(super) code_blocks: Vec<CodeBlock>,
+}
+
+impl ExtractedText {
+    fn from_element(element: ElementRef) -> Self {
+        let mut extracted = Self::default();
+        extracted.collect(element);
+        extracted.finish()
+    }
+
+    fn collect(&mut self, element: ElementRef) {
+        for child in element.children() {
+            if let Some(text) = child.value().as_text() {
+                self.push_text(text);
+            } else if let Some(child) = ElementRef::wrap(child) {
+                self.collect_element(child);
+            }
+        }
+    }
+
+    /// Collect an element's text, separated from its surroundings unless the
+    /// element is inline, as `<b>` in `<p>Cell <b>walls</b>.</p>`.
+    fn collect_element(&mut self, element: ElementRef) {
+        match element.value().name() {
+            "script" | "style" => {}
+            "pre" => {
+                self.break_word();
+                self.push_code(element);
+            }
+            name if INLINE_ELEMENTS.contains(&name) => self.collect(element),
+            _ => {
+                self.break_word();
+                self.collect(element);
+                self.break_word();
             }
         }
+    }
+
+    /// Append a text node with its whitespace runs collapsed to one space.
+    fn push_text(&mut self, text: &str) {
+        if text.starts_with(char::is_whitespace) {
+            self.break_word();
+        }
+        let mut words = text.split_whitespace();
+        if let Some(first) = words.next() {
+            self.text.push_str(first);
+            for word in words {
+                self.text.push(' ');
+                self.text.push_str(word);
+            }
+            if text.ends_with(char::is_whitespace) {
+                self.break_word();
+            }
+        }
+    }
+
+    /// Separate what follows from the text so far.
+    fn break_word(&mut self) {
+        if !self.text.is_empty() && !self.text.ends_with(char::is_whitespace) {
+            self.text.push(' ');
+        }
+    }
+
+    /// Append a `<pre>` block verbatim, keeping its line structure.
+    fn push_code(&mut self, pre: ElementRef) {
+        let raw: String = pre.text().collect();
+        let code = raw.trim_start_matches('\n').trim_end();
+        if code.trim().is_empty() {
+            return;
+        }
+
+        // `<pre class="language-x">` or `<pre><code class="language-x">`
+        let language = std::iter::once(pre)
+            .chain(pre.children().filter_map(ElementRef::wrap))
+            .find_map(|el| el.value().attr("class").and_then(code::language_from_class))
+            .or_else(|| code::detect_language(code));
+
+        let start = self.text.len();
+        self.text.push_str(code);
+        self.code_blocks.push(CodeBlock {
+            start,
+            end: self.text.len(),
+            language,
+        });
+        self.text.push('\n');
+    }
+
+    /// Trim surrounding whitespace, keeping code block offsets in step.
+    fn finish(mut self) -> Self {
+        let leading = self.text.len() - self.text.trim_start().len();
+        self.text = self.text.trim().to_string();
+        for block in &mut self.code_blocks {
+            block.start -= leading;
+            block.end = (block.end - leading).min(self.text.len());
+        }
+        self
+    }
+}
 
-        Ok(text.trim().to_string())
+impl Default for HtmlParser {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Parser for HtmlParser {
-    fn parse<R: Read>(&self, mut reader: R) -> Result<Vec<Page>, ParserError> {
-        // Read bytes from reader
-        let mut data = Vec::new();
-        reader.read_to_end(&mut data)
-            .map_err(|e| ParserError::Io(e))?;
-
+    fn parse_bytes(&self, data: &[u8]) -> Result<Vec<Page>, ParserError> {
         // Convert bytes to string
-        let html = String::from_utf8(data)
+        let html = std::str::from_utf8(data)
             .map_err(|e| ParserError::ParseError(format!("Invalid UTF-8: {}", e)))?;
 
+        if !self.section_selectors.is_empty() {
+            let sections = self.extract_sections(html)?;
+            if !sections.is_empty() {
+                return Ok(sections
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, section)| Page {
+                        page_num: i as u32 + 1,
+                        text: section.text,
+                        images: Vec::new(),
+                        code_blocks: section.code_blocks,
+                        ..Default::default()
+                    })
+                    .collect());
+            }
+        }
+
         // Extract text
-        let text = self.extract_text(&html)?;
+        let ExtractedText { text, code_blocks } = self.extract_text(html)?;
 
         if text.is_empty() {
-            return Err(ParserError::ParseError(
-                "No text content found in HTML".to_string(),
-            ));
+            return Err(ParserError::EmptyDocument("HTML".to_string()));
         }
 
-        // Split into pages (every ~2000 characters)
+        // Split into pages of about page_chars characters
+        let page_chars = if self.page_chars == 0 { text.len() } else { self.page_chars };
         let mut pages = Vec::new();
         let mut page_num = 1u32;
         let mut current_pos = 0;
 
         while current_pos < text.len() {
-            let end_pos = std::cmp::min(current_pos + 2000, text.len());
+            // Back off to a grapheme boundary so multi-byte characters, emoji
+            // sequences and combining marks stay whole
+            let rest = &text[current_pos..];
+            let mut end_pos = current_pos + grapheme_floor(rest, page_chars);
+            if end_pos == current_pos {
+                end_pos += rest.graphemes(true).next().map_or(rest.len(), str::len);
+            }
+            // End the page after the last whitespace before the cut, so no
+            // word is split; a page holding a single word is cut as is
+            if end_pos < text.len() && !text[end_pos..].starts_with(char::is_whitespace) {
+                if let Some((i, space)) = text[current_pos..end_pos].char_indices().rfind(|(_, c)| c.is_whitespace()) {
+                    end_pos = current_pos + i + space.len_utf8();
+                }
+            }
+            // Never cut a code block in half; let the page run to its end
+            if let Some(block) = code_blocks.iter().find(|b| b.start < end_pos && end_pos < b.end) {
+                end_pos = block.end;
+            }
             let page_text = &text[current_pos..end_pos];
 
             pages.push(Page {
                 page_num,
                 text: page_text.to_string(),
                 images: Vec::new(),
+                code_blocks: code_blocks
+                    .iter()
+                    .filter(|b| b.start >= current_pos && b.end <= end_pos)
+                    .map(|b| CodeBlock {
+                        start: b.start - current_pos,
+                        end: b.end - current_pos,
+                        language: b.language.clone(),
+                    })
+                    .collect(),
+                ..Default::default()
             });
 
             current_pos = end_pos;
@@ -137,5 +356,106 @@ mod tests {
         let pages = result.unwrap();
         assert!(!pages.is_empty());
     }
-}
 
+    #[test]
+    fn test_html_without_text_is_empty_document() {
+        let html = b"<html><head><title>Notes</title></head><body><script>init();</script></body></html>";
+        let error = HtmlParser::new().parse(Cursor::new(html.to_vec())).unwrap_err();
+        assert!(matches!(error, ParserError::EmptyDocument(_)));
+    }
+
+    #[test]
+    fn test_html_parser_pages_by_section() {
+        let parser = HtmlParser::new().with_section_selectors(DEFAULT_SECTION_SELECTORS.iter().copied());
+        let html = b"<html><body><nav>Menu</nav>\
+            <section><h2>Intro</h2><p>First section.</p></section>\
+            <section><h2>Methods</h2><p>Second <b>section</b>.</p><section><p>Nested.</p></section></section>\
+            <article><p>Third part.</p></article></body></html>";
+
+        let pages = parser.parse(Cursor::new(html.to_vec())).unwrap();
+
+        assert_eq!(pages.len(), 4);
+        // Text outside the sections keeps its place as a page of its own
+        assert_eq!(pages[0].text, "Menu");
+        assert_eq!(pages[1].text, "Intro First section.");
+        assert_eq!(pages[2].text, "Methods Second section. Nested.");
+        assert_eq!(pages[3].text, "Third part.");
+        assert_eq!(pages.iter().map(|p| p.page_num).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_html_parser_section_fallback() {
+        let parser = HtmlParser::new().with_section_selectors(["section"]);
+        let html = b"<html><body><h1>Test</h1><p>Content</p></body></html>";
+
+        let pages = parser.parse(Cursor::new(html.to_vec())).unwrap();
+
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].text, "Test Content");
+    }
+
+    #[test]
+    fn test_html_parser_marks_pre_blocks() {
+        let parser = HtmlParser::new();
+        let html = b"<html><body><p>Example:</p>\
+            <pre><code class=\"language-rust\">fn main() {\n    println!(\"hi\");\n}</code></pre>\
+            <script>var tracking = 1;</script><p>The end.</p></body></html>";
+
+        let pages = parser.parse(Cursor::new(html.to_vec())).unwrap();
+
+        let page = &pages[0];
+        assert_eq!(page.code_blocks.len(), 1);
+        let block = &page.code_blocks[0];
+        assert_eq!(block.language.as_deref(), Some("rust"));
+        assert_eq!(&page.text[block.start..block.end], "fn main() {\n    println!(\"hi\");\n}");
+        assert!(!page.text.contains("tracking"));
+    }
+
+    #[test]
+    fn test_html_parser_paginates_multibyte_text() {
+        let parser = HtmlParser::new();
+        // Two ASCII bytes first so the 2000-byte cut lands inside a "ü"
+        let body = format!("xx{}", "Grüße äöü ".repeat(300));
+        let html = format!("<html><body><p>{}</p></body></html>", body);
+        let expected: String = body.split_whitespace().collect::<Vec<_>>().join(" ");
+        assert!(!expected.is_char_boundary(2000));
+
+        let pages = parser.parse(Cursor::new(html.into_bytes())).unwrap();
+
+        assert!(pages.len() > 1);
+        let joined: String = pages.iter().map(|p| p.text.as_str()).collect();
+        assert_eq!(joined.replace(' ', ""), expected.replace(' ', ""));
+    }
+
+    #[test]
+    fn test_html_parser_pages_end_between_words() {
+        let paragraph = "International collaboration accelerated the characterisation of \
+            extraordinarily heterogeneous intracellular compartments. "
+            .repeat(40);
+        let html = format!("<html><body><p>{}</p></body></html>", paragraph);
+        let words: HashSet<&str> = paragraph.split_whitespace().collect();
+
+        let pages = HtmlParser::new().with_page_chars(100).parse_bytes(html.as_bytes()).unwrap();
+
+        assert!(pages.len() > 1);
+        for page in &pages {
+            assert!(page.text.split_whitespace().all(|word| words.contains(word)), "{:?}", page.text);
+        }
+        let joined: String = pages.iter().map(|p| p.text.as_str()).collect();
+        assert_eq!(joined, paragraph.split_whitespace().collect::<Vec<_>>().join(" "));
+    }
+
+    #[test]
+    fn test_html_parser_custom_page_size() {
+        let html = format!("<html><body><p>{}</p></body></html>", "Cells divide. ".repeat(100));
+        let text_len = "Cells divide. ".repeat(100).trim_end().len();
+
+        let pages = HtmlParser::new().with_page_chars(500).parse_bytes(html.as_bytes()).unwrap();
+        assert_eq!(pages.len(), text_len.div_ceil(500));
+        assert!(pages.iter().all(|p| p.text.len() <= 500));
+
+        let pages = HtmlParser::new().with_page_chars(0).parse_bytes(html.as_bytes()).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].text.len(), text_len);
+    }
+}
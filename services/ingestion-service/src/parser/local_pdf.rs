@@ -1,56 +1,257 @@
-use std::io::Read;
-use super::traits::{Page, Parser, ParserError};
+use std::ops::Range;
+use std::sync::Arc;
 
-pub struct LocalPdfParser;
+use super::ocr::OcrEngine;
+use super::pdf_layout::{self, TextRun};
+use super::traits::{DocumentInfo, Highlight, Image, Page, Parser, ParserError, TableBlock};
+use super::{normalize, pdf_encoding, pdf_images, pdf_text};
+
+/// Images kept per PDF page unless configured otherwise.
+pub const DEFAULT_MAX_IMAGES_PER_PAGE: usize = 16;
+
+/// Typographic ligatures and the letters they stand for.
+const LIGATURES: &[(char, &str)] = &[
+    ('\u{FB00}', "ff"),
+    ('\u{FB01}', "fi"),
+    ('\u{FB02}', "fl"),
+    ('\u{FB03}', "ffi"),
+    ('\u{FB04}', "ffl"),
+    ('\u{FB05}', "st"),
+    ('\u{FB06}', "st"),
+];
+
+pub struct LocalPdfParser {
+    infer_headings: bool,
+    normalize_rotation: bool,
+    expand_ligatures: bool,
+    extract_images: bool,
+    max_images_per_page: usize,
+    detect_tables: bool,
+    ocr: Option<Arc<dyn OcrEngine>>,
+    normalize_whitespace: bool,
+    max_retries: u32,
+}
 
 impl LocalPdfParser {
     pub fn new() -> Self {
-        Self
+        Self {
+            infer_headings: false,
+            normalize_rotation: true,
+            expand_ligatures: true,
+            extract_images: true,
+            max_images_per_page: DEFAULT_MAX_IMAGES_PER_PAGE,
+            detect_tables: false,
+            ocr: None,
+            normalize_whitespace: false,
+            max_retries: 0,
+        }
     }
-}
 
-impl Default for LocalPdfParser {
-    fn default() -> Self {
-        Self::new()
+    /// Extract the text again, up to `max` more times, when `pdf_extract`
+    /// fails: it fails on some PDFs only on the first attempt. Only that
+    /// stage is retried, so files that do not load fail at once and images
+    /// are read and recognized once. 0, the default, fails on the first
+    /// error.
+    pub fn with_max_retries(mut self, max: u32) -> Self {
+        self.max_retries = max;
+        self
     }
-}
 
-impl Parser for LocalPdfParser {
-    fn parse<R: Read>(&self, mut reader: R) -> Result<Vec<Page>, ParserError> {
-        let mut buffer = Vec::new();
-        reader.read_to_end(&mut buffer)?;
+    /// Clean up extraction artifacts: rejoin words hyphenated across lines,
+    /// drop page-number lines and join each paragraph's lines into one, so
+    /// line breaks no longer end sentences. Off by default, which keeps the
+    /// text as extracted.
+    pub fn with_normalize_whitespace(mut self, enabled: bool) -> Self {
+        self.normalize_whitespace = enabled;
+        self
+    }
+
+    /// Recognize the text of pages without a text layer, as scans have, from
+    /// their embedded images. Without OCR a PDF with no text at all fails
+    /// to parse.
+    pub fn with_ocr(mut self, ocr: Option<Arc<dyn OcrEngine>>) -> Self {
+        self.ocr = ocr;
+        self
+    }
+
+    /// Rebuild tables from text positions and put them in the page text as
+    /// Markdown, in place of the jumbled cells plain extraction yields.
+    pub fn with_detect_tables(mut self, enabled: bool) -> Self {
+        self.detect_tables = enabled;
+        self
+    }
+
+    /// Keep at most `max` embedded images of each PDF page, bounding the
+    /// memory image-heavy slide decks take; 0 skips image extraction. OCR
+    /// reads no more images of a page either.
+    pub fn with_max_images_per_page(mut self, max: usize) -> Self {
+        self.max_images_per_page = max;
+        self
+    }
 
-        let doc = lopdf::Document::load_mem(&buffer)
+    /// Return the embedded images with their pages. Enabled by default;
+    /// when disabled, images are still read for OCR.
+    pub fn with_extract_images(mut self, enabled: bool) -> Self {
+        self.extract_images = enabled;
+        self
+    }
+
+    /// Replace typographic ligatures such as `ﬁ` with their letters so words
+    /// match on search. Enabled by default.
+    pub fn with_expand_ligatures(mut self, enabled: bool) -> Self {
+        self.expand_ligatures = enabled;
+        self
+    }
+
+    /// Normalize text positions on pages with a `/Rotate` of 90, 180 or 270
+    /// degrees to the upright page, so position-based features (reading order,
+    /// headings, highlights) follow the page as displayed. Enabled by default.
+    pub fn with_normalize_rotation(mut self, enabled: bool) -> Self {
+        self.normalize_rotation = enabled;
+        self
+    }
+
+    /// Infer a heading outline from font sizes for PDFs without bookmarks.
+    pub fn with_infer_headings(mut self, enabled: bool) -> Self {
+        self.infer_headings = enabled;
+        self
+    }
+
+    fn parse_pages(&self, data: &[u8]) -> Result<Vec<Page>, ParserError> {
+        let doc = lopdf::Document::load_mem(data)
             .map_err(|e| ParserError::PdfParse(e.to_string()))?;
 
-        let mut pages = Vec::new();
-        let page_count = doc.get_pages().len();
+        let page_ids: Vec<lopdf::ObjectId> = doc.get_pages().into_values().collect();
+        let page_count = page_ids.len();
+        let texts = if page_count > 0 {
+            with_retries(self.max_retries, || pdf_text::page_texts(data))
+                .map_err(|e| ParserError::PdfParse(e.to_string()))?
+        } else {
+            Vec::new()
+        };
+        let mut pages: Vec<Page> = texts
+            .into_iter()
+            .enumerate()
+            .map(|(i, text)| Page {
+                page_num: i as u32 + 1,
+                text: pdf_encoding::repair_codepage(&doc, &text).unwrap_or(text),
+                ..Default::default()
+            })
+            .collect();
+
+        let page_runs: Vec<Vec<TextRun>> = page_ids
+            .iter()
+            .map(|&page_id| pdf_layout::page_text_runs(&doc, page_id, self.normalize_rotation))
+            .collect();
 
-        for page_num in 1..=page_count {
-            let text = pdf_extract::extract_text_from_mem(&buffer)
-                .map_err(|e| ParserError::PdfParse(e.to_string()))?;
+        if self.infer_headings {
+            for (page, headings) in pages.iter_mut().zip(pdf_layout::infer_headings(&page_runs)) {
+                page.headings = headings;
+            }
+        }
 
-            pages.push(Page {
-                page_num: page_num as u32,
-                text,
-                images: Vec::new(),
-            });
-            break; // pdf_extract extracts all pages at once
+        // Image IDs number the images through the document
+        let mut image_count = 0;
+        for (page, &page_id) in pages.iter_mut().zip(&page_ids).filter(|_| self.extract_images) {
+            for (data, content_type) in pdf_images::page_images(&doc, page_id, self.max_images_per_page) {
+                image_count += 1;
+                page.images.push(Image {
+                    id: format!("img-{}", image_count),
+                    data,
+                    content_type: content_type.to_string(),
+                });
+            }
         }
 
-        if pages.is_empty() && page_count > 0 {
-            let text = pdf_extract::extract_text_from_mem(&buffer)
-                .unwrap_or_default();
-            pages.push(Page {
-                page_num: 1,
-                text,
-                images: Vec::new(),
-            });
+        // Scanned pages carry no text runs, only the image of the page
+        if let Some(ocr) = &self.ocr {
+            for ((page, &page_id), runs) in pages.iter_mut().zip(&page_ids).zip(&page_runs) {
+                if runs.iter().any(|run| !run.text.trim().is_empty()) {
+                    continue;
+                }
+                tracing::info!("PDF page {} has no text layer, falling back to OCR", page.page_num);
+                for (data, content_type) in pdf_images::page_images(&doc, page_id, self.max_images_per_page) {
+                    // One unreadable image shouldn't cost the rest of the document
+                    match ocr.recognize(&data, content_type) {
+                        Ok(text) => page.text = format!("{}\n\n{}", page.text.trim_end(), text.trim()),
+                        Err(e) => tracing::warn!("OCR failed for an image on PDF page {}: {}", page.page_num, e),
+                    }
+                }
+                page.text = page.text.trim_start().to_string();
+            }
+        }
+        if page_count > 0 && pages.iter().all(|page| page.text.trim().is_empty()) {
+            let reason = if self.ocr.is_some() {
+                "OCR recognized no text in the page images"
+            } else {
+                "the PDF has no text layer and OCR is not configured"
+            };
+            return Err(ParserError::NoExtractableText(reason.to_string()));
+        }
+
+        // Attach each highlight to the page whose text contains it
+        for highlight in self.extract_highlights(&doc, &page_runs) {
+            let needle = collapse_whitespace(&highlight.text);
+            let target = pages
+                .iter()
+                .position(|p| collapse_whitespace(&p.text).contains(&needle))
+                .unwrap_or(0);
+            if let Some(page) = pages.get_mut(target) {
+                page.highlights.push(highlight);
+            }
+        }
+
+        if self.expand_ligatures {
+            for page in &mut pages {
+                page.text = expand_ligatures(&page.text);
+                page.headings.iter_mut().for_each(|h| h.text = expand_ligatures(&h.text));
+                page.highlights.iter_mut().for_each(|h| h.text = expand_ligatures(&h.text));
+            }
+        }
+
+        if self.detect_tables {
+            for (page, runs) in pages.iter_mut().zip(&page_runs) {
+                for mut rows in pdf_layout::detect_tables(runs) {
+                    if self.expand_ligatures {
+                        rows.iter_mut().flatten().for_each(|cell| *cell = expand_ligatures(cell));
+                    }
+                    insert_table(page, &rows);
+                }
+            }
+        }
+
+        if self.normalize_whitespace {
+            pages.iter_mut().for_each(normalize::normalize_whitespace);
         }
 
         Ok(pages)
     }
 
+    /// Collect highlight annotations from every page of the document.
+    fn extract_highlights(&self, doc: &lopdf::Document, page_runs: &[Vec<TextRun>]) -> Vec<Highlight> {
+        doc.get_pages()
+            .values()
+            .zip(page_runs)
+            .flat_map(|(&page_id, runs)| pdf_layout::page_highlights(doc, page_id, runs, self.normalize_rotation))
+            .collect()
+    }
+}
+
+impl Default for LocalPdfParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser for LocalPdfParser {
+    fn parse_bytes(&self, data: &[u8]) -> Result<Vec<Page>, ParserError> {
+        self.parse_pages(data)
+    }
+
+    fn document_info(&self, data: &[u8]) -> DocumentInfo {
+        lopdf::Document::load_mem(data).map(|doc| read_info(&doc)).unwrap_or_default()
+    }
+
     fn supported_extensions(&self) -> &[&str] {
         &["pdf"]
     }
@@ -60,3 +261,517 @@ impl Parser for LocalPdfParser {
     }
 }
 
+/// Runs `attempt` until it succeeds or has been retried `max_retries` times.
+fn with_retries<T, E: std::fmt::Display>(max_retries: u32, mut attempt: impl FnMut() -> Result<T, E>) -> Result<T, E> {
+    let mut retries = 0;
+    loop {
+        match attempt() {
+            Err(e) if retries < max_retries => {
+                retries += 1;
+                tracing::warn!("PDF text extraction failed ({}), retry {} of {}", e, retries, max_retries);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Document properties from the trailer's `/Info` dictionary.
+fn read_info(doc: &lopdf::Document) -> DocumentInfo {
+    let info = doc
+        .trailer
+        .get(b"Info")
+        .and_then(|info| doc.dereference(info))
+        .and_then(|(_, info)| info.as_dict());
+    let Ok(info) = info else {
+        return DocumentInfo::default();
+    };
+    let text = |key: &[u8]| {
+        info.get(key)
+            .and_then(lopdf::decode_text_string)
+            .map(|value| value.trim().to_string())
+            .ok()
+            .filter(|value| !value.is_empty())
+    };
+    DocumentInfo {
+        title: text(b"Title"),
+        author: text(b"Author"),
+        created_at: text(b"CreationDate").map(|date| pdf_date(&date)),
+        modified_at: text(b"ModDate").map(|date| pdf_date(&date)),
+        ..Default::default()
+    }
+}
+
+/// Convert a PDF date (`D:20240131120000+01'00'`) to RFC 3339. Omitted
+/// fields take their PDF defaults and a date without a zone stays local;
+/// anything unrecognised is returned as is.
+fn pdf_date(raw: &str) -> String {
+    let date = raw.strip_prefix("D:").unwrap_or(raw);
+    let digits = date.chars().take_while(char::is_ascii_digit).count();
+    if !(4..=14).contains(&digits) || digits % 2 != 0 {
+        return raw.to_string();
+    }
+    let field = |start: usize, default: &'static str| {
+        date.get(start..start + 2).filter(|_| start < digits).unwrap_or(default)
+    };
+    let rest = &date[digits..];
+    let zone = match rest.chars().next() {
+        Some('Z') => "Z".to_string(),
+        Some(sign @ ('+' | '-')) => {
+            let offset: String = rest[1..].chars().filter(char::is_ascii_digit).collect();
+            format!(
+                "{}{}:{}",
+                sign,
+                offset.get(0..2).unwrap_or("00"),
+                offset.get(2..4).unwrap_or("00")
+            )
+        }
+        _ => String::new(),
+    };
+    format!(
+        "{}-{}-{}T{}:{}:{}{}",
+        &date[..4],
+        field(4, "01"),
+        field(6, "01"),
+        field(8, "00"),
+        field(10, "00"),
+        field(12, "00"),
+        zone
+    )
+}
+
+/// Render rows of cells, at least one, as a Markdown table with the first
+/// row as its header.
+fn markdown_table(rows: &[Vec<String>]) -> String {
+    let row = |cells: &[String]| {
+        let cells: Vec<String> = cells.iter().map(|cell| cell.replace('|', "\\|")).collect();
+        format!("| {} |", cells.join(" | "))
+    };
+    let mut lines = vec![row(&rows[0]), format!("|{}", " --- |".repeat(rows[0].len()))];
+    lines.extend(rows[1..].iter().map(|cells| row(cells)));
+    lines.join("\n")
+}
+
+/// Put `rows` as a Markdown table in place of the text extracted for them:
+/// from the first row to the last, each found as its whole run of cells,
+/// after any earlier table. Tables whose text can't be found are appended
+/// to the page.
+fn insert_table(page: &mut Page, rows: &[Vec<String>]) {
+    let table = markdown_table(rows);
+    let from = page.tables.last().map_or(0, |t| t.end);
+    let range = find_row(&page.text, from, &rows[0]).and_then(|first| {
+        let end = match rows {
+            [_] => first.end,
+            [.., last] => find_row(&page.text, first.end, last)?.end,
+            [] => return None,
+        };
+        Some(first.start..end)
+    });
+
+    let start = match range {
+        Some(range) => {
+            // On lines of its own, so it renders and sentence-splits apart
+            let (head, tail) = (&page.text[..range.start], &page.text[range.end..]);
+            let before = if head.is_empty() || head.ends_with('\n') { "" } else { "\n\n" };
+            let after = if tail.is_empty() || tail.starts_with('\n') { "" } else { "\n\n" };
+            let start = range.start + before.len();
+            page.text.replace_range(range, &format!("{}{}{}", before, table, after));
+            start
+        }
+        None => {
+            if !page.text.is_empty() {
+                page.text.push_str("\n\n");
+            }
+            let start = page.text.len();
+            page.text.push_str(&table);
+            start
+        }
+    };
+    page.tables.push(TableBlock {
+        start,
+        end: start + table.len(),
+    });
+}
+
+/// Where the cells of `row` first appear in `text` at or after `from`, in
+/// order and separated by whitespace only, as a table row is extracted.
+fn find_row(text: &str, from: usize, row: &[String]) -> Option<Range<usize>> {
+    let first = row.first()?;
+    text[from..].match_indices(first.as_str()).find_map(|(i, _)| {
+        let start = from + i;
+        let mut end = start + first.len();
+        for cell in &row[1..] {
+            let rest = &text[end..];
+            let skipped = rest.len() - rest.trim_start().len();
+            if skipped == 0 || !rest[skipped..].starts_with(cell.as_str()) {
+                return None;
+            }
+            end += skipped + cell.len();
+        }
+        Some(start..end)
+    })
+}
+
+fn expand_ligatures(text: &str) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    for c in text.chars() {
+        match LIGATURES.iter().find(|(ligature, _)| *ligature == c) {
+            Some((_, letters)) => expanded.push_str(letters),
+            None => expanded.push(c),
+        }
+    }
+    expanded
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::fixtures;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_reads_info_dictionary() {
+        let pdf = fixtures::PdfBuilder::new()
+            .page(&["Cells are the basic unit of life."])
+            .info(&[
+                ("Title", "Zellbiologie – Grundlagen"),
+                ("Author", "Dr. Ada Teacher"),
+                ("CreationDate", "D:20240131120000+01'00'"),
+                ("ModDate", "D:20240205"),
+            ])
+            .build();
+
+        assert_eq!(
+            LocalPdfParser::new().document_info(&pdf),
+            DocumentInfo {
+                title: Some("Zellbiologie – Grundlagen".to_string()),
+                author: Some("Dr. Ada Teacher".to_string()),
+                created_at: Some("2024-01-31T12:00:00+01:00".to_string()),
+                modified_at: Some("2024-02-05T00:00:00".to_string()),
+                ..Default::default()
+            }
+        );
+
+        let plain = fixtures::PdfBuilder::new().page(&["No properties."]).build();
+        assert_eq!(LocalPdfParser::new().document_info(&plain), DocumentInfo::default());
+        assert_eq!(pdf_date("D:20231105083000Z"), "2023-11-05T08:30:00Z");
+        assert_eq!(pdf_date("last Tuesday"), "last Tuesday");
+    }
+
+    #[test]
+    fn test_recovers_cyrillic_codepage_text() {
+        let pdf = fixtures::PdfBuilder::new()
+            .codepage(encoding_rs::WINDOWS_1251, "TimesNewRomanCyr")
+            .page(&["Клетка – основная единица жизни.", "Клетки делятся митозом."])
+            .build();
+
+        let pages = LocalPdfParser::new().parse(Cursor::new(pdf)).unwrap();
+
+        assert!(pages[0].text.contains("Клетка – основная единица жизни."));
+        assert!(pages[0].text.contains("Клетки делятся митозом."));
+    }
+
+    #[test]
+    fn test_extracts_highlight_annotation() {
+        let pdf = fixtures::PdfBuilder::new()
+            .page(&["Plain introduction text.", "Photosynthesis converts light into energy."])
+            .highlight(1, 1, [1.0, 1.0, 0.0])
+            .build();
+
+        let pages = LocalPdfParser::new().parse(Cursor::new(pdf)).unwrap();
+
+        assert_eq!(pages[0].highlights.len(), 1);
+        assert_eq!(pages[0].highlights[0].text, "Photosynthesis converts light into energy.");
+        assert_eq!(pages[0].highlights[0].color.as_deref(), Some("#ffff00"));
+    }
+
+    #[test]
+    fn test_infers_headings_from_font_size() {
+        let pdf = fixtures::PdfBuilder::new()
+            .sized_page(&[
+                ("Cell Biology", 24.0),
+                ("Membranes", 18.0),
+                ("Every cell is enclosed by a membrane.", 12.0),
+                ("Lipid Bilayers", 14.0),
+                ("The membrane is made of two layers of lipids.", 12.0),
+            ])
+            .sized_page(&[
+                ("Organelles", 18.0),
+                ("Organelles carry out specialised tasks inside the cell.", 12.0),
+            ])
+            .build();
+
+        let headings = |page: &Page| -> Vec<(u8, String)> {
+            page.headings.iter().map(|h| (h.level, h.text.clone())).collect()
+        };
+
+        let pages = LocalPdfParser::new()
+            .with_infer_headings(true)
+            .parse(Cursor::new(&pdf))
+            .unwrap();
+        assert_eq!(
+            headings(&pages[0]),
+            vec![
+                (1, "Cell Biology".to_string()),
+                (2, "Membranes".to_string()),
+                (3, "Lipid Bilayers".to_string()),
+            ]
+        );
+        assert_eq!(headings(&pages[1]), vec![(2, "Organelles".to_string())]);
+
+        let pages = LocalPdfParser::new().parse(Cursor::new(&pdf)).unwrap();
+        assert!(pages.iter().all(|page| page.headings.is_empty()));
+    }
+
+    #[test]
+    fn test_normalizes_rotated_page_positions() {
+        let pdf = fixtures::PdfBuilder::new()
+            .sized_page(&[("Introduction", 18.0), ("This page is upright.", 12.0)])
+            .sized_page(&[
+                ("Landscape Table", 18.0),
+                ("The first row of the table.", 12.0),
+                ("The second row of the table.", 12.0),
+            ])
+            .rotate(2, 90)
+            .highlight(2, 2, [1.0, 1.0, 0.0])
+            .build();
+
+        let pages = LocalPdfParser::new()
+            .with_infer_headings(true)
+            .parse(Cursor::new(&pdf))
+            .unwrap();
+        let headings: Vec<&str> = pages.iter().flat_map(|p| &p.headings).map(|h| h.text.as_str()).collect();
+        assert_eq!(headings, vec!["Introduction", "Landscape Table"]);
+        assert_eq!(pages[1].highlights.len(), 1);
+        assert_eq!(pages[1].highlights[0].text, "The second row of the table.");
+
+        // Runs sit on the upright 792x612 page, top to bottom at the left margin
+        let doc = lopdf::Document::load_mem(&pdf).unwrap();
+        let page_id = doc.get_pages()[&2];
+        assert_eq!(pdf_layout::page_rotation(&doc, page_id), 90);
+        let runs = pdf_layout::page_text_runs(&doc, page_id, true);
+        assert!(runs.iter().all(|r| (r.x - 72.0).abs() < 0.01 && r.y > 0.0 && r.y < 612.0));
+        assert!(runs.windows(2).all(|w| w[0].y > w[1].y));
+        assert!(runs.iter().all(|r| (r.font_size - 12.0).abs() < 0.01 || (r.font_size - 18.0).abs() < 0.01));
+
+        // Without normalization every line shares a raw baseline and merges
+        let pages = LocalPdfParser::new()
+            .with_infer_headings(true)
+            .with_normalize_rotation(false)
+            .parse(Cursor::new(&pdf))
+            .unwrap();
+        assert!(!pages[1].headings.iter().any(|h| h.text == "Landscape Table"));
+    }
+
+    #[test]
+    fn test_expands_ligatures() {
+        use crate::splitter::{SentenceTextSplitter, TextSplitter};
+
+        // As pdf_extract passes them through
+        let extracted = "The \u{FB01}nal \u{FB02}ow of e\u{FB00}ort was o\u{FB03}cial.";
+        let page = Page {
+            page_num: 1,
+            text: expand_ligatures(extracted),
+            ..Default::default()
+        };
+        assert_eq!(page.text, "The final flow of effort was official.");
+
+        let chunks = SentenceTextSplitter::new(500, 0).split(std::slice::from_ref(&page));
+        assert!(chunks[0].text.contains("final") && chunks[0].text.contains("official"));
+        assert_eq!(chunks[0].char_count, page.text.chars().count());
+    }
+
+    /// Reads every image as the same line of text.
+    struct FixedOcr(&'static str);
+
+    impl OcrEngine for FixedOcr {
+        fn recognize(&self, _image: &[u8], content_type: &str) -> Result<String, ParserError> {
+            assert_eq!(content_type, "image/png");
+            Ok(self.0.to_string())
+        }
+    }
+
+    #[test]
+    fn test_scanned_pages_need_ocr() {
+        let rgb = [255; 12];
+        let scan = fixtures::PdfBuilder::new().page(&[]).image(1, 2, 2, &rgb).build();
+
+        let error = LocalPdfParser::new().parse(Cursor::new(scan.clone())).unwrap_err();
+        assert!(matches!(error, ParserError::NoExtractableText(_)));
+        assert_eq!(
+            error.to_string(),
+            "No extractable text: the PDF has no text layer and OCR is not configured"
+        );
+
+        let ocr: Arc<dyn OcrEngine> = Arc::new(FixedOcr("Cells divide by mitosis."));
+        let pages = LocalPdfParser::new()
+            .with_ocr(Some(ocr.clone()))
+            .parse(Cursor::new(scan))
+            .unwrap();
+        assert_eq!(pages[0].text, "Cells divide by mitosis.");
+
+        // Only the page without a text layer is read
+        let mixed = fixtures::PdfBuilder::new()
+            .page(&["Chapter 1"])
+            .page(&[])
+            .image(2, 2, 2, &rgb)
+            .build();
+        let pages = LocalPdfParser::new()
+            .with_ocr(Some(ocr))
+            .with_extract_images(false)
+            .parse(Cursor::new(mixed))
+            .unwrap();
+        assert_eq!(pages[0].text.trim(), "Chapter 1");
+        assert_eq!(pages[1].text, "Cells divide by mitosis.");
+        assert!(pages[1].images.is_empty());
+    }
+
+    /// Fails on the first image and numbers the ones after it.
+    struct FirstFailsOcr(std::sync::atomic::AtomicUsize);
+
+    impl OcrEngine for FirstFailsOcr {
+        fn recognize(&self, _image: &[u8], _content_type: &str) -> Result<String, ParserError> {
+            match self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 => Err(ParserError::ParseError("OCR service returned 500".to_string())),
+                n => Ok(format!("Image {} was read.", n)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_ocr_skips_failed_images_within_page_limit() {
+        let rgb = [255; 12];
+        let scan = fixtures::PdfBuilder::new()
+            .page(&[])
+            .image(1, 2, 2, &rgb)
+            .image(1, 2, 2, &rgb)
+            .image(1, 2, 2, &rgb)
+            .build();
+        let ocr = Arc::new(FirstFailsOcr(Default::default()));
+
+        let pages = LocalPdfParser::new()
+            .with_ocr(Some(ocr.clone()))
+            .with_max_images_per_page(2)
+            .parse(Cursor::new(scan))
+            .unwrap();
+        assert_eq!(pages[0].text, "Image 1 was read.");
+        assert_eq!(ocr.0.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_extracts_embedded_images() {
+        // A 2x2 image: red, green / blue, white
+        let rgb = [255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255];
+        let pdf = fixtures::PdfBuilder::new()
+            .page(&["Figure 1 shows a cell."])
+            .page(&["Figure 2 shows mitosis."])
+            .image(2, 2, 2, &rgb)
+            .build();
+
+        let pages = LocalPdfParser::new().parse(Cursor::new(pdf.clone())).unwrap();
+
+        assert!(pages[0].images.is_empty());
+        let images = &pages[1].images;
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].id, "img-1");
+        assert_eq!(images[0].content_type, "image/png");
+        let decoder = png::Decoder::new(Cursor::new(&images[0].data));
+        let mut reader = decoder.read_info().unwrap();
+        let mut decoded = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame(&mut decoded).unwrap();
+        assert_eq!(decoded, rgb);
+
+        let pages = LocalPdfParser::new()
+            .with_max_images_per_page(0)
+            .parse(Cursor::new(pdf))
+            .unwrap();
+        assert!(pages.iter().all(|page| page.images.is_empty()));
+
+        // Dimensions past the sample cap are skipped before decoding
+        let pdf = fixtures::PdfBuilder::new()
+            .page(&["Figure 3 is too large."])
+            .image(1, 1 << 20, 1 << 20, &rgb)
+            .image(1, 2, 2, &rgb)
+            .build();
+        let pages = LocalPdfParser::new().parse(Cursor::new(pdf)).unwrap();
+        assert_eq!(pages[0].images.len(), 1);
+    }
+
+    #[test]
+    fn test_detects_two_column_table() {
+        use crate::splitter::{Chunk, SentenceTextSplitter, TextSplitter};
+
+        let pdf = fixtures::PdfBuilder::new()
+            .page(&["Grades are weighted as follows."])
+            .table_page(&[&["Assessment", "Weight"], &["Midterm exam", "30%"], &["Final project", "70%"]])
+            .build();
+
+        let pages = LocalPdfParser::new()
+            .with_detect_tables(true)
+            .parse(Cursor::new(pdf.clone()))
+            .unwrap();
+
+        let table = "| Assessment | Weight |\n| --- | --- |\n| Midterm exam | 30% |\n| Final project | 70% |";
+        assert!(pages[0].tables.is_empty());
+        assert!(pages[1].text.contains(table), "{}", pages[1].text);
+        assert_eq!(pages[1].tables.len(), 1);
+        assert_eq!(&pages[1].text[pages[1].tables[0].start..pages[1].tables[0].end], table);
+
+        let chunks = SentenceTextSplitter::new(500, 0).split(&pages);
+        let tables: Vec<&Chunk> = chunks.iter().filter(|c| c.kind.as_deref() == Some("table")).collect();
+        assert_eq!(tables.len(), 1);
+        assert_eq!(tables[0].text, table);
+
+        // Off by default
+        let pages = LocalPdfParser::new().parse(Cursor::new(pdf)).unwrap();
+        assert!(!pages[1].text.contains('|'));
+        assert!(pages[1].tables.is_empty());
+
+        // Prose mentioning the first cell stays in place
+        let pdf = fixtures::PdfBuilder::new()
+            .table_page(&[
+                &["Assessment is continuous, and the weight of each part differs."],
+                &["Assessment", "Weight"],
+                &["Midterm exam", "30%"],
+                &["Final project", "70%"],
+            ])
+            .build();
+        let pages = LocalPdfParser::new()
+            .with_detect_tables(true)
+            .parse(Cursor::new(pdf))
+            .unwrap();
+        assert!(pages[0].text.contains("each part differs.\n\n| Assessment"), "{}", pages[0].text);
+        assert!(pages[0].text.ends_with(table), "{}", pages[0].text);
+    }
+
+    #[test]
+    fn test_retries_failed_extraction() {
+        let calls = std::cell::Cell::new(0);
+        let flaky = || {
+            calls.set(calls.get() + 1);
+            match calls.get() {
+                1 => Err("unexpected end of content stream"),
+                _ => Ok("Cells divide."),
+            }
+        };
+
+        assert!(with_retries(0, flaky).is_err());
+        assert_eq!(calls.get(), 1);
+
+        calls.set(0);
+        assert_eq!(with_retries(2, flaky), Ok("Cells divide."));
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn test_malformed_file_fails_despite_retries() {
+        let error = LocalPdfParser::new()
+            .with_max_retries(3)
+            .parse(Cursor::new(b"not a PDF".to_vec()))
+            .unwrap_err();
+        assert!(matches!(error, ParserError::PdfParse(_)), "{error}");
+    }
+}
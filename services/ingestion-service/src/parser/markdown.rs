@@ -0,0 +1,384 @@
+// Markdown parser implementation
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use super::code;
+use super::traits::{CodeBlock, Heading, Page, Parser, ParserError};
+
+/// How Markdown syntax appears in the page text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MarkdownSyntax {
+    /// The source as written.
+    #[default]
+    Keep,
+    /// Setext headings rewritten as ATX, `*` and `+` bullets as `-`,
+    /// trailing whitespace dropped and runs of blank lines collapsed.
+    Normalize,
+    /// Plain text: heading markers, emphasis, inline code ticks, link and
+    /// image syntax and block quote markers removed. Heading text stays on
+    /// its own line and code blocks keep their fences.
+    Strip,
+}
+
+/// Parser for Markdown documents. Every top-level (`#` or `##`) heading
+/// starts a new page, so each section is addressable on its own.
+pub struct MarkdownParser {
+    syntax: MarkdownSyntax,
+}
+
+/// What a source line is, with fenced code and setext underlines resolved.
+#[derive(Debug, Clone, PartialEq)]
+enum LineKind {
+    Fence,
+    Code,
+    Heading(u8, String),
+    /// The `===` or `---` line under a setext heading.
+    Underline,
+    Text,
+}
+
+/// Start offset and kind of every line in `text`.
+fn classify_lines(text: &str) -> Vec<(usize, &str, LineKind)> {
+    let mut lines: Vec<(usize, &str, LineKind)> = Vec::new();
+    let mut fence: Option<String> = None;
+    let mut offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let trimmed = line.trim_start_matches(' ');
+        let indent = line.len() - trimmed.len();
+        let marker = (indent <= 3).then(|| fence_marker(trimmed)).flatten();
+
+        let closes_fence = |open: &str, marker: &str| {
+            marker.starts_with(open) && trimmed[marker.len()..].trim().is_empty()
+        };
+        let kind = match (&fence, marker) {
+            (Some(open), Some(marker)) if closes_fence(open, marker) => {
+                fence = None;
+                LineKind::Fence
+            }
+            (Some(_), _) => LineKind::Code,
+            (None, Some(marker)) => {
+                fence = Some(marker.to_string());
+                LineKind::Fence
+            }
+            (None, None) if indent <= 3 => atx_heading(trimmed.trim_end())
+                .map(|(level, title)| LineKind::Heading(level, title))
+                .or_else(|| {
+                    let underline = trimmed.trim_end();
+                    let level = match underline.chars().next()? {
+                        '=' => 1,
+                        '-' => 2,
+                        _ => return None,
+                    };
+                    let (_, previous, previous_kind) = lines.last_mut()?;
+                    let is_setext = underline.chars().all(|c| c == underline.as_bytes()[0] as char)
+                        && *previous_kind == LineKind::Text
+                        && !previous.trim().is_empty()
+                        && !is_bullet(previous.trim_start());
+                    is_setext.then(|| {
+                        *previous_kind = LineKind::Heading(level, previous.trim().to_string());
+                        LineKind::Underline
+                    })
+                })
+                .unwrap_or(LineKind::Text),
+            (None, None) => LineKind::Text,
+        };
+        lines.push((start, line, kind));
+    }
+    lines
+}
+
+fn fence_marker(line: &str) -> Option<&str> {
+    let fence_char = line.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let len = line.chars().take_while(|&c| c == fence_char).count();
+    (len >= 3).then(|| &line[..len])
+}
+
+/// Level and title of an ATX heading line (`## Setup`).
+fn atx_heading(line: &str) -> Option<(u8, String)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let rest = &line[level..];
+    let is_heading = (1..=6).contains(&level) && (rest.trim().is_empty() || rest.starts_with([' ', '\t']));
+    is_heading.then(|| (level as u8, rest.trim().trim_end_matches('#').trim_end().to_string()))
+}
+
+fn is_bullet(line: &str) -> bool {
+    line.starts_with(['-', '*', '+']) && line[1..].starts_with([' ', '\t'])
+}
+
+/// `line` without inline Markdown syntax or block quote markers.
+fn strip_inline(line: &str) -> String {
+    static PATTERNS: OnceLock<Vec<(Regex, &str)>> = OnceLock::new();
+    let patterns = PATTERNS.get_or_init(|| {
+        [
+            (r"!?\[([^\]]*)\]\([^)]*\)", "$1"),
+            (r"\*\*(.+?)\*\*", "$1"),
+            (r"__(.+?)__", "$1"),
+            (r"\*([^*\s][^*]*?)\*", "$1"),
+            (r"`([^`]*)`", "$1"),
+        ]
+        .into_iter()
+        .map(|(pattern, replacement)| (Regex::new(pattern).unwrap(), replacement))
+        .collect()
+    });
+
+    let mut line = line.trim_start();
+    while let Some(rest) = line.strip_prefix('>') {
+        line = rest.trim_start();
+    }
+    patterns
+        .iter()
+        .fold(line.to_string(), |text, (re, replacement)| re.replace_all(&text, *replacement).into_owned())
+}
+
+impl MarkdownParser {
+    pub fn new() -> Self {
+        Self {
+            syntax: MarkdownSyntax::default(),
+        }
+    }
+
+    /// Keep, normalize or strip Markdown syntax in the page text. Anything
+    /// but [`MarkdownSyntax::Keep`] means pages have no source ranges.
+    pub fn with_syntax(mut self, syntax: MarkdownSyntax) -> Self {
+        self.syntax = syntax;
+        self
+    }
+
+    /// The page text for `lines` under the configured syntax handling.
+    fn render(&self, source: &str, lines: &[(usize, &str, LineKind)]) -> String {
+        if self.syntax == MarkdownSyntax::Keep {
+            return source.to_string();
+        }
+        let mut text = String::with_capacity(source.len());
+        let mut blank = false;
+        for (_, line, kind) in lines {
+            let rendered = match (kind, self.syntax) {
+                (LineKind::Fence | LineKind::Code, _) => {
+                    text.push_str(line);
+                    blank = false;
+                    continue;
+                }
+                (LineKind::Underline, _) => continue,
+                (LineKind::Heading(level, title), MarkdownSyntax::Normalize) => {
+                    format!("{} {}", "#".repeat(*level as usize), title)
+                }
+                (LineKind::Heading(_, title), _) => title.clone(),
+                (LineKind::Text, MarkdownSyntax::Normalize) => {
+                    let content = line.trim_start();
+                    let indent = &line[..line.len() - content.len()];
+                    match content.strip_prefix(['*', '+']).filter(|_| is_bullet(content)) {
+                        Some(item) => format!("{}-{}", indent, item.trim_end()),
+                        None => line.trim_end().to_string(),
+                    }
+                }
+                (LineKind::Text, _) => strip_inline(line.trim_end()),
+            };
+            // Collapse runs of blank lines to one
+            if rendered.trim().is_empty() {
+                if blank || text.is_empty() {
+                    continue;
+                }
+                blank = true;
+            } else {
+                blank = false;
+            }
+            text.push_str(&rendered);
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Locate fenced code blocks (``` or ~~~), including their fence lines.
+    fn find_code_blocks(text: &str) -> Vec<CodeBlock> {
+        let mut blocks = Vec::new();
+        // (fence marker, block start, language)
+        let mut open: Option<(String, usize, Option<String>)> = None;
+        let mut offset = 0;
+
+        for line in text.split_inclusive('\n') {
+            let line_start = offset;
+            offset += line.len();
+
+            let trimmed = line.trim_start_matches(' ');
+            if line.len() - trimmed.len() > 3 {
+                continue;
+            }
+            let fence_char = match trimmed.chars().next() {
+                Some(c @ ('`' | '~')) => c,
+                _ => continue,
+            };
+            let fence_len = trimmed.chars().take_while(|&c| c == fence_char).count();
+            if fence_len < 3 {
+                continue;
+            }
+            let fence = trimmed[..fence_len].to_string();
+
+            match &open {
+                None => {
+                    let info = &trimmed[fence_len..];
+                    open = Some((fence, line_start, code::language_from_info(info)));
+                }
+                Some((marker, _, _))
+                    if fence.starts_with(marker.as_str()) && trimmed[fence_len..].trim().is_empty() =>
+                {
+                    let (_, start, language) = open.take().unwrap();
+                    let body = &text[start..offset];
+                    blocks.push(CodeBlock {
+                        start,
+                        end: line_start + line.trim_end().len(),
+                        language: language.or_else(|| code::detect_language(body)),
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+
+        // An unterminated fence runs to the end of the document
+        if let Some((_, start, language)) = open {
+            let end = text.trim_end().len().max(start);
+            blocks.push(CodeBlock {
+                start,
+                end,
+                language: language.or_else(|| code::detect_language(&text[start..end])),
+            });
+        }
+
+        blocks
+    }
+}
+
+impl Default for MarkdownParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser for MarkdownParser {
+    fn parse_bytes(&self, data: &[u8]) -> Result<Vec<Page>, ParserError> {
+        let text = std::str::from_utf8(data)
+            .map_err(|e| ParserError::ParseError(format!("Invalid UTF-8: {}", e)))?;
+
+        if text.trim().is_empty() {
+            return Err(ParserError::EmptyDocument("Markdown".to_string()));
+        }
+
+        // A section runs from one top-level heading to the next
+        let lines = classify_lines(text);
+        let mut sections: Vec<&[(usize, &str, LineKind)]> = Vec::new();
+        let mut first = 0;
+        for (i, (_, _, kind)) in lines.iter().enumerate() {
+            if matches!(kind, LineKind::Heading(level, _) if *level <= 2) && i > first {
+                sections.push(&lines[first..i]);
+                first = i;
+            }
+        }
+        sections.push(&lines[first..]);
+
+        let verbatim = self.syntax == MarkdownSyntax::Keep;
+        let pages = sections
+            .into_iter()
+            .filter(|section| section.iter().any(|(_, line, _)| !line.trim().is_empty()))
+            .enumerate()
+            .map(|(i, section)| {
+                let start = section[0].0;
+                let end = section.last().map_or(start, |(offset, line, _)| offset + line.len());
+                let page_text = self.render(&text[start..end], section);
+                Page {
+                    page_num: i as u32 + 1,
+                    code_blocks: Self::find_code_blocks(&page_text),
+                    headings: section
+                        .iter()
+                        .filter_map(|(_, _, kind)| match kind {
+                            LineKind::Heading(level, title) => Some(Heading {
+                                level: *level,
+                                text: title.clone(),
+                            }),
+                            _ => None,
+                        })
+                        .collect(),
+                    text: page_text,
+                    source_start: verbatim.then_some(start),
+                    source_end: verbatim.then_some(end),
+                    ..Default::default()
+                }
+            })
+            .collect();
+        Ok(pages)
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["md", "markdown"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &["text/markdown"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const NOTES: &str = "Lecture notes.\n\n# Cells\n\nCells **divide** by [mitosis](https://example.com/mitosis).\n\n\
+        ### Phases\n\n* Prophase\n* Metaphase   \n\n\n\
+        Meiosis\n-------\n\n> Halves the `chromosome` count.\n\n\
+        ```\n# not a heading\n```\n";
+
+    #[test]
+    fn test_one_page_per_top_level_heading() {
+        let pages = MarkdownParser::new().parse_bytes(NOTES.as_bytes()).unwrap();
+
+        let page_nums: Vec<u32> = pages.iter().map(|p| p.page_num).collect();
+        assert_eq!(page_nums, vec![1, 2, 3]);
+        assert_eq!(pages[0].text, "Lecture notes.\n\n");
+        assert!(pages[1].text.starts_with("# Cells\n"));
+        assert!(pages[2].text.starts_with("Meiosis\n-------\n"));
+        assert!(pages[2].text.ends_with("```\n# not a heading\n```\n"));
+        let headings: Vec<(u8, &str)> = pages[1].headings.iter().map(|h| (h.level, h.text.as_str())).collect();
+        assert_eq!(headings, vec![(1, "Cells"), (3, "Phases")]);
+        assert_eq!(pages[2].headings[0].text, "Meiosis");
+        for page in &pages {
+            assert_eq!(&NOTES[page.source_start.unwrap()..page.source_end.unwrap()], page.text);
+        }
+    }
+
+    #[test]
+    fn test_syntax_normalized_or_stripped() {
+        let pages = MarkdownParser::new()
+            .with_syntax(MarkdownSyntax::Normalize)
+            .parse_bytes(NOTES.as_bytes())
+            .unwrap();
+        assert_eq!(pages[1].text, "# Cells\n\nCells **divide** by [mitosis](https://example.com/mitosis).\n\n\
+            ### Phases\n\n- Prophase\n- Metaphase\n\n");
+        assert!(pages[2].text.starts_with("## Meiosis\n\n"));
+        assert_eq!(pages[2].source_start, None);
+
+        let pages = MarkdownParser::new()
+            .with_syntax(MarkdownSyntax::Strip)
+            .parse_bytes(NOTES.as_bytes())
+            .unwrap();
+        assert!(pages[1].text.starts_with("Cells\n\nCells divide by mitosis.\n\nPhases\n"));
+        assert_eq!(pages[2].text, "Meiosis\n\nHalves the chromosome count.\n\n```\n# not a heading\n```\n");
+        assert_eq!(pages[2].code_blocks.len(), 1);
+    }
+
+    #[test]
+    fn test_markdown_parser_marks_fenced_code() {
+        let md = "# Setup\n\nInstall it first.\n\n```python\nimport os\nprint(os.name)\n```\n\nDone.\n";
+        let pages = MarkdownParser::new().parse(Cursor::new(md)).unwrap();
+
+        assert_eq!(pages[0].code_blocks.len(), 1);
+        let block = &pages[0].code_blocks[0];
+        assert_eq!(block.language.as_deref(), Some("python"));
+        assert_eq!(
+            &pages[0].text[block.start..block.end],
+            "```python\nimport os\nprint(os.name)\n```"
+        );
+    }
+}
@@ -1,12 +1,44 @@
+mod archive;
 mod azure_doc_intelligence;
+mod code;
+mod csv_table;
+mod detect;
 mod docx;
+mod epub;
+mod footnotes;
 mod html;
 mod local_pdf;
+mod markdown;
+mod normalize;
+mod ocr;
+mod pdf_encoding;
+mod pdf_images;
+mod pdf_layout;
+mod pdf_text;
+mod quality;
+mod registry;
+mod text;
+mod timeout;
 mod traits;
 
+#[cfg(test)]
+pub(crate) mod fixtures;
+
+pub use archive::{unpack, ArchiveError, Unpacked};
 pub use azure_doc_intelligence::AzureDocIntelligenceParser;
+pub use csv_table::CsvParser;
+pub use detect::{detect_format, sniff_content_type};
 pub use docx::DocxParser;
-pub use html::HtmlParser;
-pub use local_pdf::LocalPdfParser;
-pub use traits::{Page, Parser, ParserError};
-
+pub use epub::EpubParser;
+pub use footnotes::FootnoteMarkers;
+pub use html::{HtmlParser, DEFAULT_PAGE_CHARS, DEFAULT_SECTION_SELECTORS};
+pub use local_pdf::{LocalPdfParser, DEFAULT_MAX_IMAGES_PER_PAGE};
+pub use markdown::{MarkdownParser, MarkdownSyntax};
+pub use ocr::{HttpOcr, OcrConfig, OcrEngine};
+pub use text::PlainTextParser;
+pub use timeout::parse_with_timeout;
+pub use quality::{check_text_amount, QualityRule, QualityRules, QualityWarning};
+pub use registry::{for_content_type, for_content_type_with, parse_priority, ParserRegistry};
+pub use traits::{
+    CodeBlock, DocumentInfo, Heading, Highlight, Image, Page, PageStream, Parser, ParserError, TableBlock,
+};
@@ -0,0 +1,116 @@
+// Cleanup of the line-level artifacts of PDF text extraction
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use super::Page;
+
+/// Rewrite `page.text` as paragraphs of single-spaced lines: words broken
+/// by a hyphen at a line end are rejoined, lines holding only a page number
+/// dropped, and the lines of each paragraph joined with a space. Blank
+/// lines still separate paragraphs. Tables are kept verbatim, with their
+/// offsets updated to the new text.
+pub(super) fn normalize_whitespace(page: &mut Page) {
+    let mut text = String::with_capacity(page.text.len());
+    let mut last = 0;
+    page.tables.sort_by_key(|table| table.start);
+    for table in &mut page.tables {
+        push_paragraphs(&mut text, &normalize_text(&page.text[last..table.start]));
+        let block = &page.text[table.start..table.end];
+        push_paragraphs(&mut text, block);
+        last = table.end;
+        (table.start, table.end) = (text.len() - block.len(), text.len());
+    }
+    push_paragraphs(&mut text, &normalize_text(&page.text[last..]));
+    page.text = text;
+}
+
+/// Append `block` as a paragraph of its own.
+fn push_paragraphs(text: &mut String, block: &str) {
+    if block.is_empty() {
+        return;
+    }
+    if !text.is_empty() {
+        text.push_str("\n\n");
+    }
+    text.push_str(block);
+}
+
+fn normalize_text(text: &str) -> String {
+    static PAGE_NUMBER: OnceLock<Regex> = OnceLock::new();
+    static HYPHEN_BREAK: OnceLock<Regex> = OnceLock::new();
+    static COMPOUND_BREAK: OnceLock<Regex> = OnceLock::new();
+    static PARAGRAPH_BREAK: OnceLock<Regex> = OnceLock::new();
+    // "12", "- 12 -", "Page 12", "12 / 40", "Page 12 of 40", with its line break
+    let page_number = PAGE_NUMBER.get_or_init(|| {
+        Regex::new(concat!(
+            r"(?im)^[ \t]*(?:-[ \t]*)?(?:page[ \t]+)?\d{1,4}",
+            r"(?:[ \t]*(?:/|of)[ \t]*\d{1,4})?(?:[ \t]*-)?[ \t]*\r?(?:\n|\z)"
+        ))
+        .unwrap()
+    });
+    let hyphen_break = HYPHEN_BREAK.get_or_init(|| Regex::new(r"(\p{L})-[ \t]*\r?\n[ \t]*(\p{Ll})").unwrap());
+    // A capital after the break starts a compound's second part, as in "Jean-\nPaul"
+    let compound_break = COMPOUND_BREAK.get_or_init(|| Regex::new(r"(\p{L}-)[ \t]*\r?\n[ \t]*(\p{Lu})").unwrap());
+    let paragraph_break = PARAGRAPH_BREAK.get_or_init(|| Regex::new(r"\n[ \t\r\x0C]*\n").unwrap());
+
+    let text = page_number.replace_all(text, "");
+    let text = hyphen_break.replace_all(&text, "$1$2");
+    let text = compound_break.replace_all(&text, "$1$2");
+    paragraph_break
+        .split(&text)
+        .map(|paragraph| paragraph.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::TableBlock;
+
+    fn normalized(text: &str) -> String {
+        let mut page = Page {
+            text: text.to_string(),
+            ..Default::default()
+        };
+        normalize_whitespace(&mut page);
+        page.text
+    }
+
+    #[test]
+    fn test_joins_hyphenated_line_breaks() {
+        assert_eq!(normalized("an inter-\nnational study"), "an international study");
+        assert_eq!(normalized("an inter-  \n  national study"), "an international study");
+        assert_eq!(normalized("Jean-\nPaul Sartre"), "Jean-Paul Sartre");
+        assert_eq!(normalized("cell - division"), "cell - division");
+    }
+
+    #[test]
+    fn test_collapses_whitespace_and_drops_page_numbers() {
+        let text = "Cells   divide\nby mitosis.\n\n\n12\n\nThe  phases\tare\r\nfour.\n  Page 3 of 40  \n";
+        assert_eq!(normalized(text), "Cells divide by mitosis.\n\nThe phases are four.");
+        // Numbers within a line stay; a number line within a paragraph goes
+        assert_eq!(normalized("Table 12\nlists 40 cells."), "Table 12 lists 40 cells.");
+        assert_eq!(normalized("Cells divide\n- 7 -\nby mitosis."), "Cells divide by mitosis.");
+    }
+
+    #[test]
+    fn test_tables_kept_verbatim() {
+        let table = "| Phase | Hours |\n| --- | --- |\n| G1 | 11 |";
+        let text = format!("Cells  divide\nslowly.\n\n{}\n\n\n7\n", table);
+        let mut page = Page {
+            tables: vec![TableBlock {
+                start: text.find(table).unwrap(),
+                end: text.find(table).unwrap() + table.len(),
+            }],
+            text,
+            ..Default::default()
+        };
+        normalize_whitespace(&mut page);
+        assert_eq!(page.text, format!("Cells divide slowly.\n\n{}", table));
+        assert_eq!(&page.text[page.tables[0].start..page.tables[0].end], table);
+    }
+}
@@ -0,0 +1,114 @@
+// Text recognition for scanned PDF pages without a text layer
+
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::Deserialize;
+
+use super::traits::ParserError;
+
+/// Wait for the OCR service to accept a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Wait for the text of one image, recognition included.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Recognizes the text in an image of a page.
+pub trait OcrEngine: Send + Sync {
+    fn recognize(&self, image: &[u8], content_type: &str) -> Result<String, ParserError>;
+}
+
+/// Connection settings for an external OCR service, such as a Tesseract
+/// server.
+#[derive(Debug, Clone)]
+pub struct OcrConfig {
+    /// Endpoint images are posted to, one per request, with their MIME type
+    /// as `Content-Type`; it answers `{"text": "..."}`.
+    pub url: String,
+    pub api_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OcrResponse {
+    text: String,
+}
+
+/// Posts page images to the service configured in [`OcrConfig`].
+pub struct HttpOcr {
+    config: OcrConfig,
+    client: Client,
+}
+
+impl HttpOcr {
+    pub fn new(config: OcrConfig) -> Self {
+        let client = Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self { config, client }
+    }
+
+    pub async fn recognize_async(&self, image: &[u8], content_type: &str) -> Result<String, ParserError> {
+        let mut request = self
+            .client
+            .post(&self.config.url)
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .body(image.to_vec());
+        if let Some(key) = &self.config.api_key {
+            request = request.bearer_auth(key);
+        }
+
+        let failed = |e: reqwest::Error| ParserError::ParseError(format!("OCR request failed: {}", e));
+        let response = request.send().await.map_err(failed)?;
+        if !response.status().is_success() {
+            return Err(ParserError::ParseError(format!("OCR service returned {}", response.status())));
+        }
+        Ok(response.json::<OcrResponse>().await.map_err(failed)?.text)
+    }
+}
+
+impl OcrEngine for HttpOcr {
+    /// Blocks on `recognize_async` from a thread of its own, as
+    /// `AzureDocIntelligenceParser::parse_bytes` does, since parsers run
+    /// synchronously inside the server's runtime.
+    fn recognize(&self, image: &[u8], content_type: &str) -> Result<String, ParserError> {
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .map_err(|e| ParserError::ParseError(format!("Failed to start runtime: {}", e)))?
+                        .block_on(self.recognize_async(image, content_type))
+                })
+                .join()
+                .unwrap_or_else(|_| Err(ParserError::ParseError("OCR request panicked".to_string())))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderMap;
+    use axum::routing::post;
+
+    #[tokio::test]
+    async fn test_posts_image_and_reads_text() {
+        let app = axum::Router::new().route(
+            "/ocr",
+            post(|headers: HeaderMap, body: axum::body::Bytes| async move {
+                let content_type = headers[reqwest::header::CONTENT_TYPE].to_str().unwrap().to_string();
+                axum::Json(serde_json::json!({ "text": format!("{} bytes of {}", body.len(), content_type) }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/ocr", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let ocr = HttpOcr::new(OcrConfig { url, api_key: None });
+        let text = ocr.recognize_async(&[1, 2, 3], "image/png").await.unwrap();
+        assert_eq!(text, "3 bytes of image/png");
+    }
+}
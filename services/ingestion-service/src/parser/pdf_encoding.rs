@@ -0,0 +1,78 @@
+// Recovery of text written in a non-Latin single-byte codepage
+
+use encoding_rs::{Encoding, WINDOWS_1251, WINDOWS_1252, WINDOWS_1253};
+
+/// Share of letters in the Latin-1 range above which extracted text is
+/// taken to be a mis-decoded codepage. Western languages stay far below it.
+const MISDECODED_RATIO: f64 = 0.4;
+
+/// Letters needed before the distribution means anything.
+const MIN_LETTERS: usize = 8;
+
+/// Re-decode `text` when it looks like bytes of another single-byte codepage
+/// read as WinAnsi, e.g. Windows-1251 Cyrillic extracted as `Ïðèâåò`. The
+/// codepage comes from the document's fonts where they name one, and is
+/// otherwise assumed to be Windows-1251. `None` when the text looks fine or
+/// re-decoding doesn't produce a plausible script.
+pub(super) fn repair_codepage(doc: &lopdf::Document, text: &str) -> Option<String> {
+    if !looks_misdecoded(text) {
+        return None;
+    }
+    let (bytes, _, unmappable) = WINDOWS_1252.encode(text);
+    if unmappable {
+        return None;
+    }
+    let codepage = font_codepage(doc).unwrap_or(WINDOWS_1251);
+    let (decoded, _, malformed) = codepage.decode(&bytes);
+    let (letters, foreign) = letter_counts(&decoded, |c| c > '\u{24F}');
+    (!malformed && foreign * 2 >= letters).then(|| {
+        tracing::debug!("Re-decoded PDF text as {}", codepage.name());
+        decoded.into_owned()
+    })
+}
+
+fn looks_misdecoded(text: &str) -> bool {
+    let (letters, latin1) = letter_counts(text, |c| ('\u{C0}'..='\u{FF}').contains(&c));
+    letters >= MIN_LETTERS && latin1 as f64 / letters as f64 > MISDECODED_RATIO
+}
+
+/// Alphabetic characters in `text`, and how many of them satisfy `matches`.
+fn letter_counts(text: &str, matches: impl Fn(char) -> bool) -> (usize, usize) {
+    text.chars()
+        .filter(|c| c.is_alphabetic())
+        .fold((0, 0), |(letters, matched), c| (letters + 1, matched + matches(c) as usize))
+}
+
+/// The codepage the document's fonts point at: an `/Encoding` naming a
+/// single-byte codepage, or a legacy font name such as `ArialCyr`.
+fn font_codepage(doc: &lopdf::Document) -> Option<&'static Encoding> {
+    doc.get_pages().values().find_map(|&page_id| {
+        doc.get_page_fonts(page_id).ok()?.values().find_map(|font| {
+            let name = |key: &[u8]| font.get(key).and_then(lopdf::Object::as_name).ok();
+            let by_encoding = name(b"Encoding")
+                .and_then(Encoding::for_label)
+                .filter(|encoding| encoding.is_single_byte() && *encoding != WINDOWS_1252);
+            by_encoding.or_else(|| {
+                let base_font = String::from_utf8_lossy(name(b"BaseFont")?).to_ascii_lowercase();
+                if base_font.contains("cyr") {
+                    Some(WINDOWS_1251)
+                } else if base_font.contains("greek") {
+                    Some(WINDOWS_1253)
+                } else {
+                    None
+                }
+            })
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_western_text_left_alone() {
+        assert!(!looks_misdecoded("Müller ging über die Straße, à la française, señor."));
+        assert!(looks_misdecoded("Ïðèâåò, ìèð! Êëåòêè äåëÿòñÿ ìèòîçîì."));
+    }
+}
@@ -0,0 +1,115 @@
+// Embedded raster images (image XObjects) of PDF pages
+
+use std::io::Read;
+
+use lopdf::xobject::PdfImage;
+use lopdf::{Document, Object, ObjectId};
+
+/// Decoded samples an image may take up, 64 MiB; larger images are skipped
+/// before anything is allocated for them.
+const MAX_SAMPLE_BYTES: usize = 64 << 20;
+
+/// Images of the page `page_id`, at most `max` of them, in resource order.
+/// JPEG and JPEG 2000 data is kept as is; uncompressed and Flate-encoded
+/// 8-bit grayscale or RGB samples are re-encoded as PNG. Images in other
+/// encodings (CCITT, JBIG2, indexed or CMYK colour) are skipped.
+pub(super) fn page_images(doc: &Document, page_id: ObjectId, max: usize) -> Vec<(Vec<u8>, &'static str)> {
+    if max == 0 {
+        return Vec::new();
+    }
+    let Ok(images) = doc.get_page_images(page_id) else {
+        return Vec::new();
+    };
+    images.iter().filter_map(|image| convert(doc, image)).take(max).collect()
+}
+
+fn convert(doc: &Document, image: &PdfImage) -> Option<(Vec<u8>, &'static str)> {
+    let filters = image.filters.as_deref().unwrap_or_default();
+    match filters.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["DCTDecode"] => Some((image.content.to_vec(), "image/jpeg")),
+        ["JPXDecode"] => Some((image.content.to_vec(), "image/jp2")),
+        [] => {
+            let layout = Layout::of(doc, image)?;
+            encode_png(&layout, image.content)
+        }
+        ["FlateDecode"] => {
+            // Predictors pre-filter the samples; rare for images, so not undone
+            if image.origin_dict.get(b"DecodeParms").is_ok() {
+                return None;
+            }
+            let layout = Layout::of(doc, image)?;
+            // Inflate no further than the image needs, however much the stream holds
+            let mut samples = Vec::new();
+            flate2::read::ZlibDecoder::new(image.content)
+                .take(layout.bytes as u64)
+                .read_to_end(&mut samples)
+                .ok()?;
+            encode_png(&layout, &samples)
+        }
+        _ => None,
+    }
+}
+
+/// Dimensions and colour of an image rendered as 8-bit PNG.
+struct Layout {
+    width: u32,
+    height: u32,
+    color: png::ColorType,
+    /// Sample bytes the image takes up, at most `MAX_SAMPLE_BYTES`.
+    bytes: usize,
+}
+
+impl Layout {
+    fn of(doc: &Document, image: &PdfImage) -> Option<Self> {
+        if image.bits_per_component != Some(8) {
+            return None;
+        }
+        let color = match components(doc, image)? {
+            1 => png::ColorType::Grayscale,
+            3 => png::ColorType::Rgb,
+            _ => return None,
+        };
+        let (width, height) = (u32::try_from(image.width).ok()?, u32::try_from(image.height).ok()?);
+        let bytes = (width as usize)
+            .checked_mul(height as usize)?
+            .checked_mul(color.samples())
+            .filter(|&bytes| bytes <= MAX_SAMPLE_BYTES)?;
+        Some(Self {
+            width,
+            height,
+            color,
+            bytes,
+        })
+    }
+}
+
+fn encode_png(layout: &Layout, samples: &[u8]) -> Option<(Vec<u8>, &'static str)> {
+    let samples = samples.get(..layout.bytes)?;
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, layout.width, layout.height);
+    encoder.set_color(layout.color);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().ok()?;
+    writer.write_image_data(samples).ok()?;
+    writer.finish().ok()?;
+    Some((out, "image/png"))
+}
+
+/// Colour components per pixel, for the colour spaces rendered as PNG.
+fn components(doc: &Document, image: &PdfImage) -> Option<usize> {
+    match image.color_space.as_deref()? {
+        "DeviceGray" | "CalGray" => Some(1),
+        "DeviceRGB" | "CalRGB" => Some(3),
+        "ICCBased" => {
+            // [/ICCBased stream], the profile stream giving the count as /N
+            let space = image.origin_dict.get(b"ColorSpace").ok()?.as_array().ok()?;
+            let profile = match space.get(1)? {
+                Object::Reference(id) => doc.get_object(*id).ok()?,
+                object => object,
+            };
+            let n = profile.as_stream().ok()?.dict.get(b"N").ok()?.as_i64().ok()?;
+            usize::try_from(n).ok()
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,558 @@
+// Positioned text extraction for PDF pages using lopdf content streams.
+//
+// pdf_extract gives us good plain text but no geometry. Features that need to
+// know *where* text sits on a page (annotations, headings, tables) use the
+// runs produced here instead.
+
+use std::collections::BTreeMap;
+
+use lopdf::content::Content;
+use lopdf::{Dictionary, Document, Encoding, Object, ObjectId};
+
+use super::traits::{Heading, Highlight};
+
+/// Average glyph advance as a fraction of the font size. We don't load font
+/// metrics, so widths are estimates good enough for layout heuristics.
+const AVG_GLYPH_WIDTH: f32 = 0.5;
+
+/// A line must be at least this much larger than body text to be a heading.
+const HEADING_SIZE_RATIO: f32 = 1.15;
+
+/// Deepest heading level we infer; smaller distinct sizes share it.
+const MAX_HEADING_LEVEL: u8 = 6;
+
+/// A horizontal gap between runs wider than this many font sizes separates
+/// table cells rather than words.
+const CELL_GAP_RATIO: f32 = 1.5;
+
+/// Cells in consecutive rows whose left edges are within this many font
+/// sizes of each other form one column.
+const COLUMN_TOLERANCE_RATIO: f32 = 1.0;
+
+/// Page size assumed when no `/MediaBox` is found (US Letter).
+const DEFAULT_MEDIA_BOX: [f32; 4] = [0.0, 0.0, 612.0, 792.0];
+
+/// Guard against `/Parent` cycles when looking up inherited page attributes.
+const MAX_PAGE_TREE_DEPTH: usize = 32;
+
+/// A piece of text shown by a single text-showing operator.
+#[derive(Debug, Clone)]
+pub(crate) struct TextRun {
+    pub text: String,
+    /// Baseline origin in user space.
+    pub x: f32,
+    pub y: f32,
+    /// Estimated advance width in user space.
+    pub width: f32,
+    /// Effective font size after applying the text and transformation matrices.
+    pub font_size: f32,
+}
+
+/// Axis-aligned rectangle in user space.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Rect {
+    pub x0: f32,
+    pub y0: f32,
+    pub x1: f32,
+    pub y1: f32,
+}
+
+impl Rect {
+    fn from_points(points: &[f32]) -> Option<Self> {
+        let pairs: Vec<&[f32]> = points.chunks_exact(2).collect();
+        if pairs.len() < 2 {
+            return None;
+        }
+        let mut rect = Self {
+            x0: f32::INFINITY,
+            y0: f32::INFINITY,
+            x1: f32::NEG_INFINITY,
+            y1: f32::NEG_INFINITY,
+        };
+        for pair in pairs {
+            rect.x0 = rect.x0.min(pair[0]);
+            rect.x1 = rect.x1.max(pair[0]);
+            rect.y0 = rect.y0.min(pair[1]);
+            rect.y1 = rect.y1.max(pair[1]);
+        }
+        Some(rect)
+    }
+
+    /// Whether a run's baseline sits inside this rectangle, allowing a little
+    /// slack for descenders and imprecise annotation quads.
+    fn covers(&self, run: &TextRun) -> bool {
+        let slack = run.font_size * 0.3;
+        run.y >= self.y0 - slack
+            && run.y <= self.y1 + slack
+            && run.x < self.x1
+            && run.x + run.width > self.x0
+    }
+}
+
+/// Row-major 2D affine matrix `[a b c d e f]` as used throughout PDF.
+#[derive(Debug, Clone, Copy)]
+struct Matrix([f32; 6]);
+
+impl Matrix {
+    const IDENTITY: Matrix = Matrix([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    fn translate(tx: f32, ty: f32) -> Self {
+        Matrix([1.0, 0.0, 0.0, 1.0, tx, ty])
+    }
+
+    fn from_operands(operands: &[Object]) -> Option<Self> {
+        let values = floats(operands);
+        if values.len() != 6 {
+            return None;
+        }
+        Some(Matrix([values[0], values[1], values[2], values[3], values[4], values[5]]))
+    }
+
+    /// `self × other`, i.e. apply `self` first and then `other`.
+    fn then(&self, other: &Matrix) -> Matrix {
+        let [a, b, c, d, e, f] = self.0;
+        let [a2, b2, c2, d2, e2, f2] = other.0;
+        Matrix([
+            a * a2 + b * c2,
+            a * b2 + b * d2,
+            c * a2 + d * c2,
+            c * b2 + d * d2,
+            e * a2 + f * c2 + e2,
+            e * b2 + f * d2 + f2,
+        ])
+    }
+
+    fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+        let [a, b, c, d, e, f] = self.0;
+        (a * x + c * y + e, b * x + d * y + f)
+    }
+
+    fn vertical_scale(&self) -> f32 {
+        self.0[2].hypot(self.0[3])
+    }
+
+    fn horizontal_scale(&self) -> f32 {
+        self.0[0].hypot(self.0[1])
+    }
+}
+
+fn floats(operands: &[Object]) -> Vec<f32> {
+    operands.iter().filter_map(|o| o.as_float().ok()).collect()
+}
+
+/// A page attribute, looked up through the page tree for inheritable keys.
+fn inherited<'a>(doc: &'a Document, page_id: ObjectId, key: &[u8]) -> Option<&'a Object> {
+    let mut dict = doc.get_dictionary(page_id).ok()?;
+    for _ in 0..MAX_PAGE_TREE_DEPTH {
+        if let Ok(value) = dict.get(key) {
+            return doc.dereference(value).ok().map(|(_, object)| object);
+        }
+        dict = doc.get_dictionary(dict.get(b"Parent").and_then(Object::as_reference).ok()?).ok()?;
+    }
+    None
+}
+
+/// The page's `/Rotate` value normalized to 0, 90, 180 or 270 degrees clockwise.
+pub(crate) fn page_rotation(doc: &Document, page_id: ObjectId) -> u16 {
+    let degrees = inherited(doc, page_id, b"Rotate")
+        .and_then(|o| o.as_i64().ok())
+        .unwrap_or(0);
+    // Only multiples of 90 are valid; anything else is treated as upright
+    match degrees.rem_euclid(360) {
+        r @ (90 | 180 | 270) => r as u16,
+        _ => 0,
+    }
+}
+
+/// Matrix from user space to upright page space: the page as displayed after
+/// `/Rotate`, with its origin at the bottom-left of the media box.
+fn upright_matrix(doc: &Document, page_id: ObjectId) -> Matrix {
+    let media = inherited(doc, page_id, b"MediaBox")
+        .and_then(|o| o.as_array().ok())
+        .map(|values| floats(values))
+        .filter(|values| values.len() == 4)
+        .unwrap_or_else(|| DEFAULT_MEDIA_BOX.to_vec());
+    let (width, height) = ((media[2] - media[0]).abs(), (media[3] - media[1]).abs());
+
+    let rotate = match page_rotation(doc, page_id) {
+        90 => Matrix([0.0, -1.0, 1.0, 0.0, 0.0, width]),
+        180 => Matrix([-1.0, 0.0, 0.0, -1.0, width, height]),
+        270 => Matrix([0.0, 1.0, -1.0, 0.0, height, 0.0]),
+        _ => Matrix::IDENTITY,
+    };
+    Matrix::translate(-media[0].min(media[2]), -media[1].min(media[3])).then(&rotate)
+}
+
+/// Coordinate space for a page's runs and annotation areas. With `upright`,
+/// positions are normalized for `/Rotate` so reading order follows the page as
+/// displayed; otherwise they stay in raw user space.
+fn page_space(doc: &Document, page_id: ObjectId, upright: bool) -> Matrix {
+    if upright {
+        upright_matrix(doc, page_id)
+    } else {
+        Matrix::IDENTITY
+    }
+}
+
+struct TextState<'a> {
+    ctm: Matrix,
+    stack: Vec<Matrix>,
+    tm: Matrix,
+    tlm: Matrix,
+    font_size: f32,
+    leading: f32,
+    encoding: Option<&'a Encoding<'a>>,
+}
+
+impl TextState<'_> {
+    fn new_line(&mut self, tx: f32, ty: f32) {
+        self.tlm = Matrix::translate(tx, ty).then(&self.tlm);
+        self.tm = self.tlm;
+    }
+
+    fn show(&mut self, bytes: &[u8], runs: &mut Vec<TextRun>) {
+        let text = match self.encoding {
+            Some(encoding) => Document::decode_text(encoding, bytes).unwrap_or_default(),
+            None => bytes.iter().map(|&b| b as char).collect(),
+        };
+        let advance = text.chars().count() as f32 * AVG_GLYPH_WIDTH * self.font_size;
+        let trm = self.tm.then(&self.ctm);
+        if !text.trim().is_empty() {
+            runs.push(TextRun {
+                x: trm.0[4],
+                y: trm.0[5],
+                width: advance * trm.horizontal_scale(),
+                font_size: self.font_size * trm.vertical_scale(),
+                text,
+            });
+        }
+        self.adjust(advance);
+    }
+
+    fn adjust(&mut self, tx: f32) {
+        self.tm = Matrix::translate(tx, 0.0).then(&self.tm);
+    }
+}
+
+/// Extract positioned text runs from a page, in content-stream order.
+///
+/// With `upright`, coordinates are normalized for the page's `/Rotate`.
+pub(crate) fn page_text_runs(doc: &Document, page_id: ObjectId, upright: bool) -> Vec<TextRun> {
+    let content = match doc.get_page_content(page_id).and_then(|data| Content::decode(&data)) {
+        Ok(content) => content,
+        Err(_) => return Vec::new(),
+    };
+
+    let encodings: BTreeMap<Vec<u8>, Encoding> = doc
+        .get_page_fonts(page_id)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(name, font)| font.get_font_encoding(doc).ok().map(|enc| (name, enc)))
+        .collect();
+
+    // The initial CTM maps user space to the (upright) page
+    let base = page_space(doc, page_id, upright);
+    let mut runs = Vec::new();
+    let mut state = TextState {
+        ctm: base,
+        stack: Vec::new(),
+        tm: Matrix::IDENTITY,
+        tlm: Matrix::IDENTITY,
+        font_size: 0.0,
+        leading: 0.0,
+        encoding: None,
+    };
+
+    for op in &content.operations {
+        let operands = &op.operands;
+        match op.operator.as_str() {
+            "q" => state.stack.push(state.ctm),
+            "Q" => state.ctm = state.stack.pop().unwrap_or(base),
+            "cm" => {
+                if let Some(m) = Matrix::from_operands(operands) {
+                    state.ctm = m.then(&state.ctm);
+                }
+            }
+            "BT" => {
+                state.tm = Matrix::IDENTITY;
+                state.tlm = Matrix::IDENTITY;
+            }
+            "Tf" => {
+                if let [Object::Name(name), size] = operands.as_slice() {
+                    state.encoding = encodings.get(name);
+                    state.font_size = size.as_float().unwrap_or(0.0);
+                }
+            }
+            "TL" => state.leading = floats(operands).first().copied().unwrap_or(0.0),
+            "Td" | "TD" => {
+                let v = floats(operands);
+                if v.len() == 2 {
+                    if op.operator == "TD" {
+                        state.leading = -v[1];
+                    }
+                    state.new_line(v[0], v[1]);
+                }
+            }
+            "Tm" => {
+                if let Some(m) = Matrix::from_operands(operands) {
+                    state.tlm = m;
+                    state.tm = m;
+                }
+            }
+            "T*" => state.new_line(0.0, -state.leading),
+            "Tj" => {
+                if let Some(Ok(bytes)) = operands.first().map(Object::as_str) {
+                    state.show(bytes, &mut runs);
+                }
+            }
+            "'" | "\"" => {
+                state.new_line(0.0, -state.leading);
+                if let Some(Ok(bytes)) = operands.last().map(Object::as_str) {
+                    state.show(bytes, &mut runs);
+                }
+            }
+            "TJ" => {
+                if let Some(Ok(items)) = operands.first().map(Object::as_array) {
+                    for item in items {
+                        match item {
+                            Object::String(bytes, _) => state.show(bytes, &mut runs),
+                            other => {
+                                let adjust = other.as_float().unwrap_or(0.0);
+                                state.adjust(-adjust / 1000.0 * state.font_size);
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    runs
+}
+
+/// Join runs into text in reading order (top to bottom, left to right).
+pub(crate) fn runs_to_text(runs: &[&TextRun]) -> String {
+    let mut sorted: Vec<&TextRun> = runs.to_vec();
+    sorted.sort_by(|a, b| {
+        b.y.partial_cmp(&a.y)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.x.partial_cmp(&b.x).unwrap_or(std::cmp::Ordering::Equal))
+    });
+    sorted
+        .iter()
+        .map(|r| r.text.trim())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A visual line: runs sharing a baseline.
+struct TextLine {
+    text: String,
+    font_size: f32,
+}
+
+/// Group a page's runs into lines, top to bottom.
+fn text_lines(runs: &[TextRun]) -> Vec<TextLine> {
+    line_runs(runs)
+        .into_iter()
+        .map(|members| TextLine {
+            text: runs_to_text(&members),
+            font_size: line_font_size(&members),
+        })
+        .collect()
+}
+
+/// The non-blank runs of each line, lines top to bottom and runs left to right.
+fn line_runs(runs: &[TextRun]) -> Vec<Vec<&TextRun>> {
+    let mut lines: Vec<(f32, Vec<&TextRun>)> = Vec::new();
+    for run in runs.iter().filter(|r| !r.text.trim().is_empty()) {
+        match lines.iter_mut().find(|(y, _)| (y - run.y).abs() < run.font_size * 0.3) {
+            Some((_, members)) => members.push(run),
+            None => lines.push((run.y, vec![run])),
+        }
+    }
+    lines.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    for (_, members) in &mut lines {
+        members.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap_or(std::cmp::Ordering::Equal));
+    }
+    lines.into_iter().map(|(_, members)| members).collect()
+}
+
+fn line_font_size(runs: &[&TextRun]) -> f32 {
+    runs.iter().map(|r| r.font_size).fold(0.0, f32::max)
+}
+
+/// A line's cells: its runs split at wide gaps, each with its left edge.
+fn line_cells(runs: &[&TextRun]) -> Vec<(f32, String)> {
+    let mut cells: Vec<(f32, f32, Vec<&TextRun>)> = Vec::new();
+    for &run in runs {
+        match cells.last_mut() {
+            Some((_, end, members)) if run.x - *end < run.font_size * CELL_GAP_RATIO => {
+                *end = end.max(run.x + run.width);
+                members.push(run);
+            }
+            _ => cells.push((run.x, run.x + run.width, vec![run])),
+        }
+    }
+    cells
+        .into_iter()
+        .map(|(x, _, members)| (x, runs_to_text(&members)))
+        .collect()
+}
+
+/// Detect tables on a page from text positions.
+///
+/// A table is two or more consecutive lines with the same number (at least
+/// two) of cells, their left edges lined up column by column. Returns each
+/// table's rows of cell text, top to bottom.
+pub(crate) fn detect_tables(runs: &[TextRun]) -> Vec<Vec<Vec<String>>> {
+    let mut tables = Vec::new();
+    let mut rows: Vec<Vec<(f32, String)>> = Vec::new();
+    let mut finish = |rows: &mut Vec<Vec<(f32, String)>>| {
+        if rows.len() >= 2 {
+            tables.push(
+                rows.drain(..)
+                    .map(|row| row.into_iter().map(|(_, text)| text).collect())
+                    .collect(),
+            );
+        }
+        rows.clear();
+    };
+
+    for line in line_runs(runs) {
+        let cells = line_cells(&line);
+        let tolerance = line_font_size(&line) * COLUMN_TOLERANCE_RATIO;
+        let aligned = rows.last().is_some_and(|previous| {
+            previous.len() == cells.len()
+                && previous.iter().zip(&cells).all(|((a, _), (b, _))| (a - b).abs() <= tolerance)
+        });
+        if !aligned {
+            finish(&mut rows);
+        }
+        if cells.len() >= 2 {
+            rows.push(cells);
+        }
+    }
+    finish(&mut rows);
+    tables
+}
+
+/// Font size rounded to half points so near-identical sizes compare equal.
+fn size_key(size: f32) -> i32 {
+    (size * 2.0).round() as i32
+}
+
+/// Infer headings from relative font sizes across the whole document.
+///
+/// The size covering the most characters is taken as body text. Lines set
+/// noticeably larger are headings, and each distinct heading size gets a
+/// level, largest first. Returns the headings of each page in reading order.
+pub(crate) fn infer_headings(pages: &[Vec<TextRun>]) -> Vec<Vec<Heading>> {
+    let lines: Vec<Vec<TextLine>> = pages.iter().map(|runs| text_lines(runs)).collect();
+
+    let mut chars_by_size: BTreeMap<i32, usize> = BTreeMap::new();
+    for line in lines.iter().flatten() {
+        *chars_by_size.entry(size_key(line.font_size)).or_default() += line.text.chars().count();
+    }
+    let Some(body) = chars_by_size.iter().max_by_key(|(_, chars)| **chars).map(|(size, _)| *size) else {
+        return vec![Vec::new(); pages.len()];
+    };
+
+    let threshold = body as f32 * HEADING_SIZE_RATIO;
+    let mut heading_sizes: Vec<i32> = chars_by_size
+        .keys()
+        .copied()
+        .filter(|&size| size as f32 >= threshold)
+        .collect();
+    heading_sizes.sort_unstable_by(|a, b| b.cmp(a));
+
+    lines
+        .into_iter()
+        .map(|page_lines| {
+            page_lines
+                .into_iter()
+                .filter_map(|line| {
+                    let rank = heading_sizes.iter().position(|&s| s == size_key(line.font_size))?;
+                    Some(Heading {
+                        level: (rank as u8 + 1).min(MAX_HEADING_LEVEL),
+                        text: line.text,
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Resolve the page's `/Highlight` annotations to the text runs they cover.
+///
+/// `upright` must match the setting the runs were extracted with.
+pub(crate) fn page_highlights(doc: &Document, page_id: ObjectId, runs: &[TextRun], upright: bool) -> Vec<Highlight> {
+    let space = page_space(doc, page_id, upright);
+    let annotations = doc.get_page_annotations(page_id).unwrap_or_default();
+
+    annotations
+        .into_iter()
+        .filter(|annot| annot.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Highlight"))
+        .filter_map(|annot| {
+            let areas = annotation_areas(doc, annot, &space);
+            let covered: Vec<&TextRun> = runs
+                .iter()
+                .filter(|run| areas.iter().any(|area| area.covers(run)))
+                .collect();
+            if covered.is_empty() {
+                return None;
+            }
+            Some(Highlight {
+                text: runs_to_text(&covered),
+                color: annotation_color(doc, annot),
+            })
+        })
+        .collect()
+}
+
+/// The regions an annotation marks: one per `/QuadPoints` quad, or `/Rect`,
+/// mapped into `space`.
+fn annotation_areas(doc: &Document, annot: &Dictionary, space: &Matrix) -> Vec<Rect> {
+    let numbers = |key: &[u8]| -> Vec<f32> {
+        let values = annot
+            .get_deref(key, doc)
+            .and_then(Object::as_array)
+            .map(|values| floats(values))
+            .unwrap_or_default();
+        values
+            .chunks_exact(2)
+            .flat_map(|pair| {
+                let (x, y) = space.apply(pair[0], pair[1]);
+                [x, y]
+            })
+            .collect()
+    };
+
+    let quads: Vec<Rect> = numbers(b"QuadPoints")
+        .chunks_exact(8)
+        .filter_map(Rect::from_points)
+        .collect();
+    if !quads.is_empty() {
+        return quads;
+    }
+    Rect::from_points(&numbers(b"Rect")).into_iter().collect()
+}
+
+/// Annotation `/C` color as a `#rrggbb` hex string.
+fn annotation_color(doc: &Document, annot: &Dictionary) -> Option<String> {
+    let components = annot
+        .get_deref(b"C", doc)
+        .and_then(Object::as_array)
+        .map(|values| floats(values))
+        .ok()?;
+
+    let rgb = match components.as_slice() {
+        [gray] => [*gray, *gray, *gray],
+        [r, g, b] => [*r, *g, *b],
+        [c, m, y, k] => [(1.0 - c) * (1.0 - k), (1.0 - m) * (1.0 - k), (1.0 - y) * (1.0 - k)],
+        _ => return None,
+    };
+    let [r, g, b] = rgb.map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8);
+    Some(format!("#{:02x}{:02x}{:02x}", r, g, b))
+}
@@ -0,0 +1,99 @@
+// Plain text of each PDF page, from a single extraction pass
+
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+use pdf_extract::{MediaBox, OutputDev, OutputError, PlainTextOutput, Transform};
+
+/// The text `pdf_extract` extracts from each page of the PDF in `data`, in
+/// page order. The document runs through one output, as when extracting it
+/// whole, which notes where each page's text ends.
+pub(super) fn page_texts(data: &[u8]) -> Result<Vec<String>, OutputError> {
+    let mut doc = pdf_extract::Document::load_mem(data)?;
+    if doc.is_encrypted() {
+        doc.decrypt("")?;
+    }
+    let buffer = SharedBuffer::default();
+    let mut sink = buffer.clone();
+    let mut output = PageBreaks {
+        text: PlainTextOutput::new(&mut sink as &mut dyn Write),
+        buffer: buffer.clone(),
+        ends: Vec::new(),
+    };
+    pdf_extract::output_doc(&doc, &mut output)?;
+
+    let text = buffer.0.borrow();
+    let mut start = 0;
+    Ok(output
+        .ends
+        .iter()
+        .map(|&end| {
+            let page = String::from_utf8_lossy(&text[start..end]).into_owned();
+            start = end;
+            page
+        })
+        .collect())
+}
+
+/// Bytes written through one handle and read through another.
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Plain text output recording the length of the text at each page end.
+struct PageBreaks<'a> {
+    text: PlainTextOutput<&'a mut dyn Write>,
+    buffer: SharedBuffer,
+    ends: Vec<usize>,
+}
+
+impl OutputDev for PageBreaks<'_> {
+    fn begin_page(
+        &mut self,
+        page_num: u32,
+        media_box: &MediaBox,
+        art_box: Option<(f64, f64, f64, f64)>,
+    ) -> Result<(), OutputError> {
+        self.text.begin_page(page_num, media_box, art_box)
+    }
+
+    fn end_page(&mut self) -> Result<(), OutputError> {
+        self.text.end_page()?;
+        self.ends.push(self.buffer.0.borrow().len());
+        Ok(())
+    }
+
+    fn output_character(
+        &mut self,
+        trm: &Transform,
+        width: f64,
+        spacing: f64,
+        font_size: f64,
+        char: &str,
+    ) -> Result<(), OutputError> {
+        self.text.output_character(trm, width, spacing, font_size, char)
+    }
+
+    fn begin_word(&mut self) -> Result<(), OutputError> {
+        self.text.begin_word()
+    }
+
+    fn end_word(&mut self) -> Result<(), OutputError> {
+        self.text.end_word()
+    }
+
+    fn end_line(&mut self) -> Result<(), OutputError> {
+        self.text.end_line()
+    }
+}
@@ -0,0 +1,165 @@
+// Post-extraction checks for pages whose text looks garbled
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::registry::normalize_mime;
+use super::Page;
+
+/// Thresholds the extracted pages of one format must meet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityRule {
+    /// Largest share of a page's non-whitespace characters that may be
+    /// U+FFFD, control or private-use characters before the page counts as
+    /// corrupt. A font or encoding failure shows up as characters that map
+    /// to nothing; punctuation, tables and code are fine.
+    pub max_garbage_ratio: f64,
+    /// Pages with fewer non-whitespace characters are too short to judge.
+    pub min_chars: usize,
+}
+
+impl QualityRule {
+    pub fn new(max_garbage_ratio: f64) -> Self {
+        Self {
+            max_garbage_ratio,
+            min_chars: 20,
+        }
+    }
+}
+
+/// A page that failed its format's quality rule.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QualityWarning {
+    pub page_num: u32,
+    /// Parser whose output failed the check.
+    pub parser: String,
+    pub reason: String,
+}
+
+/// Quality rules per MIME type. Formats without a rule are not checked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualityRules {
+    rules: HashMap<String, QualityRule>,
+}
+
+impl Default for QualityRules {
+    /// Rules for the binary formats, where garbled text means extraction
+    /// went wrong rather than that the document is full of markup.
+    fn default() -> Self {
+        Self::empty()
+            .with_rule("application/pdf", QualityRule::new(0.5))
+            .with_rule(
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+                QualityRule::new(0.5),
+            )
+    }
+}
+
+impl QualityRules {
+    /// No rules; nothing is checked.
+    pub fn empty() -> Self {
+        Self { rules: HashMap::new() }
+    }
+
+    /// Set the rule for `mime_type`, replacing any existing one.
+    pub fn with_rule(mut self, mime_type: &str, rule: QualityRule) -> Self {
+        self.rules.insert(normalize_mime(mime_type), rule);
+        self
+    }
+
+    /// The default rules with overrides from `application/pdf=0.6;text/html=0.8`,
+    /// each value being the maximum garbage ratio for that MIME type.
+    pub fn parse(spec: &str) -> Self {
+        spec.split(';')
+            .filter_map(|entry| {
+                let (mime, ratio) = entry.split_once('=')?;
+                let ratio: f64 = ratio.trim().parse().ok()?;
+                (!mime.trim().is_empty() && (0.0..=1.0).contains(&ratio)).then_some((mime, ratio))
+            })
+            .fold(Self::default(), |rules, (mime, ratio)| rules.with_rule(mime, QualityRule::new(ratio)))
+    }
+
+    /// Warnings for each page of a `mime_type` document, as extracted by
+    /// `parser`, that breaks the format's rule.
+    pub fn check(&self, mime_type: &str, parser: &str, pages: &[Page]) -> Vec<QualityWarning> {
+        let Some(rule) = self.rules.get(&normalize_mime(mime_type)) else {
+            return Vec::new();
+        };
+        pages
+            .iter()
+            .filter_map(|page| {
+                let (chars, garbage) = page
+                    .text
+                    .chars()
+                    .filter(|c| !c.is_whitespace())
+                    .fold((0, 0), |(chars, garbage), c| (chars + 1, garbage + is_garbage(c) as usize));
+                let ratio = garbage as f64 / chars.max(1) as f64;
+                (chars >= rule.min_chars && ratio > rule.max_garbage_ratio).then(|| QualityWarning {
+                    page_num: page.page_num,
+                    parser: parser.to_string(),
+                    reason: format!(
+                        "{:.0}% of the page's characters are replacement or unmapped characters (limit {:.0}%)",
+                        ratio * 100.0,
+                        rule.max_garbage_ratio * 100.0
+                    ),
+                })
+            })
+            .collect()
+    }
+}
+
+/// A warning, reported against page 0, when a document extracted by
+/// `parser` holds fewer than `min_chars` non-whitespace characters in all,
+/// as a scanned PDF without a text layer does.
+pub fn check_text_amount(parser: &str, pages: &[Page], min_chars: usize) -> Option<QualityWarning> {
+    let chars: usize = pages
+        .iter()
+        .map(|page| page.text.chars().filter(|c| !c.is_whitespace()).count())
+        .sum();
+    (chars < min_chars).then(|| QualityWarning {
+        page_num: 0,
+        parser: parser.to_string(),
+        reason: format!("text too short: {} of at least {} characters", chars, min_chars),
+    })
+}
+
+fn is_garbage(c: char) -> bool {
+    let private_use = matches!(c, '\u{E000}'..='\u{F8FF}' | '\u{F0000}'..='\u{FFFFD}' | '\u{100000}'..='\u{10FFFD}');
+    c == char::REPLACEMENT_CHARACTER || c.is_control() || private_use
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(page_num: u32, text: &str) -> Page {
+        Page {
+            page_num,
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_garbled_pages_flagged_per_format() {
+        let pages = vec![
+            page(1, "Cells are the basic unit of life, and every organism has them."),
+            // Unmapped glyphs come out as U+FFFD or private-use code points
+            page(2, &format!("{} {} of it", "\u{FFFD}".repeat(12), "\u{F021}".repeat(4))),
+            page(3, "\u{FFFD}\u{FFFD}"),
+            // Symbols are not garbage
+            page(4, "| Phase | Share |\n| --- | --- |\n| G1 | 40% |\n| S/G2 | 60% |\n`x += 1; // ok`"),
+        ];
+
+        let warnings = QualityRules::default().check("application/pdf", "LocalPdfParser", &pages);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!((warnings[0].page_num, warnings[0].parser.as_str()), (2, "LocalPdfParser"));
+
+        // Markup-heavy formats have no rule unless configured
+        assert!(QualityRules::default().check("text/html", "HtmlParser", &pages).is_empty());
+        let rules = QualityRules::parse("text/html = 0.7; application/pdf=0.9; text/plain=oops");
+        assert_eq!(rules.check("Text/HTML; charset=utf-8", "HtmlParser", &pages).len(), 1);
+        assert!(rules.check("application/pdf", "LocalPdfParser", &pages).is_empty());
+    }
+}
@@ -0,0 +1,280 @@
+// Parser selection when several parsers claim the same MIME type
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+use super::{
+    AzureDocIntelligenceParser, CsvParser, DocxParser, EpubParser, HtmlParser, LocalPdfParser, MarkdownParser, Parser,
+    ParserError, PlainTextParser, DEFAULT_PAGE_CHARS,
+};
+use crate::config::Config;
+
+/// Which registered parser handles a MIME type.
+///
+/// Tie-break rules, in order:
+/// 1. A per-request preference wins if that parser is registered and claims
+///    the MIME type.
+/// 2. Otherwise the first parser in the configured priority list for the MIME
+///    type that is registered and claims it.
+/// 3. Otherwise the earliest registered parser claiming the MIME type.
+///
+/// MIME types are compared case-insensitively, ignoring parameters such as
+/// `; charset=utf-8`.
+#[derive(Debug, Clone, Default)]
+pub struct ParserRegistry {
+    parsers: Vec<(&'static str, Vec<String>)>,
+    /// File extensions of the parsers registered with `register_parser`.
+    extensions: BTreeSet<String>,
+    priority: HashMap<String, Vec<String>>,
+}
+
+impl ParserRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The built-in parsers that run in-process.
+    pub fn local() -> Self {
+        Self::new()
+            .register_parser("LocalPdfParser", &LocalPdfParser::new())
+            .register_parser("DocxParser", &DocxParser::new())
+            .register_parser("HtmlParser", &HtmlParser::new())
+            .register_parser("MarkdownParser", &MarkdownParser::new())
+            .register_parser("PlainTextParser", &PlainTextParser::new())
+            .register_parser("CsvParser", &CsvParser::new())
+            .register_parser("EpubParser", &EpubParser::new())
+    }
+
+    /// The built-in parsers available under `config`, with its priorities.
+    /// Azure is only registered when configured and network access is allowed.
+    pub fn from_config(config: &Config) -> Self {
+        let mut registry = Self::local();
+        if let Some(azure) = config.azure() {
+            let parser = AzureDocIntelligenceParser::new(azure.endpoint.clone(), azure.api_key.clone());
+            registry = registry.register_parser("AzureDocIntelligenceParser", &parser);
+        }
+        registry.with_priority(config.parser_priority.clone())
+    }
+
+    /// Register `parser` as `name`, for the MIME types and file extensions
+    /// it supports.
+    pub fn register_parser(mut self, name: &'static str, parser: &dyn Parser) -> Self {
+        self.extensions
+            .extend(parser.supported_extensions().iter().map(|e| e.to_ascii_lowercase()));
+        self.register(name, parser.supported_mime_types())
+    }
+
+    /// Register `name` as able to handle `mime_types`.
+    pub fn register(mut self, name: &'static str, mime_types: &[&str]) -> Self {
+        self.parsers
+            .push((name, mime_types.iter().map(|m| normalize_mime(m)).collect()));
+        self
+    }
+
+    /// Preferred parser order per MIME type, e.g. from `PARSER_PRIORITY`.
+    pub fn with_priority(mut self, priority: HashMap<String, Vec<String>>) -> Self {
+        self.priority = priority
+            .into_iter()
+            .map(|(mime, names)| (normalize_mime(&mime), names))
+            .collect();
+        self
+    }
+
+    /// File extensions of the registered parsers, deduplicated and sorted.
+    pub fn extensions(&self) -> Vec<String> {
+        self.extensions.iter().cloned().collect()
+    }
+
+    /// MIME types of the registered parsers, deduplicated and sorted.
+    pub fn mime_types(&self) -> Vec<String> {
+        let mimes: BTreeSet<&String> = self.parsers.iter().flat_map(|(_, mimes)| mimes).collect();
+        mimes.into_iter().cloned().collect()
+    }
+
+    /// Pick the parser for `mime_type`, honouring `preferred` when possible.
+    pub fn select(&self, mime_type: &str, preferred: Option<&str>) -> Option<&'static str> {
+        let mime = normalize_mime(mime_type);
+        let claims = |name: &str| {
+            self.parsers
+                .iter()
+                .find(|(registered, mimes)| *registered == name && mimes.contains(&mime))
+                .map(|(registered, _)| *registered)
+        };
+
+        preferred
+            .and_then(claims)
+            .or_else(|| {
+                self.priority
+                    .get(&mime)
+                    .and_then(|names| names.iter().find_map(|name| claims(name)))
+            })
+            .or_else(|| {
+                self.parsers
+                    .iter()
+                    .find(|(_, mimes)| mimes.contains(&mime))
+                    .map(|(name, _)| *name)
+            })
+    }
+
+    /// Parsers to retry a `mime_type` document with after `failed` produced
+    /// unusable output: the configured priorities first, then registration
+    /// order.
+    pub fn fallbacks(&self, mime_type: &str, failed: &str) -> Vec<&'static str> {
+        let mime = normalize_mime(mime_type);
+        let claiming: Vec<&'static str> = self
+            .parsers
+            .iter()
+            .filter(|(name, mimes)| *name != failed && mimes.contains(&mime))
+            .map(|(name, _)| *name)
+            .collect();
+        let mut chain: Vec<&'static str> = self
+            .priority
+            .get(&mime)
+            .into_iter()
+            .flatten()
+            .filter_map(|name| claiming.iter().copied().find(|claimed| claimed == name))
+            .collect();
+        for name in claiming {
+            if !chain.contains(&name) {
+                chain.push(name);
+            }
+        }
+        chain
+    }
+}
+
+/// The local parser for a document, chosen by MIME type and, when the type
+/// is missing or generic (`application/octet-stream`), by file extension.
+pub fn for_content_type(content_type: &str, filename: &str) -> Result<Box<dyn Parser>, ParserError> {
+    for_content_type_with(content_type, filename, LocalPdfParser::new(), HtmlParser::new(), DEFAULT_PAGE_CHARS)
+}
+
+/// Like [`for_content_type`], using `pdf` for PDF documents, `html` for HTML
+/// and virtual pages of `page_chars` characters for DOCX (0 = one page).
+pub fn for_content_type_with(
+    content_type: &str,
+    filename: &str,
+    pdf: LocalPdfParser,
+    html: HtmlParser,
+    page_chars: usize,
+) -> Result<Box<dyn Parser>, ParserError> {
+    let mut parsers: Vec<Box<dyn Parser>> = vec![
+        Box::new(pdf),
+        Box::new(DocxParser::new().with_page_chars(page_chars)),
+        Box::new(html),
+        Box::new(MarkdownParser::new()),
+        Box::new(PlainTextParser::new()),
+        Box::new(CsvParser::new()),
+        Box::new(EpubParser::new()),
+    ];
+    let mime = normalize_mime(content_type);
+    let extension = Path::new(filename)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+
+    let by_mime = parsers.iter().position(|p| p.supported_mime_types().contains(&mime.as_str()));
+    let by_extension = || {
+        let extension = extension.as_deref()?;
+        parsers.iter().position(|p| p.supported_extensions().contains(&extension))
+    };
+    let index = by_mime.or_else(by_extension).ok_or_else(|| {
+        ParserError::UnsupportedFormat(format!("{} ({})", content_type, filename))
+    })?;
+    Ok(parsers.swap_remove(index))
+}
+
+pub(super) fn normalize_mime(mime: &str) -> String {
+    mime.split(';').next().unwrap_or_default().trim().to_ascii_lowercase()
+}
+
+/// Parse `application/pdf=AzureDocIntelligenceParser,LocalPdfParser;text/html=HtmlParser`.
+pub fn parse_priority(spec: &str) -> HashMap<String, Vec<String>> {
+    spec.split(';')
+        .filter_map(|entry| {
+            let (mime, names) = entry.split_once('=')?;
+            let names: Vec<String> = names
+                .split(',')
+                .map(str::trim)
+                .filter(|n| !n.is_empty())
+                .map(str::to_string)
+                .collect();
+            (!mime.trim().is_empty() && !names.is_empty()).then(|| (normalize_mime(mime), names))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pdf_registry() -> ParserRegistry {
+        ParserRegistry::new()
+            .register("LocalPdfParser", &["application/pdf"])
+            .register("AzureDocIntelligenceParser", &["application/pdf", "image/png"])
+    }
+
+    #[test]
+    fn test_priority_and_request_override() {
+        // Without configuration the earliest registration wins
+        assert_eq!(pdf_registry().select("application/pdf", None), Some("LocalPdfParser"));
+
+        let registry = pdf_registry().with_priority(parse_priority(
+            "application/pdf = AzureDocIntelligenceParser, LocalPdfParser",
+        ));
+        assert_eq!(registry.select("application/pdf", None), Some("AzureDocIntelligenceParser"));
+        assert_eq!(registry.select("Application/PDF; charset=binary", None), Some("AzureDocIntelligenceParser"));
+        assert_eq!(registry.select("application/pdf", Some("LocalPdfParser")), Some("LocalPdfParser"));
+
+        // Preferences for parsers that can't handle the type are ignored
+        assert_eq!(registry.select("image/png", Some("LocalPdfParser")), Some("AzureDocIntelligenceParser"));
+        assert_eq!(registry.select("text/csv", None), None);
+
+        assert_eq!(registry.fallbacks("application/pdf", "LocalPdfParser"), vec!["AzureDocIntelligenceParser"]);
+        assert!(registry.fallbacks("image/png", "AzureDocIntelligenceParser").is_empty());
+    }
+
+    #[test]
+    fn test_for_content_type_by_mime_then_extension() {
+        let name = |content_type: &str, filename: &str| for_content_type(content_type, filename).map(|p| p.name());
+
+        assert_eq!(name("application/pdf", "scan").unwrap(), "LocalPdfParser");
+        assert_eq!(name("text/html; charset=utf-8", "page.pdf").unwrap(), "HtmlParser");
+        assert_eq!(
+            name("application/vnd.openxmlformats-officedocument.wordprocessingml.document", "x").unwrap(),
+            "DocxParser"
+        );
+        assert_eq!(name("application/octet-stream", "Notes.DOCX").unwrap(), "DocxParser");
+        assert_eq!(name("", "index.htm").unwrap(), "HtmlParser");
+        assert_eq!(name("application/octet-stream", "README.md").unwrap(), "MarkdownParser");
+        assert_eq!(name("text/plain; charset=utf-8", "notes").unwrap(), "PlainTextParser");
+
+        assert_eq!(name("application/octet-stream", "grades.CSV").unwrap(), "CsvParser");
+
+        assert!(matches!(name("application/zip", "slides.zip"), Err(ParserError::UnsupportedFormat(_))));
+        assert!(matches!(name("application/octet-stream", "unknown"), Err(ParserError::UnsupportedFormat(_))));
+    }
+
+    #[test]
+    fn test_parsers_as_trait_objects() {
+        let parsers: Vec<Box<dyn Parser>> = vec![
+            Box::new(LocalPdfParser::new()),
+            Box::new(DocxParser::new()),
+            Box::new(HtmlParser::new()),
+            Box::new(MarkdownParser::new()),
+            Box::new(AzureDocIntelligenceParser::new("https://example.invalid".to_string(), "key".to_string())),
+        ];
+        let by_mime: HashMap<&str, &dyn Parser> = parsers
+            .iter()
+            .rev()
+            .flat_map(|p| p.supported_mime_types().iter().map(move |m| (*m, p.as_ref())))
+            .collect();
+
+        // Earlier parsers win shared MIME types
+        assert_eq!(by_mime["application/pdf"].name(), "LocalPdfParser");
+        assert_eq!(by_mime["image/png"].name(), "AzureDocIntelligenceParser");
+        let pages = by_mime["text/html"].parse_bytes(b"<p>Cells divide.</p>").unwrap();
+        assert!(pages[0].text.contains("Cells divide."));
+        assert!(parsers.iter().all(|p| !p.supported_extensions().is_empty()));
+    }
+}
@@ -0,0 +1,146 @@
+// Plain text parser implementation
+
+use std::borrow::Cow;
+
+use super::traits::{Page, Parser, ParserError};
+
+/// Text without form feeds is cut into pages of at most this many
+/// characters, at a paragraph break where possible.
+const PAGE_CHARS: usize = 3000;
+
+/// Parser for plain text, with form feeds (`\x0c`) separating pages.
+/// Text that isn't UTF-8 is read as Latin-1.
+pub struct PlainTextParser;
+
+impl PlainTextParser {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Default for PlainTextParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser for PlainTextParser {
+    fn parse_bytes(&self, data: &[u8]) -> Result<Vec<Page>, ParserError> {
+        // Every byte is a Latin-1 character, so decoding can't fail, but
+        // offsets into the decoded text no longer match the source
+        let (text, verbatim) = match std::str::from_utf8(data) {
+            Ok(text) => (Cow::Borrowed(text), true),
+            Err(_) => (Cow::Owned(data.iter().map(|&b| b as char).collect::<String>()), false),
+        };
+        let sections = if text.contains('\x0c') {
+            form_feed_pages(&text)
+        } else {
+            sized_pages(&text)
+        };
+
+        // Blank pages are skipped but still count towards page numbers
+        let pages: Vec<Page> = sections
+            .into_iter()
+            .enumerate()
+            .filter(|(_, (_, page_text))| !page_text.trim().is_empty())
+            .map(|(i, (start, page_text))| Page {
+                page_num: i as u32 + 1,
+                text: page_text.to_string(),
+                source_start: verbatim.then_some(start),
+                source_end: verbatim.then_some(start + page_text.len()),
+                ..Default::default()
+            })
+            .collect();
+
+        if pages.is_empty() {
+            return Err(ParserError::EmptyDocument("plain text".to_string()));
+        }
+        Ok(pages)
+    }
+
+    fn supported_extensions(&self) -> &[&str] {
+        &["txt", "text"]
+    }
+
+    fn supported_mime_types(&self) -> &[&str] {
+        &["text/plain"]
+    }
+}
+
+/// Each form-feed separated section with its byte offset.
+fn form_feed_pages(text: &str) -> Vec<(usize, &str)> {
+    let mut offset = 0;
+    text.split('\x0c')
+        .map(|page_text| {
+            let start = offset;
+            offset += page_text.len() + 1;
+            (start, page_text)
+        })
+        .collect()
+}
+
+/// Sections of at most [`PAGE_CHARS`] characters with their byte offsets,
+/// cut after the last blank line or, failing that, the last whitespace.
+fn sized_pages(text: &str) -> Vec<(usize, &str)> {
+    let mut pages = Vec::new();
+    let mut start = 0;
+    while let Some((limit, _)) = text[start..].char_indices().nth(PAGE_CHARS) {
+        let window = &text[start..start + limit];
+        let cut = window
+            .rfind("\n\n")
+            .map(|i| i + 2)
+            .or_else(|| {
+                window
+                    .char_indices()
+                    .rev()
+                    .find(|(_, c)| c.is_whitespace())
+                    .map(|(i, c)| i + c.len_utf8())
+            })
+            .filter(|&cut| cut > 0)
+            .unwrap_or(limit);
+        pages.push((start, &text[start..start + cut]));
+        start += cut;
+    }
+    pages.push((start, &text[start..]));
+    pages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_ranges_reconstruct_pages() {
+        let data = "First page.\nStill the first.\x0cSecond pägé.\x0c\n\x0cFourth page.\n\x0c".as_bytes();
+        let pages = PlainTextParser::new().parse_bytes(data).unwrap();
+
+        let page_nums: Vec<u32> = pages.iter().map(|p| p.page_num).collect();
+        assert_eq!(page_nums, vec![1, 2, 4]);
+        for page in &pages {
+            let range = page.source_start.unwrap()..page.source_end.unwrap();
+            assert_eq!(&data[range], page.text.as_bytes());
+        }
+        assert_eq!(pages[1].text, "Second pägé.");
+    }
+
+    #[test]
+    fn test_long_text_paged_at_paragraph_breaks() {
+        let paragraph = "Cells divide by mitosis, and each daughter cell receives a full set of chromosomes. ".repeat(6);
+        let text = [paragraph.trim_end(); 10].join("\n\n");
+        let pages = PlainTextParser::new().parse_bytes(text.as_bytes()).unwrap();
+
+        assert!(pages.len() > 1);
+        assert!(pages.iter().all(|p| p.text.chars().count() <= PAGE_CHARS));
+        assert!(pages[..pages.len() - 1].iter().all(|p| p.text.ends_with("chromosomes.\n\n")));
+        assert_eq!(pages.iter().map(|p| p.text.as_str()).collect::<String>(), text);
+        let page_nums: Vec<u32> = pages.iter().map(|p| p.page_num).collect();
+        assert_eq!(page_nums, (1..=pages.len() as u32).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_invalid_utf8_read_as_latin1() {
+        let pages = PlainTextParser::new().parse_bytes(b"Caf\xe9 au lait, s'il vous pla\xeet.").unwrap();
+        assert_eq!(pages[0].text, "Café au lait, s'il vous plaît.");
+        assert_eq!(pages[0].source_start, None);
+    }
+}
@@ -0,0 +1,48 @@
+// Time-limited parsing off the async runtime
+
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use super::traits::{DocumentInfo, Page, Parser, ParserError};
+
+/// Parse `data` and read its document properties on the blocking thread
+/// pool, giving up after `timeout`. `data` is shared rather than copied,
+/// so the caller may keep it. A parse that times out cannot be
+/// interrupted; it runs on in the background and its result is discarded.
+pub async fn parse_with_timeout(
+    parser: Box<dyn Parser>,
+    data: Bytes,
+    timeout: Duration,
+) -> Result<(Vec<Page>, DocumentInfo), ParserError> {
+    let parse = tokio::task::spawn_blocking(move || {
+        let pages = parser.parse_bytes(&data)?;
+        Ok((pages, parser.document_info(&data)))
+    });
+    match tokio::time::timeout(timeout, parse).await {
+        Ok(Ok(parsed)) => parsed,
+        Ok(Err(e)) => Err(ParserError::ParseError(format!("Parser panicked: {}", e))),
+        Err(_) => Err(ParserError::Timeout(timeout)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::fixtures::SlowParser;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn test_slow_parse_times_out() {
+        let data = Bytes::from_static(b"Cells divide.");
+        let start = Instant::now();
+        let result = parse_with_timeout(Box::new(SlowParser), data.clone(), Duration::from_millis(50)).await;
+        assert!(matches!(result, Err(ParserError::Timeout(_))));
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let (pages, _) = parse_with_timeout(Box::new(SlowParser), data, Duration::from_secs(10))
+            .await
+            .unwrap();
+        assert_eq!(pages[0].text, "Cells divide.");
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::io::Read;
 use thiserror::Error;
 
@@ -11,13 +12,53 @@ pub enum ParserError {
     ParseError(String),
     #[error("Unsupported format: {0}")]
     UnsupportedFormat(String),
+    #[error("Network access is disabled: {0} is unavailable")]
+    NetworkDisabled(String),
+    #[error("No extractable text: {0}")]
+    NoExtractableText(String),
+    /// A well-formed document of the given format without any text.
+    #[error("No text content found in {0}")]
+    EmptyDocument(String),
+    #[error("Parsing timed out after {0:?}")]
+    Timeout(std::time::Duration),
 }
 
-#[derive(Debug, Clone)]
+impl ParserError {
+    /// Variant name, as used to label error metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Io(_) => "Io",
+            Self::PdfParse(_) => "PdfParse",
+            Self::ParseError(_) => "ParseError",
+            Self::UnsupportedFormat(_) => "UnsupportedFormat",
+            Self::NetworkDisabled(_) => "NetworkDisabled",
+            Self::NoExtractableText(_) => "NoExtractableText",
+            Self::EmptyDocument(_) => "EmptyDocument",
+            Self::Timeout(_) => "Timeout",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct Page {
     pub page_num: u32,
     pub text: String,
     pub images: Vec<Image>,
+    /// Passages the author/reader marked with highlight annotations.
+    pub highlights: Vec<Highlight>,
+    /// Code blocks within `text`, which the splitter keeps intact.
+    pub code_blocks: Vec<CodeBlock>,
+    /// Tables within `text`, rendered as Markdown, which the splitter also
+    /// keeps intact.
+    pub tables: Vec<TableBlock>,
+    /// Section headings appearing in `text`, in reading order.
+    pub headings: Vec<Heading>,
+    /// ISO 639-1 code of the language the text is written in, when detected.
+    pub language: Option<String>,
+    /// Byte range of the page in the source document, for text formats
+    /// whose pages are verbatim slices of it.
+    pub source_start: Option<usize>,
+    pub source_end: Option<usize>,
 }
 
 #[derive(Debug, Clone)]
@@ -27,9 +68,83 @@ pub struct Image {
     pub content_type: String,
 }
 
+#[derive(Debug, Clone)]
+pub struct Highlight {
+    pub text: String,
+    /// Annotation color as `#rrggbb`, when the annotation specifies one.
+    pub color: Option<String>,
+}
+
+/// A section heading; level 1 is the outermost.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heading {
+    pub level: u8,
+    pub text: String,
+}
+
+/// A code block occupying `text[start..end]` (byte offsets) of its page.
+#[derive(Debug, Clone)]
+pub struct CodeBlock {
+    pub start: usize,
+    pub end: usize,
+    pub language: Option<String>,
+}
+
+/// A Markdown table occupying `text[start..end]` (byte offsets) of its page.
+#[derive(Debug, Clone)]
+pub struct TableBlock {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Document-level properties recorded by the authoring application.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DocumentInfo {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub last_modified_by: Option<String>,
+    /// Creation timestamp as stored in the document (usually RFC 3339).
+    pub created_at: Option<String>,
+    pub modified_at: Option<String>,
+    pub revision: Option<u32>,
+    /// Form fields found by layout analysis, field name to value.
+    pub key_values: BTreeMap<String, String>,
+}
+
+/// Pages of one document in order, as produced by [`Parser::parse_stream`].
+pub type PageStream<'a> = Box<dyn Iterator<Item = Result<Page, ParserError>> + Send + 'a>;
+
 pub trait Parser: Send + Sync {
-    fn parse<R: Read>(&self, reader: R) -> Result<Vec<Page>, ParserError>;
+    /// Parse an in-memory document. Object safe, for parsers chosen at
+    /// runtime.
+    fn parse_bytes(&self, data: &[u8]) -> Result<Vec<Page>, ParserError>;
+    fn parse<R: Read>(&self, mut reader: R) -> Result<Vec<Page>, ParserError>
+    where
+        Self: Sized,
+    {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        self.parse_bytes(&data)
+    }
+    /// Parse an in-memory document page by page, so callers can split and
+    /// send the first pages before the last are built. Parsers that only
+    /// produce whole documents yield the pages of `parse_bytes`.
+    fn parse_stream<'a>(&'a self, data: &'a [u8]) -> PageStream<'a> {
+        match self.parse_bytes(data) {
+            Ok(pages) => Box::new(pages.into_iter().map(Ok)),
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        }
+    }
+    /// Document properties for `data`; formats without any report none.
+    fn document_info(&self, _data: &[u8]) -> DocumentInfo {
+        DocumentInfo::default()
+    }
     fn supported_extensions(&self) -> &[&str];
     fn supported_mime_types(&self) -> &[&str];
+    /// Type name, as used in parser priorities and `parser_used`.
+    fn name(&self) -> &'static str {
+        let path = std::any::type_name::<Self>();
+        path.rsplit("::").next().unwrap_or(path)
+    }
 }
 
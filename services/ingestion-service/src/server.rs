@@ -0,0 +1,215 @@
+// Running the REST and gRPC servers together, with graceful shutdown
+
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::Router;
+use futures::future::BoxFuture;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio_stream::wrappers::TcpListenerStream;
+use tower::{Layer, Service};
+
+use crate::auth::RequireGrpcApiKey;
+use crate::grpc::{proto::ingestion_service_server::IngestionServiceServer, IngestionServiceImpl};
+
+/// Counts requests that have arrived but not been answered yet, across
+/// every service it layers.
+#[derive(Debug, Clone, Default)]
+pub struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl<S> Layer<S> for InFlight {
+    type Service = InFlightService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        InFlightService {
+            inner,
+            count: self.0.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InFlightService<S> {
+    inner: S,
+    count: Arc<AtomicUsize>,
+}
+
+impl<S, R> Service<R> for InFlightService<S>
+where
+    S: Service<R>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<S::Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        let guard = InFlightGuard::new(self.count.clone());
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            let _guard = guard;
+            response.await
+        })
+    }
+}
+
+/// Holds one request in the count until dropped, however the request ends.
+struct InFlightGuard(Arc<AtomicUsize>);
+
+impl InFlightGuard {
+    fn new(count: Arc<AtomicUsize>) -> Self {
+        count.fetch_add(1, Ordering::SeqCst);
+        Self(count)
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Resolves on SIGINT (Ctrl+C) or, on Unix, SIGTERM as sent by Kubernetes.
+pub async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => tracing::info!("Received SIGINT"),
+        _ = terminate => tracing::info!("Received SIGTERM"),
+    }
+}
+
+/// Serve `rest` and `grpc` until one of them fails or `signal` resolves.
+/// On the signal both stop accepting connections and requests already in
+/// flight get up to `drain_timeout` to finish; any still running after
+/// that are dropped.
+pub async fn serve(
+    rest_listener: TcpListener,
+    rest: Router,
+    grpc_listener: TcpListener,
+    grpc: RequireGrpcApiKey<IngestionServiceServer<IngestionServiceImpl>>,
+    drain_timeout: Duration,
+    signal: impl Future<Output = ()>,
+) -> anyhow::Result<()> {
+    let in_flight = InFlight::default();
+    let (stop, stopping) = watch::channel(false);
+    let stopped = |mut stopping: watch::Receiver<bool>| async move {
+        let _ = stopping.wait_for(|stop| *stop).await;
+    };
+
+    let rest = axum::serve(rest_listener, rest.layer(in_flight.clone()))
+        .with_graceful_shutdown(stopped(stopping.clone()));
+    let grpc = tonic::transport::Server::builder()
+        .layer(in_flight.clone())
+        .add_service(grpc)
+        .serve_with_incoming_shutdown(TcpListenerStream::new(grpc_listener), stopped(stopping));
+    let servers = async {
+        tokio::try_join!(
+            async { rest.await.map_err(|e| anyhow::anyhow!("REST server error: {}", e)) },
+            async { grpc.await.map_err(|e| anyhow::anyhow!("gRPC server error: {}", e)) },
+        )
+    };
+    tokio::pin!(servers);
+
+    tokio::select! {
+        result = &mut servers => return result.map(|_| ()),
+        _ = signal => {}
+    }
+
+    let draining = in_flight.count();
+    tracing::info!("Shutting down, draining {} in-flight requests", draining);
+    stop.send_replace(true);
+    match tokio::time::timeout(drain_timeout, servers).await {
+        Ok(result) => {
+            tracing::info!("Drained {} requests", draining);
+            result.map(|_| ())
+        }
+        Err(_) => {
+            tracing::warn!(
+                "{} of {} requests still in flight after {:?}, exiting",
+                in_flight.count(),
+                draining,
+                drain_timeout
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::grpc::create_service;
+    use axum::routing::get;
+
+    #[tokio::test]
+    async fn test_shutdown_drains_in_flight_request() {
+        let rest_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let grpc_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = rest_listener.local_addr().unwrap();
+        let slow = Router::new().route(
+            "/slow",
+            get(|| async {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                "parsed"
+            }),
+        );
+        let (shutdown, signal) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve(
+            rest_listener,
+            slow,
+            grpc_listener,
+            create_service(Config::default()),
+            Duration::from_secs(5),
+            async {
+                let _ = signal.await;
+            },
+        ));
+
+        let request = tokio::spawn(async move { reqwest::get(format!("http://{}/slow", addr)).await });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        shutdown.send(()).unwrap();
+
+        let response = request.await.unwrap().unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "parsed");
+        server.await.unwrap().unwrap();
+
+        // Nothing is listening any more
+        assert!(reqwest::get(format!("http://{}/slow", addr)).await.is_err());
+    }
+}
@@ -0,0 +1,69 @@
+// Locality-sensitive fingerprints for near-duplicate detection across
+// documents without embeddings.
+
+use super::Chunk;
+
+/// Characters per shingle hashed into the fingerprint.
+const SHINGLE_CHARS: usize = 3;
+
+/// 64-bit SimHash of `text`. Words are lowercased and stripped of
+/// punctuation, and each overlapping three-character shingle of the result
+/// votes on every bit, so texts differing in a few words differ in only a
+/// few bits. Compare fingerprints with [`hamming_distance`].
+pub fn simhash(text: &str) -> u64 {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let chars: Vec<char> = words.join(" ").chars().collect();
+
+    let mut votes = [0i32; 64];
+    for shingle in chars.windows(SHINGLE_CHARS) {
+        let shingle: String = shingle.iter().collect();
+        let hash = blake3::hash(shingle.as_bytes());
+        let bits = u64::from_le_bytes(hash.as_bytes()[..8].try_into().unwrap());
+        for (bit, vote) in votes.iter_mut().enumerate() {
+            *vote += if bits >> bit & 1 == 1 { 1 } else { -1 };
+        }
+    }
+    votes
+        .iter()
+        .enumerate()
+        .filter(|(_, vote)| **vote > 0)
+        .fold(0, |fingerprint, (bit, _)| fingerprint | 1 << bit)
+}
+
+/// Number of bits in which two fingerprints differ.
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Set `simhash` on every chunk from its text.
+pub fn fingerprint_chunks(chunks: &mut [Chunk]) {
+    for chunk in chunks {
+        chunk.simhash = Some(simhash(&chunk.text));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_near_duplicates_have_close_fingerprints() {
+        let original = "Photosynthesis in green plants converts light energy, water and carbon dioxide \
+                        into glucose and oxygen inside the chloroplasts of leaf cells during the day.";
+        let edited = "Photosynthesis in green plants converts light energy, water and carbon dioxide \
+                      into glucose and oxygen inside the chloroplasts of leaf cells during daytime.";
+        let unrelated = "The French Revolution began in 1789 and ended the absolute monarchy, reshaping \
+                         European politics through ideas of liberty, equality and popular sovereignty.";
+
+        assert!(hamming_distance(simhash(original), simhash(edited)) <= 8);
+        assert!(hamming_distance(simhash(original), simhash(unrelated)) >= 20);
+        assert!(hamming_distance(simhash(edited), simhash(unrelated)) >= 20);
+
+        // Case and punctuation don't matter
+        assert_eq!(simhash("Cells divide."), simhash("cells, DIVIDE"));
+    }
+}
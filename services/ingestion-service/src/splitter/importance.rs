@@ -0,0 +1,99 @@
+// Importance scoring for consumers that truncate chunk lists to a budget.
+
+use serde::{Deserialize, Serialize};
+
+use super::Chunk;
+
+/// Weights of the importance components; they sum to 1.
+const QUALITY_WEIGHT: f32 = 0.6;
+const HIGHLIGHT_WEIGHT: f32 = 0.3;
+const POSITION_WEIGHT: f32 = 0.1;
+
+/// Chunks with fewer words than this score proportionally lower.
+const SUBSTANTIAL_WORDS: f32 = 12.0;
+
+/// Order in which chunks are returned.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChunkOrder {
+    /// Reading order.
+    #[default]
+    Document,
+    /// Highest importance score first.
+    Importance,
+}
+
+impl ChunkOrder {
+    /// Parse a request value; empty or unknown values keep document order.
+    pub fn parse(value: &str) -> Self {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "importance" => Self::Importance,
+            _ => Self::Document,
+        }
+    }
+}
+
+/// How much a chunk reads like real prose, from 0.0 (noise) to 1.0.
+///
+/// Penalizes text dominated by digits and symbols (tables of numbers, page
+/// furniture), implausible word shapes and short fragments.
+pub fn quality_score(text: &str) -> f32 {
+    let word_count = text.split_whitespace().count();
+    let chars = text.chars().filter(|c| !c.is_whitespace()).count();
+    if word_count == 0 || chars == 0 {
+        return 0.0;
+    }
+    let alphabetic = text.chars().filter(|c| c.is_alphabetic()).count() as f32 / chars as f32;
+    let average_word = chars as f32 / word_count as f32;
+    let word_shape = if (3.0..=12.0).contains(&average_word) { 1.0 } else { 0.5 };
+    let length = (word_count as f32 / SUBSTANTIAL_WORDS).min(1.0);
+    alphabetic * word_shape * length
+}
+
+/// Combined importance of a chunk: its quality, whether it is highlighted,
+/// and a slight preference for chunks early in the document.
+pub fn importance_score(chunk: &Chunk) -> f32 {
+    let highlighted = if chunk.highlighted { 1.0 } else { 0.0 };
+    QUALITY_WEIGHT * quality_score(&chunk.text)
+        + HIGHLIGHT_WEIGHT * highlighted
+        + POSITION_WEIGHT * (1.0 - chunk.position.clamp(0.0, 1.0))
+}
+
+/// Reorder chunks in place. Each chunk keeps its document-order `index`.
+pub fn order_chunks(chunks: &mut [Chunk], order: ChunkOrder) {
+    if order == ChunkOrder::Importance {
+        // Stable, so equal scores stay in reading order
+        chunks.sort_by(|a, b| importance_score(b).total_cmp(&importance_score(a)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Page;
+    use crate::splitter::{SentenceTextSplitter, TextSplitter};
+
+    #[test]
+    fn test_importance_order_keeps_document_index() {
+        let page = |num: u32, text: &str| Page {
+            page_num: num,
+            text: text.to_string(),
+            ..Default::default()
+        };
+        let pages = [
+            page(1, "| 12 | 34.5 | 56 | 7.8 | 90 | -- | 11 | 2.3 |"),
+            page(2, "The committee met twice during the year and reviewed the annual budget."),
+            page(3, "Every member must complete the safety training before entering the laboratory."),
+        ];
+        let mut chunks = SentenceTextSplitter::new(500, 0).split(&pages);
+        chunks[2].highlighted = true;
+
+        order_chunks(&mut chunks, ChunkOrder::Importance);
+
+        let order: Vec<u32> = chunks.iter().map(|c| c.page_num).collect();
+        assert_eq!(order, vec![3, 2, 1]);
+        let indices: Vec<usize> = chunks.iter().map(|c| c.index).collect();
+        assert_eq!(indices, vec![2, 1, 0]);
+        assert!(quality_score(&chunks[2].text) < quality_score(&chunks[1].text));
+    }
+}
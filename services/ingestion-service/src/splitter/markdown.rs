@@ -0,0 +1,366 @@
+// Markdown-aware splitting: chunks follow the heading structure, fenced code
+// blocks stay whole and a list is only ever cut between its items.
+
+use std::ops::Range;
+
+use uuid::Uuid;
+
+use super::sentence::{bpe, SplitterSettings, TokenizerKind};
+use super::{Chunk, ImageRef, OverlapAlign, SentenceTextSplitter, TextSplitter};
+use crate::parser::Page;
+
+/// Splits Markdown pages section by section. Every chunk belongs to a single
+/// section and starts with that section's heading path (`Guide > Setup`)
+/// for context. Blocks are packed into chunks of up to `max_tokens`; prose
+/// paragraphs too long on their own fall back to sentence splitting, while
+/// a code block or list item larger than the limit is kept whole.
+pub struct MarkdownTextSplitter {
+    max_tokens: usize,
+}
+
+/// A top-level Markdown block, as byte ranges into the page text.
+enum Block {
+    Heading(u8, String),
+    Code(Range<usize>),
+    /// One range per list item
+    List(Vec<Range<usize>>),
+    Paragraph(Range<usize>),
+}
+
+impl MarkdownTextSplitter {
+    pub fn new(max_tokens: usize) -> Self {
+        Self { max_tokens }
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        bpe(TokenizerKind::default()).encode_with_special_tokens(text).len()
+    }
+
+    /// The text of `block` as pieces that are never split, each within
+    /// `budget` tokens where the block allows it.
+    fn pieces(&self, text: &str, block: &Block, budget: usize) -> Vec<String> {
+        match block {
+            Block::Heading(..) => Vec::new(),
+            Block::Code(range) => vec![text[range.clone()].to_string()],
+            Block::List(items) => {
+                let whole = &text[items[0].start..items[items.len() - 1].end];
+                if self.count_tokens(whole) <= budget {
+                    vec![whole.to_string()]
+                } else {
+                    items.iter().map(|item| text[item.clone()].to_string()).collect()
+                }
+            }
+            Block::Paragraph(range) => {
+                let paragraph = &text[range.clone()];
+                if self.count_tokens(paragraph) <= budget {
+                    return vec![paragraph.to_string()];
+                }
+                let page = Page {
+                    text: paragraph.to_string(),
+                    ..Default::default()
+                };
+                SentenceTextSplitter::new(budget, 0)
+                    .split(&[page])
+                    .into_iter()
+                    .map(|chunk| chunk.text)
+                    .collect()
+            }
+        }
+    }
+
+    fn make_chunk(&self, page: &Page, path: &[(u8, String)], body: &str, code_language: Option<String>) -> Chunk {
+        let text = format!("{}{}", breadcrumb(path), body.trim());
+        Chunk {
+            id: Uuid::new_v4().to_string(),
+            index: 0,
+            position: 0.0,
+            page_num: page.page_num,
+            page_span: None,
+            token_count: self.count_tokens(&text),
+            char_count: text.chars().count(),
+            highlighted: false,
+            highlight_color: None,
+            language_spans: None,
+            language: page.language.clone(),
+            code_language,
+            kind: None,
+            heading_path: (!path.is_empty()).then(|| path.iter().map(|(_, title)| title.clone()).collect()),
+            original_text: None,
+            images: page.images.iter().map(ImageRef::from).collect(),
+            embed_text: None,
+            embed_token_count: None,
+            overlap_prefix_len: None,
+            overlap_suffix_len: None,
+            start_offset: None,
+            end_offset: None,
+            simhash: None,
+            text,
+        }
+    }
+}
+
+impl TextSplitter for MarkdownTextSplitter {
+    fn split(&self, pages: &[Page]) -> Vec<Chunk> {
+        let mut chunks = Vec::new();
+        // Open headings, outermost first; sections continue across page breaks
+        let mut path: Vec<(u8, String)> = Vec::new();
+
+        for page in pages {
+            let mut body = String::new();
+            let mut code_language: Option<String> = None;
+            // Block the last piece in `body` came from
+            let mut last_block = None;
+
+            for (b, block) in blocks(&page.text).iter().enumerate() {
+                if let Block::Heading(level, title) = block {
+                    if !body.trim().is_empty() {
+                        chunks.push(self.make_chunk(page, &path, &body, code_language.take()));
+                    }
+                    body.clear();
+                    code_language = None;
+                    while path.last().is_some_and(|(open, _)| open >= level) {
+                        path.pop();
+                    }
+                    path.push((*level, title.clone()));
+                    continue;
+                }
+
+                let prefix = breadcrumb(&path);
+                let budget = self.max_tokens.saturating_sub(self.count_tokens(&prefix)).max(1);
+                let language = match block {
+                    Block::Code(range) => page
+                        .code_blocks
+                        .iter()
+                        .find(|c| c.start == range.start)
+                        .and_then(|c| c.language.clone()),
+                    _ => None,
+                };
+
+                for piece in self.pieces(&page.text, block, budget) {
+                    let separator = match last_block {
+                        _ if body.is_empty() => "",
+                        Some(last) if last == b => "\n",
+                        _ => "\n\n",
+                    };
+                    let candidate = format!("{}{}{}", body, separator, piece);
+                    if !body.is_empty() && self.count_tokens(&format!("{}{}", prefix, candidate)) > self.max_tokens {
+                        chunks.push(self.make_chunk(page, &path, &body, code_language.take()));
+                        body = piece;
+                    } else {
+                        body = candidate;
+                    }
+                    last_block = Some(b);
+                    if code_language.is_none() {
+                        code_language = language.clone();
+                    }
+                }
+            }
+
+            if !body.trim().is_empty() {
+                chunks.push(self.make_chunk(page, &path, &body, code_language));
+            }
+        }
+
+        let last = chunks.len().saturating_sub(1).max(1) as f32;
+        for (index, chunk) in chunks.iter_mut().enumerate() {
+            chunk.index = index;
+            chunk.position = index as f32 / last;
+        }
+        chunks
+    }
+
+    fn settings(&self) -> SplitterSettings {
+        SplitterSettings {
+            splitter: "markdown".to_string(),
+            tokenizer: TokenizerKind::default().name().to_string(),
+            max_tokens: self.max_tokens,
+            overlap_tokens: 0,
+            overlap_align: OverlapAlign::Token,
+            transforms: Vec::new(),
+        }
+    }
+}
+
+/// `Guide > Setup` followed by a blank line, or nothing outside any section.
+fn breadcrumb(path: &[(u8, String)]) -> String {
+    if path.is_empty() {
+        return String::new();
+    }
+    let titles: Vec<&str> = path.iter().map(|(_, title)| title.as_str()).collect();
+    format!("{}\n\n", titles.join(" > "))
+}
+
+/// Split Markdown into top-level blocks. Lines indented under a list item,
+/// including fenced code, belong to that item.
+fn blocks(text: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    // Fence marker, start of the fence and whether it sits inside a list item
+    let mut fence: Option<(&str, usize, bool)> = None;
+    let mut paragraph: Option<Range<usize>> = None;
+    let mut list: Vec<Range<usize>> = Vec::new();
+    let mut after_blank = false;
+    let mut offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let end = start + line.trim_end().len();
+        let trimmed = line.trim_start();
+
+        if let Some((marker, open, in_list)) = fence {
+            if closes_fence(trimmed, marker) {
+                match list.last_mut() {
+                    Some(item) if in_list => item.end = end,
+                    _ => blocks.push(Block::Code(open..end)),
+                }
+                fence = None;
+            }
+            continue;
+        }
+
+        let blank = trimmed.is_empty();
+        let indented = !blank && line.starts_with([' ', '\t']);
+        if let Some(marker) = fence_marker(trimmed) {
+            let in_list = !list.is_empty() && indented;
+            if !in_list {
+                flush(&mut blocks, &mut paragraph, &mut list);
+            }
+            fence = Some((marker, start, in_list));
+        } else if blank {
+            if let Some(range) = paragraph.take() {
+                blocks.push(Block::Paragraph(range));
+            }
+        } else if let Some((level, title)) = heading(trimmed) {
+            flush(&mut blocks, &mut paragraph, &mut list);
+            blocks.push(Block::Heading(level, title));
+        } else if is_list_item(trimmed) {
+            if let Some(range) = paragraph.take() {
+                blocks.push(Block::Paragraph(range));
+            }
+            list.push(start..end);
+        } else if !list.is_empty() && (indented || !after_blank) {
+            if let Some(item) = list.last_mut() {
+                item.end = end;
+            }
+        } else {
+            if !list.is_empty() {
+                flush(&mut blocks, &mut paragraph, &mut list);
+            }
+            paragraph = Some(paragraph.map_or(start, |p| p.start)..end);
+        }
+        after_blank = blank;
+    }
+
+    // An unterminated fence runs to the end of the page
+    if let Some((_, open, in_list)) = fence {
+        let end = text.trim_end().len().max(open);
+        match list.last_mut() {
+            Some(item) if in_list => item.end = end,
+            _ => blocks.push(Block::Code(open..end)),
+        }
+    }
+    flush(&mut blocks, &mut paragraph, &mut list);
+    blocks
+}
+
+fn flush(blocks: &mut Vec<Block>, paragraph: &mut Option<Range<usize>>, list: &mut Vec<Range<usize>>) {
+    if let Some(range) = paragraph.take() {
+        blocks.push(Block::Paragraph(range));
+    }
+    if !list.is_empty() {
+        blocks.push(Block::List(std::mem::take(list)));
+    }
+}
+
+/// The run of ``` or ~~~ opening a fenced code block.
+fn fence_marker(line: &str) -> Option<&str> {
+    let fence_char = line.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let len = line.chars().take_while(|&c| c == fence_char).count();
+    (len >= 3).then(|| &line[..len])
+}
+
+fn closes_fence(line: &str, marker: &str) -> bool {
+    fence_marker(line).is_some_and(|closing| {
+        closing.len() >= marker.len() && closing[..1] == marker[..1] && line[closing.len()..].trim().is_empty()
+    })
+}
+
+/// Level and title of an ATX heading line (`## Setup`).
+fn heading(line: &str) -> Option<(u8, String)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let rest = &line[level..];
+    let is_heading = (1..=6).contains(&level) && (rest.trim().is_empty() || rest.starts_with([' ', '\t']));
+    is_heading.then(|| (level as u8, rest.trim().trim_end_matches('#').trim_end().to_string()))
+}
+
+/// Whether `line` starts a bullet (`- `, `* `, `+ `) or ordered (`1. `, `1) `) item.
+fn is_list_item(line: &str) -> bool {
+    if line.starts_with(['-', '*', '+']) {
+        return line[1..].starts_with([' ', '\t']);
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    (1..=9).contains(&digits)
+        && line[digits..].starts_with(['.', ')'])
+        && line[digits + 1..].starts_with([' ', '\t'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{MarkdownParser, Parser};
+
+    const GUIDE: &str = "# Guide\n\nThis guide walks through installing the tool.\n\n\
+        ## Install\n\nRun the installer, then check the version.\n\n\
+        ```bash\n#!/bin/bash\nset -e\n\n# Fetch and unpack the release\ncurl -sSL https://example.com/tool.tar.gz -o tool.tar.gz\n\
+        tar -xzf tool.tar.gz\nexport PATH=\"$PWD/tool/bin:$PATH\"\necho \"Installed $(tool --version)\"\n```\n\n\
+        ## Configure\n\nThe tool reads three settings:\n\n\
+        - `endpoint`: where requests go\n- `timeout`: seconds to wait\n  before giving up\n- `retries`: how often to retry\n";
+
+    #[test]
+    fn test_sections_code_fences_and_lists_stay_whole() {
+        let pages = MarkdownParser::new().parse_bytes(GUIDE.as_bytes()).unwrap();
+        let chunks = MarkdownTextSplitter::new(48).split(&pages);
+
+        // No chunk mixes two sections, and each starts with its heading path
+        for chunk in &chunks {
+            let path = chunk.heading_path.as_ref().unwrap();
+            assert!(chunk.text.starts_with(&format!("{}\n\n", path.join(" > "))));
+            assert!(!chunk.text.contains("## "));
+        }
+
+        // The code fence is longer than the limit but stays in one chunk
+        let fence_start = GUIDE.find("```bash").unwrap();
+        let fence_end = GUIDE.rfind("```").unwrap() + 3;
+        let fence = &GUIDE[fence_start..fence_end];
+        let code = chunks.iter().find(|c| c.text.contains("```bash")).unwrap();
+        assert!(code.text.ends_with(fence));
+        assert!(code.token_count > 48);
+        assert_eq!(code.code_language.as_deref(), Some("bash"));
+        assert_eq!(code.heading_path, Some(vec!["Guide".to_string(), "Install".to_string()]));
+
+        // The list, continuation line included, is kept with its intro
+        let configure = chunks.iter().find(|c| c.text.contains("`retries`")).unwrap();
+        assert!(configure.text.contains("- `timeout`: seconds to wait\n  before giving up\n- `retries`"));
+        assert!(configure.text.starts_with("Guide > Configure\n\nThe tool reads three settings:"));
+
+        let indices: Vec<usize> = chunks.iter().map(|c| c.index).collect();
+        assert_eq!(indices, (0..chunks.len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_long_prose_falls_back_to_sentences() {
+        let sentence = "Each cell copies its genome before it divides into two daughter cells. ";
+        let text = format!("# Biology\n\n{}\n\n```\nshort code\n```\n", sentence.repeat(20));
+        let page = Page {
+            page_num: 1,
+            text,
+            ..Default::default()
+        };
+        let chunks = MarkdownTextSplitter::new(50).split(&[page]);
+
+        assert!(chunks.len() > 2);
+        assert!(chunks.iter().all(|c| c.token_count <= 50));
+        assert!(chunks.iter().all(|c| c.text.starts_with("Biology\n\n")));
+        assert!(chunks.iter().any(|c| c.text.ends_with("```\nshort code\n```")));
+    }
+}
@@ -1,19 +1,272 @@
+mod fingerprint;
+mod importance;
+mod markdown;
+mod process;
+mod recursive;
+mod redact;
 mod sentence;
+mod structure;
 
-pub use sentence::SentenceTextSplitter;
+pub use fingerprint::{fingerprint_chunks, hamming_distance, simhash};
+pub use importance::{importance_score, order_chunks, quality_score, ChunkOrder};
+pub use markdown::MarkdownTextSplitter;
+#[cfg(test)]
+pub(crate) use process::Uppercase;
+pub use process::{ChunkProcessor, ChunkProcessors, TrimWhitespace};
+pub use recursive::RecursiveCharacterTextSplitter;
+pub use redact::RedactionProcessor;
+pub use sentence::{OverlapAlign, SentenceTextSplitter, SplitterSettings, TokenizerKind};
+pub(crate) use sentence::{bpe, load_bpe};
+pub use structure::{structure_tree, StructureNode};
+
+use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+use uuid::Uuid;
+
+use crate::language::LanguageSpan;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Chunk {
     pub id: String,
+    /// Position of the chunk in the document's output, counting from 0.
+    pub index: usize,
+    /// Relative location in the document, from 0.0 (first chunk) to 1.0 (last).
+    pub position: f32,
+    /// Page the chunk starts on.
     pub page_num: u32,
+    /// First and last page, for a chunk that carries a sentence across a
+    /// page break.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_span: Option<(u32, u32)>,
     pub text: String,
     pub token_count: usize,
+    /// Length of `text` in Unicode scalar values, not UTF-8 bytes.
     pub char_count: usize,
+    /// Whether the chunk covers a passage marked with a highlight annotation.
+    pub highlighted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight_color: Option<String>,
+    /// Per-language character ranges, present only for multilingual chunks
+    /// when language spans were requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language_spans: Option<Vec<LanguageSpan>>,
+    /// ISO 639-1 code of the language of the chunk's page, when detected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Language of the code block this chunk holds, for code chunks.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_language: Option<String>,
+    /// `table` for a chunk holding one Markdown table, `code` for one code
+    /// block, with or without a language; unset for prose.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// Titles of the enclosing sections, outermost first, when the document
+    /// has a heading outline.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heading_path: Option<Vec<String>>,
+    /// The chunk's own text, when `prepend_heading` put its heading path in
+    /// front of `text`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_text: Option<String>,
+    /// Images on the chunk's page; fetch the bytes from `/api/images`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ImageRef>,
+    /// Whitespace-normalized, de-hyphenated text for embedding models.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embed_text: Option<String>,
+    /// Token count of `embed_text`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embed_token_count: Option<usize>,
+    /// Characters at the start of `text` repeated from the previous chunk,
+    /// when overlap reporting was requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overlap_prefix_len: Option<usize>,
+    /// Characters at the end of `text` repeated at the start of the next
+    /// chunk, when overlap reporting was requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overlap_suffix_len: Option<usize>,
+    /// Character offsets of the chunk's own text (`original_text` when a
+    /// heading was prepended) in its page's text: the page's characters
+    /// `start_offset..end_offset` are that text. Unset when it is not a
+    /// verbatim slice of one page, as for a sentence carried across a page
+    /// break or text rewritten by redaction or a chunk processor.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_offset: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_offset: Option<usize>,
+    /// SimHash of the text for near-duplicate detection, when requested.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub simhash: Option<u64>,
+}
+
+impl Chunk {
+    /// The chunk's text without a prepended heading path.
+    pub fn own_text(&self) -> &str {
+        self.original_text.as_deref().unwrap_or(&self.text)
+    }
+}
+
+/// Reference to an extracted image whose bytes are served separately.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageRef {
+    pub image_id: String,
+    pub content_type: String,
+    pub size_bytes: usize,
+}
+
+impl From<&crate::parser::Image> for ImageRef {
+    fn from(image: &crate::parser::Image) -> Self {
+        Self {
+            image_id: image.id.clone(),
+            content_type: image.content_type.clone(),
+            size_bytes: image.data.len(),
+        }
+    }
+}
+
+/// Prefix each chunk's text with its heading path, as
+/// `Chapter 1 > Background:\n`, which helps retrieval match a passage to the
+/// section it belongs to. See [`prepend_heading`].
+pub fn prepend_headings(chunks: &mut [Chunk], tokenizer: TokenizerKind) {
+    for chunk in chunks {
+        prepend_heading(chunk, tokenizer);
+    }
+}
+
+/// Prefix `chunk`'s text with its heading path, if it has one, moving the
+/// unprefixed text to `original_text`. Token and character counts are
+/// recomputed, and `embed_text` is prefixed too; overlap lengths still
+/// refer to `original_text`.
+pub fn prepend_heading(chunk: &mut Chunk, tokenizer: TokenizerKind) {
+    let Some(path) = chunk.heading_path.as_ref().filter(|path| !path.is_empty()) else {
+        return;
+    };
+    let heading = path.join(" > ");
+    let count_tokens = |text: &str| sentence::bpe(tokenizer).encode_with_special_tokens(text).len();
+
+    let text = format!("{}:\n{}", heading, chunk.text);
+    chunk.token_count = count_tokens(&text);
+    chunk.char_count = text.chars().count();
+    chunk.original_text = Some(std::mem::replace(&mut chunk.text, text));
+    if let Some(embed) = &mut chunk.embed_text {
+        *embed = embed_text(&format!("{}: {}", heading, embed));
+        chunk.embed_token_count = Some(count_tokens(embed));
+    }
+}
+
+/// Root of the UUIDv5 namespaces deterministic chunk IDs are derived in.
+const CHUNK_ID_NAMESPACE: Uuid = Uuid::from_u128(0x0cb16552_a70d_42e6_8f2c_c934a52275d9);
+
+/// The UUID namespace deterministic chunk IDs of documents named `name`
+/// (usually the filename) are derived in.
+pub(crate) fn chunk_id_namespace(name: &str) -> Uuid {
+    Uuid::new_v5(&CHUNK_ID_NAMESPACE, name.as_bytes())
+}
+
+/// Replace the chunks' random IDs with UUIDv5s in `namespace` of their page
+/// number and text, so identical content gets the same ID on every split.
+/// Repeats of a text on one page are told apart by their order.
+pub(crate) fn assign_deterministic_ids(namespace: &Uuid, chunks: &mut [Chunk]) {
+    let mut seen: HashMap<(u32, String), usize> = HashMap::new();
+    for chunk in chunks {
+        let occurrence = seen.entry((chunk.page_num, chunk.text.clone())).or_default();
+        let name = format!("{}\n{}\n{}", chunk.page_num, occurrence, chunk.text);
+        *occurrence += 1;
+        chunk.id = Uuid::new_v5(namespace, name.as_bytes()).to_string();
+    }
+}
+
+/// Load the default token encoder if needed, reporting why it failed
+/// instead of panicking as splitting would.
+pub fn encoder_status() -> Result<(), String> {
+    sentence::load_bpe(TokenizerKind::default()).as_ref().map(|_| ()).map_err(Clone::clone)
+}
+
+/// Normalize display text for embedding: rejoin words hyphenated across a
+/// line break and collapse all whitespace runs to single spaces.
+pub fn embed_text(text: &str) -> String {
+    let mut joined = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find("-\n") {
+        let (before, after) = rest.split_at(pos);
+        let next = after[2..].trim_start_matches([' ', '\t', '\r']);
+        let hyphenated = before.chars().last().is_some_and(char::is_alphabetic)
+            && next.chars().next().is_some_and(char::is_lowercase);
+        joined.push_str(before);
+        if hyphenated {
+            rest = next;
+        } else {
+            joined.push_str("-\n");
+            rest = &after[2..];
+        }
+    }
+    joined.push_str(rest);
+    joined.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Byte offset of the last grapheme cluster boundary at or before `byte`, so
+/// a cut there never separates an emoji sequence or a letter from its
+/// combining marks. Returns 0 when `byte` falls inside the first cluster.
+pub fn grapheme_floor(text: &str, byte: usize) -> usize {
+    if byte >= text.len() {
+        return text.len();
+    }
+    text.grapheme_indices(true)
+        .map(|(i, _)| i)
+        .take_while(|&i| i <= byte)
+        .last()
+        .unwrap_or(0)
+}
+
+/// Cut `text` into pieces of at most `max_chars` characters, only between
+/// grapheme clusters. A single cluster longer than `max_chars` stays whole.
+pub fn hard_split(text: &str, max_chars: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut chars = 0;
+    for (i, grapheme) in text.grapheme_indices(true) {
+        let len = grapheme.chars().count();
+        if chars + len > max_chars && i > start {
+            pieces.push(&text[start..i]);
+            start = i;
+            chars = 0;
+        }
+        chars += len;
+    }
+    if start < text.len() {
+        pieces.push(&text[start..]);
+    }
+    pieces
 }
 
 pub trait TextSplitter: Send + Sync {
     fn split(&self, pages: &[crate::parser::Page]) -> Vec<Chunk>;
+
+    /// The settings this splitter runs with.
+    fn settings(&self) -> SplitterSettings;
+}
+
+/// Which splitting strategy a request uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SplitterKind {
+    /// Pack whole sentences into chunks ([`SentenceTextSplitter`]).
+    #[default]
+    Sentence,
+    /// Keep paragraphs together, splitting finer only where one is too long
+    /// ([`RecursiveCharacterTextSplitter`]).
+    Recursive,
+}
+
+impl SplitterKind {
+    /// Parse a request value; empty or unknown values split by sentence.
+    pub fn parse(value: &str) -> Self {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "recursive" => Self::Recursive,
+            _ => Self::Sentence,
+        }
+    }
 }
 
@@ -0,0 +1,118 @@
+// Custom transformations run on every chunk after splitting
+
+use std::fmt;
+use std::sync::Arc;
+
+use super::Chunk;
+
+/// A transformation applied to each chunk once the pipeline is done with it
+/// (split, ordered, fingerprinted, heading prepended) and before it is
+/// returned, e.g. to redact personal data or add metadata.
+pub trait ChunkProcessor: Send + Sync {
+    fn process(&self, chunk: Chunk) -> Chunk;
+
+    fn process_all(&self, chunks: &mut Vec<Chunk>) {
+        *chunks = std::mem::take(chunks).into_iter().map(|chunk| self.process(chunk)).collect();
+    }
+}
+
+/// Processors applied in order, each to the previous one's output.
+#[derive(Clone, Default)]
+pub struct ChunkProcessors(Arc<[Box<dyn ChunkProcessor>]>);
+
+impl ChunkProcessors {
+    pub fn new(processors: Vec<Box<dyn ChunkProcessor>>) -> Self {
+        Self(processors.into())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Run every processor on `chunk`. Its offsets are cleared once one
+    /// rewrites its own text, which then no longer lies at them.
+    pub fn process(&self, chunk: Chunk) -> Chunk {
+        self.0.iter().fold(chunk, |chunk, processor| {
+            let own_text = chunk.own_text().to_string();
+            let mut chunk = processor.process(chunk);
+            if chunk.own_text() != own_text {
+                chunk.start_offset = None;
+                chunk.end_offset = None;
+            }
+            chunk
+        })
+    }
+
+    pub fn process_all(&self, chunks: &mut Vec<Chunk>) {
+        if !self.is_empty() {
+            *chunks = std::mem::take(chunks).into_iter().map(|chunk| self.process(chunk)).collect();
+        }
+    }
+}
+
+impl fmt::Debug for ChunkProcessors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ChunkProcessors({})", self.0.len())
+    }
+}
+
+/// Strips leading and trailing whitespace from chunk text. The character
+/// count follows; the token count is left as split.
+pub struct TrimWhitespace;
+
+impl ChunkProcessor for TrimWhitespace {
+    fn process(&self, mut chunk: Chunk) -> Chunk {
+        let trimmed = chunk.text.trim();
+        if trimmed.len() != chunk.text.len() {
+            chunk.text = trimmed.to_string();
+            chunk.char_count = chunk.text.chars().count();
+        }
+        chunk
+    }
+}
+
+/// Uppercases chunk text, a processor whose rewrite tests can spot.
+#[cfg(test)]
+pub(crate) struct Uppercase;
+
+#[cfg(test)]
+impl ChunkProcessor for Uppercase {
+    fn process(&self, mut chunk: Chunk) -> Chunk {
+        chunk.text = chunk.text.to_uppercase();
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Page;
+    use crate::splitter::{SentenceTextSplitter, TextSplitter};
+
+    struct Suffix(&'static str);
+
+    impl ChunkProcessor for Suffix {
+        fn process(&self, mut chunk: Chunk) -> Chunk {
+            chunk.text.push_str(self.0);
+            chunk
+        }
+    }
+
+    #[test]
+    fn test_processors_run_in_order() {
+        let page = Page {
+            page_num: 1,
+            text: "  Cells divide.\n".to_string(),
+            ..Default::default()
+        };
+        let mut chunks = SentenceTextSplitter::new(500, 0).split(&[page]);
+        chunks[0].text = "  Cells divide.\n".to_string();
+
+        let processors: Vec<Box<dyn ChunkProcessor>> =
+            vec![Box::new(TrimWhitespace), Box::new(Suffix(" [1]")), Box::new(Suffix(" [2]"))];
+        ChunkProcessors::new(processors).process_all(&mut chunks);
+        assert_eq!(chunks[0].text, "Cells divide. [1] [2]");
+        assert_eq!(chunks[0].char_count, "Cells divide.".len());
+        assert_eq!((chunks[0].start_offset, chunks[0].end_offset), (None, None));
+    }
+}
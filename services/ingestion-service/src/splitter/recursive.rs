@@ -0,0 +1,230 @@
+// Separator-driven splitting: paragraphs first, finer separators only where
+// a paragraph is too long on its own.
+
+use uuid::Uuid;
+
+use super::sentence::{bpe, HeadingTracker, SplitterSettings, TokenizerKind};
+use super::{assign_deterministic_ids, chunk_id_namespace, embed_text, Chunk, ImageRef, OverlapAlign, TextSplitter};
+use crate::parser::Page;
+
+/// Separators tried in order: paragraphs, lines, sentences, words.
+const DEFAULT_SEPARATORS: &[&str] = &["\n\n", "\n", ". ", " "];
+
+/// Splits each page on the first separator, packing consecutive pieces into
+/// chunks of up to `max_tokens`. Only a piece that is too long by itself is
+/// split again on the next separator, so chunks end on paragraph breaks
+/// wherever the paragraphs allow it. A piece that no separator can shorten
+/// is kept whole. Separators stay attached to the piece before them, so a
+/// chunk's text is an exact, trimmed slice of the page.
+pub struct RecursiveCharacterTextSplitter {
+    max_tokens: usize,
+    separators: Vec<String>,
+    tokenizer: TokenizerKind,
+    embed_text: bool,
+    /// Namespace of content-derived chunk IDs, instead of random ones
+    id_namespace: Option<Uuid>,
+}
+
+impl RecursiveCharacterTextSplitter {
+    pub fn new(max_tokens: usize) -> Self {
+        Self {
+            max_tokens: max_tokens.max(1),
+            separators: DEFAULT_SEPARATORS.iter().map(|s| s.to_string()).collect(),
+            tokenizer: TokenizerKind::default(),
+            embed_text: false,
+            id_namespace: None,
+        }
+    }
+
+    /// Replace the separators, coarsest first. Empty separators are ignored.
+    pub fn with_separators(mut self, separators: Vec<String>) -> Self {
+        self.separators = separators.into_iter().filter(|s| !s.is_empty()).collect();
+        self
+    }
+
+    /// Count tokens, and so size chunks, with `kind`'s encoding.
+    pub fn with_tokenizer(mut self, kind: TokenizerKind) -> Self {
+        self.tokenizer = kind;
+        self
+    }
+
+    /// Emit an embeddings-ready `embed_text` (and its token count) per chunk.
+    pub fn with_embed_text(mut self, enabled: bool) -> Self {
+        self.embed_text = enabled;
+        self
+    }
+
+    /// Derive chunk IDs from `namespace`, the page number and the chunk text
+    /// instead of picking them at random.
+    pub fn with_deterministic_ids(mut self, namespace: &str) -> Self {
+        self.id_namespace = Some(chunk_id_namespace(namespace));
+        self
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        bpe(self.tokenizer).encode_with_special_tokens(text).len()
+    }
+
+    /// `text` cut into consecutive pieces of at most `max_tokens`, except
+    /// where `separators` run out first.
+    fn split_text<'a>(&self, text: &'a str, separators: &[String]) -> Vec<&'a str> {
+        let Some((separator, finer)) = separators.split_first() else {
+            return vec![text];
+        };
+        let mut pieces = Vec::new();
+        // Byte range of `text` packed so far
+        let (mut start, mut end) = (0, 0);
+        for part in text.split_inclusive(separator.as_str()) {
+            let part_start = end;
+            end += part.len();
+            if self.count_tokens(&text[start..end]) <= self.max_tokens {
+                continue;
+            }
+            if part_start > start {
+                pieces.push(&text[start..part_start]);
+            }
+            start = part_start;
+            if self.count_tokens(part) > self.max_tokens {
+                pieces.extend(self.split_text(part, finer));
+                start = end;
+            }
+        }
+        if start < end {
+            pieces.push(&text[start..end]);
+        }
+        pieces
+    }
+
+    fn make_chunk(&self, page: &Page, text: &str, heading_path: Option<Vec<String>>) -> Chunk {
+        let embed_text = self.embed_text.then(|| embed_text(text));
+        Chunk {
+            id: Uuid::new_v4().to_string(),
+            index: 0,
+            position: 0.0,
+            page_num: page.page_num,
+            page_span: None,
+            text: text.to_string(),
+            token_count: self.count_tokens(text),
+            char_count: text.chars().count(),
+            highlighted: false,
+            highlight_color: None,
+            language_spans: None,
+            language: page.language.clone(),
+            code_language: None,
+            kind: None,
+            heading_path,
+            original_text: None,
+            images: page.images.iter().map(ImageRef::from).collect(),
+            embed_token_count: embed_text.as_deref().map(|t| self.count_tokens(t)),
+            embed_text,
+            overlap_prefix_len: None,
+            overlap_suffix_len: None,
+            start_offset: None,
+            end_offset: None,
+            simhash: None,
+        }
+    }
+}
+
+impl TextSplitter for RecursiveCharacterTextSplitter {
+    fn split(&self, pages: &[Page]) -> Vec<Chunk> {
+        let mut chunks = Vec::new();
+        // Sections continue across page breaks
+        let mut outline = HeadingTracker::default();
+
+        for page in pages {
+            outline.start_page();
+            for piece in self.split_text(&page.text, &self.separators) {
+                let text = piece.trim();
+                if text.is_empty() {
+                    continue;
+                }
+                // A chunk belongs to the section open where it starts
+                let mut lines = text.lines();
+                if let Some(first) = lines.next() {
+                    outline.observe(page, first);
+                }
+                let path = outline.path();
+                lines.for_each(|line| outline.observe(page, line));
+                chunks.push(self.make_chunk(page, text, path));
+            }
+        }
+
+        let last = chunks.len().saturating_sub(1).max(1) as f32;
+        for (index, chunk) in chunks.iter_mut().enumerate() {
+            chunk.index = index;
+            chunk.position = index as f32 / last;
+        }
+        if let Some(namespace) = &self.id_namespace {
+            assign_deterministic_ids(namespace, &mut chunks);
+        }
+        chunks
+    }
+
+    fn settings(&self) -> SplitterSettings {
+        let flags = [("embed_text", self.embed_text), ("deterministic_ids", self.id_namespace.is_some())];
+        SplitterSettings {
+            splitter: "recursive".to_string(),
+            tokenizer: self.tokenizer.name().to_string(),
+            max_tokens: self.max_tokens,
+            overlap_tokens: 0,
+            overlap_align: OverlapAlign::Token,
+            transforms: flags
+                .iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| name.to_string())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::splitter::SentenceTextSplitter;
+
+    const LESSON: &str = "Cells are the basic unit of life. Every organism is made of them.\n\n\
+                          Mitosis splits one cell into two. The copies share the same genes. \
+                          It runs through four phases.\n\n\
+                          Meiosis makes sex cells. Each has half the chromosomes.";
+
+    fn page(text: &str) -> Page {
+        Page {
+            page_num: 1,
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_chunks_end_on_paragraph_breaks() {
+        let splitter = RecursiveCharacterTextSplitter::new(25);
+        let chunks = splitter.split(&[page(LESSON)]);
+        let texts: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "Cells are the basic unit of life. Every organism is made of them.",
+                "Mitosis splits one cell into two. The copies share the same genes. It runs through four phases.",
+                "Meiosis makes sex cells. Each has half the chromosomes.",
+            ]
+        );
+        assert!(chunks.iter().all(|c| c.token_count <= 25));
+
+        // Sentence packing runs paragraphs together to fill each chunk
+        let sentence = SentenceTextSplitter::new(25, 0).split(&[page(LESSON)]);
+        assert!(sentence.iter().any(|c| c.text.contains("of them.\n\nMitosis")));
+    }
+
+    #[test]
+    fn test_long_paragraph_split_on_finer_separators() {
+        let splitter = RecursiveCharacterTextSplitter::new(10);
+        let chunks = splitter.split(&[page(LESSON)]);
+        let texts: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(texts[0], "Cells are the basic unit of life.");
+        assert_eq!(texts[1], "Every organism is made of them.");
+        assert!(texts.contains(&"It runs through four phases."));
+        assert!(chunks.iter().all(|c| c.token_count <= 10));
+        assert_eq!(splitter.settings().splitter, "recursive");
+    }
+}
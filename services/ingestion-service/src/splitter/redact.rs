@@ -0,0 +1,154 @@
+// Redaction of personal data from chunk text
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use super::process::ChunkProcessor;
+use super::{sentence, Chunk, TokenizerKind};
+
+/// Patterns of personal data with the placeholder each match is replaced
+/// by, in the order they are applied.
+fn patterns() -> &'static [(Regex, &'static str)] {
+    static PATTERNS: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        let pattern = |re: &str| Regex::new(re).unwrap();
+        vec![
+            (pattern(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}"), "[EMAIL]"),
+            // US social security and UK national insurance numbers
+            (pattern(r"\b\d{3}-\d{2}-\d{4}\b"), "[NATIONAL_ID]"),
+            (pattern(r"\b[A-CEGHJ-PR-TW-Z]{2} ?\d{2} ?\d{2} ?\d{2} ?[A-D]\b"), "[NATIONAL_ID]"),
+            // "+49 30 1234 5678", "(555) 123-4567", "555.123.4567": grouped
+            // digits, so years, prices and page ranges are left alone
+            (
+                pattern(r"(?:\+\d{1,3}[ .-]?)?(?:\(\d{2,4}\)[ .-]?|\b\d{2,4}[ .-])\d{3,4}[ .-]\d{3,4}\b"),
+                "[PHONE]",
+            ),
+        ]
+    })
+}
+
+/// Replaces email addresses, phone numbers and national ID numbers with
+/// typed placeholders such as `[EMAIL]`, in the chunk text and in the
+/// `embed_text` and `original_text` derived from it. Token and character
+/// counts are recomputed, and offsets cleared once the chunk's own text
+/// changes.
+pub struct RedactionProcessor {
+    tokenizer: TokenizerKind,
+    redactions: AtomicUsize,
+}
+
+impl RedactionProcessor {
+    /// Redactor recounting tokens in `tokenizer`, the encoding chunks were
+    /// sized in.
+    pub fn new(tokenizer: TokenizerKind) -> Self {
+        Self {
+            tokenizer,
+            redactions: AtomicUsize::new(0),
+        }
+    }
+
+    /// Matches replaced in the chunk texts processed so far.
+    pub fn redactions(&self) -> usize {
+        self.redactions.load(Ordering::Relaxed)
+    }
+
+    /// `text` with personal data replaced, for text kept outside chunks such
+    /// as summaries. Not counted in [`redactions`](Self::redactions).
+    pub fn redact_text(text: &str) -> String {
+        Self::redact(text).0
+    }
+
+    /// `text` with every match replaced, and how many there were.
+    fn redact(text: &str) -> (String, usize) {
+        let mut text = text.to_string();
+        let mut count = 0;
+        for (re, placeholder) in patterns() {
+            let matches = re.find_iter(&text).count();
+            if matches > 0 {
+                text = re.replace_all(&text, *placeholder).into_owned();
+                count += matches;
+            }
+        }
+        (text, count)
+    }
+}
+
+impl ChunkProcessor for RedactionProcessor {
+    fn process(&self, mut chunk: Chunk) -> Chunk {
+        let count_tokens = |text: &str| sentence::bpe(self.tokenizer).encode_with_special_tokens(text).len();
+
+        let (text, count) = Self::redact(&chunk.text);
+        let mut rewritten = count > 0;
+        if count > 0 {
+            chunk.token_count = count_tokens(&text);
+            chunk.char_count = text.chars().count();
+            chunk.text = text;
+            self.redactions.fetch_add(count, Ordering::Relaxed);
+        }
+        if let Some(embed) = &mut chunk.embed_text {
+            let (text, count) = Self::redact(embed);
+            if count > 0 {
+                chunk.embed_token_count = Some(count_tokens(&text));
+                *embed = text;
+            }
+        }
+        if let Some(original) = &mut chunk.original_text {
+            // The offsets locate the text without its heading
+            let (text, count) = Self::redact(original);
+            rewritten = count > 0;
+            *original = text;
+        }
+        if rewritten {
+            chunk.start_offset = None;
+            chunk.end_offset = None;
+        }
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Page;
+    use crate::splitter::{SentenceTextSplitter, TextSplitter};
+
+    #[test]
+    fn test_redacts_contact_details_and_counts_them() {
+        let page = Page {
+            page_num: 1,
+            text: "Write to jane.doe@school.example.edu or call (555) 123-4567. \
+                   Her SSN is 123-45-6789, her office +49 30 1234 5678."
+                .to_string(),
+            ..Default::default()
+        };
+        let chunks = SentenceTextSplitter::new(500, 0).split(&[page]);
+        let redactor = RedactionProcessor::new(TokenizerKind::Cl100kBase);
+
+        let chunk = redactor.process(chunks[0].clone());
+        assert_eq!(
+            chunk.text,
+            "Write to [EMAIL] or call [PHONE]. Her SSN is [NATIONAL_ID], her office [PHONE]."
+        );
+        assert_eq!(redactor.redactions(), 4);
+        assert_eq!(chunk.char_count, chunk.text.chars().count());
+        assert!(chunk.token_count < chunks[0].token_count);
+        assert!(chunks[0].start_offset.is_some());
+        assert_eq!((chunk.start_offset, chunk.end_offset), (None, None));
+    }
+
+    #[test]
+    fn test_redacts_text_outside_chunks() {
+        let redactor = RedactionProcessor::new(TokenizerKind::Cl100kBase);
+        let summary = RedactionProcessor::redact_text("Mail jane@school.example.edu for grades.");
+        assert_eq!(summary, "Mail [EMAIL] for grades.");
+        assert_eq!(redactor.redactions(), 0);
+    }
+
+    #[test]
+    fn test_leaves_ordinary_numbers_alone() {
+        let text = "In 1998-2004 the lab grew 12.5% a year; see pages 10-12 and call 2024.";
+        assert_eq!(RedactionProcessor::redact(text), (text.to_string(), 0));
+    }
+}
@@ -1,98 +1,972 @@
-use super::{Chunk, TextSplitter};
-use crate::parser::Page;
-use tiktoken_rs::cl100k_base;
+use super::{assign_deterministic_ids, embed_text, grapheme_floor, hard_split, Chunk, ImageRef, TextSplitter};
+use crate::language;
+use crate::parser::{CodeBlock, Heading, Highlight, Page, TableBlock};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use tiktoken_rs::{cl100k_base, o200k_base, p50k_base, CoreBPE};
+use unicode_segmentation::UnicodeSegmentation;
 use uuid::Uuid;
 
+/// Shared encoder of `kind`; building one parses its whole BPE vocabulary,
+/// so each is built on first use only.
+pub(crate) fn bpe(kind: TokenizerKind) -> &'static CoreBPE {
+    load_bpe(kind)
+        .as_ref()
+        .unwrap_or_else(|e| panic!("{} encoder failed to load: {}", kind.name(), e))
+}
+
+/// The encoder of `kind`, or why it couldn't be built.
+pub(crate) fn load_bpe(kind: TokenizerKind) -> &'static Result<CoreBPE, String> {
+    static CL100K: OnceLock<Result<CoreBPE, String>> = OnceLock::new();
+    static O200K: OnceLock<Result<CoreBPE, String>> = OnceLock::new();
+    static P50K: OnceLock<Result<CoreBPE, String>> = OnceLock::new();
+    match kind {
+        TokenizerKind::Cl100kBase => CL100K.get_or_init(|| cl100k_base().map_err(|e| e.to_string())),
+        TokenizerKind::O200kBase => O200K.get_or_init(|| o200k_base().map_err(|e| e.to_string())),
+        TokenizerKind::P50kBase => P50K.get_or_init(|| p50k_base().map_err(|e| e.to_string())),
+    }
+}
+
+/// The tiktoken encoding token counts are measured in; pick the one the
+/// target embedding model uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenizerKind {
+    /// GPT-3.5, GPT-4 and the text-embedding-3 models.
+    #[default]
+    Cl100kBase,
+    /// GPT-4o and newer models.
+    O200kBase,
+    /// Codex and text-davinci models.
+    P50kBase,
+}
+
+impl TokenizerKind {
+    /// Parse a request value; empty or unknown values use `cl100k_base`.
+    pub fn parse(value: &str) -> Self {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "o200k_base" => Self::O200kBase,
+            "p50k_base" => Self::P50kBase,
+            _ => Self::Cl100kBase,
+        }
+    }
+
+    /// The encoding's tiktoken name.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Cl100kBase => "cl100k_base",
+            Self::O200kBase => "o200k_base",
+            Self::P50kBase => "p50k_base",
+        }
+    }
+}
+
+/// Abbreviations whose trailing period does not end a sentence.
+const DEFAULT_ABBREVIATIONS: &[&str] = &[
+    "dr", "mr", "mrs", "ms", "prof", "sr", "jr", "st", "vs", "etc", "e.g", "i.e", "cf", "fig", "approx",
+];
+
+/// Punctuation that ends a sentence: Latin, CJK full-width, Arabic and the
+/// ellipsis. A period only does when `period_ends_sentence` agrees.
+const DEFAULT_TERMINATORS: &[char] = &['.', '!', '?', '。', '！', '？', '؟', '…'];
+
+/// Where the overlap carried into the next chunk may start.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverlapAlign {
+    /// Exactly the last `overlap_tokens` tokens, even mid-sentence.
+    #[default]
+    Token,
+    /// The last whole sentences that fit within `overlap_tokens`.
+    Sentence,
+}
+
+impl OverlapAlign {
+    /// Parse a request value; empty or unknown values align to tokens.
+    pub fn parse(value: &str) -> Self {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "sentence" => Self::Sentence,
+            _ => Self::Token,
+        }
+    }
+}
+
+/// The settings a splitter runs with once defaults are applied, for
+/// reporting alongside its output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SplitterSettings {
+    pub splitter: String,
+    /// Encoding token counts are measured in.
+    pub tokenizer: String,
+    pub max_tokens: usize,
+    pub overlap_tokens: usize,
+    pub overlap_align: OverlapAlign,
+    /// Optional behaviours that are switched on, as `name` or `name=value`.
+    pub transforms: Vec<String>,
+}
+
 pub struct SentenceTextSplitter {
     max_tokens: usize,
     overlap_tokens: usize,
+    overlap_align: OverlapAlign,
+    tokenizer: TokenizerKind,
+    language_spans: bool,
+    boundary_tolerance: Option<usize>,
+    embed_text: bool,
+    drop_empty_chunks: bool,
+    cross_page_merge: bool,
+    max_chars: Option<usize>,
+    min_chunk_tokens: usize,
+    emit_overlap: bool,
+    /// Separator to join pages with when splitting them as one text
+    merge_pages: Option<String>,
+    /// Namespace of content-derived chunk IDs, instead of random ones
+    id_namespace: Option<Uuid>,
+    /// Lowercased, without the trailing period
+    abbreviations: Vec<String>,
+    terminators: Vec<char>,
+}
+
+/// A sentence, the original whitespace preceding it, and whether a paragraph
+/// or heading boundary follows it.
+struct Sentence {
+    text: String,
+    gap: String,
+    ends_paragraph: bool,
 }
 
 impl SentenceTextSplitter {
     pub fn new(max_tokens: usize, overlap_percent: usize) -> Self {
-        let overlap_tokens = (max_tokens * overlap_percent) / 100;
+        Self::with_overlap_tokens(max_tokens, (max_tokens * overlap_percent) / 100)
+    }
+
+    /// A splitter carrying a fixed `overlap_tokens` into each chunk, however
+    /// large `max_tokens` is. The overlap should be less than `max_tokens`.
+    pub fn with_overlap_tokens(max_tokens: usize, overlap_tokens: usize) -> Self {
         Self {
             max_tokens,
             overlap_tokens,
+            overlap_align: OverlapAlign::Token,
+            tokenizer: TokenizerKind::Cl100kBase,
+            language_spans: false,
+            boundary_tolerance: None,
+            embed_text: false,
+            drop_empty_chunks: true,
+            cross_page_merge: false,
+            max_chars: None,
+            min_chunk_tokens: 0,
+            emit_overlap: false,
+            merge_pages: None,
+            id_namespace: None,
+            abbreviations: DEFAULT_ABBREVIATIONS.iter().map(|a| a.to_string()).collect(),
+            terminators: DEFAULT_TERMINATORS.to_vec(),
+        }
+    }
+
+    /// Prefer ending a chunk at a paragraph or heading boundary when it is
+    /// already within `tolerance_percent` of `max_tokens` and the next
+    /// paragraph would not fit, instead of greedily breaking mid-paragraph.
+    pub fn with_boundary_lookahead(mut self, tolerance_percent: usize) -> Self {
+        self.boundary_tolerance = (tolerance_percent > 0)
+            .then(|| self.max_tokens * tolerance_percent.min(100) / 100);
+        self
+    }
+
+    /// Annotate multilingual chunks with per-language character ranges.
+    pub fn with_language_spans(mut self, enabled: bool) -> Self {
+        self.language_spans = enabled;
+        self
+    }
+
+    /// Emit an embeddings-ready `embed_text` (and its token count) per chunk.
+    pub fn with_embed_text(mut self, enabled: bool) -> Self {
+        self.embed_text = enabled;
+        self
+    }
+
+    /// Drop chunks whose text is empty or whitespace-only (on by default).
+    pub fn with_drop_empty_chunks(mut self, enabled: bool) -> Self {
+        self.drop_empty_chunks = enabled;
+        self
+    }
+
+    /// Carry a page's trailing text into the next page when it ends
+    /// mid-sentence, so a sentence cut by a page break stays in one chunk.
+    /// Such chunks record the pages they cover in `page_span`.
+    pub fn with_cross_page_merge(mut self, enabled: bool) -> Self {
+        self.cross_page_merge = enabled;
+        self
+    }
+
+    /// Cap chunks at `max_chars` characters (0 = no cap). Longer sentences
+    /// are hard-split, always between grapheme clusters.
+    pub fn with_max_chars(mut self, max_chars: usize) -> Self {
+        self.max_chars = (max_chars > 0).then_some(max_chars);
+        self
+    }
+
+    /// Merge the last chunk of a page into the one before it when it has
+    /// fewer than `min_tokens` tokens, instead of emitting a fragment such
+    /// as a lone "Appendix". The merged chunk may exceed `max_tokens` by
+    /// that much. 0 (the default) keeps every chunk.
+    pub fn with_min_chunk_tokens(mut self, min_tokens: usize) -> Self {
+        self.min_chunk_tokens = min_tokens;
+        self
+    }
+
+    /// Report on each chunk how many characters it shares with its
+    /// neighbours through overlap, so consumers can strip them.
+    pub fn with_overlap_lengths(mut self, enabled: bool) -> Self {
+        self.emit_overlap = enabled;
+        self
+    }
+
+    /// Round the overlap down to whole sentences instead of cutting it at
+    /// exactly `overlap_tokens` tokens.
+    pub fn with_overlap_align(mut self, align: OverlapAlign) -> Self {
+        self.overlap_align = align;
+        self
+    }
+
+    /// Count tokens, and so size chunks and overlap, with `kind`'s encoding.
+    pub fn with_tokenizer(mut self, kind: TokenizerKind) -> Self {
+        self.tokenizer = kind;
+        self
+    }
+
+    /// Derive chunk IDs from `namespace` (e.g. the filename), the page
+    /// number and the chunk text instead of picking them at random, so
+    /// re-ingesting a document yields the same IDs for unchanged chunks.
+    pub fn with_deterministic_ids(mut self, namespace: &str) -> Self {
+        self.id_namespace = Some(super::chunk_id_namespace(namespace));
+        self
+    }
+
+    /// Split the document as one continuous text, its pages joined with
+    /// `separator`, instead of page by page. Each chunk reports the page it
+    /// starts on and, when it runs onto later pages, its `page_span`.
+    pub fn with_merge_pages(mut self, enabled: bool, separator: &str) -> Self {
+        self.merge_pages = enabled.then(|| separator.to_string());
+        self
+    }
+
+    /// Replace the abbreviations after which a period does not end a
+    /// sentence, e.g. `"Dr"` or `"e.g."`. Matching ignores case.
+    pub fn with_abbreviations(mut self, abbreviations: Vec<String>) -> Self {
+        self.abbreviations = abbreviations
+            .into_iter()
+            .map(|a| a.trim().trim_end_matches('.').to_lowercase())
+            .filter(|a| !a.is_empty())
+            .collect();
+        self
+    }
+
+    /// Replace the punctuation that ends a sentence, e.g. to add `।` for
+    /// Hindi. Line breaks always end one.
+    pub fn with_terminators(mut self, terminators: &[char]) -> Self {
+        self.terminators = terminators.to_vec();
+        self
+    }
+
+    /// Whether the period at byte `i` of `text` closes a sentence. Periods
+    /// inside a token ("3.14", "U.S.A") and after a known abbreviation or
+    /// a run of initials ("U.S.A.") do not.
+    fn period_ends_sentence(&self, text: &str, i: usize) -> bool {
+        if text[i + 1..].chars().next().is_some_and(char::is_alphanumeric) {
+            return false;
+        }
+        let word = text[..i]
+            .rsplit(char::is_whitespace)
+            .next()
+            .unwrap_or_default()
+            .trim_start_matches(|c: char| !c.is_alphanumeric());
+        if word.is_empty() {
+            return true;
+        }
+        let initials = word.contains('.')
+            && word.split('.').all(|part| part.len() == 1 && part.chars().all(char::is_alphabetic));
+        !initials && !self.abbreviations.iter().any(|a| a.eq_ignore_ascii_case(word))
+    }
+
+    /// Whether `text` ends with a sentence terminator, ignoring closing
+    /// quotes and brackets.
+    fn ends_sentence(&self, text: &str) -> bool {
+        text.trim_end()
+            .trim_end_matches(['"', '\'', '”', '’', ')', ']', '」', '』'])
+            .ends_with(self.terminators.as_slice())
+    }
+
+    /// Whether appending `sentence` to `chunk` would break the character cap.
+    fn exceeds_max_chars(&self, chunk: &str, sentence: &Sentence) -> bool {
+        self.max_chars.is_some_and(|max| {
+            let gap = sentence.gap.chars().count().max(1);
+            chunk.chars().count() + gap + sentence.text.chars().count() > max
+        })
+    }
+
+    /// Hard-split sentences longer than `max_tokens` at token boundaries,
+    /// leaving room for the overlap carried in front of each piece, then
+    /// those longer than the character cap. Only the last piece keeps the
+    /// sentence's paragraph break.
+    fn cap_sentences(&self, sentences: Vec<Sentence>) -> Vec<Sentence> {
+        let budget = self.max_tokens.saturating_sub(self.overlap_tokens).max(1);
+        let mut capped = Vec::with_capacity(sentences.len());
+        for sentence in sentences {
+            let mut pieces = if self.count_tokens(&sentence.text) > self.max_tokens {
+                self.token_split(&sentence.text, budget)
+            } else {
+                vec![sentence.text.clone()]
+            };
+            if let Some(max) = self.max_chars {
+                pieces = pieces
+                    .iter()
+                    .flat_map(|piece| hard_split(piece, max))
+                    .map(str::to_string)
+                    .collect();
+            }
+            if pieces.len() == 1 {
+                capped.push(sentence);
+                continue;
+            }
+            let last = pieces.len() - 1;
+            for (i, piece) in pieces.into_iter().enumerate() {
+                let text = piece.trim();
+                if text.is_empty() {
+                    continue;
+                }
+                let gap = if i == 0 {
+                    sentence.gap.clone()
+                } else {
+                    piece[..piece.len() - piece.trim_start().len()].to_string()
+                };
+                capped.push(Sentence {
+                    text: text.to_string(),
+                    gap,
+                    ends_paragraph: i == last && sentence.ends_paragraph,
+                });
+            }
+        }
+        capped
+    }
+
+    /// Cut `text` into consecutive slices of at most `budget` tokens once
+    /// trimmed, which can tokenize a leading word differently. Each cut is a
+    /// token boundary snapped back to a grapheme cluster boundary, so tokens
+    /// inside a multi-byte character or cluster carry into the next slice; a
+    /// single cluster over the budget stays whole.
+    fn token_split(&self, text: &str, budget: usize) -> Vec<String> {
+        let bpe = bpe(self.tokenizer);
+        // Byte offset in `text` at which each token ends
+        let ends: Vec<usize> = bpe
+            ._decode_native_and_split(bpe.encode_with_special_tokens(text))
+            .scan(0, |end, bytes| {
+                *end += bytes.len();
+                Some(*end)
+            })
+            .collect();
+        let fits = |piece: &str| self.count_tokens(piece.trim()) <= budget;
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        let mut token = 0;
+        while start < text.len() {
+            let end = ends[token..]
+                .iter()
+                .take(budget)
+                .rev()
+                .map(|&end| grapheme_floor(text, end))
+                .find(|&end| end > start && fits(&text[start..end]))
+                .unwrap_or_else(|| start + text[start..].graphemes(true).next().map_or(0, str::len));
+            pieces.push(text[start..end].to_string());
+            start = end;
+            while ends[token] <= start && token + 1 < ends.len() {
+                token += 1;
+            }
         }
+        pieces
     }
 
     fn count_tokens(&self, text: &str) -> usize {
-        let bpe = cl100k_base().unwrap();
-        bpe.encode_with_special_tokens(text).len()
+        bpe(self.tokenizer).encode_with_special_tokens(text).len()
+    }
+
+    /// Split text into trimmed sentences.
+    pub fn split_into_sentences(&self, text: &str) -> Vec<String> {
+        self.split_sentences(text).into_iter().map(|s| s.text).collect()
     }
 
-    fn split_into_sentences(&self, text: &str) -> Vec<String> {
-        let mut sentences = Vec::new();
-        let mut current = String::new();
+    fn split_sentences(&self, text: &str) -> Vec<Sentence> {
+        let mut sentences: Vec<Sentence> = Vec::new();
+        let mut newlines = 0;
+        // Byte offsets of the end of the previous sentence and the start of the current one
+        let mut prev_end = 0;
+        let mut start = 0;
 
-        for c in text.chars() {
-            current.push(c);
-            if c == '.' || c == '!' || c == '?' || c == '\n' {
-                let trimmed = current.trim().to_string();
-                if !trimmed.is_empty() {
-                    sentences.push(trimmed);
+        let push = |sentences: &mut Vec<Sentence>, start: usize, end: usize, prev_end: &mut usize| {
+            let current = &text[start..end];
+            let trimmed = current.trim();
+            if trimmed.is_empty() {
+                return;
+            }
+            let text_start = start + (current.len() - current.trim_start().len());
+            // A Markdown heading starts a new section
+            if trimmed.starts_with('#') {
+                if let Some(last) = sentences.last_mut() {
+                    last.ends_paragraph = true;
                 }
-                current = String::new();
+            }
+            sentences.push(Sentence {
+                text: trimmed.to_string(),
+                gap: text[*prev_end..text_start].to_string(),
+                ends_paragraph: false,
+            });
+            *prev_end = text_start + trimmed.len();
+        };
+
+        for (i, c) in text.char_indices() {
+            if c == '\n' {
+                newlines += 1;
+                // A blank line closes the paragraph
+                if newlines >= 2 {
+                    if let Some(last) = sentences.last_mut() {
+                        last.ends_paragraph = true;
+                    }
+                }
+            } else if !c.is_whitespace() {
+                newlines = 0;
+            }
+            let ends = match c {
+                '\n' => true,
+                '.' => self.terminators.contains(&c) && self.period_ends_sentence(text, i),
+                _ => self.terminators.contains(&c),
+            };
+            if ends {
+                let end = i + c.len_utf8();
+                push(&mut sentences, start, end, &mut prev_end);
+                start = end;
             }
         }
+        push(&mut sentences, start, text.len(), &mut prev_end);
 
-        if !current.trim().is_empty() {
-            sentences.push(current.trim().to_string());
+        if let Some(last) = sentences.last_mut() {
+            last.ends_paragraph = true;
         }
 
         sentences
     }
-}
 
-impl TextSplitter for SentenceTextSplitter {
-    fn split(&self, pages: &[Page]) -> Vec<Chunk> {
-        let mut chunks = Vec::new();
+    /// Whether to close the chunk after sentence `i` to land on a paragraph
+    /// boundary rather than splitting the following paragraph.
+    fn should_break_at_boundary(&self, sentences: &[Sentence], tokens: &[usize], i: usize, current_tokens: usize) -> bool {
+        let Some(tolerance) = self.boundary_tolerance else {
+            return false;
+        };
+        if !sentences[i].ends_paragraph
+            || i + 1 >= sentences.len()
+            || current_tokens + tolerance < self.max_tokens
+        {
+            return false;
+        }
+        let next_paragraph: usize = sentences[i + 1..]
+            .iter()
+            .zip(&tokens[i + 1..])
+            .scan(false, |done, (sentence, &t)| {
+                if *done {
+                    return None;
+                }
+                *done = sentence.ends_paragraph;
+                Some(t)
+            })
+            .sum();
+        current_tokens + next_paragraph > self.max_tokens
+    }
+
+    /// The last `overlap_tokens` tokens of a finished chunk, to carry into
+    /// the next one.
+    fn overlap_tail(&self, chunk: &str) -> String {
+        if self.overlap_tokens == 0 {
+            return String::new();
+        }
+        if self.overlap_align == OverlapAlign::Sentence {
+            return self.sentence_overlap_tail(chunk).to_string();
+        }
+        let bpe = bpe(self.tokenizer);
+        let tokens = bpe.encode_with_special_tokens(chunk);
+        // A cut inside a multi-byte character doesn't decode; start one token later
+        let mut start = tokens.len().saturating_sub(self.overlap_tokens);
+        while start < tokens.len() {
+            if let Ok(tail) = bpe.decode(tokens[start..].to_vec()) {
+                return tail.trim_start().to_string();
+            }
+            start += 1;
+        }
+        String::new()
+    }
+
+    /// The longest run of whole sentences ending `chunk` that fits within
+    /// `overlap_tokens`; empty when even the last sentence is too long.
+    fn sentence_overlap_tail<'a>(&self, chunk: &'a str) -> &'a str {
+        let mut tail = "";
+        let mut end = chunk.len();
+        for sentence in self.split_sentences(chunk).iter().rev() {
+            let Some(start) = chunk[..end].rfind(sentence.text.as_str()) else {
+                break;
+            };
+            if self.count_tokens(&chunk[start..]) > self.overlap_tokens {
+                break;
+            }
+            tail = &chunk[start..];
+            end = start;
+        }
+        tail
+    }
 
+    /// Split `pages` joined into a single page, then give each chunk the
+    /// pages its text came from.
+    fn split_merged(&self, pages: &[Page], separator: &str) -> Vec<Chunk> {
+        let mut merged = Page {
+            page_num: pages[0].page_num,
+            ..Default::default()
+        };
+        // Byte and character offset of each page in the merged text
+        let mut starts = Vec::with_capacity(pages.len());
+        let mut char_starts = Vec::with_capacity(pages.len());
+        let mut chars = 0;
         for page in pages {
-            let sentences = self.split_into_sentences(&page.text);
-            let mut current_chunk = String::new();
-            let mut current_tokens = 0;
-
-            for sentence in sentences {
-                let sentence_tokens = self.count_tokens(&sentence);
-
-                if current_tokens + sentence_tokens > self.max_tokens && !current_chunk.is_empty() {
-                    chunks.push(Chunk {
-                        id: Uuid::new_v4().to_string(),
-                        page_num: page.page_num,
-                        text: current_chunk.trim().to_string(),
-                        token_count: current_tokens,
-                        char_count: current_chunk.len(),
+            if !starts.is_empty() {
+                merged.text.push_str(separator);
+                chars += separator.chars().count();
+            }
+            let offset = merged.text.len();
+            starts.push(offset);
+            char_starts.push(chars);
+            merged.text.push_str(&page.text);
+            chars += page.text.chars().count();
+            merged.code_blocks.extend(page.code_blocks.iter().map(|b| CodeBlock {
+                start: b.start + offset,
+                end: b.end + offset,
+                language: b.language.clone(),
+            }));
+            merged.tables.extend(page.tables.iter().map(|t| TableBlock {
+                start: t.start + offset,
+                end: t.end + offset,
+            }));
+            merged.headings.extend(page.headings.iter().cloned());
+            merged.highlights.extend(page.highlights.iter().cloned());
+        }
+        // A separator belongs to the page before it
+        let page_at = |byte: usize| starts.partition_point(|&start| start <= byte).saturating_sub(1);
+
+        let mut chunks = self.split_pages(std::slice::from_ref(&merged));
+        // Chunk text is a slice of the merged text, and chunks start in text order
+        let mut from = 0;
+        for chunk in &mut chunks {
+            let start = merged.text[from..].find(chunk.text.as_str()).map_or(from, |i| from + i);
+            let (first, last) = (page_at(start), page_at(start + chunk.text.len().max(1) - 1));
+            chunk.page_num = pages[first].page_num;
+            chunk.page_span = (first != last).then(|| (pages[first].page_num, pages[last].page_num));
+            chunk.language = pages[first].language.clone();
+            chunk.images = pages[first..=last].iter().flat_map(|p| &p.images).map(ImageRef::from).collect();
+            // Offsets into the merged text, made relative to the one page holding the chunk
+            let offset = |offset: Option<usize>| offset.filter(|_| first == last).map(|o| o - char_starts[first]);
+            chunk.start_offset = offset(chunk.start_offset);
+            chunk.end_offset = offset(chunk.end_offset);
+            from = start + merged.text[start..].chars().next().map_or(0, char::len_utf8);
+        }
+        chunks
+    }
+
+    /// Split `pages` one after another, carrying text across page breaks
+    /// when asked to.
+    fn split_pages(&self, pages: &[Page]) -> Vec<Chunk> {
+        let mut chunks = Vec::new();
+        // Sections continue across page breaks
+        let mut outline = HeadingTracker::default();
+
+        // Unfinished sentence text carried over from an earlier page, the page
+        // it started on, its tokens and its overlap length
+        let mut carry: Option<(u32, String, usize, usize)> = None;
+
+        for (page_index, page) in pages.iter().enumerate() {
+            let first_on_page = chunks.len();
+            // `current_overlap` counts the characters of `current_chunk` repeated from the last chunk
+            let (carried_from, mut current_chunk, mut current_tokens, mut current_overlap) = match carry.take() {
+                Some((from, text, tokens, overlap)) => (Some(from), text, tokens, overlap),
+                None => (None, String::new(), 0, 0),
+            };
+            let mut chunk_path = outline.path();
+            outline.start_page();
+            // Source whitespace before the first sentence new since the last chunk
+            let mut joining_gap: Option<String> = None;
+
+            for segment in page_segments(page) {
+                let text = match segment {
+                    Segment::Prose(text) => text,
+                    Segment::Code(block_text, _) | Segment::Table(block_text) => {
+                        // Code blocks and tables are atomic: close the running chunk and emit the block whole
+                        if !current_chunk.trim().is_empty() {
+                            let path = chunk_path.clone();
+                            chunks.push(self.make_prose_chunk(page, &current_chunk, current_tokens, path, current_overlap));
+                        }
+                        current_chunk.clear();
+                        current_tokens = 0;
+                        current_overlap = 0;
+                        joining_gap = None;
+
+                        let tokens = self.count_tokens(block_text);
+                        let mut chunk = self.make_chunk(page, block_text, tokens, outline.path());
+                        match segment {
+                            Segment::Code(_, block) => {
+                                chunk.code_language = block.language.clone();
+                                chunk.kind = Some("code".to_string());
+                            }
+                            _ => chunk.kind = Some("table".to_string()),
+                        }
+                        chunks.push(chunk);
+                        continue;
+                    }
+                };
+
+                let sentences = self.cap_sentences(self.split_sentences(text));
+                let tokens: Vec<usize> = sentences.iter().map(|s| self.count_tokens(&s.text)).collect();
+
+                for (i, sentence) in sentences.iter().enumerate() {
+                    let sentence_tokens = tokens[i];
+                    outline.observe(page, &sentence.text);
+
+                    let over_cap = current_tokens + sentence_tokens > self.max_tokens
+                        || self.exceeds_max_chars(&current_chunk, sentence);
+                    if over_cap && !current_chunk.is_empty() {
+                        let path = chunk_path.clone();
+                        chunks.push(self.make_prose_chunk(page, &current_chunk, current_tokens, path, current_overlap));
+
+                        // Keep overlap, unless it would break either cap
+                        current_chunk = self.overlap_tail(&current_chunk);
+                        current_tokens = self.count_tokens(&current_chunk);
+                        if current_tokens + sentence_tokens > self.max_tokens
+                            || self.exceeds_max_chars(&current_chunk, sentence)
+                        {
+                            current_chunk.clear();
+                            current_tokens = 0;
+                        }
+                        current_overlap = current_chunk.chars().count();
+                        chunk_path = outline.path();
+                        joining_gap = None;
+                    }
+
+                    joining_gap.get_or_insert_with(|| match sentence.gap.as_str() {
+                        "" => " ".to_string(),
+                        gap => gap.to_string(),
                     });
+                    if !current_chunk.is_empty() {
+                        // Keep the source formatting between sentences
+                        if sentence.gap.is_empty() {
+                            current_chunk.push(' ');
+                        } else {
+                            current_chunk.push_str(&sentence.gap);
+                        }
+                    } else {
+                        chunk_path = outline.path();
+                    }
+                    current_chunk.push_str(&sentence.text);
+                    current_tokens += sentence_tokens;
 
-                    // Keep overlap
-                    let words: Vec<&str> = current_chunk.split_whitespace().collect();
-                    let overlap_word_count = words.len() * self.overlap_tokens / self.max_tokens;
-                    current_chunk = words[words.len().saturating_sub(overlap_word_count)..].join(" ");
-                    current_tokens = self.count_tokens(&current_chunk);
+                    if self.should_break_at_boundary(&sentences, &tokens, i, current_tokens) {
+                        let path = chunk_path.clone();
+                        chunks.push(self.make_prose_chunk(page, &current_chunk, current_tokens, path, current_overlap));
+                        current_chunk = self.overlap_tail(&current_chunk);
+                        current_tokens = self.count_tokens(&current_chunk);
+                        current_overlap = current_chunk.chars().count();
+                        chunk_path = outline.path();
+                        joining_gap = None;
+                    }
                 }
+            }
 
-                if !current_chunk.is_empty() {
-                    current_chunk.push(' ');
+            let carry_over = self.cross_page_merge
+                && page_index + 1 < pages.len()
+                && !current_chunk.trim().is_empty()
+                && !self.ends_sentence(&current_chunk);
+            if carry_over {
+                // Nothing emitted on this page means the carried text started earlier
+                let from = if chunks.len() == first_on_page {
+                    carried_from.unwrap_or(page.page_num)
+                } else {
+                    page.page_num
+                };
+                carry = Some((from, current_chunk, current_tokens, current_overlap));
+            } else if !current_chunk.trim().is_empty() {
+                let too_small = current_tokens < self.min_chunk_tokens;
+                let previous = chunks[first_on_page..]
+                    .last_mut()
+                    .filter(|c| too_small && c.kind.is_none());
+                match previous {
+                    Some(previous) => {
+                        // The remainder starts with the overlap it repeats from the previous chunk
+                        let rest: String = current_chunk.chars().skip(current_overlap).collect();
+                        let gap = joining_gap.as_deref().unwrap_or(" ");
+                        let text = format!("{}{}{}", previous.text, gap, rest.trim());
+                        let overlap = previous.overlap_prefix_len.unwrap_or(0);
+                        let path = previous.heading_path.clone();
+                        *previous = self.make_prose_chunk(page, &text, self.count_tokens(&text), path, overlap);
+                    }
+                    None => chunks.push(self.make_prose_chunk(
+                        page,
+                        &current_chunk,
+                        current_tokens,
+                        chunk_path,
+                        current_overlap,
+                    )),
                 }
-                current_chunk.push_str(&sentence);
-                current_tokens += sentence_tokens;
             }
 
-            if !current_chunk.trim().is_empty() {
-                chunks.push(Chunk {
-                    id: Uuid::new_v4().to_string(),
-                    page_num: page.page_num,
-                    text: current_chunk.trim().to_string(),
-                    token_count: current_tokens,
-                    char_count: current_chunk.len(),
-                });
+            locate_chunks(&page.text, &mut chunks[first_on_page..]);
+
+            // The first chunk emitted on a page holds any carried text
+            if let (Some(from), Some(chunk)) = (carried_from, chunks.get_mut(first_on_page)) {
+                chunk.page_num = from;
+                chunk.page_span = Some((from, page.page_num));
+            }
+        }
+
+        if self.drop_empty_chunks {
+            chunks.retain(|c| !c.text.trim().is_empty());
+        }
+        if self.emit_overlap {
+            // The overlap carried into a chunk is a verbatim tail of the one before
+            for i in 0..chunks.len() {
+                let shared = match chunks.get(i + 1).and_then(|next| next.overlap_prefix_len) {
+                    Some(len) => {
+                        let prefix: String = chunks[i + 1].text.chars().take(len).collect();
+                        if chunks[i].text.ends_with(&prefix) { len } else { 0 }
+                    }
+                    None => 0,
+                };
+                chunks[i].overlap_suffix_len = Some(shared);
+                chunks[i].overlap_prefix_len.get_or_insert(0);
             }
         }
+        let last = chunks.len().saturating_sub(1).max(1) as f32;
+        for (index, chunk) in chunks.iter_mut().enumerate() {
+            chunk.index = index;
+            chunk.position = index as f32 / last;
+        }
+        chunks
+    }
+
+    fn make_chunk(&self, page: &Page, text: &str, token_count: usize, heading_path: Option<Vec<String>>) -> Chunk {
+        let text = text.trim();
+        let highlight = find_highlight(page, text);
+        let embed_text = self.embed_text.then(|| embed_text(text));
+        Chunk {
+            id: Uuid::new_v4().to_string(),
+            index: 0,
+            position: 0.0,
+            page_num: page.page_num,
+            page_span: None,
+            text: text.to_string(),
+            token_count,
+            char_count: text.chars().count(),
+            highlighted: highlight.is_some(),
+            highlight_color: highlight.and_then(|h| h.color.clone()),
+            language_spans: self
+                .language_spans
+                .then(|| language::language_spans(text))
+                .filter(|spans| spans.len() > 1),
+            language: page.language.clone(),
+            code_language: None,
+            kind: None,
+            heading_path,
+            original_text: None,
+            images: page.images.iter().map(ImageRef::from).collect(),
+            embed_token_count: embed_text.as_deref().map(|t| self.count_tokens(t)),
+            embed_text,
+            overlap_prefix_len: None,
+            overlap_suffix_len: None,
+            start_offset: None,
+            end_offset: None,
+            simhash: None,
+        }
+    }
+
+    /// A chunk of running prose whose first `overlap` characters were
+    /// carried over from the previous chunk.
+    fn make_prose_chunk(
+        &self,
+        page: &Page,
+        text: &str,
+        token_count: usize,
+        heading_path: Option<Vec<String>>,
+        overlap: usize,
+    ) -> Chunk {
+        let mut chunk = self.make_chunk(page, text, token_count, heading_path);
+        chunk.overlap_prefix_len = self.emit_overlap.then_some(overlap);
+        chunk
+    }
+}
+
+/// Give each of `chunks`, cut from `text` in order, the character range of
+/// `text` it holds. A chunk opening with overlap starts before the previous
+/// one ends, so the search resumes just after the previous chunk's start.
+fn locate_chunks(text: &str, chunks: &mut [Chunk]) {
+    let mut from = 0;
+    // Characters before the byte offset, counted on from the last chunk found
+    let (mut counted_bytes, mut start_offset) = (0, 0);
+    for chunk in chunks {
+        let Some(start) = text[from..].find(chunk.text.as_str()).map(|i| from + i) else {
+            continue;
+        };
+        start_offset += text[counted_bytes..start].chars().count();
+        counted_bytes = start;
+        chunk.start_offset = Some(start_offset);
+        chunk.end_offset = Some(start_offset + chunk.char_count);
+        from = start + text[start..].chars().next().map_or(0, char::len_utf8);
+    }
+}
+
+/// Find a highlight on `page` that overlaps `chunk_text`: either the highlighted
+/// passage lies within the chunk, or the chunk is part of a longer highlight.
+fn find_highlight<'a>(page: &'a Page, chunk_text: &str) -> Option<&'a Highlight> {
+    let chunk = collapse_whitespace(chunk_text);
+    if chunk.is_empty() {
+        return None;
+    }
+    page.highlights.iter().find(|h| {
+        let passage = collapse_whitespace(&h.text);
+        !passage.is_empty() && (chunk.contains(&passage) || passage.contains(&chunk))
+    })
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Tracks the enclosing headings while walking a document's sentences.
+#[derive(Default)]
+pub(super) struct HeadingTracker {
+    path: Vec<Heading>,
+    /// Index of the next unseen heading on the current page.
+    next: usize,
+}
+
+impl HeadingTracker {
+    pub(super) fn start_page(&mut self) {
+        self.next = 0;
+    }
+
+    /// Enter the page's next heading if `sentence` begins with it.
+    pub(super) fn observe(&mut self, page: &Page, sentence: &str) {
+        let Some(heading) = page.headings.get(self.next) else {
+            return;
+        };
+        let title = collapse_whitespace(&heading.text);
+        if title.is_empty() || !collapse_whitespace(sentence).starts_with(&title) {
+            return;
+        }
+        while self.path.last().is_some_and(|h| h.level >= heading.level) {
+            self.path.pop();
+        }
+        self.path.push(heading.clone());
+        self.next += 1;
+    }
 
+    pub(super) fn path(&self) -> Option<Vec<String>> {
+        (!self.path.is_empty()).then(|| self.path.iter().map(|h| h.text.clone()).collect())
+    }
+}
+
+/// A run of page text that is either sentence-split prose or an atomic code
+/// block or table.
+enum Segment<'a> {
+    Prose(&'a str),
+    Code(&'a str, &'a CodeBlock),
+    Table(&'a str),
+}
+
+/// Cut a page's text around its code blocks and tables, ignoring malformed
+/// or overlapping block ranges.
+fn page_segments(page: &Page) -> Vec<Segment<'_>> {
+    let text = page.text.as_str();
+    // Each block's range, and the code block it is unless it's a table
+    let mut blocks: Vec<(usize, usize, Option<&CodeBlock>)> = page
+        .code_blocks
+        .iter()
+        .map(|b| (b.start, b.end, Some(b)))
+        .chain(page.tables.iter().map(|t| (t.start, t.end, None)))
+        .filter(|&(start, end, _)| {
+            start < end && end <= text.len() && text.is_char_boundary(start) && text.is_char_boundary(end)
+        })
+        .collect();
+    blocks.sort_by_key(|&(start, ..)| start);
+
+    let mut segments = Vec::new();
+    let mut pos = 0;
+    for (start, end, code) in blocks {
+        if start < pos {
+            continue;
+        }
+        if start > pos {
+            segments.push(Segment::Prose(&text[pos..start]));
+        }
+        segments.push(match code {
+            Some(block) => Segment::Code(&text[start..end], block),
+            None => Segment::Table(&text[start..end]),
+        });
+        pos = end;
+    }
+    if pos < text.len() {
+        segments.push(Segment::Prose(&text[pos..]));
+    }
+    segments
+}
+
+impl TextSplitter for SentenceTextSplitter {
+    fn split(&self, pages: &[Page]) -> Vec<Chunk> {
+        let mut chunks = match (&self.merge_pages, pages.len() > 1) {
+            (Some(separator), true) => self.split_merged(pages, separator),
+            _ => self.split_pages(pages),
+        };
+        if let Some(namespace) = &self.id_namespace {
+            assign_deterministic_ids(namespace, &mut chunks);
+        }
         chunks
     }
+
+    fn settings(&self) -> SplitterSettings {
+        let flags = [
+            ("language_spans", self.language_spans),
+            ("embed_text", self.embed_text),
+            ("drop_empty_chunks", self.drop_empty_chunks),
+            ("cross_page_merge", self.cross_page_merge),
+            ("emit_overlap", self.emit_overlap),
+            ("merge_pages", self.merge_pages.is_some()),
+            ("deterministic_ids", self.id_namespace.is_some()),
+        ];
+        let mut transforms: Vec<String> = flags
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| name.to_string())
+            .collect();
+        if let Some(tolerance) = self.boundary_tolerance {
+            transforms.push(format!("boundary_tolerance_tokens={}", tolerance));
+        }
+        if let Some(max_chars) = self.max_chars {
+            transforms.push(format!("max_chars={}", max_chars));
+        }
+        if self.min_chunk_tokens > 0 {
+            transforms.push(format!("min_chunk_tokens={}", self.min_chunk_tokens));
+        }
+        if self.terminators != DEFAULT_TERMINATORS {
+            transforms.push(format!("terminators={}", self.terminators.iter().collect::<String>()));
+        }
+        SplitterSettings {
+            splitter: "sentence".to_string(),
+            tokenizer: self.tokenizer.name().to_string(),
+            max_tokens: self.max_tokens,
+            overlap_tokens: self.overlap_tokens,
+            overlap_align: self.overlap_align,
+            transforms,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -112,6 +986,46 @@ mod tests {
         assert_eq!(sentences[3], "Yes.");
     }
 
+    #[test]
+    fn test_splits_cjk_and_arabic_sentences() {
+        let splitter = SentenceTextSplitter::new(100, 0);
+        let text = "细胞通过有丝分裂进行分裂。这个过程有四个阶段！你知道吗？";
+        assert_eq!(
+            splitter.split_into_sentences(text),
+            vec!["细胞通过有丝分裂进行分裂。", "这个过程有四个阶段！", "你知道吗？"]
+        );
+        assert_eq!(splitter.split_into_sentences("هل تنقسم الخلية؟ نعم.").len(), 2);
+
+        let latin_only = SentenceTextSplitter::new(100, 0).with_terminators(&['.', '!', '?']);
+        assert_eq!(latin_only.split_into_sentences(text).len(), 1);
+        assert!(latin_only.settings().transforms.contains(&"terminators=.!?".to_string()));
+    }
+
+    #[test]
+    fn test_abbreviations_and_decimals_do_not_end_sentences() {
+        let splitter = SentenceTextSplitter::new(100, 0);
+
+        let sentences = splitter.split_into_sentences("Dr. Smith paid $3.50. The bill was settled.");
+        assert_eq!(sentences, vec!["Dr. Smith paid $3.50.", "The bill was settled."]);
+
+        let sentences = splitter.split_into_sentences("Pi is about 3.14. It is irrational.");
+        assert_eq!(sentences, vec!["Pi is about 3.14.", "It is irrational."]);
+
+        let sentences = splitter.split_into_sentences("She moved to the U.S.A. in 1990. Then she stayed.");
+        assert_eq!(sentences, vec!["She moved to the U.S.A. in 1990.", "Then she stayed."]);
+
+        let sentences = splitter.split_into_sentences("Use a solvent, e.g. ethanol. Stir well.");
+        assert_eq!(sentences, vec!["Use a solvent, e.g. ethanol.", "Stir well."]);
+    }
+
+    #[test]
+    fn test_custom_abbreviations_replace_defaults() {
+        let splitter = SentenceTextSplitter::new(100, 0).with_abbreviations(vec!["Art.".to_string()]);
+
+        let sentences = splitter.split_into_sentences("See art. 5 of the treaty. Dr. Jones agreed.");
+        assert_eq!(sentences, vec!["See art. 5 of the treaty.", "Dr.", "Jones agreed."]);
+    }
+
     #[test]
     fn test_split_respects_max_tokens() {
         let splitter = SentenceTextSplitter::new(10, 0); 
@@ -120,13 +1034,697 @@ mod tests {
         let page = Page {
             page_num: 1,
             text: text.to_string(),
-            images: vec![],
+            ..Default::default()
         };
         
         let chunks = splitter.split(&[page]);
-        // Even if the logic puts it in one chunk if a single sentence is too long (depending on implementation),
-        // let's just ensure it returns something valid.
-        assert!(!chunks.is_empty());
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.token_count <= 10));
     }
-}
 
+    #[test]
+    fn test_run_on_sentence_cut_at_token_boundaries_with_overlap() {
+        let words = ["cells", "divide", "and", "grow", "while", "membranes", "stretch", "around", "them"];
+        let text = (0..200).map(|i| words[i % words.len()]).collect::<Vec<_>>().join(" ") + ".";
+        let page = Page {
+            page_num: 1,
+            text: text.clone(),
+            ..Default::default()
+        };
+
+        let chunks = SentenceTextSplitter::new(10, 0).split(std::slice::from_ref(&page));
+        assert!(chunks.len() >= 20);
+        for chunk in &chunks {
+            assert!(chunk.token_count <= 10, "{} tokens: {}", chunk.token_count, chunk.text);
+            assert_eq!(chunk.token_count, SentenceTextSplitter::new(10, 0).count_tokens(&chunk.text));
+        }
+        let rejoined = chunks.iter().map(|c| c.text.as_str()).collect::<Vec<_>>().join(" ");
+        assert_eq!(rejoined, text);
+
+        // Pieces carry the previous overlap wherever it still fits the cap
+        let splitter = SentenceTextSplitter::new(10, 30);
+        let chunks = splitter.split(&[page]);
+        assert!(chunks.iter().all(|c| c.token_count <= 10));
+        let overlapping = chunks
+            .windows(2)
+            .filter(|pair| pair[1].text.starts_with(&splitter.overlap_tail(&pair[0].text)))
+            .count();
+        assert!(overlapping * 2 > chunks.len(), "{overlapping} of {} chunks overlap", chunks.len());
+    }
+
+    #[test]
+    fn test_highlighted_passage_flags_covering_chunk() {
+        use crate::parser::{fixtures::PdfBuilder, LocalPdfParser, Parser};
+        use std::io::Cursor;
+
+        let pdf = PdfBuilder::new()
+            .page(&[
+                "Cells need energy to survive.",
+                "Photosynthesis converts light into chemical energy.",
+                "Roots absorb water from the soil.",
+            ])
+            .highlight(1, 1, [0.0, 1.0, 0.0])
+            .build();
+        let pages = LocalPdfParser::new().parse(Cursor::new(pdf)).unwrap();
+
+        let splitter = SentenceTextSplitter::new(12, 0);
+        let chunks = splitter.split(&pages);
+
+        let flagged: Vec<&Chunk> = chunks.iter().filter(|c| c.highlighted).collect();
+        assert_eq!(flagged.len(), 1);
+        assert!(flagged[0].text.contains("Photosynthesis converts light"));
+        assert_eq!(flagged[0].highlight_color.as_deref(), Some("#00ff00"));
+        assert!(chunks.iter().any(|c| !c.highlighted));
+    }
+
+    #[test]
+    fn test_language_spans_only_for_multilingual_chunks() {
+        let splitter = SentenceTextSplitter::new(100, 0).with_language_spans(true);
+        let pages = [
+            Page {
+                page_num: 1,
+                text: "I really enjoy reading long books in the evening, aber morgens lese ich lieber die Zeitung.".to_string(),
+                ..Default::default()
+            },
+            Page {
+                page_num: 2,
+                text: "This page is written entirely in one language.".to_string(),
+                ..Default::default()
+            },
+        ];
+
+        let chunks = splitter.split(&pages);
+
+        let spans = chunks[0].language_spans.as_ref().unwrap();
+        assert_eq!(spans.iter().map(|s| s.lang.as_str()).collect::<Vec<_>>(), vec!["en", "de"]);
+        assert!(chunks[1].language_spans.is_none());
+    }
+
+    #[test]
+    fn test_markdown_code_block_is_one_tagged_chunk() {
+        use crate::parser::{MarkdownParser, Parser};
+        use std::io::Cursor;
+
+        let md = "# Loops\n\nPython loops are simple. They read well.\n\n\
+            ```python\nfor i in range(3):\n    print(i)\nprint(\"done.\")\n```\n\n\
+            That is all. Thanks for reading.\n";
+        let pages = MarkdownParser::new().parse(Cursor::new(md)).unwrap();
+
+        let splitter = SentenceTextSplitter::new(8, 0);
+        let chunks = splitter.split(&pages);
+
+        let code: Vec<&Chunk> = chunks.iter().filter(|c| c.code_language.is_some()).collect();
+        assert_eq!(code.len(), 1);
+        assert_eq!(code[0].code_language.as_deref(), Some("python"));
+        assert_eq!(
+            code[0].text,
+            "```python\nfor i in range(3):\n    print(i)\nprint(\"done.\")\n```"
+        );
+        // No other chunk carries a piece of the block
+        assert!(chunks
+            .iter()
+            .filter(|c| c.code_language.is_none())
+            .all(|c| !c.text.contains("range(3)") && !c.text.contains("```")));
+    }
+
+    #[test]
+    fn test_boundary_lookahead_breaks_at_paragraph() {
+        let text = "Plants need light to grow. Leaves capture sunlight through chlorophyll. \
+            The energy is stored as sugar.\n\n\
+            Roots anchor the plant. They absorb water. Minerals come from soil.";
+        let page = Page {
+            page_num: 1,
+            text: text.to_string(),
+            ..Default::default()
+        };
+
+        let greedy = SentenceTextSplitter::new(30, 0).split(std::slice::from_ref(&page));
+        assert!(greedy[0].text.ends_with("Roots anchor the plant."));
+
+        let aligned = SentenceTextSplitter::new(30, 0)
+            .with_boundary_lookahead(30)
+            .split(&[page]);
+        assert_eq!(aligned.len(), 2);
+        assert!(aligned[0].text.ends_with("stored as sugar."));
+        assert!(aligned[0].token_count < 30);
+        assert!(aligned[1].text.starts_with("Roots anchor the plant."));
+    }
+
+    #[test]
+    fn test_min_chunk_tokens_merges_trailing_fragment() {
+        let page = Page {
+            page_num: 1,
+            text: "Cells divide by mitosis into two daughter cells. Thanks.".to_string(),
+            ..Default::default()
+        };
+
+        let first = "Cells divide by mitosis into two daughter cells.";
+        let max_tokens = SentenceTextSplitter::new(1, 0).count_tokens(first);
+        let chunks = SentenceTextSplitter::new(max_tokens, 0).split(std::slice::from_ref(&page));
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1].text, "Thanks.");
+        assert_eq!(chunks[1].token_count, 2);
+
+        let splitter = SentenceTextSplitter::new(max_tokens, 0).with_min_chunk_tokens(3);
+        let chunks = splitter.split(std::slice::from_ref(&page));
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].text, page.text);
+        assert_eq!(chunks[0].token_count, splitter.count_tokens(&page.text));
+        assert!(splitter.settings().transforms.contains(&"min_chunk_tokens=3".to_string()));
+
+        // The merged chunk keeps the source whitespace between the two
+        let paragraphs = Page {
+            text: format!("{}\n\nThanks.", first),
+            ..page.clone()
+        };
+        let chunks = splitter.split(std::slice::from_ref(&paragraphs));
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].text, paragraphs.text);
+
+        // Nor is a fragment merged into a code block, tagged or not
+        let fence = "```\nmake test\n```";
+        let code = Page {
+            text: format!("{}\n\nThanks.", fence),
+            code_blocks: vec![CodeBlock {
+                start: 0,
+                end: fence.len(),
+                language: None,
+            }],
+            ..page.clone()
+        };
+        let chunks = splitter.split(&[code]);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].text, fence);
+        assert_eq!(chunks[0].kind.as_deref(), Some("code"));
+        assert_eq!(chunks[1].text, "Thanks.");
+
+        // A page's only chunk is kept however short
+        let short = Page {
+            text: "Thanks.".to_string(),
+            ..page
+        };
+        assert_eq!(splitter.split(&[short])[0].text, "Thanks.");
+    }
+
+    #[test]
+    fn test_char_count_counts_characters() {
+        let page = Page {
+            page_num: 1,
+            text: "Die Zellmembran schützt das Zellinnere. Élève, café, naïve.".to_string(),
+            ..Default::default()
+        };
+
+        let chunks = SentenceTextSplitter::new(500, 0).split(std::slice::from_ref(&page));
+
+        assert_eq!(chunks[0].text, page.text);
+        assert_eq!(chunks[0].char_count, 59);
+        assert_eq!(chunks[0].text.len(), 64);
+    }
+
+    #[test]
+    fn test_tokenizer_kinds_count_differently() {
+        let page = Page {
+            page_num: 1,
+            text: "Клетки делятся путём митоза.".to_string(),
+            ..Default::default()
+        };
+        let tokens = |kind: TokenizerKind| {
+            let splitter = SentenceTextSplitter::new(500, 0).with_tokenizer(kind);
+            assert_eq!(splitter.settings().tokenizer, kind.name());
+            splitter.split(std::slice::from_ref(&page))[0].token_count
+        };
+
+        assert_eq!(tokens(TokenizerKind::Cl100kBase), 17);
+        assert_eq!(tokens(TokenizerKind::O200kBase), 10);
+        assert_eq!(tokens(TokenizerKind::P50kBase), 30);
+        assert_eq!(TokenizerKind::parse(" O200K_base "), TokenizerKind::O200kBase);
+        assert_eq!(TokenizerKind::parse("gpt2"), TokenizerKind::Cl100kBase);
+    }
+
+    #[test]
+    fn test_deterministic_ids_are_stable() {
+        let pages: Vec<Page> = ["Cells divide by mitosis. See the figure.", "See the figure. See the figure."]
+            .iter()
+            .enumerate()
+            .map(|(i, text)| Page {
+                page_num: i as u32 + 1,
+                text: text.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let ids = |splitter: SentenceTextSplitter| -> Vec<String> {
+            splitter.split(&pages).into_iter().map(|chunk| chunk.id).collect()
+        };
+        let stable = || SentenceTextSplitter::new(5, 0).with_deterministic_ids("cells.pdf");
+
+        let first = ids(stable());
+        assert_eq!(first.len(), 4);
+        assert_eq!(first, ids(stable()));
+        // Repeated text gets an ID of its own, on another page or the same one
+        let unique: std::collections::HashSet<&String> = first.iter().collect();
+        assert_eq!(unique.len(), first.len());
+
+        assert_ne!(first, ids(SentenceTextSplitter::new(5, 0).with_deterministic_ids("other.pdf")));
+        assert_ne!(ids(SentenceTextSplitter::new(5, 0)), ids(SentenceTextSplitter::new(5, 0)));
+        assert!(stable().settings().transforms.contains(&"deterministic_ids".to_string()));
+    }
+
+    #[test]
+    fn test_embed_text_normalizes_whitespace() {
+        let text = "Data   ingestion turns raw\ndocuments into   infor-\nmation.\n\n\tIt is well-known.";
+        let page = Page {
+            page_num: 1,
+            text: text.to_string(),
+            ..Default::default()
+        };
+
+        let chunks = SentenceTextSplitter::new(500, 0).with_embed_text(true).split(std::slice::from_ref(&page));
+        assert_eq!(chunks.len(), 1);
+        let chunk = &chunks[0];
+        assert_eq!(chunk.text, text);
+        assert_eq!(
+            chunk.embed_text.as_deref(),
+            Some("Data ingestion turns raw documents into information. It is well-known.")
+        );
+        let splitter = SentenceTextSplitter::new(500, 0);
+        assert_eq!(
+            chunk.embed_token_count,
+            Some(splitter.count_tokens(chunk.embed_text.as_deref().unwrap()))
+        );
+
+        let plain = splitter.split(&[page]);
+        assert_eq!(plain[0].embed_text, None);
+        assert_eq!(plain[0].embed_token_count, None);
+    }
+
+    #[test]
+    fn test_drop_empty_chunks() {
+        // An HTML `<pre>` holding only whitespace yields an empty code block
+        let text = "Intro paragraph.\n    \nClosing paragraph.";
+        let blank = text.find("    ").unwrap();
+        let page = Page {
+            page_num: 1,
+            text: text.to_string(),
+            code_blocks: vec![CodeBlock {
+                start: blank,
+                end: blank + 4,
+                language: None,
+            }],
+            ..Default::default()
+        };
+
+        let chunks = SentenceTextSplitter::new(500, 0).split(std::slice::from_ref(&page));
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks.iter().all(|c| !c.text.trim().is_empty()));
+        assert_eq!(chunks.iter().map(|c| c.index).collect::<Vec<_>>(), vec![0, 1]);
+
+        let kept = SentenceTextSplitter::new(500, 0)
+            .with_drop_empty_chunks(false)
+            .split(&[page]);
+        assert_eq!(kept.len(), 3);
+        assert!(kept[1].text.is_empty());
+        assert_eq!(kept.iter().map(|c| c.index).collect::<Vec<_>>(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_heading_path_follows_outline() {
+        let heading = |level, text: &str| Heading {
+            level,
+            text: text.to_string(),
+        };
+        let page = Page {
+            page_num: 1,
+            text: "Cell Biology\nMembranes\nEvery cell is enclosed by a membrane.\nLipid Bilayers\n\
+                   The membrane is made of two layers of lipids.\nOrganelles\n\
+                   Organelles carry out specialised tasks inside the cell."
+                .to_string(),
+            headings: vec![
+                heading(1, "Cell Biology"),
+                heading(2, "Membranes"),
+                heading(3, "Lipid Bilayers"),
+                heading(2, "Organelles"),
+            ],
+            ..Default::default()
+        };
+
+        let chunks = SentenceTextSplitter::new(8, 0).split(&[page]);
+        let path_of = |start: &str| {
+            chunks
+                .iter()
+                .find(|c| c.text.starts_with(start))
+                .and_then(|c| c.heading_path.clone())
+                .unwrap()
+        };
+
+        assert_eq!(path_of("The membrane"), vec!["Cell Biology", "Membranes", "Lipid Bilayers"]);
+        assert_eq!(path_of("Organelles carry"), vec!["Cell Biology", "Organelles"]);
+
+        let plain = Page {
+            page_num: 1,
+            text: "No headings here.".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(SentenceTextSplitter::new(8, 0).split(&[plain])[0].heading_path, None);
+    }
+
+    #[test]
+    fn test_prepended_heading_recounted_with_original_kept() {
+        let heading = |level, text: &str| Heading {
+            level,
+            text: text.to_string(),
+        };
+        let page = Page {
+            page_num: 1,
+            text: "Chapter 1\nBackground\nEvery cell is enclosed by a membrane.".to_string(),
+            headings: vec![heading(1, "Chapter 1"), heading(2, "Background")],
+            ..Default::default()
+        };
+        let splitter = SentenceTextSplitter::new(8, 0).with_embed_text(true);
+        let mut chunks = splitter.split(&[page]);
+        let plain = chunks.clone();
+        super::super::prepend_headings(&mut chunks, TokenizerKind::default());
+
+        let chunk = chunks.iter().find(|c| c.text.ends_with("membrane.")).unwrap();
+        let body = plain.iter().find(|c| c.text.starts_with("Every cell")).unwrap();
+        assert_eq!(chunk.text, format!("Chapter 1 > Background:\n{}", body.text));
+        assert_eq!(chunk.original_text.as_deref(), Some(body.text.as_str()));
+        assert_eq!(chunk.token_count, splitter.count_tokens(&chunk.text));
+        assert!(chunk.token_count > body.token_count);
+        assert_eq!(chunk.char_count, chunk.text.chars().count());
+        assert!(chunk.embed_text.as_deref().unwrap().starts_with("Chapter 1 > Background: "));
+        assert_eq!(chunk.embed_token_count, Some(splitter.count_tokens(chunk.embed_text.as_deref().unwrap())));
+
+        // Chunks outside any section stay as they are
+        let mut untitled = SentenceTextSplitter::new(500, 0).split(&[Page {
+            page_num: 1,
+            text: "No headings here.".to_string(),
+            ..Default::default()
+        }]);
+        super::super::prepend_headings(&mut untitled, TokenizerKind::default());
+        assert_eq!((untitled[0].text.as_str(), untitled[0].original_text.as_ref()), ("No headings here.", None));
+    }
+
+    #[test]
+    fn test_chunk_positions_span_document() {
+        let text = (1..=12)
+            .map(|i| format!("Sentence number {} adds a little more text to the document.", i))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let page = Page {
+            page_num: 1,
+            text,
+            ..Default::default()
+        };
+
+        let chunks = SentenceTextSplitter::new(30, 0).split(&[page]);
+        assert!(chunks.len() > 2);
+        assert_eq!(chunks[0].position, 0.0);
+        assert!((chunks.last().unwrap().position - 1.0).abs() < f32::EPSILON);
+        assert!(chunks.windows(2).all(|w| w[0].position < w[1].position));
+    }
+
+    #[test]
+    fn test_cross_page_merge_joins_sentence_split_by_page_break() {
+        let page = |num: u32, text: &str| Page {
+            page_num: num,
+            text: text.to_string(),
+            ..Default::default()
+        };
+        let pages = [
+            page(1, "Samples were collected daily. The experiment was"),
+            page(2, "repeated three times with"),
+            page(3, "fresh reagents. Results follow."),
+            page(4, "Conclusions are drawn last."),
+        ];
+
+        let chunks = SentenceTextSplitter::new(500, 0).split(&pages);
+        assert_eq!(chunks.len(), 4);
+        assert!(chunks.iter().all(|c| c.page_span.is_none()));
+
+        let chunks = SentenceTextSplitter::new(500, 0).with_cross_page_merge(true).split(&pages);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(
+            chunks[0].text,
+            "Samples were collected daily. The experiment was repeated three times with fresh reagents. Results follow."
+        );
+        assert_eq!(chunks[0].page_num, 1);
+        assert_eq!(chunks[0].page_span, Some((1, 3)));
+        // Page 3 ends a sentence, so page 4 starts fresh
+        assert_eq!(chunks[1].text, "Conclusions are drawn last.");
+        assert_eq!(chunks[1].page_num, 4);
+        assert_eq!(chunks[1].page_span, None);
+    }
+
+    #[test]
+    fn test_cross_page_merge_keeps_last_page_text() {
+        let pages = [
+            Page {
+                page_num: 1,
+                text: "A complete sentence.".to_string(),
+                ..Default::default()
+            },
+            Page {
+                page_num: 2,
+                text: "A trailing fragment without an end".to_string(),
+                ..Default::default()
+            },
+        ];
+
+        let chunks = SentenceTextSplitter::new(500, 0).with_cross_page_merge(true).split(&pages);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1].text, "A trailing fragment without an end");
+        assert_eq!(chunks[1].page_span, None);
+    }
+
+    #[test]
+    fn test_merge_pages_keeps_sentence_across_page_break() {
+        let pages = [
+            Page {
+                page_num: 1,
+                text: "Cells need energy. Mitochondria convert".to_string(),
+                ..Default::default()
+            },
+            Page {
+                page_num: 2,
+                text: "glucose into ATP. Ribosomes build proteins.".to_string(),
+                ..Default::default()
+            },
+        ];
+
+        let chunks = SentenceTextSplitter::new(10, 0).with_merge_pages(true, " ").split(&pages);
+        let texts: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec!["Cells need energy.", "Mitochondria convert glucose into ATP.", "Ribosomes build proteins."]
+        );
+        let pages: Vec<(u32, Option<(u32, u32)>)> = chunks.iter().map(|c| (c.page_num, c.page_span)).collect();
+        assert_eq!(pages, vec![(1, None), (1, Some((1, 2))), (2, None)]);
+    }
+
+    #[test]
+    fn test_repeated_large_splits_reuse_encoder() {
+        let text = (1..=2000)
+            .map(|i| format!("Sentence number {} adds a little more text to the document.", i))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let page = Page {
+            page_num: 1,
+            text,
+            ..Default::default()
+        };
+        let splitter = SentenceTextSplitter::new(200, 10);
+
+        let start = std::time::Instant::now();
+        for _ in 0..5 {
+            assert!(!splitter.split(std::slice::from_ref(&page)).is_empty());
+        }
+        // Rebuilding the encoder per sentence took minutes for this input
+        assert!(start.elapsed() < std::time::Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_token_split_keeps_every_cluster() {
+        let family = "\u{1F469}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466}";
+        let text = format!("Zellen {} teilen sich {}", family.repeat(5), "\u{4E2D}\u{6587}".repeat(7));
+        let splitter = SentenceTextSplitter::new(4, 0);
+
+        let pieces = splitter.token_split(&text, 4);
+        assert!(pieces.len() > 3);
+        assert_eq!(pieces.concat(), text);
+        let boundaries: Vec<usize> = text.grapheme_indices(true).map(|(i, _)| i).collect();
+        let mut offset = 0;
+        for piece in &pieces {
+            assert!(boundaries.contains(&offset), "cut inside a cluster before {:?}", piece);
+            offset += piece.len();
+        }
+    }
+
+    #[test]
+    fn test_max_chars_never_splits_grapheme_clusters() {
+        // A family emoji is 7 code points; "é" here is "e" plus a combining accent
+        let family = "\u{1F469}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466}";
+        let accented = "e\u{301}";
+        let text = format!("{}{}{}", family.repeat(4), accented.repeat(9), family.repeat(2));
+        let page = Page {
+            page_num: 1,
+            text: text.clone(),
+            ..Default::default()
+        };
+
+        let chunks = SentenceTextSplitter::new(500, 20).with_max_chars(10).split(&[page]);
+        assert!(chunks.len() > 3);
+        for chunk in &chunks {
+            assert!(chunk.text.chars().count() <= 10, "{:?}", chunk.text);
+            assert!(chunk.text.graphemes(true).all(|g| g == family || g == accented), "{:?}", chunk.text);
+        }
+        assert_eq!(chunks.iter().map(|c| c.text.as_str()).collect::<String>(), text);
+
+        // Cuts fall only on cluster boundaries
+        let boundaries: Vec<usize> = text.grapheme_indices(true).map(|(i, _)| i).collect();
+        let mut offset = 0;
+        for piece in hard_split(&text, 3) {
+            assert!(boundaries.contains(&offset));
+            offset += piece.len();
+        }
+        assert_eq!(offset, text.len());
+    }
+
+    #[test]
+    fn test_overlap_lengths_match_shared_text() {
+        let text = (1..=15)
+            .map(|i| format!("Sentence number {} adds a little more text to the document.", i))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let page = Page {
+            page_num: 1,
+            text,
+            ..Default::default()
+        };
+
+        let chunks = SentenceTextSplitter::new(40, 30).with_overlap_lengths(true).split(&[page]);
+        assert!(chunks.len() > 2);
+        assert_eq!(chunks[0].overlap_prefix_len, Some(0));
+        assert_eq!(chunks.last().unwrap().overlap_suffix_len, Some(0));
+
+        let head = |text: &str, len: usize| text.chars().take(len).collect::<String>();
+        let tail = |text: &str, len: usize| text.chars().skip(text.chars().count() - len).collect::<String>();
+        for pair in chunks.windows(2) {
+            let prefix = head(&pair[1].text, pair[1].overlap_prefix_len.unwrap());
+            let suffix = tail(&pair[0].text, pair[0].overlap_suffix_len.unwrap());
+            assert!(!prefix.is_empty());
+            assert_eq!(prefix, suffix);
+        }
+    }
+
+    #[test]
+    fn test_sentence_aligned_overlap() {
+        let text = (1..=30)
+            .map(|i| match i % 3 {
+                0 => format!("Cells divide {} times.", i),
+                1 => format!("Sentence {} adds a little more text to the growing document.", i),
+                _ => "Mitosis has phases.".to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let page = Page {
+            page_num: 1,
+            text,
+            ..Default::default()
+        };
+        let splitter = SentenceTextSplitter::new(60, 25)
+            .with_overlap_lengths(true)
+            .with_overlap_align(OverlapAlign::Sentence);
+
+        let chunks = splitter.split(&[page]);
+        assert!(chunks.len() > 3);
+        for pair in chunks.windows(2) {
+            let overlap: String = pair[1].text.chars().take(pair[1].overlap_prefix_len.unwrap()).collect();
+            assert!(!overlap.is_empty());
+            assert!(splitter.count_tokens(&overlap) <= 15, "{:?}", overlap);
+            // The overlap starts a sentence and holds only whole sentences
+            let before = &pair[0].text[..pair[0].text.len() - overlap.len()];
+            assert!(before.trim_end().ends_with('.'), "{:?}", overlap);
+            assert!(overlap.starts_with(char::is_uppercase) && overlap.ends_with('.'));
+        }
+    }
+
+    #[test]
+    fn test_overlap_is_token_accurate() {
+        let text = (1..=40)
+            .map(|i| format!("Sentence number {} adds a little more text to the document.", i))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let page = Page {
+            page_num: 1,
+            text,
+            ..Default::default()
+        };
+        let splitter = SentenceTextSplitter::new(60, 20).with_overlap_lengths(true);
+
+        let chunks = splitter.split(&[page]);
+        assert!(chunks.len() > 3);
+        for chunk in &chunks[1..] {
+            let overlap: String = chunk.text.chars().take(chunk.overlap_prefix_len.unwrap()).collect();
+            let tokens = splitter.count_tokens(&overlap);
+            assert!((11..=13).contains(&tokens), "{} tokens in {:?}", tokens, overlap);
+        }
+    }
+
+    #[test]
+    fn test_fixed_overlap_whatever_the_chunk_size() {
+        let text = (1..=80)
+            .map(|i| format!("Sentence number {} adds a little more text to the document.", i))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let page = Page {
+            page_num: 1,
+            text,
+            ..Default::default()
+        };
+        for max_tokens in [60, 200] {
+            let splitter = SentenceTextSplitter::with_overlap_tokens(max_tokens, 12).with_overlap_lengths(true);
+            assert_eq!(splitter.settings().overlap_tokens, 12);
+            let chunks = splitter.split(std::slice::from_ref(&page));
+            assert!(chunks.len() > 3);
+            for chunk in &chunks[1..] {
+                let overlap: String = chunk.text.chars().take(chunk.overlap_prefix_len.unwrap()).collect();
+                let tokens = splitter.count_tokens(&overlap);
+                assert!((11..=13).contains(&tokens), "{} tokens in {:?}", tokens, overlap);
+            }
+        }
+    }
+
+    #[test]
+    fn test_offsets_slice_chunk_text_from_page() {
+        let pages: Vec<Page> = [1, 2]
+            .into_iter()
+            .map(|page_num| Page {
+                page_num,
+                text: (1..=12)
+                    .map(|i| format!("Über Zelle {} steht hier ein weiterer kurzer Satz.", i))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                ..Default::default()
+            })
+            .collect();
+        let slice = |page: &Page, chunk: &Chunk| -> String {
+            let (start, end) = (chunk.start_offset.unwrap(), chunk.end_offset.unwrap());
+            page.text.chars().skip(start).take(end - start).collect()
+        };
+
+        let chunks = SentenceTextSplitter::new(40, 30).split(&pages);
+        assert!(chunks.iter().filter(|c| c.page_num == 2).count() > 2);
+        for chunk in &chunks {
+            assert_eq!(slice(&pages[chunk.page_num as usize - 1], chunk), chunk.text);
+        }
+        // Overlapping chunks point back into the text of the one before
+        assert!(chunks[1].start_offset.unwrap() < chunks[0].end_offset.unwrap());
+
+        // Offsets stay relative to the page when pages are merged
+        let merged = SentenceTextSplitter::new(40, 30).with_merge_pages(true, "\n\n").split(&pages);
+        for chunk in merged.iter().filter(|c| c.page_span.is_none()) {
+            assert_eq!(slice(&pages[chunk.page_num as usize - 1], chunk), chunk.text);
+        }
+        assert!(merged.iter().filter(|c| c.page_span.is_some()).all(|c| c.start_offset.is_none()));
+    }
+}
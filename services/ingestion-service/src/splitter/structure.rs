@@ -0,0 +1,158 @@
+// Hierarchical document structure built from the heading outline, with each
+// section pointing at the chunks it contains.
+
+use serde::{Deserialize, Serialize};
+
+use super::Chunk;
+use crate::parser::{Heading, Page};
+
+/// A section of the document. The root stands for the whole document and
+/// holds the chunks that precede the first heading.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StructureNode {
+    /// Heading text; empty for the root.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub title: String,
+    /// Heading level; 0 for the root.
+    pub level: u8,
+    /// Page the heading is on; absent for the root.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_num: Option<u32>,
+    /// Document-order `index` of each chunk directly under this heading.
+    pub chunk_indices: Vec<usize>,
+    /// Subsections, in reading order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<StructureNode>,
+}
+
+impl StructureNode {
+    /// Levels below the root; 0 for a document without headings.
+    pub fn depth(&self) -> usize {
+        self.children.iter().map(|c| c.depth() + 1).max().unwrap_or(0)
+    }
+}
+
+/// Nest the headings of `pages` into a tree and attach each chunk to the
+/// section named by its `heading_path`. A document without headings yields
+/// a single root node holding every chunk.
+pub fn structure_tree(pages: &[Page], chunks: &[Chunk]) -> StructureNode {
+    // Every heading in reading order with the titles of its enclosing headings
+    let mut headings: Vec<(Vec<String>, &Heading, u32)> = Vec::new();
+    let mut open: Vec<&Heading> = Vec::new();
+    for page in pages {
+        for heading in &page.headings {
+            while open.last().is_some_and(|h| h.level >= heading.level) {
+                open.pop();
+            }
+            open.push(heading);
+            headings.push((open.iter().map(|h| h.text.clone()).collect(), heading, page.page_num));
+        }
+    }
+
+    // Chunks may be reordered; walk them in reading order so a repeated
+    // heading path resolves to the section the chunk actually follows
+    let mut ordered: Vec<&Chunk> = chunks.iter().collect();
+    ordered.sort_by_key(|c| c.index);
+    let mut assigned: Vec<Vec<usize>> = vec![Vec::new(); headings.len()];
+    let mut root = StructureNode::default();
+    let mut cursor = 0;
+    for chunk in ordered {
+        let section = chunk.heading_path.as_ref().and_then(|path| {
+            headings[cursor..].iter().position(|(p, _, _)| p == path).map(|i| cursor + i)
+        });
+        match section {
+            Some(i) => {
+                cursor = i;
+                assigned[i].push(chunk.index);
+            }
+            None => root.chunk_indices.push(chunk.index),
+        }
+    }
+
+    for ((path, heading, page_num), chunk_indices) in headings.into_iter().zip(assigned) {
+        // The enclosing headings are the latest node at each depth
+        let mut parent = &mut root;
+        for _ in 1..path.len() {
+            parent = parent.children.last_mut().expect("enclosing heading precedes its subsections");
+        }
+        parent.children.push(StructureNode {
+            title: heading.text.clone(),
+            level: heading.level,
+            page_num: Some(page_num),
+            chunk_indices,
+            children: Vec::new(),
+        });
+    }
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::splitter::{SentenceTextSplitter, TextSplitter};
+
+    #[test]
+    fn test_tree_nests_headings_and_references_chunks() {
+        let heading = |level, text: &str| Heading {
+            level,
+            text: text.to_string(),
+        };
+        let page = Page {
+            page_num: 1,
+            text: "Cell Biology\nMembranes\nEvery cell is enclosed by a membrane.\nLipid Bilayers\n\
+                   The membrane is made of two layers of lipids.\nOrganelles\n\
+                   Organelles carry out specialised tasks inside the cell."
+                .to_string(),
+            headings: vec![
+                heading(1, "Cell Biology"),
+                heading(2, "Membranes"),
+                heading(3, "Lipid Bilayers"),
+                heading(2, "Organelles"),
+            ],
+            ..Default::default()
+        };
+        let chunks = SentenceTextSplitter::new(8, 0).split(std::slice::from_ref(&page));
+
+        let tree = structure_tree(&[page], &chunks);
+        assert_eq!(tree.depth(), 3);
+        assert_eq!(tree.children.len(), 1);
+        let biology = &tree.children[0];
+        assert_eq!(biology.title, "Cell Biology");
+        let titles: Vec<&str> = biology.children.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["Membranes", "Organelles"]);
+        assert_eq!(biology.children[0].children[0].title, "Lipid Bilayers");
+
+        // Every chunk is referenced once, by the section its path names
+        let mut referenced = Vec::new();
+        let mut stack = vec![(&tree, Vec::<String>::new())];
+        while let Some((node, path)) = stack.pop() {
+            for &index in &node.chunk_indices {
+                let expected = (!path.is_empty()).then(|| path.clone());
+                assert_eq!(chunks[index].heading_path, expected);
+                referenced.push(index);
+            }
+            for child in &node.children {
+                let mut child_path = path.clone();
+                child_path.push(child.title.clone());
+                stack.push((child, child_path));
+            }
+        }
+        referenced.sort_unstable();
+        assert_eq!(referenced, (0..chunks.len()).collect::<Vec<_>>());
+        assert!(!biology.children[0].children[0].chunk_indices.is_empty());
+    }
+
+    #[test]
+    fn test_unstructured_document_is_single_node() {
+        let page = Page {
+            page_num: 1,
+            text: "No headings here. Just two sentences.".to_string(),
+            ..Default::default()
+        };
+        let chunks = SentenceTextSplitter::new(5, 0).split(std::slice::from_ref(&page));
+
+        let tree = structure_tree(&[page], &chunks);
+        assert_eq!(tree.depth(), 0);
+        assert_eq!(tree.chunk_indices, (0..chunks.len()).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,132 @@
+// Summarizer backed by an OpenAI-compatible `/chat/completions` endpoint
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use super::{truncate_to_tokens, SummarizeError, Summarizer, SummarizerConfig};
+
+const INSTRUCTIONS: &str =
+    "Summarize the following document in one paragraph for a teacher deciding whether to use it in class.";
+
+#[derive(Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: [ChatMessage<'a>; 2],
+}
+
+#[derive(Serialize)]
+struct ChatMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatReply,
+}
+
+#[derive(Deserialize)]
+struct ChatReply {
+    content: Option<String>,
+}
+
+/// Sends the document text, cut to the configured token budget, as a single
+/// chat completion request.
+pub struct HttpSummarizer {
+    config: SummarizerConfig,
+    client: Client,
+}
+
+impl HttpSummarizer {
+    pub fn new(config: SummarizerConfig) -> Self {
+        Self {
+            config,
+            client: Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Summarizer for HttpSummarizer {
+    async fn summarize(&self, text: &str) -> Result<String, SummarizeError> {
+        let input = truncate_to_tokens(text, self.config.max_input_tokens);
+        let url = format!("{}/chat/completions", self.config.base_url.trim_end_matches('/'));
+        let mut request = self.client.post(&url).json(&ChatRequest {
+            model: &self.config.model,
+            messages: [
+                ChatMessage {
+                    role: "system",
+                    content: INSTRUCTIONS,
+                },
+                ChatMessage {
+                    role: "user",
+                    content: &input,
+                },
+            ],
+        });
+        if let Some(key) = &self.config.api_key {
+            request = request.bearer_auth(key);
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(SummarizeError::Provider(format!("status {}", response.status())));
+        }
+        response
+            .json::<ChatResponse>()
+            .await?
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .map(|summary| summary.trim().to_string())
+            .filter(|summary| !summary.is_empty())
+            .ok_or_else(|| SummarizeError::Provider("empty completion".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State, routing::post, Json, Router};
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+
+    async fn completions(State(requests): State<Arc<Mutex<Vec<Value>>>>, Json(body): Json<Value>) -> Json<Value> {
+        requests.lock().unwrap().push(body);
+        Json(json!({ "choices": [{ "message": { "role": "assistant", "content": " A short summary. " } }] }))
+    }
+
+    #[tokio::test]
+    async fn test_sends_truncated_text_and_reads_reply() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let app = Router::new()
+            .route("/v1/chat/completions", post(completions))
+            .with_state(requests.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let summarizer = HttpSummarizer::new(SummarizerConfig {
+            base_url: format!("http://{}/v1/", addr),
+            model: "test-chat".to_string(),
+            api_key: Some("secret".to_string()),
+            max_input_tokens: 20,
+        });
+        let text = "Photosynthesis turns light into chemical energy. ".repeat(50);
+        assert_eq!(summarizer.summarize(&text).await.unwrap(), "A short summary.");
+
+        let body = requests.lock().unwrap()[0].clone();
+        assert_eq!(body["model"], "test-chat");
+        assert_eq!(body["messages"][0]["role"], "system");
+        let sent = body["messages"][1]["content"].as_str().unwrap();
+        assert!(text.starts_with(sent));
+        assert!(sent.len() < 200);
+    }
+}
@@ -0,0 +1,65 @@
+mod http;
+
+pub use http::HttpSummarizer;
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+use crate::splitter::{bpe, TokenizerKind};
+
+#[derive(Error, Debug)]
+pub enum SummarizeError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Summary provider error: {0}")]
+    Provider(String),
+}
+
+/// Writes a short summary of a document's text.
+#[async_trait]
+pub trait Summarizer: Send + Sync {
+    async fn summarize(&self, text: &str) -> Result<String, SummarizeError>;
+}
+
+/// Connection settings for an OpenAI-compatible chat completions API.
+#[derive(Debug, Clone)]
+pub struct SummarizerConfig {
+    /// API root, e.g. `https://api.openai.com/v1`; requests go to `{base_url}/chat/completions`.
+    pub base_url: String,
+    pub model: String,
+    pub api_key: Option<String>,
+    /// Document text beyond this many tokens is cut before sending.
+    pub max_input_tokens: usize,
+}
+
+/// The leading `max_tokens` tokens of `text`.
+pub(crate) fn truncate_to_tokens(text: &str, max_tokens: usize) -> String {
+    let bpe = bpe(TokenizerKind::Cl100kBase);
+    let tokens = bpe.encode_with_special_tokens(text);
+    if tokens.len() <= max_tokens {
+        return text.to_string();
+    }
+    // A cut inside a multi-byte character doesn't decode; end one token earlier
+    let mut end = max_tokens;
+    while end > 0 {
+        if let Ok(head) = bpe.decode(tokens[..end].to_vec()) {
+            return head;
+        }
+        end -= 1;
+    }
+    String::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_to_tokens() {
+        let text = "Cells divide. ".repeat(100);
+        let head = truncate_to_tokens(&text, 10);
+        assert!(text.starts_with(&head));
+        assert!(head.len() < text.len());
+        assert_eq!(truncate_to_tokens("Short text.", 10), "Short text.");
+    }
+}
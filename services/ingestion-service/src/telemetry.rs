@@ -0,0 +1,105 @@
+// Prometheus metrics and audit log events for parse traffic, shared by the
+// REST and gRPC paths
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use metrics::{counter, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+use crate::parser::ParserError;
+
+pub const PARSE_REQUESTS: &str = "keiko_ingestion_parse_requests_total";
+pub const PARSE_ERRORS: &str = "keiko_ingestion_parse_errors_total";
+pub const PARSE_DURATION: &str = "keiko_ingestion_parse_duration_seconds";
+pub const CHUNKS: &str = "keiko_ingestion_chunks_total";
+pub const TOKENS: &str = "keiko_ingestion_tokens_total";
+
+/// Upper bounds, in seconds, of the parse duration buckets. Scanned PDFs
+/// sent to Azure take tens of seconds; text formats a few milliseconds.
+const DURATION_BUCKETS: &[f64] = &[0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// The process-wide Prometheus recorder, installed on first use so the REST
+/// and gRPC services, and every router built in tests, report into one
+/// registry.
+pub fn handle() -> PrometheusHandle {
+    HANDLE
+        .get_or_init(|| {
+            let recorder = PrometheusBuilder::new()
+                .set_buckets_for_metric(Matcher::Full(PARSE_DURATION.to_string()), DURATION_BUCKETS)
+                .expect("duration buckets are not empty")
+                .build_recorder();
+            let handle = recorder.handle();
+            if let Err(e) = metrics::set_global_recorder(recorder) {
+                tracing::warn!("Metrics recorder not installed, another one already is: {}", e);
+            }
+            handle
+        })
+        .clone()
+}
+
+/// Count a document received for parsing over `interface` (`rest` or `grpc`).
+pub fn record_request(interface: &'static str) {
+    counter!(PARSE_REQUESTS, "interface" => interface).increment(1);
+}
+
+/// Count a document that failed to parse, by error variant.
+pub fn record_error(interface: &'static str, error: &ParserError) {
+    counter!(PARSE_ERRORS, "interface" => interface, "error" => error.kind()).increment(1);
+}
+
+/// Record a finished parse: how long `parser` took, from upload to chunks,
+/// and how many chunks and tokens it produced.
+pub fn record_parse(interface: &'static str, parser: &'static str, elapsed: Duration, chunks: usize, tokens: usize) {
+    histogram!(PARSE_DURATION, "interface" => interface, "parser" => parser).record(elapsed.as_secs_f64());
+    counter!(CHUNKS, "interface" => interface).increment(chunks as u64);
+    counter!(TOKENS, "interface" => interface).increment(tokens as u64);
+}
+
+/// An upload as the parse audit log identifies it: by a hash of its bytes,
+/// never by its content.
+#[derive(Debug, Clone)]
+pub struct UploadFingerprint {
+    /// Hex BLAKE3 hash of the uploaded bytes.
+    pub content_hash: String,
+    pub filename: String,
+    pub content_type: String,
+    pub size_bytes: usize,
+}
+
+impl UploadFingerprint {
+    pub fn new(data: &[u8], filename: &str, content_type: &str) -> Self {
+        Self {
+            content_hash: blake3::hash(data).to_hex().to_string(),
+            filename: filename.to_string(),
+            content_type: content_type.to_string(),
+            size_bytes: data.len(),
+        }
+    }
+}
+
+/// Log a finished parse of `upload` at info level, for correlating log
+/// lines with uploads. Counts only; no document or chunk text.
+pub fn log_parse(
+    interface: &'static str,
+    upload: &UploadFingerprint,
+    parser: &str,
+    page_count: usize,
+    chunk_count: usize,
+    elapsed: Duration,
+) {
+    tracing::info!(
+        interface,
+        content_hash = %upload.content_hash,
+        filename = %upload.filename,
+        content_type = %upload.content_type,
+        size_bytes = upload.size_bytes,
+        parser,
+        page_count,
+        chunk_count,
+        duration_ms = elapsed.as_millis() as u64,
+        "Parsed document"
+    );
+}
@@ -1,9 +1,9 @@
 // Integration tests for ingestion service
+// The placeholder tests below assert nothing yet
+#![allow(clippy::assertions_on_constants)]
 
 #[cfg(test)]
 mod tests {
-    use std::path::PathBuf;
-
     #[tokio::test]
     async fn test_pdf_parsing_integration() {
         // This is a placeholder for actual gRPC integration tests